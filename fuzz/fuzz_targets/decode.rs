@@ -0,0 +1,13 @@
+// Feed random 32-bit words to the decoder: `cargo fuzz run decode`
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use naive_sysy::simulator::{check_inst, decode_stage, disassemble, CPUState, IFIDReg};
+
+fuzz_target!(|inst: u32| {
+    let known = check_inst(inst).is_some();
+    let _ = disassemble(0x8000_0000, inst);
+    let cpu = CPUState::new();
+    let decoded = decode_stage(&cpu, &IFIDReg { pc: 0x8000_0000, inst, seq: 1, compressed: false });
+    assert_eq!(decoded.is_ok(), known);
+});
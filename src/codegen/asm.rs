@@ -8,6 +8,9 @@ use super::label::Label;
 pub struct AsmProgram {
     global_defs: Vec<AsmGlobalDef>,
     globals: Vec<AsmGlobal>,
+    // emit our own _start, _trm_init and stack; without it the built-in assembler or
+    // build/scripts/linker.ld provides them
+    startup: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -42,9 +45,14 @@ impl AsmProgram {
         Self {
             global_defs: Vec::new(),
             globals: Vec::new(),
+            startup: true,
         }
     }
 
+    pub fn without_startup() -> Self {
+        Self { startup: false, ..Self::new() }
+    }
+
     pub fn push_global(&mut self, global: AsmGlobal) {
         self.globals.push(global);
     }
@@ -56,6 +64,19 @@ impl AsmProgram {
     pub fn emit_asm(&self) -> String {
         let mut asm_txt = String::new();
 
+        if !self.startup {
+            asm_txt.push_str(".data\n");
+            for global_def in &self.global_defs {
+                asm_txt.push_str(&format!("  .globl {}\n", global_def.label.to_string()));
+                asm_txt.push_str(&global_def.emit_asm());
+            }
+            asm_txt.push_str(".text\n");
+            for global in &self.globals {
+                asm_txt.push_str(&format!(".globl {}\n", global.label.name()));
+                asm_txt.push_str(&global.emit_asm());
+            }
+            return asm_txt;
+        }
         
         asm_txt.push_str(".globl _trm_init\n");
         asm_txt.push_str(".globl _start\n");
@@ -238,6 +259,29 @@ impl AsmLocal {
         }
     }
 
+    // 64-bit accesses (ra, pointers, saved registers) are not in Inst and go out as text
+    pub fn ld_inst(&mut self, rd: Reg, imm: i32, rs: Reg) {
+        if is_imm12(imm) {
+            self.push_inst(Inst::Raw(format!("ld\t{}, {}({})", rd, imm, rs)));
+        } else {
+            let temp = "t0";
+            self.push_inst(Inst::Li  { rd: temp, imm: imm });
+            self.push_inst(Inst::Add { rd: temp, rs1: rs, rs2: temp });
+            self.push_inst(Inst::Raw(format!("ld\t{}, 0({})", rd, temp)));
+        }
+    }
+
+    pub fn sd_inst(&mut self, rs: Reg, imm: i32, rd: Reg) {
+        if is_imm12(imm) {
+            self.push_inst(Inst::Raw(format!("sd\t{}, {}({})", rs, imm, rd)));
+        } else {
+            let temp = "t0";
+            self.push_inst(Inst::Li  { rd: temp, imm: imm });
+            self.push_inst(Inst::Add { rd: temp, rs1: rd, rs2: temp });
+            self.push_inst(Inst::Raw(format!("sd\t{}, 0({})", rs, temp)));
+        }
+    }
+
     pub fn add_inst(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
         self.push_inst(Inst::Add { rd: rd, rs1: rs1, rs2: rs2 });
     }
@@ -345,7 +389,12 @@ impl AsmLocal {
     pub fn emit_asm(&self) -> String {
         let mut asm_txt = String::new();
         for inst in &self.insts {
-            asm_txt.push_str(&format!("  {}\n", inst.emit_asm()));
+            // opcode and operands separated by a tab, like the peephole pass
+            let text = match inst {
+                Inst::Raw(text) => text.clone(),
+                inst => inst.emit_asm().replacen(' ', "\t", 1),
+            };
+            asm_txt.push_str(&format!("  {}\n", text));
         }
         asm_txt
     }
@@ -1,15 +1,22 @@
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use koopa::ir::{
     entities::{BasicBlock, ValueData},
     FunctionData, Program, Value, ValueKind,
 };
 use koopa::ir::types::TypeKind;
+use koopa::ir::Type;
 use koopa::ir::values::GlobalAlloc;
 
+use super::array::InitVal;
+use super::asm::{AsmGlobal, AsmGlobalDef, AsmLocal, AsmProgram, Section};
+use super::instruction::Inst;
+use super::label::Label;
+use super::{peephole, regalloc, DebugInfo, Target};
 
-/// RISC-V 寄存器名称（下标对应寄存器编号），我们只用 t0/t1/t2/t6 临时计算，不做持久分配。
+
+/// RISC-V 寄存器名称（下标对应寄存器编号），t0/t1/t2/t6 用来临时计算，s1–s11 由 regalloc.rs 分配。
 // 函数调用时，前 8 个参数放到 a0–a7（寄存器号 10–17），返回值放到 a0。
 const REGISTER_NAMES: [&str; 32] = [
     "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "fp", "s1",
@@ -39,59 +46,77 @@ impl GlobalSymbolTable {
         self.symbols.get(&value).map(|s| s.strip_prefix('@').unwrap_or(s.as_str()))
     }
 }
-/// 将整个 Koopa `program` 转成一整串 RISC-V 汇编字符串。
-pub fn build_riscv(program: &Program) -> String {
-    let mut output = String::new();
-    let mut symbol_table = GlobalSymbolTable::default(); // 初始化符号表
+/// 将整个 Koopa `program` 降成 AsmProgram：数据段放全局变量，每个函数一个 AsmGlobal。
+pub fn build_riscv(program: &Program, target: Target, debug: Option<&DebugInfo>) -> AsmProgram {
+    // 指针类型的 size() 跟着目标走（数组参数的 alloc、getptr 的步长）
+    Type::set_ptr_size(target.ptr_size());
 
-    match program.build(program, &mut symbol_table) {
-        Ok(lines) => {
-            for line in lines {
-                output.push_str(&line);
-                output.push('\n');
-            }
-        }
+    match lower_program(program, target, debug) {
+        Ok(asm) => asm,
         Err(e) => {
             eprintln!("Codegen error: {}", e);
             panic!("Code generation failed");
         }
     }
-    output
 }
 
-/// 把各个 Koopa IR 组件编译成 RISC-V 指令行。
-pub trait AssBuilder {
-    fn build(&self, program: &Program, symbol_table: &mut GlobalSymbolTable) -> Result<Vec<String>, String>;
-}
-
-
-impl AssBuilder for Program {
-    fn build(&self, _: &Program, symbol_table: &mut GlobalSymbolTable) -> Result<Vec<String>, String> {
-        let mut program_codes = Vec::new();
+fn lower_program(program: &Program, target: Target, debug: Option<&DebugInfo>) -> Result<AsmProgram, String> {
+    let mut symbol_table = GlobalSymbolTable::default(); // 初始化符号表
+    let mut asm = AsmProgram::without_startup();
+
+    // 1) 数据段（.data）
+    for &global in program.inst_layout() {
+        let global_name = symbol_table.generate_name(global);
+        let lines = program.borrow_value(global).build(program, &mut symbol_table, target, debug)?;
+        let init_val = lines.iter().map(|line| init_val(line)).collect::<Result<_, _>>()?;
+        asm.push_globaldef(AsmGlobalDef { label: Label::new(global_name), init_val });
+    }
 
-        // 1) Emit 数据段（.data）
-        program_codes.push(".data".to_string());
-        for &global in self.inst_layout() {
-            let vd = self.borrow_value(global);
-            let global_name = symbol_table.generate_name(global);
-            program_codes.push(format!("  .globl {}", global_name));
-            program_codes.push(format!("{}:", global_name));
-            program_codes.extend(vd.build(self, symbol_table)?);
+    // 2) 代码段（.text）
+    for &func in program.func_layout() {
+        let func_data = program.func(func);
+        if func_data.layout().bbs().len() > 0 {
+            let lines = peephole::optimize(func_data.build(program, &mut symbol_table, target, debug)?);
+            asm.push_global(lower_function(func_data.name(), lines));
         }
+    }
+    Ok(asm)
+}
 
-        // 2) Emit 代码段（.text）
-        program_codes.push(".text".to_string());
-        for &func in self.func_layout() {
-            if self.func(func).layout().bbs().len() > 0 {
-                program_codes.extend(self.func(func).build(self, symbol_table)?);
-            }
+/// 数据段的一行（"  .word 7" / "  .zero 16"）-> InitVal
+fn init_val(line: &str) -> Result<InitVal, String> {
+    let err = || format!("无法解析的数据行: {}", line);
+    let (directive, value) = line.trim().split_once(' ').ok_or_else(err)?;
+    match directive {
+        ".word" => Ok(InitVal::Word(value.parse().map_err(|_| err())?)),
+        ".zero" => Ok(InitVal::Zero(value.parse().map_err(|_| err())?)),
+        _ => Err(err()),
+    }
+}
+
+/// 一个函数的汇编行 -> AsmGlobal：遇到基本块标签就开一个新的 AsmLocal，
+/// 窥孔优化认识的指令解析成 Inst，其余的（ld/sd、addw 等、注释）原样保留
+fn lower_function(name: &str, lines: Vec<String>) -> AsmGlobal {
+    let mut asm_func = AsmGlobal::new(Section::Text, Label::new(name.to_string()));
+    let mut local = AsmLocal::new(None); // prologue，没有自己的标签
+    for line in lines {
+        if let Some(label) = line.strip_suffix(':').filter(|_| !line.starts_with(' ')) {
+            asm_func.push_local(std::mem::replace(&mut local, AsmLocal::new(Some(Label::new(label.to_string())))));
+        } else {
+            local.push_inst(peephole::parse(&line).unwrap_or_else(|| Inst::Raw(line.trim().to_string())));
         }
-        Ok(program_codes)
     }
+    asm_func.push_local(local);
+    asm_func
+}
+
+/// 把各个 Koopa IR 组件编译成 RISC-V 指令行。
+pub trait AssBuilder {
+    fn build(&self, program: &Program, symbol_table: &mut GlobalSymbolTable, target: Target, debug: Option<&DebugInfo>) -> Result<Vec<String>, String>;
 }
 
 impl AssBuilder for FunctionData {
-    fn build(&self, program: &Program, symbol_table: &mut GlobalSymbolTable) -> Result<Vec<String>, String> {
+    fn build(&self, program: &Program, symbol_table: &mut GlobalSymbolTable, target: Target, debug: Option<&DebugInfo>) -> Result<Vec<String>, String> {
         let mut function_codes = Vec::new();
 
         // 函数名（去掉 leading '@'）
@@ -122,47 +147,88 @@ impl AssBuilder for FunctionData {
             bb_index += 1;
         }
 
-        // --- 2) 收集所有本地 Value 并分配栈槽 ---
-        // 这里只把函数内部的“本地” Value（参数和基本块里出现的）注册到 slot_offsets
-        let mut slot_offsets: HashMap<Value, i32> = HashMap::new();
-        // fp+0 放旧的 fp，fp+4 放旧的 ra，fp+8 开始放本地 Value
-        let mut current_offset: i32 = 8;
-
-        // 2.1) 给函数参数分配栈槽
+        // --- 2) 给每个本地 Value 找个位置 ---
+        // 先做寄存器分配（regalloc.rs），分到 s 寄存器的值不占栈槽；
+        // 其余的值、以及 alloc 出来的变量和数组放在栈上
+        let mut frame = Frame {
+            regs: regalloc::allocate(self),
+            slots: HashMap::new(),
+            wide: HashSet::new(),
+        };
+        // 一个机器字：rv32 是 4 字节，rv64 是 8 字节
+        let word = target.ptr_size() as i32;
+        // fp+0 放旧的 fp，fp+word 放旧的 ra，再往上放本地 Value
+        let mut current_offset: i32 = 2 * word;
+
+        // 2.1) 没分到寄存器的函数参数
         for &param in self.params() {
-            slot_offsets.insert(param, current_offset);
-            current_offset += 4;
+            if !frame.regs.contains_key(&param) {
+                frame.add_slot(param, self.dfg().value(param).ty(), target, &mut current_offset);
+            }
         }
 
-        // 2.2) 提前把基本块里所有 insts() 中出现的本地 Value 都插一遍
-        for (_, node) in self.layout().bbs() {
-            for &value in node.insts().keys() {
-                if slot_offsets.contains_key(&value) {
-                    continue;
+        // 2.2) 基本块参数和指令结果各占一个栈槽，alloc 按它分配的类型大小分配
+        for (&bb, node) in self.layout().bbs() {
+            for &param in self.dfg().bbs()[&bb].params() {
+                if !frame.regs.contains_key(&param) {
+                    frame.add_slot(param, self.dfg().value(param).ty(), target, &mut current_offset);
                 }
-                // 如果是 alloc（可能是数组或指针），一次性分配它的整个类型大小
+            }
+            for &value in node.insts().keys() {
                 let value_data = self.dfg().value(value);
-                let size_bytes = if let ValueKind::Alloc(_) = value_data.kind() {
-                    // alloc 的类型有可能是数组或更复杂的结构，直接用 ty().size() 拿到字节数
-                    value_data.ty().size() as i32
-                } else {
-                    // 其他普通值（整数常量、运算结果等），按 4 字节分配
-                    4
-                };
-                slot_offsets.insert(value, current_offset);
-                current_offset += size_bytes;
+                if let ValueKind::Alloc(_) = value_data.kind() {
+                    // alloc 的类型是“指向被分配类型的指针”，被分配的可能是数组或更复杂的结构，
+                    // 用它的 size() 拿到字节数
+                    let size = match value_data.ty().kind() {
+                        TypeKind::Pointer(base) => base.size() as i32,
+                        _ => unreachable!("alloc 的类型不是指针"),
+                    };
+                    let align = if size % word == 0 { word } else { 4 };
+                    current_offset = (current_offset + align - 1) / align * align;
+                    frame.slots.insert(value, current_offset);
+                    current_offset += size;
+                } else if regalloc::has_result(self, value) && !frame.regs.contains_key(&value) {
+                    frame.add_slot(value, value_data.ty(), target, &mut current_offset);
+                }
             }
         }
 
+        // 2.3) 带参数的 jump 先把实参全部写到这片中转区，再拷进目标基本块的参数槽，
+        //      避免参数之间互相覆盖（例如循环里交换两个变量）
+        let max_bb_params = self
+            .layout()
+            .bbs()
+            .keys()
+            .map(|bb| self.dfg().bbs()[bb].params().len())
+            .max()
+            .unwrap_or(0);
+        current_offset = (current_offset + word - 1) / word * word;
+        let staging_offset = current_offset;
+        current_offset += word * max_bb_params as i32;
+
+        // 2.4) s 寄存器是 callee-saved，只保存真正用到的，每个占一个机器字
+        let saved: Vec<&str> = regalloc::ALLOCATABLE
+            .iter()
+            .copied()
+            .filter(|reg| frame.regs.values().any(|r| r == reg))
+            .collect();
+        let save_offset = current_offset;
+        current_offset += word * saved.len() as i32;
+
+        // 叶子函数不会改 ra，不用保存
+        let is_leaf = !self
+            .layout()
+            .bbs()
+            .iter()
+            .flat_map(|(_, node)| node.insts().keys())
+            .any(|&inst| matches!(self.dfg().value(inst).kind(), ValueKind::Call(_)));
+
         // 向 4 字节对齐
         let total_slots = ((current_offset + 3) / 4) * 4;
         // 向 16 字节对齐
         let frame_size = ((total_slots + 15) / 16) * 16;
 
-        // --- 3) Emit 函数 prologue ---
-        function_codes.push(format!(".globl {}", func_name));
-        function_codes.push(format!("{}:", func_name));
-
+        // --- 3) Emit 函数 prologue（.globl 和函数标签由 AsmProgram 输出）---
         // --- frame_size 可能超出 ±2047，要用 li+t6 再 sub sp ---
         if frame_size > 0 {
             if (-2048..=2047).contains(&(-frame_size)) {
@@ -175,389 +241,145 @@ impl AssBuilder for FunctionData {
             }
         }
 
-        function_codes.push("  sw\tfp, 0(sp)".to_string());
-        function_codes.push("  sw\tra, 4(sp)".to_string());
+        // fp、ra 和 s 寄存器都按机器字保存：rv32 用 sw/lw，rv64 用 sd/ld
+        let (load_word, store_word) = if target == Target::Rv64 { ("ld", "sd") } else { ("lw", "sw") };
+        function_codes.push(format!("  {}\tfp, 0(sp)", store_word));
+        if !is_leaf {
+            function_codes.push(format!("  {}\tra, {}(sp)", store_word, word));
+        }
         function_codes.push("  addi\tfp, sp, 0".to_string());
+        for (i, reg) in saved.iter().enumerate() {
+            access_slot(&mut function_codes, store_word, reg, save_offset + word * i as i32);
+        }
 
-        // 保存前 8 个参数到栈槽
+        // 前 8 个参数从 a0–a7 搬到各自的位置
         for (i, &param) in self.params().iter().enumerate().take(8) {
-            let offset = *slot_offsets.get(&param).expect("参数必定已插入 slot_offsets");
-            // --- 对偏移量做超范围检查 ---
-            if (-2048..=2047).contains(&offset) {
-                function_codes.push(format!(
-                    "  sw\t{}, {}(fp)",
-                    REGISTER_NAMES[10 + i], // a0–a7
-                    offset
-                ));
-            } else {
-                function_codes.push(format!("  li\t{}, {}", REGISTER_NAMES[31], offset)); // t6 = offset
-                function_codes.push(format!(
-                    "  add\t{}, fp, {}",
-                    REGISTER_NAMES[31], REGISTER_NAMES[31]
-                )); // t6 = fp + offset
-                function_codes.push(format!(
-                    "  sw\t{}, 0({})",
-                    REGISTER_NAMES[10 + i], REGISTER_NAMES[31]
-                )); // sw a_reg, 0(t6)
-            }
+            frame.store_result(&mut function_codes, param, REGISTER_NAMES[10 + i]);
         }
 
-        // 保存第 9..nargs 个参数
-        // 正确偏移要加上 frame_size
+        // 第 9..nargs 个参数在 caller 的栈区里，偏移要加上 frame_size
         let num_params = self.params().len();
         if num_params > 8 {
             for i in 8..num_params {
                 let param = self.params()[i];
-                let offset = *slot_offsets.get(&param).expect("参数必定已插入 slot_offsets");
-                // 计算 caller 栈区里第 i 个参数所在偏移：frame_size + 4*(i-8)
-                let caller_offset = frame_size + 4 * (i as i32 - 8);
-
-                // --- 对 caller_offset 做范围检查 ---
-                if (-2048..=2047).contains(&caller_offset) {
-                    function_codes.push(format!(
-                        "  lw\t{}, {}(fp)",
-                        REGISTER_NAMES[5], // t0
-                        caller_offset
-                    ));
-                } else {
-                    function_codes.push(format!(
-                        "  li\t{}, {}",
-                        REGISTER_NAMES[31], caller_offset
-                    )); // t6 = caller_offset
-                    function_codes.push(format!(
-                        "  add\t{}, fp, {}",
-                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                    )); // t6 = fp + caller_offset
-                    function_codes.push(format!(
-                        "  lw\t{}, 0({})",
-                        REGISTER_NAMES[5], REGISTER_NAMES[31]
-                    )); // lw t0, 0(t6)
-                }
+                // 计算 caller 栈区里第 i 个参数所在偏移：frame_size + word*(i-8)
+                let caller_offset = frame_size + word * (i as i32 - 8);
+                let reg = frame.result_reg(param, REGISTER_NAMES[5]); // t0
+                let (load_op, _) = mem_ops(self.dfg().value(param).ty(), target);
+                access_slot(&mut function_codes, load_op, reg, caller_offset);
+                frame.store_result(&mut function_codes, param, reg);
+            }
+        }
 
-                // 再把 t0 存到本地的 slot
-                if (-2048..=2047).contains(&offset) {
-                    function_codes.push(format!("  sw\t{}, {}(fp)", REGISTER_NAMES[5], offset));
+        // epilogue：恢复 s 寄存器、ra、fp 和 sp，然后返回
+        let epilogue = |codes: &mut Vec<String>| {
+            for (i, reg) in saved.iter().enumerate() {
+                access_slot(codes, load_word, reg, save_offset + word * i as i32);
+            }
+            if !is_leaf {
+                codes.push(format!("  {}\tra, {}(sp)", load_word, word));
+            }
+            codes.push(format!("  {}\tfp, 0(sp)", load_word));
+            if frame_size > 0 {
+                // --- 恢复 sp 时可能超范围，要用 li + add ---
+                if (-2048..=2047).contains(&frame_size) {
+                    codes.push(format!("  addi\tsp, sp, {}", frame_size));
                 } else {
-                    function_codes.push(format!("  li\t{}, {}", REGISTER_NAMES[31], offset)); // t6 = offset
-                    function_codes.push(format!(
-                        "  add\t{}, fp, {}",
-                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                    )); // t6 = fp + offset
-                    function_codes.push(format!(
-                        "  sw\t{}, 0({})",
-                        REGISTER_NAMES[5], REGISTER_NAMES[31]
-                    )); // sw t0, 0(t6)
+                    codes.push(format!("  li\t{}, {}", REGISTER_NAMES[31], frame_size)); // t6 = frame_size
+                    codes.push(format!("  add\tsp, sp, {}", REGISTER_NAMES[31])); // sp = sp + t6
                 }
             }
-        }
+            codes.push("  ret".to_string());
+        };
 
         let mut saw_ret = false;
 
+        // -g：给有结果的值按出现顺序编号（%0、%1……），注释里的 IR 用这些名字
+        let mut names = HashMap::new();
+        for &param in self.params() {
+            names.insert(param, names.len());
+        }
+        for (&bb, node) in self.layout().bbs() {
+            for &param in self.dfg().bbs()[&bb].params() {
+                names.insert(param, names.len());
+            }
+            for &inst in node.insts().keys() {
+                if !matches!(self.dfg().value(inst).ty().kind(), TypeKind::Unit) {
+                    names.insert(inst, names.len());
+                }
+            }
+        }
+        let source_lines: Vec<&str> = debug.map(|d| d.source.lines().collect()).unwrap_or_default();
+        let mut last_line = None;
+
         // --- 4) 遍历基本块并生成指令 ---
+        // 操作数用 Frame::operand 取到寄存器里（分到 s 寄存器的直接用，否则装进 t0/t1），
+        // 结果写进 Frame::result_reg 给出的寄存器，在栈上的再由 store_result 写回栈槽
         for (&bb, node) in self.layout().bbs() {
             if let Some(lbl) = bb_labels.get(&bb) {
                 function_codes.push(format!("{}:", lbl));
             }
 
             for &value in node.insts().keys() {
+                // -g：源码行变了就先写一行 `# line N: ...`（汇编器据此记下 PC 到源码行的对应），
+                //     再写这条 IR 本身
+                if let Some(debug) = debug {
+                    if let Some(&line) = debug.lines.get(&value) {
+                        if last_line != Some(line) {
+                            let text = source_lines.get(line.wrapping_sub(1)).map_or("", |l| l.trim());
+                            function_codes.push(format!("  # line {}: {}", line, text));
+                            last_line = Some(line);
+                        }
+                    }
+                    function_codes.push(format!("  # {}", describe(self, program, value, &names, &bb_labels, symbol_table)));
+                }
+
                 let value_data = self.dfg().value(value);
                 match value_data.kind() {
                     // --- 整数常量: %dst = integer <imm> ---
                     ValueKind::Integer(int_val) => {
-                        let offset = *slot_offsets.get(&value).unwrap_or_else(|| {
-                            panic!("Value {:?} 没在 slot_offsets 注册", value)
-                        });
-                        function_codes.push(format!(
-                            "  li\t{}, {}",
-                            REGISTER_NAMES[5], // t0
-                            int_val.value()
-                        ));
-                        // --- 对超范围偏移做处理 ---
-                        if (-2048..=2047).contains(&offset) {
-                            function_codes.push(format!(
-                                "  sw\t{}, {}(fp)",
-                                REGISTER_NAMES[5], offset
-                            ));
-                        } else {
-                            function_codes.push(format!(
-                                "  li\t{}, {}",
-                                REGISTER_NAMES[31], offset
-                            )); // t6 = offset
-                            function_codes.push(format!(
-                                "  add\t{}, fp, {}",
-                                REGISTER_NAMES[31], REGISTER_NAMES[31]
-                            )); // t6 = fp + offset
-                            function_codes.push(format!(
-                                "  sw\t{}, 0({})",
-                                REGISTER_NAMES[5], REGISTER_NAMES[31]
-                            )); // sw t0, 0(t6)
-                        }
+                        let rd = frame.result_reg(value, REGISTER_NAMES[5]); // t0
+                        function_codes.push(format!("  li\t{}, {}", rd, int_val.value()));
+                        frame.store_result(&mut function_codes, value, rd);
                     }
 
                     // --- 二元运算: %dst = binary %lhs, %rhs ---
                     ValueKind::Binary(binary) => {
-                        let dst_offset = *slot_offsets.get(&value).unwrap_or_else(|| {
-                            panic!("Value {:?} 没在 slot_offsets 注册", value)
-                        });
-
-                        // 1) 取 lhs
-                        match self.dfg().value(binary.lhs()).kind() {
-                            ValueKind::Integer(lhs_int) => {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[5], // t0
-                                    lhs_int.value()
-                                ));
-                            }
-                            _ => {
-                                let lhs_offset = *slot_offsets
-                                    .get(&binary.lhs())
-                                    .unwrap_or_else(|| {
-                                        panic!(
-                                            "Value {:?} 没在 slot_offsets 注册",
-                                            binary.lhs()
-                                        )
-                                    });
-                                // --- load 超范围偏移 ---
-                                if (-2048..=2047).contains(&lhs_offset) {
-                                    function_codes.push(format!(
-                                        "  lw\t{}, {}(fp)",
-                                        REGISTER_NAMES[5], lhs_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], lhs_offset
-                                    )); // t6 = lhs_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  lw\t{}, 0({})",
-                                        REGISTER_NAMES[5], REGISTER_NAMES[31]
-                                    )); // lw t0, 0(t6)
-                                }
-                            }
-                        }
-
-                        // 2) 取 rhs
-                        match self.dfg().value(binary.rhs()).kind() {
-                            ValueKind::Integer(rhs_int) => {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[6], // t1
-                                    rhs_int.value()
-                                ));
-                            }
-                            _ => {
-                                let rhs_offset = *slot_offsets
-                                    .get(&binary.rhs())
-                                    .unwrap_or_else(|| {
-                                        panic!(
-                                            "Value {:?} 没在 slot_offsets 注册",
-                                            binary.rhs()
-                                        )
-                                    });
-                                // --- load 超范围偏移 ---
-                                if (-2048..=2047).contains(&rhs_offset) {
-                                    function_codes.push(format!(
-                                        "  lw\t{}, {}(fp)",
-                                        REGISTER_NAMES[6], rhs_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], rhs_offset
-                                    )); // t6 = rhs_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  lw\t{}, 0({})",
-                                        REGISTER_NAMES[6], REGISTER_NAMES[31]
-                                    )); // lw t1, 0(t6)
-                                }
-                            }
-                        }
-
-                        // 3) 生成指令结果放到 t3
-                        let instr = match binary.op() {
-                            koopa::ir::BinaryOp::Add => "add",
-                            koopa::ir::BinaryOp::Sub => "sub",
-                            koopa::ir::BinaryOp::Mul => "mul",
-                            koopa::ir::BinaryOp::Div => "div",
-                            koopa::ir::BinaryOp::Mod => "rem",
-                            koopa::ir::BinaryOp::Eq => {
-                                function_codes.push(format!(
-                                    "  xor\t{}, {}, {}",
-                                    REGISTER_NAMES[7], // t3
-                                    REGISTER_NAMES[5], // t0
-                                    REGISTER_NAMES[6]  // t1
-                                ));
-                                function_codes.push(format!(
-                                    "  seqz\t{}, {}",
-                                    REGISTER_NAMES[7], // t3
-                                    REGISTER_NAMES[7]  // t3
-                                ));
-                                // --- 存储超范围偏移 ---
-                                if (-2048..=2047).contains(&dst_offset) {
-                                    function_codes.push(format!(
-                                        "  sw\t{}, {}(fp)",
-                                        REGISTER_NAMES[7], dst_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], dst_offset
-                                    )); // t6 = dst_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  sw\t{}, 0({})",
-                                        REGISTER_NAMES[7], REGISTER_NAMES[31]
-                                    ));
-                                }
-                                continue;
-                            }
-                            koopa::ir::BinaryOp::NotEq => {
-                                function_codes.push(format!(
-                                    "  xor\t{}, {}, {}",
-                                    REGISTER_NAMES[7], // t3
-                                    REGISTER_NAMES[5], // t0
-                                    REGISTER_NAMES[6]  // t1
-                                ));
-                                function_codes.push(format!(
-                                    "  snez\t{}, {}",
-                                    REGISTER_NAMES[7], // t3
-                                    REGISTER_NAMES[7]  // t3
-                                ));
-                                // --- 存储超范围偏移 ---
-                                if (-2048..=2047).contains(&dst_offset) {
-                                    function_codes.push(format!(
-                                        "  sw\t{}, {}(fp)",
-                                        REGISTER_NAMES[7], dst_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], dst_offset
-                                    )); // t6 = dst_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  sw\t{}, 0({})",
-                                        REGISTER_NAMES[7], REGISTER_NAMES[31]
-                                    ));
-                                }
-                                continue;
-                            }
-                            koopa::ir::BinaryOp::Lt => "slt",
-                            koopa::ir::BinaryOp::Gt => "sgt",
-                            koopa::ir::BinaryOp::Le => {
-                                function_codes.push(format!(
-                                    "  sgt\t{}, {}, {}",
-                                    REGISTER_NAMES[7], // t3
-                                    REGISTER_NAMES[5], // t0
-                                    REGISTER_NAMES[6]  // t1
-                                ));
-                                function_codes.push(format!(
-                                    "  seqz\t{}, {}",
-                                    REGISTER_NAMES[7], // t3
-                                    REGISTER_NAMES[7]  // t3
-                                ));
-                                // --- 存储超范围偏移 ---
-                                if (-2048..=2047).contains(&dst_offset) {
-                                    function_codes.push(format!(
-                                        "  sw\t{}, {}(fp)",
-                                        REGISTER_NAMES[7], dst_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], dst_offset
-                                    )); // t6 = dst_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  sw\t{}, 0({})",
-                                        REGISTER_NAMES[7], REGISTER_NAMES[31]
-                                    ));
-                                }
-                                continue;
-                            }
-                            koopa::ir::BinaryOp::Ge => {
-                                function_codes.push(format!(
-                                    "  slt\t{}, {}, {}",
-                                    REGISTER_NAMES[7], // t3
-                                    REGISTER_NAMES[5], // t0
-                                    REGISTER_NAMES[6]  // t1
-                                ));
-                                function_codes.push(format!(
-                                    "  seqz\t{}, {}",
-                                    REGISTER_NAMES[7], // t3
-                                    REGISTER_NAMES[7]  // t3
-                                ));
-                                // --- 存储超范围偏移 ---
-                                if (-2048..=2047).contains(&dst_offset) {
-                                    function_codes.push(format!(
-                                        "  sw\t{}, {}(fp)",
-                                        REGISTER_NAMES[7], dst_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], dst_offset
-                                    )); // t6 = dst_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  sw\t{}, 0({})",
-                                        REGISTER_NAMES[7], REGISTER_NAMES[31]
-                                    ));
-                                }
-                                continue;
-                            }
-                            koopa::ir::BinaryOp::And => "and",
-                            koopa::ir::BinaryOp::Or => "or",
-                            koopa::ir::BinaryOp::Xor => "xor",
-                            koopa::ir::BinaryOp::Shl => "sll",
-                            koopa::ir::BinaryOp::Shr => "srl",
-                            koopa::ir::BinaryOp::Sar => "sra",
+                        // 1) 取 lhs、rhs
+                        let lhs = frame.operand(self, &mut function_codes, binary.lhs(), REGISTER_NAMES[5]); // t0
+                        let rhs = frame.operand(self, &mut function_codes, binary.rhs(), REGISTER_NAMES[6]); // t1
+
+                        // 2) 结果放到 rd（s 寄存器或 t2）
+                        let rd = frame.result_reg(value, REGISTER_NAMES[7]); // t2
+                        let (instr, fixup) = match binary.op() {
+                            koopa::ir::BinaryOp::Add => ("add", None),
+                            koopa::ir::BinaryOp::Sub => ("sub", None),
+                            koopa::ir::BinaryOp::Mul => ("mul", None),
+                            koopa::ir::BinaryOp::Div => ("div", None),
+                            koopa::ir::BinaryOp::Mod => ("rem", None),
+                            koopa::ir::BinaryOp::Eq => ("xor", Some("seqz")),
+                            koopa::ir::BinaryOp::NotEq => ("xor", Some("snez")),
+                            koopa::ir::BinaryOp::Lt => ("slt", None),
+                            koopa::ir::BinaryOp::Gt => ("sgt", None),
+                            koopa::ir::BinaryOp::Le => ("sgt", Some("seqz")),
+                            koopa::ir::BinaryOp::Ge => ("slt", Some("seqz")),
+                            koopa::ir::BinaryOp::And => ("and", None),
+                            koopa::ir::BinaryOp::Or => ("or", None),
+                            koopa::ir::BinaryOp::Xor => ("xor", None),
+                            koopa::ir::BinaryOp::Shl => ("sll", None),
+                            koopa::ir::BinaryOp::Shr => ("srl", None),
+                            koopa::ir::BinaryOp::Sar => ("sra", None),
                         };
-                        function_codes.push(format!(
-                            "  {}\t{}, {}, {}",
-                            instr, REGISTER_NAMES[7], REGISTER_NAMES[5], REGISTER_NAMES[6]
-                        ));
-                        // 存储结果 t3 -> dst_offset(fp)
-                        if (-2048..=2047).contains(&dst_offset) {
-                            function_codes.push(format!(
-                                "  sw\t{}, {}(fp)",
-                                REGISTER_NAMES[7], dst_offset
-                            ));
-                        } else {
-                            function_codes.push(format!(
-                                "  li\t{}, {}",
-                                REGISTER_NAMES[31], dst_offset
-                            )); // t6 = dst_offset
-                            function_codes.push(format!(
-                                "  add\t{}, fp, {}",
-                                REGISTER_NAMES[31], REGISTER_NAMES[31]
-                            ));
-                            function_codes.push(format!(
-                                "  sw\t{}, 0({})",
-                                REGISTER_NAMES[7], REGISTER_NAMES[31]
-                            ));
+                        // rv64 上 i32 的算术和移位用 w 结尾的指令，结果按 32 位截断再符号扩展
+                        let instr = match instr {
+                            "add" | "sub" | "mul" | "div" | "rem" | "sll" | "srl" | "sra" if target == Target::Rv64 => format!("{}w", instr),
+                            _ => instr.to_string(),
+                        };
+                        function_codes.push(format!("  {}\t{}, {}, {}", instr, rd, lhs, rhs));
+                        if let Some(fixup) = fixup {
+                            function_codes.push(format!("  {}\t{}, {}", fixup, rd, rd));
                         }
+                        frame.store_result(&mut function_codes, value, rd);
                     }
 
                     // --- 局部分配: %slot = alloc i32/数组/指针 ---
@@ -570,280 +392,58 @@ impl AssBuilder for FunctionData {
 
                     // --- 存储: store %value, %ptr ---
                     ValueKind::Store(st) => {
-                        // 先打印标记说明这是 store
-                        // 1) 先把要存的值加载到 t0
-                        let val_repr = match self.dfg().value(st.value()).kind() {
-                            ValueKind::Integer(iv) => {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[5], // t0
-                                    iv.value()
-                                ));
-                                REGISTER_NAMES[5]
-                            }
-                            _ => {
-                                let arg_offset = *slot_offsets.get(&st.value()).unwrap_or_else(
-                                    || panic!("Store 的值 {:?} 没在 slot_offsets 注册", st.value()),
-                                );
-                                // --- load 超范围偏移 ---
-                                if (-2048..=2047).contains(&arg_offset) {
-                                    function_codes.push(format!(
-                                        "  lw\t{}, {}(fp)",
-                                        REGISTER_NAMES[5], arg_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], arg_offset
-                                    )); // t6 = arg_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    )); // t6 = fp + arg_offset
-                                    function_codes.push(format!(
-                                        "  lw\t{}, 0({})",
-                                        REGISTER_NAMES[5], REGISTER_NAMES[31]
-                                    ));
-                                }
-                                REGISTER_NAMES[5]
-                            }
-                        };
-
-                        // 2) 根据 dest 是否在 slot_offsets 来决定局部还是全局
-                        if slot_offsets.contains_key(&st.dest()) {
-                            // 本地指针：先从栈上把这个“指针值”恢复到 t1，然后 sw 值到 0(t1)
-                            let dest_offset = *slot_offsets
-                                .get(&st.dest())
-                                .unwrap_or_else(|| {
-                                    panic!("Store 目标 {:?} 没在 slot_offsets 注册", st.dest())
-                                });
-
-                            // 如果 dest 是 alloc 返回的 value，本身 slot 就是数组第一元素的地址，
-                            // “取指针”应为 fp + dest_offset
-                            let base_ptr_is_alloc = matches!(
-                                self.dfg().value(st.dest()).kind(),
-                                ValueKind::Alloc(_)
-                            );
-                            if base_ptr_is_alloc {
-                                // 需要判断 dest_offset 范围
-                                if (-2048..=2047).contains(&dest_offset) {
-                                    function_codes.push(format!(
-                                        "  addi\t{}, fp, {}",
-                                        REGISTER_NAMES[6], dest_offset
-                                    )); // t1 = &arr[0]
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], dest_offset
-                                    )); // t6 = dest_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[6], REGISTER_NAMES[31]
-                                    )); // t1 = fp + t6
-                                }
-                            } else {
-                                // 先 load 指针到 t1
-                                if (-2048..=2047).contains(&dest_offset) {
-                                    function_codes.push(format!(
-                                        "  lw\t{}, {}(fp)",
-                                        REGISTER_NAMES[6], dest_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], dest_offset
-                                    )); // t6 = dest_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  lw\t{}, 0({})",
-                                        REGISTER_NAMES[6], REGISTER_NAMES[31]
-                                    ));
-                                }
-                            }
-                            // 把 t0 (val_repr) 存到 0(t1)
-                            function_codes.push(format!(
-                                "  sw\t{}, 0({})",
-                                val_repr, REGISTER_NAMES[6]
-                            ));
-                        } else {
-                            // 全局：用 t1 作为地址寄存器，避免覆盖 t0
-                            let addr_reg = REGISTER_NAMES[6]; // t1
-                            let dest_name = symbol_table.get_global_name(st.dest())
-            .unwrap_or_else(|| panic!("Store 目标 {:?} 未在符号表中注册", st.dest()));
-                            function_codes.push(format!("  la\t{}, {}", addr_reg, dest_name));
-                            function_codes.push(format!("  sw\t{}, 0({})", val_repr, addr_reg));
-                        }
+                        // 1) 要存的值
+                        let val_repr = frame.operand(self, &mut function_codes, st.value(), REGISTER_NAMES[5]); // t0
+                        // 2) 目标地址：alloc 直接用 fp + 偏移，本地指针取它的值，全局变量用 la
+                        let addr_reg = frame.address(self, &mut function_codes, symbol_table, st.dest(), REGISTER_NAMES[6]); // t1
+                        let (_, store_op) = mem_ops(self.dfg().value(st.value()).ty(), target);
+                        function_codes.push(format!("  {}\t{}, 0({})", store_op, val_repr, addr_reg));
                     }
 
                     // --- 加载: %dst = load %ptr ---
                     ValueKind::Load(ld) => {
-                        // 先打印标记说明这是 load
-                        // 1) 判断 src 是否在 slot_offsets
-                        if slot_offsets.contains_key(&ld.src()) {
-                            // 本地指针：先从栈上或 alloc 计算得到指针，再从 0(指针) 读值
-                            let src_offset = *slot_offsets.get(&ld.src()).unwrap_or_else(|| {
-                                panic!("Load 源 {:?} 没在 slot_offsets 注册", ld.src())
-                            });
-
-                            // 如果 src 是 alloc 返回的 value，本身 slot 就是数组第一元素地址
-                            let base_ptr_is_alloc = matches!(
-                                self.dfg().value(ld.src()).kind(),
-                                ValueKind::Alloc(_)
-                            );
-                            if base_ptr_is_alloc {
-                                // 需判断 src_offset 范围
-                                if (-2048..=2047).contains(&src_offset) {
-                                    function_codes.push(format!(
-                                        "  addi\t{}, fp, {}",
-                                        REGISTER_NAMES[5], src_offset
-                                    )); // t0 = &arr[0]
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], src_offset
-                                    )); // t6 = src_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[5], REGISTER_NAMES[31]
-                                    )); // t0 = fp + t6
-                                }
-                            } else {
-                                // 否则先 load 指针到 t0
-                                if (-2048..=2047).contains(&src_offset) {
-                                    function_codes.push(format!(
-                                        "  lw\t{}, {}(fp)",
-                                        REGISTER_NAMES[5], src_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], src_offset
-                                    )); // t6 = src_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  lw\t{}, 0({})",
-                                        REGISTER_NAMES[5], REGISTER_NAMES[31]
-                                    ));
-                                }
-                            }
-
-                            // 从 0(t0) 读取到 t1
-                            function_codes.push(format!(
-                                "  lw\t{}, 0({})",
-                                REGISTER_NAMES[6], REGISTER_NAMES[5]
-                            ));
-                            // 把 t1 存回 dst_offset(fp)
-                            let dst_offset = *slot_offsets.get(&value).unwrap_or_else(|| {
-                                panic!("Load 结果 {:?} 没在 slot_offsets 注册", value)
-                            });
-                            if (-2048..=2047).contains(&dst_offset) {
-                                function_codes.push(format!(
-                                    "  sw\t{}, {}(fp)",
-                                    REGISTER_NAMES[6], dst_offset
-                                ));
-                            } else {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[31], dst_offset
-                                )); // t6 = dst_offset
-                                function_codes.push(format!(
-                                    "  add\t{}, fp, {}",
-                                    REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                ));
-                                function_codes.push(format!(
-                                    "  sw\t{}, 0({})",
-                                    REGISTER_NAMES[6], REGISTER_NAMES[31]
-                                ));
-                            }
-                        } else {
-                            // 全局：用 t0 取地址，用 t1 从内存里读值
-                            let addr_reg = REGISTER_NAMES[5]; // t0
-                            let val_reg = REGISTER_NAMES[6];  // t1
-                            let src_name = symbol_table.get_global_name(ld.src())
-            .unwrap_or_else(|| panic!("Load 源 {:?} 未在符号表中注册", ld.src()));
-                            function_codes.push(format!("  la\t{}, {}", addr_reg, src_name));
-                            function_codes.push(format!("  lw\t{}, 0({})", val_reg, addr_reg));
-                            let dst_offset = *slot_offsets.get(&value).unwrap_or_else(|| {
-                                panic!("Load 结果 {:?} 没在 slot_offsets 注册", value)
-                            });
-                            if (-2048..=2047).contains(&dst_offset) {
-                                function_codes.push(format!(
-                                    "  sw\t{}, {}(fp)",
-                                    val_reg, dst_offset
-                                ));
-                            } else {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[31], dst_offset
-                                )); // t6 = dst_offset
-                                function_codes.push(format!(
-                                    "  add\t{}, fp, {}",
-                                    REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                ));
-                                function_codes.push(format!(
-                                    "  sw\t{}, 0({})",
-                                    val_reg, REGISTER_NAMES[31]
-                                ));
-                            }
-                        }
+                        let addr_reg = frame.address(self, &mut function_codes, symbol_table, ld.src(), REGISTER_NAMES[5]); // t0
+                        let rd = frame.result_reg(value, REGISTER_NAMES[6]); // t1
+                        let (load_op, _) = mem_ops(value_data.ty(), target);
+                        function_codes.push(format!("  {}\t{}, 0({})", load_op, rd, addr_reg));
+                        frame.store_result(&mut function_codes, value, rd);
                     }
 
                     // --- 分支: branch %cond, %then_bb, %else_bb ---
                     ValueKind::Branch(branch) => {
-                        let cond_repr = match self.dfg().value(branch.cond()).kind() {
-                            ValueKind::Integer(int_val) => {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[5], // t0
-                                    int_val.value()
-                                ));
-                                REGISTER_NAMES[5]
-                            }
-                            _ => {
-                                let cond_offset =
-                                    *slot_offsets.get(&branch.cond()).unwrap_or_else(|| {
-                                        panic!(
-                                            "Branch 条件 {:?} 没在 slot_offsets 注册",
-                                            branch.cond()
-                                        )
-                                    });
-                                // --- load 超范围偏移 ---
-                                if (-2048..=2047).contains(&cond_offset) {
-                                    function_codes.push(format!(
-                                        "  lw\t{}, {}(fp)",
-                                        REGISTER_NAMES[5], cond_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], cond_offset
-                                    )); // t6 = cond_offset
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  lw\t{}, 0({})",
-                                        REGISTER_NAMES[5], REGISTER_NAMES[31]
-                                    ));
-                                }
-                                REGISTER_NAMES[5]
-                            }
-                        };
+                        let cond_repr = frame.operand(self, &mut function_codes, branch.cond(), REGISTER_NAMES[5]); // t0
                         let then_lbl = &bb_labels[&branch.true_bb()];
                         let else_lbl = &bb_labels[&branch.false_bb()];
                         function_codes.push(format!("  bnez\t{}, {}", cond_repr, then_lbl));
                         function_codes.push(format!("  j\t{}", else_lbl));
                     }
 
-                    // --- 跳转: jump %target_bb ---
+                    // --- 跳转: jump %target_bb(args...) ---
                     ValueKind::Jump(jump) => {
+                        let params = self.dfg().bbs()[&jump.target()].params();
+                        if let [param] = params {
+                            // 只有一个参数时直接写过去
+                            let rd = frame.result_reg(*param, REGISTER_NAMES[5]); // t0
+                            let arg = frame.operand(self, &mut function_codes, jump.args()[0], rd);
+                            if arg != rd {
+                                function_codes.push(format!("  mv\t{}, {}", rd, arg));
+                            }
+                            frame.store_result(&mut function_codes, *param, rd);
+                        } else {
+                            // 1) 实参 -> 中转区
+                            for (i, &arg) in jump.args().iter().enumerate() {
+                                let (_, store_op) = mem_ops(self.dfg().value(arg).ty(), target);
+                                let arg = frame.operand(self, &mut function_codes, arg, REGISTER_NAMES[5]); // t0
+                                access_slot(&mut function_codes, store_op, arg, staging_offset + word * i as i32);
+                            }
+                            // 2) 中转区 -> 目标基本块的参数
+                            for (i, &param) in params.iter().enumerate() {
+                                let rd = frame.result_reg(param, REGISTER_NAMES[5]); // t0
+                                let (load_op, _) = mem_ops(self.dfg().value(param).ty(), target);
+                                access_slot(&mut function_codes, load_op, rd, staging_offset + word * i as i32);
+                                frame.store_result(&mut function_codes, param, rd);
+                            }
+                        }
                         let target_lbl = &bb_labels[&jump.target()];
                         function_codes.push(format!("  j\t{}", target_lbl));
                     }
@@ -860,90 +460,25 @@ impl AssBuilder for FunctionData {
                         // 2) 参数数量 (nargs) 与超过 8 个的数量 (extra)
                         let nargs = callv.args().len();
                         let extra = if nargs > 8 { nargs - 8 } else { 0 };
+                        // 栈上传参的区域按 16 字节对齐，sp 在 call 时保持 16 字节对齐
+                        let stack_args = (extra as i32 * word + 15) / 16 * 16;
 
                         // 3) 若参数超过 8 个，则为第 9..nargs 个参数在调用者栈上腾空间并依次存储
                         if extra > 0 {
-                            function_codes.push(format!("  addi\tsp, sp, -{}", extra * 4));
+                            function_codes.push(format!("  addi\tsp, sp, -{}", stack_args));
                             for j in 8..nargs {
-                                let arg = callv.args()[j];
-                                let offset_sp = 4 * (j as i32 - 8);
-                                match self.dfg().value(arg).kind() {
-                                    ValueKind::Integer(iv) => {
-                                        function_codes.push(format!(
-                                            "  li\t{}, {}",
-                                            REGISTER_NAMES[5], // t0
-                                            iv.value()
-                                        ));
-                                    }
-                                    _ => {
-                                        let arg_offset = *slot_offsets.get(&arg).unwrap_or_else(
-                                            || panic!("Call 参数 {:?} 没在 slot_offsets 注册", arg),
-                                        );
-                                        // --- load 超范围偏移 ---
-                                        if (-2048..=2047).contains(&arg_offset) {
-                                            function_codes.push(format!(
-                                                "  lw\t{}, {}(fp)",
-                                                REGISTER_NAMES[5], arg_offset
-                                            ));
-                                        } else {
-                                            function_codes.push(format!(
-                                                "  li\t{}, {}",
-                                                REGISTER_NAMES[31], arg_offset
-                                            )); // t6 = arg_offset
-                                            function_codes.push(format!(
-                                                "  add\t{}, fp, {}",
-                                                REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                            ));
-                                            function_codes.push(format!(
-                                                "  lw\t{}, 0({})",
-                                                REGISTER_NAMES[5], REGISTER_NAMES[31]
-                                            ));
-                                        }
-                                    }
-                                }
-                                function_codes.push(format!(
-                                    "  sw\t{}, {}(sp)",
-                                    REGISTER_NAMES[5], offset_sp
-                                ));
+                                let (_, store_op) = mem_ops(self.dfg().value(callv.args()[j]).ty(), target);
+                                let arg = frame.operand(self, &mut function_codes, callv.args()[j], REGISTER_NAMES[5]); // t0
+                                function_codes.push(format!("  {}\t{}, {}(sp)", store_op, arg, word * (j as i32 - 8)));
                             }
                         }
 
-                        // 4) 前 8 个参数装入 a0..a7
+                        // 4) 前 8 个参数装入 a0..a7（值都在 s 寄存器或栈上，不会被前面的装入覆盖）
                         for i in 0..std::cmp::min(nargs, 8) {
-                            let arg = callv.args()[i];
-                            let a_reg = 10 + i; // a0..a7 对应寄存器号 10..17
-                            match self.dfg().value(arg).kind() {
-                                ValueKind::Integer(iv) => {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[a_reg], iv.value()
-                                    ));
-                                }
-                                _ => {
-                                    let arg_offset = *slot_offsets.get(&arg).unwrap_or_else(
-                                        || panic!("Call 参数 {:?} 没在 slot_offsets 注册", arg),
-                                    );
-                                    // --- load 超范围偏移 ---
-                                    if (-2048..=2047).contains(&arg_offset) {
-                                        function_codes.push(format!(
-                                            "  lw\t{}, {}(fp)",
-                                            REGISTER_NAMES[a_reg], arg_offset
-                                        ));
-                                    } else {
-                                        function_codes.push(format!(
-                                            "  li\t{}, {}",
-                                            REGISTER_NAMES[31], arg_offset
-                                        )); // t6 = arg_offset
-                                        function_codes.push(format!(
-                                            "  add\t{}, fp, {}",
-                                            REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                        ));
-                                        function_codes.push(format!(
-                                            "  lw\t{}, 0({})",
-                                            REGISTER_NAMES[a_reg], REGISTER_NAMES[31]
-                                        ));
-                                    }
-                                }
+                            let a_reg = REGISTER_NAMES[10 + i]; // a0..a7 对应寄存器号 10..17
+                            let arg = frame.operand(self, &mut function_codes, callv.args()[i], a_reg);
+                            if arg != a_reg {
+                                function_codes.push(format!("  mv\t{}, {}", a_reg, arg));
                             }
                         }
 
@@ -952,409 +487,74 @@ impl AssBuilder for FunctionData {
 
                         // 6) 恢复 sp（如果有额外参数）
                         if extra > 0 {
-                            function_codes.push(format!("  addi\tsp, sp, {}", extra * 4));
+                            function_codes.push(format!("  addi\tsp, sp, {}", stack_args));
                         }
 
-                        // 7) 把 a0（返回值）存到 dst 的栈槽
-                        let dst_offset = *slot_offsets.get(&value).unwrap_or_else(|| {
-                            panic!("Call 返回值 {:?} 没在 slot_offsets 注册", value)
-                        });
-                        // --- 存储超范围偏移 ---
-                        if (-2048..=2047).contains(&dst_offset) {
-                            function_codes.push(format!(
-                                "  sw\t{}, {}(fp)",
-                                REGISTER_NAMES[10], // a0
-                                dst_offset
-                            ));
-                        } else {
-                            function_codes.push(format!(
-                                "  li\t{}, {}",
-                                REGISTER_NAMES[31], dst_offset
-                            )); // t6 = dst_offset
-                            function_codes.push(format!(
-                                "  add\t{}, fp, {}",
-                                REGISTER_NAMES[31], REGISTER_NAMES[31]
-                            ));
-                            function_codes.push(format!(
-                                "  sw\t{}, 0({})",
-                                REGISTER_NAMES[10], REGISTER_NAMES[31]
-                            ));
+                        // 7) 把 a0（返回值）放到 dst 的位置；void 调用没有位置
+                        if frame.is_local(value) {
+                            let rd = frame.result_reg(value, REGISTER_NAMES[10]); // a0
+                            if rd != REGISTER_NAMES[10] {
+                                function_codes.push(format!("  mv\t{}, {}", rd, REGISTER_NAMES[10]));
+                            }
+                            frame.store_result(&mut function_codes, value, rd);
                         }
                     }
 
                     // --- getelemptr: %dst = getelemptr %base, %index ---
-                    ValueKind::GetElemPtr(gep) => {
-                        // 先打印一个标记，说明是 getelemptr
-                        let dst_offset = *slot_offsets.get(&value).unwrap_or_else(|| {
-                            panic!("Value {:?} not registered in slot_offsets", value)
-                        });
-
-                        // 1) 取基址指针（如果 base 是 alloc，就直接用 fp+offset；否则从 栈 页加载 / 或者 全局）
-                        let base_val = gep.src();
-                        let base_addr_reg = REGISTER_NAMES[5]; // t0
-                        //不应该直接在value上找base_val,可能是全局变量，不在value上
-                        let base_is_alloc = if slot_offsets.contains_key(&base_val) {
-                            matches!(
-                                self.dfg().value(base_val).kind(),
-                                ValueKind::Alloc(_)
-                            )
-                        } else {
-                            false
+                    // --- getptr: %dst = getptr %src, %index ---
+                    ValueKind::GetElemPtr(_) | ValueKind::GetPtr(_) => {
+                        let (base_val, index) = match value_data.kind() {
+                            ValueKind::GetElemPtr(gep) => (gep.src(), gep.index()),
+                            ValueKind::GetPtr(gp) => (gp.src(), gp.index()),
+                            _ => unreachable!(),
                         };
 
-                        if base_is_alloc {
-                            // 直接计算：t0 = fp + slot_offsets[base_val]
-                            let base_offset = *slot_offsets.get(&base_val).unwrap();
-                            if (-2048..=2047).contains(&base_offset) {
-                                function_codes.push(format!(
-                                    "  addi\t{}, fp, {}",
-                                    base_addr_reg, base_offset
-                                )); // t0 = &array_base
-                            } else {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[31], base_offset
-                                )); // t6 = base_offset
-                                function_codes.push(format!(
-                                    "  add\t{}, fp, {}",
-                                    base_addr_reg, REGISTER_NAMES[31]
-                                )); // t0 = fp + t6
-                            }
-                        } else if slot_offsets.contains_key(&base_val) {
-                            // 本地变量指针：先 load 指针：t0 = [fp + base_offset]
-                            let base_offset = *slot_offsets.get(&base_val).unwrap();
-                            if (-2048..=2047).contains(&base_offset) {
-                                function_codes.push(format!(
-                                    "  lw\t{}, {}(fp)",
-                                    base_addr_reg, base_offset
-                                ));
-                            } else {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[31], base_offset
-                                )); // t6 = base_offset
-                                function_codes.push(format!(
-                                    "  add\t{}, fp, {}",
-                                    REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                ));
-                                function_codes.push(format!(
-                                    "  lw\t{}, 0({})",
-                                    base_addr_reg, REGISTER_NAMES[31]
-                                ));
-                            }
-                        } else {
-                            // 全局变量指针：直接加载全局变量名到 t0
-                            //为base_val去掉前缀@
-                            //在终端打印base_val
-
-                            let base_name = symbol_table.get_global_name(base_val)
-            .unwrap_or_else(|| panic!("全局变量 {:?} 未在符号表中注册", base_val));
-                            function_codes.push(format!("  la\t{}, {}", base_addr_reg, base_name));
-
-                        }
+                        // 1) 取基址指针（alloc 用 fp+offset；本地指针取它的值；全局变量用 la）
+                        let base_reg = frame.address(self, &mut function_codes, symbol_table, base_val, REGISTER_NAMES[5]); // t0
 
-                        // 2) 取索引值到 t1（常量或变量）
-                        let index_reg = REGISTER_NAMES[6]; // t1
-                        match self.dfg().value(gep.index()).kind() {
-                            ValueKind::Integer(int_val) => {
-                                // 常量索引：li t1, constant
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    index_reg, int_val.value()
-                                ));
-                            }
-                            _ => {
-                                // 变量索引：从栈加载
-                                let index_offset = *slot_offsets.get(&gep.index()).unwrap();
-                                if (-2048..=2047).contains(&index_offset) {
-                                    function_codes.push(format!(
-                                        "  lw\t{}, {}(fp)",
-                                        index_reg, index_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], index_offset
-                                    ));
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  lw\t{}, 0({})",
-                                        index_reg, REGISTER_NAMES[31]
-                                    ));
-                                }
-                            }
-                        }
+                        // 2) 取索引值（常量或变量）
+                        let index_reg = frame.operand(self, &mut function_codes, index, REGISTER_NAMES[6]); // t1
 
-                        // 3) 计算偏移量 = index * sizeof(element)
-                        //    先拿到 “指针指向的类型”：如果 base_val 是本地，则从 dfg()；否则从 program.borrow_value()
-                        let ptr_type = if slot_offsets.contains_key(&base_val) {
-                            let local_vd = self.dfg().value(base_val);
-                            local_vd.ty().clone()
+                        // 3) 元素大小：先拿到“指针指向的类型”，本地的从 dfg()，全局的从 program.borrow_value()；
+                        //    getelemptr 再取数组的元素类型，getptr 就是指向的类型本身
+                        let ptr_type = if frame.is_local(base_val) {
+                            self.dfg().value(base_val).ty().clone()
                         } else {
-                            //在终端打印base_val
-
-                            let global_vd = program.borrow_value(base_val);
-                            global_vd.ty().clone()
+                            program.borrow_value(base_val).ty().clone()
                         };
                         let pointed_type = if let TypeKind::Pointer(inner) = ptr_type.kind() {
                             inner.clone()
                         } else {
-                            panic!("getelemptr 源类型不是指针: {:?}", ptr_type);
+                            panic!("getelemptr/getptr 源类型不是指针: {:?}", ptr_type);
                         };
-                        let element_type = if let TypeKind::Array(elem, _) = pointed_type.kind() {
-                            elem.clone()
-                        } else {
-                            panic!("getelemptr 基址类型不是数组: {:?}", pointed_type);
+                        let element_type = match (value_data.kind(), pointed_type.kind()) {
+                            (ValueKind::GetPtr(_), _) => pointed_type.clone(),
+                            (_, TypeKind::Array(elem, _)) => elem.clone(),
+                            _ => panic!("getelemptr 基址类型不是数组: {:?}", pointed_type),
                         };
                         let element_size = element_type.size() as i32;
-                        let mul_temp_reg = REGISTER_NAMES[7]; // t3
-                        function_codes.push(format!("  li\t{}, {}", mul_temp_reg, element_size)); // t3 = element_size
-                        function_codes.push(format!(
-                            "  mul\t{}, {}, {}",
-                            index_reg, index_reg, mul_temp_reg
-                        )); // t1 = index * element_size
 
-                        // 4) 计算最终地址：add t0, t0, t1
-                        function_codes.push(format!(
-                            "  add\t{}, {}, {}",
-                            base_addr_reg, base_addr_reg, index_reg
-                        )); // t0 = base_addr + (index * element_size)
-
-                        // 5) 保存结果到栈槽
-                        if (-2048..=2047).contains(&dst_offset) {
-                            function_codes.push(format!(
-                                "  sw\t{}, {}(fp)",
-                                base_addr_reg, dst_offset
-                            ));
-                        } else {
-                            function_codes.push(format!(
-                                "  li\t{}, {}",
-                                REGISTER_NAMES[31], dst_offset
-                            )); // t6 = dst_offset
-                            function_codes.push(format!(
-                                "  add\t{}, fp, {}",
-                                REGISTER_NAMES[31], REGISTER_NAMES[31]
-                            ));
-                            function_codes.push(format!(
-                                "  sw\t{}, 0({})",
-                                base_addr_reg, REGISTER_NAMES[31]
-                            ));
-                        }
-                    }
-
-                    // --- getptr: %dst = getptr %src, %index ---
-                    ValueKind::GetPtr(gep) => {
-
-                        let dst_offset = *slot_offsets.get(&value).unwrap_or_else(|| {
-                            panic!("Value {:?} not registered in slot_offsets", value)
-                        });
-
-                        // 1) 取基址指针（如果 base 是 alloc，就直接用 fp+offset；否则从栈或全局加载）
-                        let base_val = gep.src();
-                        let base_addr_reg = REGISTER_NAMES[5]; // t0
-                        let base_is_alloc = matches!(
-                            self.dfg().value(base_val).kind(),
-                            ValueKind::Alloc(_)
-                        );
-                        if base_is_alloc {
-                            let base_offset = *slot_offsets.get(&base_val).unwrap();
-                            if (-2048..=2047).contains(&base_offset) {
-                                function_codes.push(format!(
-                                    "  addi\t{}, fp, {}",
-                                    base_addr_reg, base_offset
-                                ));
-                            } else {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[31], base_offset
-                                ));
-                                function_codes.push(format!(
-                                    "  add\t{}, fp, {}",
-                                    base_addr_reg, REGISTER_NAMES[31]
-                                ));
-                            }
-                        } else if slot_offsets.contains_key(&base_val) {
-                            let base_offset = *slot_offsets.get(&base_val).unwrap();
-                            if (-2048..=2047).contains(&base_offset) {
-                                function_codes.push(format!(
-                                    "  lw\t{}, {}(fp)",
-                                    base_addr_reg, base_offset
-                                ));
-                            } else {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[31], base_offset
-                                ));
-                                function_codes.push(format!(
-                                    "  add\t{}, fp, {}",
-                                    REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                ));
-                                function_codes.push(format!(
-                                    "  lw\t{}, 0({})",
-                                    base_addr_reg, REGISTER_NAMES[31]
-                                ));
-                            }
-                        } else {
-                            let base_name = symbol_table.get_global_name(base_val)
-            .unwrap_or_else(|| panic!("全局变量 {:?} 未在符号表中注册", base_val));
-                            function_codes.push(format!("  la\t{}, {}", base_addr_reg, base_name));
-                        }
-
-                        // 2) 取索引值到 t1（常量或变量）
-                        let index_reg = REGISTER_NAMES[6]; // t1
-                        match self.dfg().value(gep.index()).kind() {
-                            ValueKind::Integer(int_val) => {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    index_reg, int_val.value()
-                                ));
-                            }
-                            _ => {
-                                let index_offset = *slot_offsets.get(&gep.index()).unwrap();
-                                if (-2048..=2047).contains(&index_offset) {
-                                    function_codes.push(format!(
-                                        "  lw\t{}, {}(fp)",
-                                        index_reg, index_offset
-                                    ));
-                                } else {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[31], index_offset
-                                    ));
-                                    function_codes.push(format!(
-                                        "  add\t{}, fp, {}",
-                                        REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                    ));
-                                    function_codes.push(format!(
-                                        "  lw\t{}, 0({})",
-                                        index_reg, REGISTER_NAMES[31]
-                                    ));
-                                }
-                            }
-                        }
-
-                        // 3) 计算元素大小（element_size）：
-                        //    如果 base_val 是本地，就用 self.dfg().value(local).ty()；否则从全局拿 program.borrow_value()
-                        let ptr_type = if slot_offsets.contains_key(&base_val)
-                            || matches!(self.dfg().value(base_val).kind(), ValueKind::Alloc(_))
-                        {
-                            let local_vd = self.dfg().value(base_val);
-                            local_vd.ty().clone()
-                        } else {
-                            //z
-                            let global_vd = program.borrow_value(base_val);
-                            global_vd.ty().clone()
-                        };
-                        let element_type = if let TypeKind::Pointer(inner) = ptr_type.kind() {
-                            inner.clone()
-                        } else {
-                            panic!("getptr 源类型不是指针: {:?}", ptr_type);
-                        };
-                        let element_size = element_type.size() as i32;
-
-                        // 把 element_size 装到 t3
-                        let mul_temp_reg = REGISTER_NAMES[7]; // t3
-                        function_codes.push(format!(
-                            "  li\t{}, {}",
-                            mul_temp_reg, element_size
-                        ));
-
-                        // 4) 计算偏移量 = index * element_size
+                        // 4) 偏移量 t1 = index * element_size（t2 装 element_size），地址 = base + t1
+                        function_codes.push(format!("  li\t{}, {}", REGISTER_NAMES[7], element_size)); // t2
                         function_codes.push(format!(
                             "  mul\t{}, {}, {}",
-                            index_reg, index_reg, mul_temp_reg
-                        ));
-
-                        // 5) 计算最终地址：add t0, t0, t1
-                        function_codes.push(format!(
-                            "  add\t{}, {}, {}",
-                            base_addr_reg, base_addr_reg, index_reg
+                            REGISTER_NAMES[6], index_reg, REGISTER_NAMES[7]
                         ));
-
-                        // 6) 保存结果到 dst_offset(fp)
-                        if (-2048..=2047).contains(&dst_offset) {
-                            function_codes.push(format!(
-                                "  sw\t{}, {}(fp)",
-                                base_addr_reg, dst_offset
-                            ));
-                        } else {
-                            function_codes.push(format!(
-                                "  li\t{}, {}",
-                                REGISTER_NAMES[31], dst_offset
-                            ));
-                            function_codes.push(format!(
-                                "  add\t{}, fp, {}",
-                                REGISTER_NAMES[31], REGISTER_NAMES[31]
-                            ));
-                            function_codes.push(format!(
-                                "  sw\t{}, 0({})",
-                                base_addr_reg, REGISTER_NAMES[31]
-                            ));
-                        }
+                        let rd = frame.result_reg(value, REGISTER_NAMES[5]); // t0
+                        function_codes.push(format!("  add\t{}, {}, {}", rd, base_reg, REGISTER_NAMES[6]));
+                        frame.store_result(&mut function_codes, value, rd);
                     }
 
                     // --- 返回: return %opt ---
                     ValueKind::Return(ret) => {
-
                         saw_ret = true;
                         if let Some(val) = ret.value() {
-                            match self.dfg().value(val).kind() {
-                                ValueKind::Integer(int_val) => {
-                                    function_codes.push(format!(
-                                        "  li\t{}, {}",
-                                        REGISTER_NAMES[5], // t0
-                                        int_val.value()
-                                    ));
-                                }
-                                _ => {
-                                    let val_offset = *slot_offsets.get(&val).unwrap_or_else(|| {
-                                        panic!("Return 值 {:?} 没在 slot_offsets 注册", val)
-                                    });
-                                    // --- load 超范围偏移 ---
-                                    if (-2048..=2047).contains(&val_offset) {
-                                        function_codes.push(format!(
-                                            "  lw\t{}, {}(fp)",
-                                            REGISTER_NAMES[5], val_offset
-                                        ));
-                                    } else {
-                                        function_codes.push(format!(
-                                            "  li\t{}, {}",
-                                            REGISTER_NAMES[31], val_offset
-                                        ));
-                                        function_codes.push(format!(
-                                            "  add\t{}, fp, {}",
-                                            REGISTER_NAMES[31], REGISTER_NAMES[31]
-                                        ));
-                                        function_codes.push(format!(
-                                            "  lw\t{}, 0({})",
-                                            REGISTER_NAMES[5], REGISTER_NAMES[31]
-                                        ));
-                                    }
-                                }
+                            let reg = frame.operand(self, &mut function_codes, val, REGISTER_NAMES[10]); // a0
+                            if reg != REGISTER_NAMES[10] {
+                                function_codes.push(format!("  mv\ta0, {}", reg));
                             }
-                            function_codes.push(format!("  mv\ta0, {}", REGISTER_NAMES[5]));
                         }
-                        function_codes.push("  lw\tra, 4(sp)".to_string());
-                        function_codes.push("  lw\tfp, 0(sp)".to_string());
-                        if frame_size > 0 {
-                            // --- 恢复 sp 时可能超范围，要用 li + add ---
-                            if (-2048..=2047).contains(&frame_size) {
-                                function_codes.push(format!("  addi\tsp, sp, {}", frame_size));
-                            } else {
-                                function_codes.push(format!(
-                                    "  li\t{}, {}",
-                                    REGISTER_NAMES[31], frame_size
-                                )); // t6 = frame_size
-                                function_codes.push(format!(
-                                    "  add\tsp, sp, {}",
-                                    REGISTER_NAMES[31]
-                                )); // sp = sp + t6
-                            }
-                        }
-                        function_codes.push("  ret".to_string());
+                        epilogue(&mut function_codes);
                     }
 
                     _ => {
@@ -1370,32 +570,175 @@ impl AssBuilder for FunctionData {
         // --- 5) 若从未遇到任何 Return，则补一个“默认 ret 0” ---
         if !saw_ret {
             function_codes.push("  li\ta0, 0".to_string());
-            function_codes.push("  lw\tra, 4(sp)".to_string());
-            function_codes.push("  lw\tfp, 0(sp)".to_string());
-            if frame_size > 0 {
-                // --- 恢复 sp 时可能超范围，要用 li + add ---
-                if (-2048..=2047).contains(&frame_size) {
-                    function_codes.push(format!("  addi\tsp, sp, {}", frame_size));
-                } else {
-                    function_codes.push(format!(
-                        "  li\t{}, {}",
-                        REGISTER_NAMES[31], frame_size
-                    )); // t6 = frame_size
-                    function_codes.push(format!(
-                        "  add\tsp, sp, {}",
-                        REGISTER_NAMES[31]
-                    )); // sp = sp + t6
-                }
-            }
-            function_codes.push("  ret".to_string());
+            epilogue(&mut function_codes);
         }
 
         Ok(function_codes)
     }
 }
 
+/// 函数里每个本地 Value 的位置：分到的 s 寄存器，或者 fp 上的栈槽（alloc 的栈槽就是变量本身）
+struct Frame {
+    regs: HashMap<Value, &'static str>,
+    slots: HashMap<Value, i32>,
+    wide: HashSet<Value>, // 栈槽占 8 字节、用 ld/sd 存取的值（rv64 的指针）
+}
+
+impl Frame {
+    /// 在 offset 处给 value 分一个栈槽，按槽的大小对齐
+    fn add_slot(&mut self, value: Value, ty: &Type, target: Target, offset: &mut i32) {
+        let size = if mem_ops(ty, target).0 == "ld" {
+            self.wide.insert(value);
+            8
+        } else {
+            4
+        };
+        *offset = (*offset + size - 1) / size * size;
+        self.slots.insert(value, *offset);
+        *offset += size;
+    }
+
+    /// 不在这里的是全局变量（或者没有结果的指令）
+    fn is_local(&self, value: Value) -> bool {
+        self.regs.contains_key(&value) || self.slots.contains_key(&value)
+    }
+
+    /// 把操作数放进寄存器并返回这个寄存器：分到 s 寄存器的直接用，常量 li 到 scratch，其余从栈槽 lw 到 scratch
+    fn operand(&self, func: &FunctionData, codes: &mut Vec<String>, value: Value, scratch: &'static str) -> &'static str {
+        if let Some(&reg) = self.regs.get(&value) {
+            return reg;
+        }
+        match func.dfg().value(value).kind() {
+            ValueKind::Integer(int_val) => codes.push(format!("  li\t{}, {}", scratch, int_val.value())),
+            _ => {
+                let offset = *self
+                    .slots
+                    .get(&value)
+                    .unwrap_or_else(|| panic!("Value {:?} 没在 slot_offsets 注册", value));
+                let op = if self.wide.contains(&value) { "ld" } else { "lw" };
+                access_slot(codes, op, scratch, offset);
+            }
+        }
+        scratch
+    }
+
+    /// 指针操作数：alloc 的地址是 fp + 偏移，本地指针就是它的值，全局变量用 la 取地址
+    fn address(&self, func: &FunctionData, codes: &mut Vec<String>, symbol_table: &GlobalSymbolTable, ptr: Value, scratch: &'static str) -> &'static str {
+        if !self.is_local(ptr) {
+            let name = symbol_table
+                .get_global_name(ptr)
+                .unwrap_or_else(|| panic!("全局变量 {:?} 未在符号表中注册", ptr));
+            codes.push(format!("  la\t{}, {}", scratch, name));
+            return scratch;
+        }
+        if !matches!(func.dfg().value(ptr).kind(), ValueKind::Alloc(_)) {
+            return self.operand(func, codes, ptr, scratch);
+        }
+        let offset = self.slots[&ptr];
+        if (-2048..=2047).contains(&offset) {
+            codes.push(format!("  addi\t{}, fp, {}", scratch, offset));
+        } else {
+            codes.push(format!("  li\t{}, {}", REGISTER_NAMES[31], offset)); // t6 = offset
+            codes.push(format!("  add\t{}, fp, {}", scratch, REGISTER_NAMES[31]));
+        }
+        scratch
+    }
+
+    /// 结果应该写进的寄存器：分到的 s 寄存器，或者 scratch（之后用 store_result 写回栈槽）
+    fn result_reg(&self, value: Value, scratch: &'static str) -> &'static str {
+        self.regs.get(&value).copied().unwrap_or(scratch)
+    }
+
+    /// 结果在 reg 里；分到了别的寄存器就 mv 过去，在栈上就写回栈槽
+    fn store_result(&self, codes: &mut Vec<String>, value: Value, reg: &str) {
+        match self.regs.get(&value) {
+            Some(&dst) if dst != reg => codes.push(format!("  mv\t{}, {}", dst, reg)),
+            Some(_) => {}
+            None => {
+                let offset = *self
+                    .slots
+                    .get(&value)
+                    .unwrap_or_else(|| panic!("Value {:?} 没在 slot_offsets 注册", value));
+                let op = if self.wide.contains(&value) { "sd" } else { "sw" };
+                access_slot(codes, op, reg, offset);
+            }
+        }
+    }
+}
+
+/// op reg, offset(fp)；偏移超出 ±2047 时借 t6 算地址
+fn access_slot(codes: &mut Vec<String>, op: &str, reg: &str, offset: i32) {
+    if (-2048..=2047).contains(&offset) {
+        codes.push(format!("  {}\t{}, {}(fp)", op, reg, offset));
+    } else {
+        codes.push(format!("  li\t{}, {}", REGISTER_NAMES[31], offset)); // t6 = offset
+        codes.push(format!("  add\t{}, fp, {}", REGISTER_NAMES[31], REGISTER_NAMES[31]));
+        codes.push(format!("  {}\t{}, 0({})", op, reg, REGISTER_NAMES[31]));
+    }
+}
+
+/// -g 注释里的一条 IR，写法接近 Koopa IR 文本；本地的值用 names 里的编号，全局变量用它在 .data 里的名字
+fn describe(
+    func: &FunctionData,
+    program: &Program,
+    value: Value,
+    names: &HashMap<Value, usize>,
+    bb_labels: &HashMap<BasicBlock, String>,
+    symbol_table: &GlobalSymbolTable,
+) -> String {
+    let name = |v: Value| -> String {
+        if let Some(n) = names.get(&v) {
+            return format!("%{}", n);
+        }
+        if let Some(global) = symbol_table.get_global_name(v) {
+            return format!("@{}", global);
+        }
+        match func.dfg().value(v).kind() {
+            ValueKind::Integer(int_val) => int_val.value().to_string(),
+            kind => format!("{:?}", kind),
+        }
+    };
+    let list = |vs: &[Value]| vs.iter().map(|&v| name(v)).collect::<Vec<_>>().join(", ");
+    let target = |bb: BasicBlock, args: &[Value]| {
+        if args.is_empty() { bb_labels[&bb].clone() } else { format!("{}({})", bb_labels[&bb], list(args)) }
+    };
+    let text = match func.dfg().value(value).kind() {
+        ValueKind::Binary(bin) => format!("{} {}, {}", format!("{:?}", bin.op()).to_lowercase(), name(bin.lhs()), name(bin.rhs())),
+        ValueKind::Alloc(_) => "alloc".to_string(),
+        ValueKind::Load(ld) => format!("load {}", name(ld.src())),
+        ValueKind::Store(st) => format!("store {}, {}", name(st.value()), name(st.dest())),
+        ValueKind::GetPtr(gp) => format!("getptr {}, {}", name(gp.src()), name(gp.index())),
+        ValueKind::GetElemPtr(gep) => format!("getelemptr {}, {}", name(gep.src()), name(gep.index())),
+        ValueKind::Branch(br) => format!(
+            "br {}, {}, {}",
+            name(br.cond()),
+            target(br.true_bb(), br.true_args()),
+            target(br.false_bb(), br.false_args())
+        ),
+        ValueKind::Jump(jump) => format!("jump {}", target(jump.target(), jump.args())),
+        ValueKind::Call(callv) => format!("call {}({})", program.func(callv.callee()).name(), list(callv.args())),
+        ValueKind::Return(ret) => match ret.value() {
+            Some(val) => format!("ret {}", name(val)),
+            None => "ret".to_string(),
+        },
+        kind => format!("{:?}", kind),
+    };
+    match names.get(&value) {
+        Some(n) => format!("%{} = {}", n, text),
+        None => text,
+    }
+}
+
+/// 存取一个 ty 类型的值用的 (load, store) 指令：rv64 的指针是 ld/sd，其余都是 lw/sw
+fn mem_ops(ty: &Type, target: Target) -> (&'static str, &'static str) {
+    match ty.kind() {
+        TypeKind::Pointer(_) if target == Target::Rv64 => ("ld", "sd"),
+        _ => ("lw", "sw"),
+    }
+}
+
 impl AssBuilder for ValueData {
-    fn build(&self, program: &Program, symbol_table: &mut GlobalSymbolTable) -> Result<Vec<String>, String> {
+    fn build(&self, program: &Program, symbol_table: &mut GlobalSymbolTable, _: Target, _: Option<&DebugInfo>) -> Result<Vec<String>, String> {
         if let ValueKind::GlobalAlloc(global) = self.kind() {
             let mut value_codes = Vec::new();
             
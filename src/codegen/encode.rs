@@ -0,0 +1,906 @@
+// Built-in assembler: RISC-V assembly text (the compiler's own output) -> flat binary image,
+// so a program can be run on the simulator without an external riscv64 toolchain.
+//
+// Supports RV64IM integer instructions, Zicsr and mret, the usual pseudo-instructions (li, la, mv,
+// call, ret, j, beqz/bnez, seqz/snez, sgt, not, neg, csrr/csrw, rdcycle/rdtime, ...) and the
+// .text/.data/.bss/.section, .globl, .align/.balign, .word/.half/.byte/.dword and .zero/.space/.skip
+// directives.
+// The layout follows build/scripts/linker.ld: a small _start stub at `base` sets sp, calls main
+// and stops with ebreak (exit code in a0), then the rest of .text, .data and .bss. A program that
+// defines _start itself (the stack backend's _start/_trm_init prologue) keeps its own, which must
+// then be the first thing in .text. Conditional branches whose target is out of reach become an
+// inverted branch over a jal.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+const STACK_SIZE: u64 = 0x8000; // like linker.ld: stack above the page-aligned end of .bss
+
+const STARTUP: &str = "\
+_start:
+  la sp, _stack_pointer
+  call main
+  ebreak
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Section {
+    Text,
+    Data,
+    Bss,
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Label(String),
+    Inst { op: String, args: Vec<String> },
+    Data { size: usize, values: Vec<String> },
+    Zero(u64),
+    Align(u64), // bytes
+    SourceLine(usize), // `# line N: ...` from the compiler's -g output
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    no: usize,
+    item: Item,
+}
+
+#[derive(Debug, Default)]
+pub struct Image {
+    pub base: u64,
+    pub bytes: Vec<u8>, // .text and .data; .bss is left to the zeroed memory
+    pub symbols: BTreeMap<String, u64>,
+    pub lines: BTreeMap<u64, usize>, // address -> SysY source line, from -g comments
+}
+
+impl Image {
+    // flat binary for Memory::load_image, plus NAME.map with the symbols (and the source file,
+    // which the line map format requires) next to it
+    pub fn write(&self, path: &Path, source: &Path) -> Result<(), String> {
+        fs::write(path, &self.bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut map = format!("# symbols from the built-in assembler\nsource {}\n", source.display());
+        for (name, addr) in &self.symbols {
+            map.push_str(&format!("sym {} {:x}\n", name, addr));
+        }
+        for (addr, line) in &self.lines {
+            map.push_str(&format!("{:x} {}\n", addr, line));
+        }
+        let map_path = path.with_extension("map");
+        fs::write(&map_path, map).map_err(|e| format!("{}: {}", map_path.display(), e))
+    }
+}
+
+fn reg(name: &str) -> Result<u32, String> {
+    const ABI: [&str; 32] = [
+        "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+        "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+    ];
+    let name = name.trim();
+    if name == "fp" {
+        return Ok(8);
+    }
+    if let Some(i) = ABI.iter().position(|r| *r == name) {
+        return Ok(i as u32);
+    }
+    match name.strip_prefix('x').and_then(|n| n.parse::<u32>().ok()) {
+        Some(i) if i < 32 => Ok(i),
+        _ => Err(format!("unknown register '{}'", name)),
+    }
+}
+
+fn freg(name: &str) -> Result<u32, String> {
+    const ABI: [&str; 32] = [
+        "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2", "fa3", "fa4", "fa5",
+        "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+    ];
+    let name = name.trim();
+    if let Some(i) = ABI.iter().position(|r| *r == name) {
+        return Ok(i as u32);
+    }
+    match name.strip_prefix('f').and_then(|n| n.parse::<u32>().ok()) {
+        Some(i) if i < 32 => Ok(i),
+        _ => Err(format!("unknown float register '{}'", name)),
+    }
+}
+
+fn number(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (neg, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    // up to 64 bits, so 0xffffffffffffffff is -1
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()? as i64,
+        None => digits.parse::<u64>().ok()? as i64,
+    };
+    Some(if neg { value.wrapping_neg() } else { value })
+}
+
+fn imm(text: &str) -> Result<i64, String> {
+    number(text).ok_or_else(|| format!("invalid immediate '{}'", text.trim()))
+}
+
+fn check_range(value: i64, bits: u32, what: &str) -> Result<(), String> {
+    let limit = 1i64 << (bits - 1);
+    if value < -limit || value >= limit {
+        return Err(format!("{} {} does not fit in {} bits", what, value, bits));
+    }
+    Ok(())
+}
+
+// "imm(reg)" or "(reg)"
+fn mem_operand(text: &str) -> Result<(i64, u32), String> {
+    let text = text.trim();
+    let open = text.find('(').ok_or_else(|| format!("expected offset(reg), got '{}'", text))?;
+    let base = text[open + 1..].strip_suffix(')').ok_or_else(|| format!("expected offset(reg), got '{}'", text))?;
+    let offset = if open == 0 { 0 } else { imm(&text[..open])? };
+    Ok((offset, reg(base)?))
+}
+
+// CSR by name or number
+fn csr(text: &str) -> Result<i64, String> {
+    Ok(match text.trim() {
+        "fflags" => 0x001, "frm" => 0x002, "fcsr" => 0x003,
+        "mstatus" => 0x300, "mie" => 0x304, "mtvec" => 0x305, "mscratch" => 0x340, "mepc" => 0x341,
+        "mcause" => 0x342, "mtval" => 0x343, "mip" => 0x344, "mcycle" => 0xb00, "minstret" => 0xb02,
+        "cycle" => 0xc00, "time" => 0xc01, "instret" => 0xc02,
+        name if name.starts_with("hpmcounter") || name.starts_with("mhpmcounter") => {
+            let (prefix, n) = name.split_at(name.find(|c: char| c.is_ascii_digit()).unwrap_or(name.len()));
+            match n.parse::<i64>() {
+                Ok(n @ 3..=31) => if prefix == "hpmcounter" { 0xc00 + n } else { 0xb00 + n },
+                _ => return Err(format!("unknown CSR '{}'", name)),
+            }
+        }
+        name => match number(name) {
+            Some(n) if (0..0x1000).contains(&n) => n,
+            _ => return Err(format!("unknown CSR '{}'", name)),
+        },
+    })
+}
+
+// 5-bit immediate of csrrwi and friends
+fn uimm(text: &str) -> Result<u32, String> {
+    match imm(text)? {
+        n @ 0..=31 => Ok(n as u32),
+        n => Err(format!("CSR immediate {} out of range", n)),
+    }
+}
+
+fn r_type(funct7: u32, rs2: u32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+fn i_type(imm: i64, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    ((imm as u32) & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+fn s_type(imm: i64, rs2: u32, rs1: u32, funct3: u32, opcode: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0x7f) << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | (imm & 0x1f) << 7 | opcode
+}
+
+fn b_type(offset: i64, rs2: u32, rs1: u32, funct3: u32) -> u32 {
+    let imm = offset as u32;
+    (imm >> 12 & 1) << 31 | (imm >> 5 & 0x3f) << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12
+        | (imm >> 1 & 0xf) << 8 | (imm >> 11 & 1) << 7 | 0x63
+}
+
+fn u_type(imm20: i64, rd: u32, opcode: u32) -> u32 {
+    ((imm20 as u32) & 0xfffff) << 12 | rd << 7 | opcode
+}
+
+fn j_type(offset: i64, rd: u32) -> u32 {
+    let imm = offset as u32;
+    (imm >> 20 & 1) << 31 | (imm >> 1 & 0x3ff) << 21 | (imm >> 11 & 1) << 20 | (imm >> 12 & 0xff) << 12 | rd << 7 | 0x6f
+}
+
+// upper and lower parts of a 32-bit pc-relative or absolute value, lower sign-extended
+fn hi_lo(value: i64) -> (i64, i64) {
+    let lo = (value << 52) >> 52;
+    ((value - lo) >> 12, lo)
+}
+
+// li: addi for 12 bits, lui + addiw for a sign-extended 32-bit value, anything wider is the upper
+// part loaded the same way, shifted into place and topped up with addi (the sequence gcc emits)
+fn li_words(rd: u32, value: i64) -> Vec<u32> {
+    if (-2048..2048).contains(&value) {
+        return vec![i_type(value, 0, 0, rd, 0x13)];
+    }
+    if value == value as i32 as i64 {
+        let (hi, lo) = hi_lo(value);
+        let mut words = vec![u_type(hi, rd, 0x37)];
+        if lo != 0 {
+            words.push(i_type(lo, rd, 0, rd, 0x1b)); // addiw keeps the value sign-extended
+        }
+        return words;
+    }
+    let lo = (value << 52) >> 52;
+    // the upper 52 bits rounded for the sign of lo, sign-extended; i64::MAX rounds up to i64::MIN
+    let hi = ((value as u64).wrapping_add(0x800) >> 12 << 12) as i64 >> 12;
+    let shift = hi.trailing_zeros();
+    let mut words = li_words(rd, hi >> shift);
+    words.push(i_type(12 + shift as i64, rd, 1, rd, 0x13)); // slli
+    if lo != 0 {
+        words.push(i_type(lo, rd, 0, rd, 0x13));
+    }
+    words
+}
+
+fn r_op(op: &str) -> Option<(u32, u32, u32)> {
+    // (funct7, funct3, opcode)
+    Some(match op {
+        "add" => (0, 0, 0x33), "sub" => (0x20, 0, 0x33), "sll" => (0, 1, 0x33), "slt" => (0, 2, 0x33),
+        "sltu" => (0, 3, 0x33), "xor" => (0, 4, 0x33), "srl" => (0, 5, 0x33), "sra" => (0x20, 5, 0x33),
+        "or" => (0, 6, 0x33), "and" => (0, 7, 0x33),
+        "mul" => (1, 0, 0x33), "mulh" => (1, 1, 0x33), "mulhsu" => (1, 2, 0x33), "mulhu" => (1, 3, 0x33),
+        "div" => (1, 4, 0x33), "divu" => (1, 5, 0x33), "rem" => (1, 6, 0x33), "remu" => (1, 7, 0x33),
+        "addw" => (0, 0, 0x3b), "subw" => (0x20, 0, 0x3b), "sllw" => (0, 1, 0x3b), "srlw" => (0, 5, 0x3b),
+        "sraw" => (0x20, 5, 0x3b), "mulw" => (1, 0, 0x3b), "divw" => (1, 4, 0x3b), "divuw" => (1, 5, 0x3b),
+        "remw" => (1, 6, 0x3b), "remuw" => (1, 7, 0x3b),
+        _ => return None,
+    })
+}
+
+// A extension as (funct7, funct3); .aq / .rl / .aqrl set the ordering bits, which mean nothing
+// to a single hart but are kept in the encoding
+fn amo_op(op: &str) -> Option<(u32, u32)> {
+    let (base, order) = match op.rsplit_once('.') {
+        Some((base, "aq")) => (base, 2),
+        Some((base, "rl")) => (base, 1),
+        Some((base, "aqrl")) => (base, 3),
+        _ => (op, 0),
+    };
+    let (name, width) = base.rsplit_once('.')?;
+    let funct3 = match width { "w" => 2, "d" => 3, _ => return None };
+    let funct5 = match name {
+        "amoadd" => 0x00, "amoswap" => 0x01, "lr" => 0x02, "sc" => 0x03, "amoxor" => 0x04,
+        "amoor" => 0x08, "amoand" => 0x0c, "amomin" => 0x10, "amomax" => 0x14, "amominu" => 0x18,
+        "amomaxu" => 0x1c,
+        _ => return None,
+    };
+    Some((funct5 << 2 | order, funct3))
+}
+
+fn i_op(op: &str) -> Option<(u32, u32)> {
+    // (funct3, opcode)
+    Some(match op {
+        "addi" => (0, 0x13), "slti" => (2, 0x13), "sltiu" => (3, 0x13), "xori" => (4, 0x13),
+        "ori" => (6, 0x13), "andi" => (7, 0x13), "addiw" => (0, 0x1b),
+        _ => return None,
+    })
+}
+
+fn shift_op(op: &str) -> Option<(u32, u32, u32, u32)> {
+    // (funct6/funct7 upper bits, funct3, opcode, shamt bits)
+    Some(match op {
+        "slli" => (0, 1, 0x13, 6), "srli" => (0, 5, 0x13, 6), "srai" => (0x400, 5, 0x13, 6),
+        "slliw" => (0, 1, 0x1b, 5), "srliw" => (0, 5, 0x1b, 5), "sraiw" => (0x400, 5, 0x1b, 5),
+        _ => return None,
+    })
+}
+
+fn load_op(op: &str) -> Option<u32> {
+    ["lb", "lh", "lw", "ld", "lbu", "lhu", "lwu"].iter().position(|l| *l == op).map(|f| f as u32)
+}
+
+fn store_op(op: &str) -> Option<u32> {
+    ["sb", "sh", "sw", "sd"].iter().position(|s| *s == op).map(|f| f as u32)
+}
+
+// F instructions as (funct7, funct3, fixed rs2, rd is an F register, rs1 is an F register);
+// without a fixed funct3 it holds the rounding mode, an optional last operand (default dyn)
+type FpOp = (u32, Option<u32>, Option<u32>, bool, bool);
+
+fn fp_op(op: &str) -> Option<FpOp> {
+    Some(match op {
+        "fadd.s" => (0x00, None, None, true, true), "fsub.s" => (0x04, None, None, true, true),
+        "fmul.s" => (0x08, None, None, true, true), "fdiv.s" => (0x0c, None, None, true, true),
+        "fsqrt.s" => (0x2c, None, Some(0), true, true),
+        "fsgnj.s" => (0x10, Some(0), None, true, true), "fsgnjn.s" => (0x10, Some(1), None, true, true),
+        "fsgnjx.s" => (0x10, Some(2), None, true, true),
+        "fmin.s" => (0x14, Some(0), None, true, true), "fmax.s" => (0x14, Some(1), None, true, true),
+        "fcvt.w.s" => (0x60, None, Some(0), false, true), "fcvt.wu.s" => (0x60, None, Some(1), false, true),
+        "fcvt.l.s" => (0x60, None, Some(2), false, true), "fcvt.lu.s" => (0x60, None, Some(3), false, true),
+        "fcvt.s.w" => (0x68, None, Some(0), true, false), "fcvt.s.wu" => (0x68, None, Some(1), true, false),
+        "fcvt.s.l" => (0x68, None, Some(2), true, false), "fcvt.s.lu" => (0x68, None, Some(3), true, false),
+        "fmv.x.w" => (0x70, Some(0), Some(0), false, true), "fclass.s" => (0x70, Some(1), Some(0), false, true),
+        "fmv.w.x" => (0x78, Some(0), Some(0), true, false),
+        "feq.s" => (0x50, Some(2), None, false, true), "flt.s" => (0x50, Some(1), None, false, true),
+        "fle.s" => (0x50, Some(0), None, false, true),
+        "fcvt.s.d" => (0x20, None, Some(1), true, true), "fcvt.d.s" => (0x21, None, Some(0), true, true),
+        "fmv.x.d" => (0x71, Some(0), Some(0), false, true), "fmv.d.x" => (0x79, Some(0), Some(0), true, false),
+        // the other D instructions differ from their S forms only in the fmt bit
+        _ if op.ends_with(".d") || op.starts_with("fcvt.d.") => {
+            let (funct7, funct3, rs2, rd_fp, rs1_fp) = fp_op(&op.replace(".d", ".s"))?;
+            (funct7 | 1, funct3, rs2, rd_fp, rs1_fp)
+        }
+        _ => return None,
+    })
+}
+
+fn rounding_mode(text: &str) -> Result<u32, String> {
+    ["rne", "rtz", "rdn", "rup", "rmm"].iter().position(|m| *m == text.trim()).map(|m| m as u32)
+        .or((text.trim() == "dyn").then_some(7))
+        .ok_or_else(|| format!("unknown rounding mode '{}'", text.trim()))
+}
+
+fn encode_fp(op: &str, args: &[String], (funct7, funct3, fixed_rs2, rd_fp, rs1_fp): FpOp) -> Result<u32, String> {
+    let regs = if fixed_rs2.is_some() { 2 } else { 3 };
+    let funct3 = match (funct3, args.len()) {
+        (Some(f), _) => { expect_args(op, args, regs)?; f }
+        (None, n) if n == regs + 1 => rounding_mode(&args[regs])?,
+        (None, _) => { expect_args(op, args, regs)?; 7 }
+    };
+    let operand = |i: usize, fp: bool| if fp { freg(&args[i]) } else { reg(&args[i]) };
+    let rs2 = match fixed_rs2 {
+        Some(rs2) => rs2,
+        None => operand(2, rs1_fp)?,
+    };
+    Ok(r_type(funct7, rs2, operand(1, rs1_fp)?, funct3, operand(0, rd_fp)?, 0x53))
+}
+
+// conditional branches and their pseudo forms as (funct3, rs1, rs2)
+fn branch(op: &str, args: &[String]) -> Result<Option<(u32, u32, u32)>, String> {
+    let funct3 = |op: &str| match op {
+        "beq" => Some(0), "bne" => Some(1), "blt" => Some(4), "bge" => Some(5), "bltu" => Some(6), "bgeu" => Some(7),
+        _ => None,
+    };
+    let two = || -> Result<(u32, u32), String> { Ok((reg(&args[0])?, reg(&args[1])?)) };
+    Ok(Some(match op {
+        "beqz" => (0, reg(&args[0])?, 0),
+        "bnez" => (1, reg(&args[0])?, 0),
+        "bltz" => (4, reg(&args[0])?, 0),
+        "bgez" => (5, reg(&args[0])?, 0),
+        "blez" => (5, 0, reg(&args[0])?),
+        "bgtz" => (4, 0, reg(&args[0])?),
+        "bgt" | "ble" | "bgtu" | "bleu" => {
+            let (a, b) = two()?;
+            let base = match op { "bgt" => "blt", "ble" => "bge", "bgtu" => "bltu", _ => "bgeu" };
+            (funct3(base).unwrap(), b, a)
+        }
+        _ => match funct3(op) {
+            Some(f) => {
+                let (a, b) = two()?;
+                (f, a, b)
+            }
+            None => return Ok(None),
+        },
+    }))
+}
+
+fn expect_args(op: &str, args: &[String], n: usize) -> Result<(), String> {
+    if args.len() != n {
+        return Err(format!("'{}' expects {} operand(s), got {}", op, n, args.len()));
+    }
+    Ok(())
+}
+
+// Machine words for one instruction at `pc`. `resolve` gives symbol addresses (a dummy during
+// layout). Ok(None): a branch or jump that needs the long form. A long jump is auipc + jalr, which
+// reaches ±2 GiB; a long branch is the inverted branch over a jal and stays within ±1 MiB.
+fn encode(op: &str, args: &[String], pc: u64, long: bool, resolve: &dyn Fn(&str) -> Result<u64, String>) -> Result<Option<Vec<u32>>, String> {
+    let target = |label: &str| -> Result<i64, String> { Ok(resolve(label.trim())?.wrapping_sub(pc) as i64) };
+    let words = match op {
+        "nop" => vec![i_type(0, 0, 0, 0, 0x13)],
+        "ebreak" => vec![0x0010_0073],
+        "ecall" => vec![0x0000_0073],
+        "mret" => vec![0x3020_0073],
+        "csrrw" | "csrrs" | "csrrc" => {
+            expect_args(op, args, 3)?;
+            let funct3 = match op { "csrrw" => 1, "csrrs" => 2, _ => 3 };
+            vec![i_type(csr(&args[1])?, reg(&args[2])?, funct3, reg(&args[0])?, 0x73)]
+        }
+        "csrrwi" | "csrrsi" | "csrrci" => {
+            expect_args(op, args, 3)?;
+            let funct3 = match op { "csrrwi" => 5, "csrrsi" => 6, _ => 7 };
+            vec![i_type(csr(&args[1])?, uimm(&args[2])?, funct3, reg(&args[0])?, 0x73)]
+        }
+        "csrr" => { expect_args(op, args, 2)?; vec![i_type(csr(&args[1])?, 0, 2, reg(&args[0])?, 0x73)] }
+        "csrw" | "csrs" | "csrc" => {
+            expect_args(op, args, 2)?;
+            let funct3 = match op { "csrw" => 1, "csrs" => 2, _ => 3 };
+            vec![i_type(csr(&args[0])?, reg(&args[1])?, funct3, 0, 0x73)]
+        }
+        "csrwi" | "csrsi" | "csrci" => {
+            expect_args(op, args, 2)?;
+            let funct3 = match op { "csrwi" => 5, "csrsi" => 6, _ => 7 };
+            vec![i_type(csr(&args[0])?, uimm(&args[1])?, funct3, 0, 0x73)]
+        }
+        "rdcycle" | "rdtime" | "rdinstret" => {
+            expect_args(op, args, 1)?;
+            let counter = match op { "rdcycle" => 0xc00, "rdtime" => 0xc01, _ => 0xc02 };
+            vec![i_type(counter, 0, 2, reg(&args[0])?, 0x73)]
+        }
+        "ret" => vec![i_type(0, 1, 0, 0, 0x67)],
+        "li" => {
+            expect_args(op, args, 2)?;
+            let rd = reg(&args[0])?;
+            li_words(rd, imm(&args[1])?)
+        }
+        "la" | "lla" => {
+            expect_args(op, args, 2)?;
+            let rd = reg(&args[0])?;
+            let offset = target(&args[1])?;
+            check_range(offset, 32, "la offset")?;
+            let (hi, lo) = hi_lo(offset);
+            vec![u_type(hi, rd, 0x17), i_type(lo, rd, 0, rd, 0x13)]
+        }
+        "lui" | "auipc" => {
+            expect_args(op, args, 2)?;
+            vec![u_type(imm(&args[1])?, reg(&args[0])?, if op == "lui" { 0x37 } else { 0x17 })]
+        }
+        "mv" => { expect_args(op, args, 2)?; vec![i_type(0, reg(&args[1])?, 0, reg(&args[0])?, 0x13)] }
+        "not" => { expect_args(op, args, 2)?; vec![i_type(-1, reg(&args[1])?, 4, reg(&args[0])?, 0x13)] }
+        "neg" => { expect_args(op, args, 2)?; vec![r_type(0x20, reg(&args[1])?, 0, 0, reg(&args[0])?, 0x33)] }
+        "negw" => { expect_args(op, args, 2)?; vec![r_type(0x20, reg(&args[1])?, 0, 0, reg(&args[0])?, 0x3b)] }
+        "sext.w" => { expect_args(op, args, 2)?; vec![i_type(0, reg(&args[1])?, 0, reg(&args[0])?, 0x1b)] }
+        "seqz" => { expect_args(op, args, 2)?; vec![i_type(1, reg(&args[1])?, 3, reg(&args[0])?, 0x13)] }
+        "snez" => { expect_args(op, args, 2)?; vec![r_type(0, reg(&args[1])?, 0, 3, reg(&args[0])?, 0x33)] }
+        "sltz" => { expect_args(op, args, 2)?; vec![r_type(0, 0, reg(&args[1])?, 2, reg(&args[0])?, 0x33)] }
+        "sgtz" => { expect_args(op, args, 2)?; vec![r_type(0, reg(&args[1])?, 0, 2, reg(&args[0])?, 0x33)] }
+        "sgt" | "sgtu" => {
+            expect_args(op, args, 3)?;
+            let funct3 = if op == "sgt" { 2 } else { 3 };
+            vec![r_type(0, reg(&args[1])?, reg(&args[2])?, funct3, reg(&args[0])?, 0x33)]
+        }
+        "j" | "jal" | "call" => {
+            let (rd, label) = match (op, args.len()) {
+                ("j", 1) => (0, &args[0]),
+                ("call" | "jal", 1) => (1, &args[0]),
+                ("jal", 2) => (reg(&args[0])?, &args[1]),
+                _ => return Err(format!("bad operands for '{}'", op)),
+            };
+            let offset = target(label)?;
+            if long {
+                // the link register holds the upper part; j has none and borrows t1, as tail does
+                check_range(offset, 32, "jump offset")?;
+                let (hi, lo) = hi_lo(offset);
+                let base = if rd == 0 { 6 } else { rd };
+                vec![u_type(hi, base, 0x17), i_type(lo, base, 0, rd, 0x67)]
+            } else {
+                if check_range(offset, 21, "jump offset").is_err() {
+                    return Ok(None);
+                }
+                vec![j_type(offset, rd)]
+            }
+        }
+        "jr" => { expect_args(op, args, 1)?; vec![i_type(0, reg(&args[0])?, 0, 0, 0x67)] }
+        "flw" | "fld" => {
+            expect_args(op, args, 2)?;
+            let (offset, rs1) = mem_operand(&args[1])?;
+            check_range(offset, 12, "offset")?;
+            vec![i_type(offset, rs1, if op == "flw" { 2 } else { 3 }, freg(&args[0])?, 0x07)]
+        }
+        "fsw" | "fsd" => {
+            expect_args(op, args, 2)?;
+            let (offset, rs1) = mem_operand(&args[1])?;
+            check_range(offset, 12, "offset")?;
+            vec![s_type(offset, freg(&args[0])?, rs1, if op == "fsw" { 2 } else { 3 }, 0x27)]
+        }
+        "fmadd.s" | "fmsub.s" | "fnmsub.s" | "fnmadd.s" | "fmadd.d" | "fmsub.d" | "fnmsub.d" | "fnmadd.d" => {
+            // rd, rs1, rs2, rs3 and an optional rounding mode
+            let rm = if args.len() == 5 { rounding_mode(&args[4])? } else { expect_args(op, args, 4)?; 7 };
+            let opcode = match &op[..op.len() - 2] { "fmadd" => 0x43, "fmsub" => 0x47, "fnmsub" => 0x4b, _ => 0x4f };
+            let fmt = op.ends_with(".d") as u32;
+            vec![freg(&args[3])? << 27 | r_type(fmt, freg(&args[2])?, freg(&args[1])?, rm, freg(&args[0])?, opcode)]
+        }
+        "fmv.s" | "fneg.s" | "fabs.s" => {
+            expect_args(op, args, 2)?;
+            let funct3 = match op { "fmv.s" => 0, "fneg.s" => 1, _ => 2 };
+            let rs = freg(&args[1])?;
+            vec![r_type(0x10, rs, rs, funct3, freg(&args[0])?, 0x53)]
+        }
+        "jalr" => match args.len() {
+            1 => vec![i_type(0, reg(&args[0])?, 0, 1, 0x67)],
+            2 => {
+                let (offset, rs1) = mem_operand(&args[1])?;
+                vec![i_type(offset, rs1, 0, reg(&args[0])?, 0x67)]
+            }
+            _ => return Err(String::from("bad operands for 'jalr'")),
+        },
+        _ => {
+            if let Some((funct7, funct3, opcode)) = r_op(op) {
+                expect_args(op, args, 3)?;
+                vec![r_type(funct7, reg(&args[2])?, reg(&args[1])?, funct3, reg(&args[0])?, opcode)]
+            } else if let Some((funct7, funct3)) = amo_op(op) {
+                // lr.w rd, (rs1) / sc.w rd, rs2, (rs1) / amoadd.w rd, rs2, (rs1)
+                let lr = op.starts_with("lr.");
+                expect_args(op, args, if lr { 2 } else { 3 })?;
+                let (offset, rs1) = mem_operand(&args[args.len() - 1])?;
+                if offset != 0 {
+                    return Err(format!("'{}' takes no offset", op));
+                }
+                let rs2 = if lr { 0 } else { reg(&args[1])? };
+                vec![r_type(funct7, rs2, rs1, funct3, reg(&args[0])?, 0x2f)]
+            } else if let Some((funct3, opcode)) = i_op(op) {
+                expect_args(op, args, 3)?;
+                let value = imm(&args[2])?;
+                check_range(value, 12, "immediate")?;
+                vec![i_type(value, reg(&args[1])?, funct3, reg(&args[0])?, opcode)]
+            } else if let Some((upper, funct3, opcode, bits)) = shift_op(op) {
+                expect_args(op, args, 3)?;
+                let shamt = imm(&args[2])?;
+                if !(0..1 << bits).contains(&shamt) {
+                    return Err(format!("shift amount {} out of range", shamt));
+                }
+                vec![i_type(upper as i64 | shamt, reg(&args[1])?, funct3, reg(&args[0])?, opcode)]
+            } else if let Some(funct3) = load_op(op) {
+                expect_args(op, args, 2)?;
+                let (offset, rs1) = mem_operand(&args[1])?;
+                check_range(offset, 12, "offset")?;
+                vec![i_type(offset, rs1, funct3, reg(&args[0])?, 0x03)]
+            } else if let Some(fp) = fp_op(op) {
+                vec![encode_fp(op, args, fp)?]
+            } else if let Some(funct3) = store_op(op) {
+                expect_args(op, args, 2)?;
+                let (offset, rs1) = mem_operand(&args[1])?;
+                check_range(offset, 12, "offset")?;
+                vec![s_type(offset, reg(&args[0])?, rs1, funct3, 0x23)]
+            } else {
+                let operands = if matches!(op, "beqz" | "bnez" | "bltz" | "bgez" | "blez" | "bgtz") { 2 } else { 3 };
+                expect_args(op, args, operands)?;
+                let (funct3, rs1, rs2) = branch(op, args)?.ok_or_else(|| format!("unknown instruction '{}'", op))?;
+                let label = &args[operands - 1];
+                if long {
+                    // inverted branch over a jal: the funct3 pairs differ in the low bit
+                    let offset = target(label)? - 4;
+                    check_range(offset, 21, "jump offset")?;
+                    vec![b_type(8, rs2, rs1, funct3 ^ 1), j_type(offset, 0)]
+                } else {
+                    let offset = target(label)?;
+                    if check_range(offset, 13, "branch offset").is_err() {
+                        return Ok(None);
+                    }
+                    vec![b_type(offset, rs2, rs1, funct3)]
+                }
+            }
+        }
+    };
+    Ok(Some(words))
+}
+
+fn split_args(text: &str) -> Vec<String> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    text.split(',').map(|a| a.trim().to_string()).collect()
+}
+
+fn parse(asm: &str, first_line: usize, sections: &mut BTreeMap<Section, Vec<Line>>) -> Result<(), String> {
+    let mut section = Section::Text;
+    for (i, raw) in asm.lines().enumerate() {
+        let no = first_line + i;
+        let err = |e: String| format!("line {}: {}", no, e);
+        let (code, comment) = raw.split_once('#').unwrap_or((raw, ""));
+        // the instructions after `# line N: ...` come from source line N
+        let source_line = comment.trim().strip_prefix("line ").and_then(|rest| rest.split(':').next()?.trim().parse().ok());
+        if let Some(line) = source_line {
+            sections.entry(section).or_default().push(Line { no, item: Item::SourceLine(line) });
+        }
+        let mut text = code.trim();
+        // labels, possibly followed by an instruction on the same line
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                break;
+            }
+            sections.entry(section).or_default().push(Line { no, item: Item::Label(label.to_string()) });
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (op, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let args = split_args(rest);
+        let item = match op {
+            ".text" => { section = Section::Text; continue; }
+            ".data" | ".rodata" | ".sdata" => { section = Section::Data; continue; }
+            ".bss" | ".sbss" => { section = Section::Bss; continue; }
+            ".section" => {
+                let name = args.first().map(|a| a.as_str()).unwrap_or("");
+                section = if name.starts_with(".text") {
+                    Section::Text
+                } else if name.starts_with(".bss") || name.starts_with(".sbss") {
+                    Section::Bss
+                } else {
+                    Section::Data
+                };
+                continue;
+            }
+            ".globl" | ".global" | ".local" | ".type" | ".size" | ".file" | ".option" | ".ident" | ".attribute" => continue,
+            ".align" | ".p2align" => Item::Align(1 << imm(args.first().map_or("", |a| a)).map_err(err)?),
+            ".balign" => Item::Align(imm(args.first().map_or("", |a| a)).map_err(err)?.max(1) as u64),
+            ".zero" | ".space" | ".skip" => Item::Zero(imm(args.first().map_or("", |a| a)).map_err(err)?.max(0) as u64),
+            ".byte" => Item::Data { size: 1, values: args },
+            ".half" | ".short" => Item::Data { size: 2, values: args },
+            ".word" | ".long" => Item::Data { size: 4, values: args },
+            ".dword" | ".quad" => Item::Data { size: 8, values: args },
+            op if op.starts_with('.') => return Err(err(format!("unsupported directive '{}'", op))),
+            op => Item::Inst { op: op.to_lowercase(), args },
+        };
+        sections.entry(section).or_default().push(Line { no, item });
+    }
+    Ok(())
+}
+
+fn align_up(addr: u64, align: u64) -> u64 {
+    addr.div_ceil(align.max(1)) * align.max(1)
+}
+
+// assemble `asm` for an image loaded at `base`
+pub fn assemble(asm: &str, base: u64) -> Result<Image, String> {
+    let mut program = BTreeMap::new();
+    parse(asm, 1, &mut program)?;
+    let mut sections = BTreeMap::new();
+    let has_start = program.values().flatten().any(|l: &Line| matches!(&l.item, Item::Label(name) if name == "_start"));
+    if !has_start {
+        parse(STARTUP, 0, &mut sections)?;
+    }
+    for (section, lines) in program {
+        sections.entry(section).or_insert_with(Vec::new).extend(lines);
+    }
+    let order = [Section::Text, Section::Data, Section::Bss];
+    let empty = Vec::new();
+    let lines = |s: Section| sections.get(&s).unwrap_or(&empty);
+
+    let mut long = HashSet::new(); // (section, index) of branches using the long form
+    loop {
+        // lay out with the current branch forms
+        let mut symbols = BTreeMap::new();
+        let mut source_lines = BTreeMap::new();
+        let mut addrs = Vec::new();
+        let mut addr = base;
+        let mut etext = base;
+        for section in order {
+            if section == Section::Data {
+                etext = addr;
+            }
+            if section != Section::Text {
+                addr = align_up(addr, 8);
+            }
+            let mut section_addrs = Vec::new();
+            for (index, line) in lines(section).iter().enumerate() {
+                section_addrs.push(addr);
+                let err = |e: String| format!("line {}: {}", line.no, e);
+                match &line.item {
+                    Item::Label(name) => {
+                        if symbols.insert(name.clone(), addr).is_some() {
+                            return Err(err(format!("symbol '{}' defined twice", name)));
+                        }
+                    }
+                    Item::Inst { op, args } => {
+                        let words = encode(op, args, addr, long.contains(&(section, index)), &|_| Ok(addr)).map_err(err)?;
+                        addr += 4 * words.map_or(1, |w| w.len() as u64);
+                    }
+                    Item::Data { size, values } => addr += (*size * values.len()) as u64,
+                    Item::Zero(n) => addr += n,
+                    Item::Align(n) => addr = align_up(addr, *n),
+                    Item::SourceLine(no) => {
+                        if section == Section::Text {
+                            source_lines.insert(addr, *no);
+                        }
+                    }
+                }
+            }
+            addrs.push(section_addrs);
+        }
+        let stack = align_up(addr, 0x1000) + STACK_SIZE;
+        symbols.entry(String::from("_stack_pointer")).or_insert(stack);
+        symbols.entry(String::from("_end")).or_insert(addr);
+        symbols.entry(String::from("_etext")).or_insert(etext);
+        let resolve = |name: &str| symbols.get(name).copied().ok_or_else(|| format!("undefined symbol '{}'", name));
+
+        // encode; stop at .bss, which is not part of the file
+        let mut bytes = Vec::new();
+        let mut grown = false;
+        for (s, section) in order.into_iter().enumerate() {
+            if section == Section::Bss {
+                break;
+            }
+            if let Some(&first) = addrs[s].first() {
+                bytes.resize((first - base) as usize, 0);
+            }
+            for (index, line) in lines(section).iter().enumerate() {
+                let addr = addrs[s][index];
+                let err = |e: String| format!("line {}: {}", line.no, e);
+                match &line.item {
+                    Item::Label(_) | Item::SourceLine(_) => {}
+                    Item::Inst { op, args } => match encode(op, args, addr, long.contains(&(section, index)), &resolve).map_err(err)? {
+                        Some(words) => bytes.extend(words.iter().flat_map(|w| w.to_le_bytes())),
+                        None => {
+                            long.insert((section, index));
+                            grown = true;
+                        }
+                    },
+                    Item::Data { size, values } => {
+                        for value in values {
+                            let value = match number(value) {
+                                Some(v) => v as u64,
+                                None => resolve(value).map_err(err)?,
+                            };
+                            bytes.extend(&value.to_le_bytes()[..*size]);
+                        }
+                    }
+                    Item::Zero(n) => bytes.resize(bytes.len() + *n as usize, 0),
+                    Item::Align(n) => {
+                        let end = (align_up(addr, *n) - base) as usize;
+                        // pad code with nops
+                        while section == Section::Text && bytes.len() + 4 <= end {
+                            bytes.extend(0x13u32.to_le_bytes());
+                        }
+                        bytes.resize(end, 0);
+                    }
+                }
+            }
+        }
+        if !grown {
+            return Ok(Image { base, bytes, symbols, lines: source_lines });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::disassemble;
+
+    #[test]
+    fn test_encode() {
+        let asm = "\
+.data
+  .globl global_0
+global_0:
+  .word 7
+.text
+.globl main
+main:
+  addi\tsp, sp, -16
+  la\tt0, global_0
+  lw\ta0, 0(t0)
+  li\tt1, 100000
+  beqz\ta0, done
+  sw\tt1, 8(sp)
+done:
+  addi\tsp, sp, 16
+  ret
+";
+        let image = assemble(asm, 0x8000_0000).unwrap();
+        let disasm = |addr: u64| {
+            let i = (addr - 0x8000_0000) as usize;
+            disassemble(addr, u32::from_le_bytes(image.bytes[i..i + 4].try_into().unwrap()))
+        };
+        let main = image.symbols["main"];
+        assert_eq!(image.symbols["_start"], 0x8000_0000);
+        assert_eq!(disasm(0x8000_0008), format!("jal ra, 0x{:x}", main)); // call main
+        assert_eq!(disasm(main), "addi sp, sp, -16");
+        assert_eq!(disasm(main + 4), "auipc t0, 0x0");
+        assert_eq!(disasm(main + 16), "lui t1, 0x18");
+        assert_eq!(disasm(main + 20), "addiw t1, t1, 1696");
+        assert_eq!(disasm(main + 24), format!("beq a0, zero, 0x{:x}", image.symbols["done"]));
+        assert_eq!(disasm(image.symbols["global_0"]), ".word 0x00000007");
+        assert_eq!(image.symbols["_etext"], image.symbols["done"] + 8);
+
+        assert!(assemble("main:\n  call putint\n", 0).unwrap_err().contains("undefined symbol 'putint'"));
+        assert!(assemble("main:\n  frob a0\n", 0).unwrap_err().starts_with("line 2"));
+
+        let image = assemble("main:\n  # line 3: int a = 1;\n  li a0, 1\n  # line 4: return a;\n  ret\n", 0).unwrap();
+        let main = image.symbols["main"];
+        assert_eq!(image.lines.iter().map(|(&a, &l)| (a, l)).collect::<Vec<_>>(), vec![(main, 3), (main + 4, 4)]);
+    }
+
+    #[test]
+    fn test_float() {
+        let asm = "main:\n  flw fa0, 4(sp)\n  fadd.s fa0, fa0, ft1\n  fcvt.w.s a0, fa0, rtz\n  fmv.w.x f3, a1\n  fneg.s fa1, fa0\n";
+        let image = assemble(asm, 0x8000_0000).unwrap();
+        let main = image.symbols["main"];
+        let disasm = |k: u64| {
+            let i = (main - 0x8000_0000 + 4 * k) as usize;
+            disassemble(0, u32::from_le_bytes(image.bytes[i..i + 4].try_into().unwrap()))
+        };
+        assert_eq!(disasm(0), "flw fa0, 4(sp)");
+        assert_eq!(disasm(1), "fadd.s fa0, fa0, ft1");
+        assert_eq!(disasm(2), "fcvt.w.s a0, fa0");
+        assert_eq!(disasm(3), "fmv.w.x ft3, a1");
+        assert_eq!(disasm(4), "fsgnjn.s fa1, fa0, fa0");
+        assert!(assemble("main:\n  fadd.s fa0, a0, fa1\n", 0).is_err());
+
+        let asm = "main:\n  fld fa0, 8(sp)\n  fmadd.d fa0, fa1, fa2, fa3\n  fcvt.d.w ft0, a0\n  fmv.x.d a0, fa0\n";
+        let image = assemble(asm, 0x8000_0000).unwrap();
+        let main = image.symbols["main"];
+        let disasm = |k: u64| {
+            let i = (main - 0x8000_0000 + 4 * k) as usize;
+            disassemble(0, u32::from_le_bytes(image.bytes[i..i + 4].try_into().unwrap()))
+        };
+        assert_eq!(disasm(0), "fld fa0, 8(sp)");
+        assert_eq!(disasm(1), "fmadd.d fa0, fa1, fa2, fa3");
+        assert_eq!(disasm(2), "fcvt.d.w ft0, a0");
+        assert_eq!(disasm(3), "fmv.x.d a0, fa0");
+    }
+
+    #[test]
+    fn test_long_branch() {
+        let asm = format!("main:\n  bnez a0, far\n{}far:\n  ret\n", "  nop\n".repeat(2000));
+        let image = assemble(&asm, 0).unwrap();
+        let main = image.symbols["main"];
+        let disasm = |addr: u64| {
+            let i = addr as usize;
+            disassemble(addr, u32::from_le_bytes(image.bytes[i..i + 4].try_into().unwrap()))
+        };
+        assert_eq!(disasm(main), format!("beq a0, zero, 0x{:x}", main + 8));
+        assert_eq!(disasm(main + 4), format!("jal zero, 0x{:x}", image.symbols["far"]));
+    }
+
+    #[test]
+    fn test_li() {
+        let values = [
+            0, -2048, 2047, 100000, -0x8000_0000, 0x7fff_ffff, 0x7fff_f800, 0x8000_0000, 0xffff_ffff,
+            0x1_0000_0000, 0x1234_5678_9abc_def0, -0x1234_5678_9abc_def0, i64::MAX, i64::MIN, -1, 0x7ff << 40,
+        ];
+        for value in values {
+            let asm = format!("main:\n  li a0, {}\n  ret\n", value);
+            let mut sim = crate::simulator::Simulator::load(&crate::runner::test_image(&asm)).unwrap();
+            assert_eq!(sim.run(Some(1000)), Some(value as u64), "li a0, {:#x}", value);
+        }
+        let image = assemble("main:\n  li a0, 0xffffffffffffffff\n", 0).unwrap();
+        assert_eq!(image.bytes.len() as u64 - image.symbols["main"], 4);
+        assert!(assemble("main:\n  li a0, 0x10000000000000000\n", 0).is_err());
+    }
+
+    #[test]
+    fn test_long_jump() {
+        // beyond jal's ±1 MiB: call goes through ra, j through t1
+        let asm = format!("main:
+  addi sp, sp, -16
+  sd ra, 8(sp)
+  call far
+  ld ra, 8(sp)
+  addi sp, sp, 16
+  ret
+back:
+  addi a0, a0, 2
+  ret
+{}far:
+  li a0, 5
+  j back
+", "  nop\n".repeat(300_000));
+        let image = assemble(&asm, 0x8000_0000).unwrap();
+        let disasm = |addr: u64| {
+            let i = (addr - 0x8000_0000) as usize;
+            disassemble(addr, u32::from_le_bytes(image.bytes[i..i + 4].try_into().unwrap()))
+        };
+        assert!(disasm(image.symbols["main"] + 8).starts_with("auipc ra, "));
+        assert!(disasm(image.symbols["far"] + 4).starts_with("auipc t1, "));
+        let mut sim = crate::simulator::Simulator::load(&crate::runner::test_image(&asm)).unwrap();
+        assert_eq!(sim.run(Some(1000)), Some(7));
+    }
+
+    #[test]
+    fn test_own_startup() {
+        // what AsmProgram::new() emits around the program
+        let asm = ".section .text
+_start:
+  la sp, stack_top
+  jal _trm_init
+_trm_init:
+  addi sp, sp, -16
+  sd ra, 8(sp)
+  jal main
+  ebreak
+main:
+  li a0, 3
+  ret
+.section .bss
+.align 4
+stack_bottom:
+  .skip 4096
+stack_top:
+";
+        let image = assemble(asm, 0x8000_0000).unwrap();
+        assert_eq!(image.symbols["_start"], 0x8000_0000);
+        assert_eq!(image.symbols["stack_top"] - image.symbols["stack_bottom"], 4096);
+        assert_eq!(image.symbols["_trm_init"], 0x8000_000c); // no second _start stub in front
+    }
+}
@@ -8,7 +8,7 @@ use super::instruction::Inst;
 use super::label::Label;
 use super::instruction::Reg;
 use super::table::DescriptorTable;
-use super::instruction::{reg2idx, idx2reg};
+use super::instruction::reg2idx;
 use super::array::InitVal;
 use super::generate::PARA_REG;
 
@@ -59,9 +59,6 @@ impl<'a> Env<'a> {
     }
 
     pub fn find_global_name(&mut self, val: Value) -> String {
-        
-        println!("Search: global val = {:?}", val);
-        println!("Result: {:#?}", self.global_var.get(&val));
         match self.global_var.get(&val) {
             None => {
                 let name = generate_random_string(7);
@@ -72,12 +69,10 @@ impl<'a> Env<'a> {
         } 
     }
 
-    pub fn print_state(&self) {
-        for val in self.table.reg2val.iter().enumerate() {
-            if let Some(reg) = val.1 {
-                println!("{} {:?}", idx2reg(val.0), reg);
-            }
-        }
+    // pointers are 8 bytes on rv64 and go through ld/sd, everything else is an i32
+    fn is_pointer(&self, val: Value) -> bool {
+        let func_data = self.ctx.program.func(self.ctx.function.unwrap());
+        matches!(func_data.dfg().value(val).ty().kind(), TypeKind::Pointer(_))
     }
 
     pub fn move_inst(&mut self, asm_bb: &mut AsmLocal, val: Value, rd: Option<Reg>) -> Reg {
@@ -86,6 +81,8 @@ impl<'a> Env<'a> {
         match value_data.kind() {
             ValueKind::Integer(int) => self.build_int(asm_bb, val, int.clone(), rd).unwrap(),
             ValueKind::FuncArgRef(funargref) => self.build_func_arg_ref(asm_bb, val, funargref.clone()).unwrap(),
+            ValueKind::BlockArgRef(_) => self.load_slot(asm_bb, val, rd),
+            _ if !self.table.val2reg.contains_key(&val) && self.offset.contains_key(&val) => self.load_slot(asm_bb, val, rd),
             _ => self.move_inst_to(asm_bb, val, rd),
         }
     }
@@ -127,17 +124,10 @@ impl<'a> Env<'a> {
         };
 
 
-        let func_data = self.ctx.program.func(self.ctx.function.unwrap());
-        let value_data = func_data.dfg().value(val);
-        let used_by = value_data.used_by();
-        if used_by.len() == 1 {
-            let &callee = used_by.iter().next().unwrap();
-            if let ValueKind::Call(..) = func_data.dfg().value(callee).kind() {
-                let reg = res.unwrap();
-                let imm = self.offset.get(&val).unwrap().clone() as i32;
-                asm_bb.sw_inst(reg, imm, "sp");
-                self.table.free_reg(val, reg);
-            }
+        // a value with a slot (see generate.rs) goes there at once and every use loads it back
+        if let (Some(reg), Some(&imm)) = (res, self.offset.get(&val)) {
+            asm_bb.sd_inst(reg, imm as i32, "sp");
+            self.table.free_reg(val, reg);
         }
         res 
     }
@@ -153,7 +143,7 @@ impl<'a> Env<'a> {
         if let Some(ret_val) = ret.value() {
             self.move_inst(asm_bb, ret_val, Some("a0"));
         }
-        asm_bb.lw_inst("ra", self.frame_size - 4, "sp");
+        asm_bb.ld_inst("ra", self.frame_size - 8, "sp");
         asm_bb.addi_inst("sp", "sp", self.frame_size);
         if let Some(ret_val) = ret.value() {
             self.table.free_reg(ret_val, "a0");
@@ -167,13 +157,22 @@ impl<'a> Env<'a> {
         if idx < 8 {
             Some(PARA_REG[idx])
         } else {
-            let imm = (idx - 8) * 4 + self.frame_size as usize;
+            let imm = (idx - 8) * 8 + self.frame_size as usize;
             let dst = self.table.alloc_reg(val, None);
-            asm_bb.lw_inst(dst, imm as i32, "sp");
+            asm_bb.ld_inst(dst, imm as i32, "sp");
             Some(dst)
         }
     }
 
+    // a block parameter lives in its stack slot, where build_jump stores the argument; so does a
+    // value spilled by build_inst
+    pub fn load_slot(&mut self, asm_bb: &mut AsmLocal, val: Value, rd: Option<Reg>) -> Reg {
+        let rd = self.table.alloc_reg(val, rd);
+        let imm = self.offset.get(&val).unwrap().clone() as i32;
+        asm_bb.ld_inst(rd, imm, "sp");
+        rd
+    }
+
     pub fn build_binary(&mut self, asm_bb: &mut AsmLocal, val: Value, bin: &values::Binary, rd: Option<Reg>) -> Option<Reg> {
         let rd = self.table.alloc_reg(val, rd);
         let rs1 = self.move_inst(asm_bb, bin.lhs(), None);
@@ -210,7 +209,9 @@ impl<'a> Env<'a> {
             BinaryOp::Shr => asm_bb.srl_inst(rd, rs1, rs2),
         }
         self.table.free_reg(bin.lhs(), rs1);
-        self.table.free_reg(bin.rhs(), rs2);
+        if bin.rhs() != bin.lhs() {
+            self.table.free_reg(bin.rhs(), rs2);
+        }
         Some(rd)
     }
 
@@ -221,9 +222,10 @@ impl<'a> Env<'a> {
             func_data.dfg().bb(bb).name().as_ref().unwrap()[1..].to_string().replace('%', "")
         };
 
-        let name = get_name(branch.false_bb());
-        println!("{}",name);
-        println!("{}",name.replace("%", ""));
+        // irgen only passes arguments with jump; branch arguments come from mem2reg (-O2)
+        if !branch.true_args().is_empty() || !branch.false_args().is_empty() {
+            panic!("Branch arguments are not supported by the stack backend");
+        }
 
         let cond = self.move_inst(asm_bb, branch.cond(), None);
         asm_bb.beqz_inst(cond, get_name(branch.false_bb()));
@@ -239,6 +241,19 @@ impl<'a> Env<'a> {
             func_data.dfg().bb(bb).name().as_ref().unwrap()[1..].to_string().replace('%', "")
         };
 
+        // the arguments go to the stack slots of the target's parameters; all of them are read
+        // before any is written, an argument may be a parameter of the same block
+        let mut regs = Vec::new();
+        for &arg in jump.args() {
+            regs.push(self.move_inst(asm_bb, arg, None));
+        }
+        let params = func_data.dfg().bb(jump.target()).params();
+        for ((&arg, &param), rs) in jump.args().iter().zip(params).zip(regs) {
+            let imm = self.offset.get(&param).unwrap().clone() as i32;
+            asm_bb.sd_inst(rs, imm, "sp");
+            self.table.free_reg(arg, rs);
+        }
+
         asm_bb.J_inst(get_name(jump.target()));
         None
     }
@@ -247,26 +262,23 @@ impl<'a> Env<'a> {
         // let rd = self.table.alloc_reg(val, rd);
         let rs = self.move_inst(asm_bb, store.value(), None);
         let dst_val = store.dest();
-        
-        println!("build_Store in reg {}, store_value {:?}", rs, store.value());
+        let wide = self.is_pointer(store.value());
+
         if dst_val.is_global() {
-            println!("global val = {:?}", dst_val);
             let var_name = self.find_global_name(dst_val);
             asm_bb.la_inst("t0", var_name);
-            asm_bb.sw_inst(rs, 0, "t0");
+            store_value(asm_bb, wide, rs, 0, "t0");
             self.table.free_reg(store.value(), rs);
         } else if self.offset.get(&dst_val) != None {
             let imm = self.offset.get(&dst_val).unwrap().clone() as i32;
-            println!("offset = {}", imm);
-            asm_bb.sw_inst(rs, imm, "sp");
+            store_value(asm_bb, wide, rs, imm, "sp");
             self.table.free_reg(store.value(), rs);
         } else {
             let rd = self.move_inst(asm_bb, dst_val, None);
-            asm_bb.sw_inst(rs, 0, rd);
+            store_value(asm_bb, wide, rs, 0, rd);
             self.table.free_reg(store.value(), rs);
             self.table.free_reg(store.dest(), rd);
         }
-        self.print_state();
         None
 
 
@@ -275,18 +287,18 @@ impl<'a> Env<'a> {
     pub fn build_load(&mut self, asm_bb: &mut AsmLocal, val: Value, load: &values::Load, rd: Option<Reg>) -> Option<Reg> {
         let src_val = load.src();
         let rd = self.table.alloc_reg(val, rd);
+        let wide = self.is_pointer(val);
 
         if src_val.is_global() {
-            println!("global val = {:?}",src_val);
             let var_name = self.find_global_name(src_val);
             asm_bb.la_inst(rd, var_name);
-            asm_bb.lw_inst(rd, 0 ,rd);
+            load_value(asm_bb, wide, rd, 0, rd);
         } else if self.offset.get(&src_val) != None {
             let imm = self.offset.get(&src_val).unwrap().clone() as i32;
-            asm_bb.lw_inst(rd, imm, "sp");
+            load_value(asm_bb, wide, rd, imm, "sp");
         } else {
             let rs = self.move_inst(asm_bb, src_val, None);
-            asm_bb.lw_inst(rd, 0, rs);
+            load_value(asm_bb, wide, rd, 0, rs);
             self.table.free_reg(src_val, rs);
         }
         
@@ -305,29 +317,29 @@ impl<'a> Env<'a> {
             };
             match kind {
                 ValueKind::Integer(int) => asm_bb.li_inst(reg, int.value()),
-                _ => asm_bb.lw_inst(reg, self.offset.get(&para).unwrap().clone() as i32, "sp"),
+                _ => asm_bb.ld_inst(reg, self.offset.get(&para).unwrap().clone() as i32, "sp"),
             }
             if i >= 8 {
-                asm_bb.sw_inst("t0", (i - 8) as i32 * 4, "sp");
+                asm_bb.sd_inst("t0", (i - 8) as i32 * 8, "sp");
             }
         });
 
         // save all values 
 
-        // caculate the number of allocated reg
+        // caculate the number of allocated reg, each saved as a whole 8-byte register
         let mut off = 0;
         let regs: Vec<Reg> = self.table.val2reg.values().map(|reg| *reg).collect();
         let num = regs.len() as i32;
         if num != 0 {
-            asm_bb.addi_inst("sp", "sp", -num * 4);
-            for i in (0..(self.paranum as i32/4)) {
-                asm_bb.lw_inst("t0", i as i32 * 4 + num * 4, "sp");
-                asm_bb.sw_inst("t0", i as i32 * 4, "sp");
+            asm_bb.addi_inst("sp", "sp", -num * 8);
+            for i in (0..(self.paranum as i32/8)) {
+                asm_bb.ld_inst("t0", i as i32 * 8 + num * 8, "sp");
+                asm_bb.sd_inst("t0", i as i32 * 8, "sp");
             }
         
             for reg in regs {
-                asm_bb.sw_inst(reg, off + self.paranum as i32, "sp");
-                off += 4;
+                asm_bb.sd_inst(reg, off + self.paranum as i32, "sp");
+                off += 8;
             }
         }
 
@@ -343,10 +355,10 @@ impl<'a> Env<'a> {
             off = 0;
 
             for reg in regs {
-                asm_bb.lw_inst(reg, off + self.paranum as i32, "sp");
-                off += 4;
+                asm_bb.ld_inst(reg, off + self.paranum as i32, "sp");
+                off += 8;
             }
-            asm_bb.addi_inst("sp", "sp", num* 4);
+            asm_bb.addi_inst("sp", "sp", num* 8);
         }
         
         let func_data = self.ctx.program.func(self.ctx.function.unwrap());
@@ -480,10 +492,6 @@ impl<'a> Env<'a> {
 
         let value_data = self.ctx.program.borrow_value(val);
         let var_name = self.find_global_name(val);
-        println!("var_name = {:?}", var_name);
-
-        
-        println!("global val = {:?}", val);
 
         let ValueKind::GlobalAlloc(alloc) = value_data.kind() else {
             panic!("GlobalAlloc expected");
@@ -534,6 +542,22 @@ impl<'a> Env<'a> {
     }
 }
 
+fn load_value(asm_bb: &mut AsmLocal, wide: bool, rd: Reg, imm: i32, rs: Reg) {
+    if wide {
+        asm_bb.ld_inst(rd, imm, rs);
+    } else {
+        asm_bb.lw_inst(rd, imm, rs);
+    }
+}
+
+fn store_value(asm_bb: &mut AsmLocal, wide: bool, rs: Reg, imm: i32, rd: Reg) {
+    if wide {
+        asm_bb.sd_inst(rs, imm, rd);
+    } else {
+        asm_bb.sw_inst(rs, imm, rd);
+    }
+}
+
 pub fn generate_random_string(length: usize) -> String {
     let mut rng = rand::thread_rng();
     let dist = Uniform::from(b'a'..=b'z');
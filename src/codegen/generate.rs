@@ -45,39 +45,62 @@ impl GenerateAsm for Program {
             // ToDo : calculate offset 
             {
                 env.frame_size = 0;
+                env.paranum = 0;
                 env.offset = HashMap::new();
                 let values = func_data.layout().bbs().nodes().flat_map(|block| 
                     block.insts().keys().map(|&val| val)).collect::<Vec<_>>();
                 
-                // alloc var
+                // alloc var, a pointer (array parameter) takes 8 bytes and is aligned to them
                 values.iter().for_each(|&val| {
                     if let ValueKind::Alloc(_) = func_data.dfg().value(val).kind() {
-                        env.offset.insert(val, env.frame_size as usize);
-
                         let func_data = self.func(func);
                         let kind = func_data.dfg().value(val).ty().kind();
                         let size = if let TypeKind::Pointer(base) = kind {
-                            base.size()
+                            base.size() as i32
                         } else {
                             panic!("Unexpected type kind");
                         };
-                        env.frame_size += size as i32;
+                        let align = if size % 8 == 0 { 8 } else { 4 };
+                        env.frame_size = (env.frame_size + align - 1) / align * align;
+                        env.offset.insert(val, env.frame_size as usize);
+                        env.frame_size += size;
                     }
                 });
 
-                // temporary function call
+                // the slots below hold a whole register (the value may be a pointer), 8 bytes each
+                env.frame_size = (env.frame_size + 7) / 8 * 8;
+
+                // a value stays in its register only until a single use in the same block. One used
+                // by a call, used more than once, or used in another block gets a slot instead: the
+                // register table follows the layout order, and a block laid out earlier (the join
+                // block of a nested && or ||) may run later, after its register was reused
+                let block_of: HashMap<Value, BasicBlock> = func_data.layout().bbs().iter()
+                    .flat_map(|(&bb, node)| node.insts().keys().map(move |&val| (val, bb)))
+                    .collect();
                 values.iter().for_each(|&val| {
-                    let used_by = func_data.dfg().value(val).used_by();
-                    if used_by.len() == 1 {
-                        let &user = used_by.iter().next().unwrap();
-                        if let ValueKind::Call(..) = func_data.dfg().value(user).kind() {
-                            env.offset.insert(val, env.frame_size as usize);
-                            env.frame_size += 4;
-                        }
+                    let value_data = func_data.dfg().value(val);
+                    if value_data.ty().is_unit() || matches!(value_data.kind(), ValueKind::Alloc(_)) {
+                        return;
+                    }
+                    let used_by = value_data.used_by();
+                    let spill = used_by.len() > 1 || used_by.iter().any(|user| {
+                        block_of[user] != block_of[&val] || matches!(func_data.dfg().value(*user).kind(), ValueKind::Call(..))
+                    });
+                    if spill {
+                        env.offset.insert(val, env.frame_size as usize);
+                        env.frame_size += 8;
                     }
                 });
 
-                env.frame_size += 4; // return address
+                // block parameters (the join block of && and ||), written by the jumps into the block
+                for &bb in func_data.layout().bbs().keys() {
+                    for &param in func_data.dfg().bb(bb).params() {
+                        env.offset.insert(param, env.frame_size as usize);
+                        env.frame_size += 8;
+                    }
+                }
+
+                env.frame_size += 8; // return address
 
                 let max_arg_num = values.iter()
                     .map(|&val| match func_data.dfg().value(val).kind() {
@@ -88,7 +111,7 @@ impl GenerateAsm for Program {
                     .unwrap_or(0);
             
                 if max_arg_num > 8 {
-                    env.paranum = 4 * (max_arg_num - 8);
+                    env.paranum = 8 * (max_arg_num - 8);
                     env.frame_size += env.paranum as i32;
                     env.offset.values_mut().for_each(|offset| *offset += env.paranum);
                 }
@@ -113,11 +136,8 @@ impl GenerateAsm for FunctionData {
     fn generate(&self, env: &mut Env, asm_func: &mut AsmGlobal) {
         // generate code for each basic block in the function
 
-        println!("enter {}", self.name());
-
         // alloc parameters values to regs
         self.params().iter().take(8).enumerate().for_each(|(i, &val)| {
-            println!("parameter: {:?}", val);
             env.table.alloc_reg(val, Some(PARA_REG[i]));
         });
 
@@ -138,12 +158,11 @@ impl GenerateAsm for FunctionData {
             if is_entry {
                 let size = env.frame_size;
                 asm_bb.addi_inst("sp", "sp", -size);
-                asm_bb.sw_inst("ra", size - 4, "sp");
+                asm_bb.sd_inst("ra", size - 8, "sp");
             }
 
             // generate code for each instruction in the basic block
             for &inst in node.insts().keys() {
-                println!("intst: {:?}", inst);
                 let value_data = self.dfg().value(inst);
                 // value_data.generate(env, &mut asm_bb);
                 env.build_inst(&mut asm_bb, inst,None, value_data.kind());
@@ -24,7 +24,7 @@ pub type Reg = &'static str;
 // | `li rd, imm32`             | `R[rd] = imm32`                               |
 // | `la rd, label`             | 将标号 `label` 的绝对地址加载到寄存器 `rd` 中                |
 // | `mv rd, rs`                | 将寄存器 `rs` 的值复制到寄存器 `rd`                       |
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Inst {
     Beqz { rs: Reg, label: String },
     Bnez { rs: Reg, label: String },
@@ -55,6 +55,9 @@ pub enum Inst {
     Li { rd: Reg, imm: i32 },
     La { rd: Reg, label: String },
     Mv { rd: Reg, rs: Reg },
+    SextW { rd: Reg, rs: Reg },
+    // 上表以外的指令和注释（ld/sd、rv64 的 addw 等、-g 的注释），原样输出
+    Raw(String),
 }
 
 pub fn reg2idx(reg: Reg) -> usize {
@@ -86,6 +89,10 @@ pub fn idx2reg(idx: usize) -> Reg {
     REG_NAME[idx]
 }
 
+pub fn find_reg(name: &str) -> Option<Reg> {
+    REG_NAME.iter().copied().find(|reg| *reg == name)
+}
+
 impl Inst {
     pub fn emit_asm(&self) -> String {
         match self {
@@ -118,6 +125,8 @@ impl Inst {
             Inst::Li { rd, imm } => format!("li {}, {}", rd, imm),
             Inst::La { rd, label } => format!("la {}, {}", rd, label),
             Inst::Mv { rd, rs } => format!("mv {}, {}", rd, rs),
+            Inst::SextW { rd, rs } => format!("sext.w {}, {}", rd, rs),
+            Inst::Raw(text) => text.clone(),
         }
     }
 }
\ No newline at end of file
@@ -11,8 +11,8 @@ impl Label {
     }
 
     pub fn name(&self) -> &str {
-        // remove the @ in the front
-        &self.name[1..]        
+        // remove the @ or % in front of Koopa names
+        self.name.strip_prefix(['@', '%']).unwrap_or(&self.name)
     }
 
     pub fn to_string(&self) -> String {
@@ -7,9 +7,14 @@ mod table;
 mod valuegen;
 mod array;
 mod build;
+mod regalloc;
+mod peephole;
+pub mod encode;
+pub mod runtime;
 
 use koopa::ir::*;
-use asm::{AsmProgram, AsmGlobal, AsmLocal, Section};
+pub use asm::AsmProgram;
+use asm::{AsmGlobal, AsmLocal, Section};
 use instruction::Inst;
 use label::Label;
 use build::build_riscv;
@@ -21,17 +26,159 @@ use std::io::Write;
 use std::fs::File;
 
 
-pub fn build_asm(program: &Program) -> String {
-    build_riscv(&program)
+// 代码生成的目标：rv32 按 4 字节存取所有值；rv64 的指针占 8 字节、用 ld/sd 存取，
+// i32 运算用 addw/subw 等保持 32 位语义。模拟器是 64 位的，所以默认 rv64：rv32 用 lw
+// 重新读回 0x80000000 以上的栈地址时会符号扩展成 0xffffffff8xxxxxxx，只适合外部 32 位工具链
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    Rv32,
+    #[default]
+    Rv64,
 }
 
+impl Target {
+    pub fn ptr_size(self) -> usize {
+        match self {
+            Target::Rv32 => 4,
+            Target::Rv64 => 8,
+        }
+    }
+}
+
+impl std::str::FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rv32" => Ok(Target::Rv32),
+            "rv64" => Ok(Target::Rv64),
+            _ => Err(format!("unknown target `{}`, expected rv32 or rv64", s)),
+        }
+    }
+}
 
-pub fn emit_asm(asm_program: String, output: String) {
+// -g：生成汇编时在每组指令前注明它来自哪一行 SysY 源码、哪条 Koopa IR
+pub struct DebugInfo<'a> {
+    pub lines: &'a crate::irgen::LineMap,
+    pub source: &'a str,
+}
+
+// 后端：把 Koopa IR 降成 AsmProgram，再由 emit_asm 统一写出；新的后端实现这个 trait 即可
+pub trait Backend {
+    fn lower(&self, program: &Program) -> AsmProgram;
+}
 
-    // println!("{:#?}", asm_program);
-    let asm_str =  asm_program;
-    println!("{}", asm_str);
+// build.rs：寄存器分配 + 窥孔优化，支持 rv32/rv64 和 -g，启动代码由汇编器或 linker.ld 提供
+pub struct BuildBackend<'a> {
+    pub target: Target,
+    pub debug: Option<&'a DebugInfo<'a>>,
+}
+
+impl Backend for BuildBackend<'_> {
+    fn lower(&self, program: &Program) -> AsmProgram {
+        build_riscv(program, self.target, self.debug)
+    }
+}
+
+// generate.rs / env.rs：早期的后端，值都放在栈上，自带 _start 和栈；只生成 rv64（不看 --target）
+pub struct StackBackend;
+
+impl Backend for StackBackend {
+    fn lower(&self, program: &Program) -> AsmProgram {
+        // ra、指针和调用前保存的寄存器都按 8 字节用 ld/sd 存取
+        Type::set_ptr_size(Target::Rv64.ptr_size());
+        let mut env = Env::new(program);
+        let mut asm = AsmProgram::new();
+        program.generate(&mut env, &mut asm);
+        asm
+    }
+}
+
+// 命令行 --backend 的取值
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendKind {
+    #[default]
+    Build,
+    Stack,
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "build" => Ok(BackendKind::Build),
+            "stack" => Ok(BackendKind::Stack),
+            _ => Err(format!("unknown backend `{}`, expected build or stack", s)),
+        }
+    }
+}
+
+pub fn build_asm(program: &Program, target: Target) -> String {
+    BuildBackend { target, debug: None }.lower(program).emit_asm()
+}
+
+pub fn build_asm_with_debug(program: &Program, target: Target, debug: &DebugInfo) -> String {
+    BuildBackend { target, debug: Some(debug) }.lower(program).emit_asm()
+}
+
+
+pub fn emit_asm(asm_program: AsmProgram, output: String) {
+    let asm_str = asm_program.emit_asm();
 
     let mut file =  File::create(output).expect("Create file failed");
     file.write_all(asm_str.as_bytes()).expect("Write file failed");
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rv64_target() {
+        // 数组参数是 8 字节的指针；i32 乘法溢出后要按 32 位回绕
+        let source = "int sum(int a[], int n) { int s = 0; int i = 0; while (i < n) { s = s + a[i]; i = i + 1; } return s; }
+            int main() { int a[3] = {1, 2, 3}; int x = 65536; int big = x * x; return sum(a, 3) + big; }";
+        let program = crate::irgen::build_ir(crate::parse(source).unwrap()).unwrap();
+        let asm = build_asm(&program, Target::Rv64);
+        assert!(asm.contains("mulw"), "{}", asm);
+        let (code, _) = crate::runner::build_and_run("rv64", &asm, None, &crate::runner::test_dir()).unwrap();
+        assert_eq!(code, 6);
+    }
+
+    #[test]
+    fn test_stack_backend() {
+        // 数组参数（8 字节的指针）、递归调用、跨调用保存的值，由 StackBackend 自带的 _start 启动；
+        // && 和 || 的结果是汇合块的参数，短路时右边的 check 不会执行
+        let source = "int sum(int a[], int n) { int s = 0; int i = 0; while (i < n) { s = s + a[i]; i = i + 1; } return s; }
+            int fib(int n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            int check(int x) { putint(x); return x; }
+            int main() {
+                int a[5]; int i = 0; while (i < 5) { a[i] = i + 1; i = i + 1; }
+                putint(sum(a, 5)); putch(10);
+                if (check(0) && check(1)) putch(65);
+                if (check(2) || check(3)) putch(66);
+                putch(10);
+                int c = a[0] < a[1] && a[4] == 5 || i == 0;
+                return fib(10) + c;
+            }";
+        let asm = crate::compile_to_riscv_stack(source).unwrap();
+        assert!(asm.contains("_start:"), "{}", asm);
+        let (code, output) = crate::runner::build_and_run("stack", &asm, None, &crate::runner::test_dir()).unwrap();
+        assert_eq!(output, "15\n02B\n");
+        assert_eq!(code, 56);
+    }
+
+    #[test]
+    fn test_stack_backend_slots() {
+        // 嵌套的 || 把外层的汇合块排在内层的块之前，左边的 x 要从栈上的槽位取回；
+        // 局部数组初始化时同一个首地址用了好几次
+        let source = "int main() {
+                int a[3] = {1, 2, 3}; int x = a[0] + a[1] * a[2];
+                int y = x + (a[0] == 2 || (a[1] == 3 || a[2] == 3));
+                return y * 10 + (x - 1 && (0 || a[2]));
+            }";
+        let asm = crate::compile_to_riscv_stack(source).unwrap();
+        let (code, _) = crate::runner::build_and_run("stack_slots", &asm, None, &crate::runner::test_dir()).unwrap();
+        assert_eq!(code, 81);
+    }
+}
@@ -0,0 +1,293 @@
+// 窥孔优化：把 build.rs 生成的一个函数的汇编行解析成 Inst，反复做下面几条局部化简，直到没有变化：
+// - 删掉 mv rd, rd；mv t, rs + mv rd, t 直接 mv rd, rs
+// - li t, imm + add rd, rs, t 合成 addi rd, rs, imm
+// - 从栈槽 lw 一个已经在寄存器里的值（刚 sw 进去或刚 lw 出来），换成 mv
+// - sext.w 之后再 sext.w 同一个值，换成 mv
+// - 栈槽里已经是这个寄存器的值时删掉 sw；栈槽在下一次 sw 之前没人读，删掉前一次 sw
+// - 写临时寄存器（t0–t6）却没人读的指令
+// 解析不了的行（标签、伪指令、ld/sd 等）原样保留，当作屏障；注释行（-g 的源码行和 IR）跳过，不影响化简。
+// build.rs 生成的代码里临时寄存器不会跨过跳转、调用和返回，删临时寄存器的写入靠的就是这一点；
+// 栈槽只通过 fp 加偏移访问，地址不会传出去。
+
+use std::collections::{HashMap, HashSet};
+
+use super::instruction::{find_reg, Inst, Reg};
+
+const TEMPS: [Reg; 7] = ["t0", "t1", "t2", "t3", "t4", "t5", "t6"];
+
+enum Line {
+    Inst(Inst),
+    Raw(String),
+}
+
+/// 化简一个函数的汇编行
+pub fn optimize(lines: Vec<String>) -> Vec<String> {
+    let mut code: Vec<Line> = lines
+        .into_iter()
+        .map(|line| match parse(&line) {
+            Some(inst) => Line::Inst(inst),
+            None => Line::Raw(line),
+        })
+        .collect();
+    while simplify(&mut code) {}
+    code.into_iter()
+        .map(|line| match line {
+            // 和 build.rs 一样：缩进两格，操作码后面跟一个 tab
+            Line::Inst(inst) => format!("  {}", inst.emit_asm().replacen(' ', "\t", 1)),
+            Line::Raw(line) => line,
+        })
+        .collect()
+}
+
+/// "  op\ta, b, c" -> Inst；不认识的指令或寄存器返回 None
+pub(super) fn parse(line: &str) -> Option<Inst> {
+    let line = line.trim();
+    let (op, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let args: Vec<&str> = rest.split(',').map(str::trim).filter(|arg| !arg.is_empty()).collect();
+    let reg = |i: usize| find_reg(args[i]);
+    let imm = |i: usize| args[i].parse::<i32>().ok();
+    let label = |i: usize| args[i].to_string();
+    // offset(base)
+    let mem = |i: usize| {
+        let (offset, base) = args[i].strip_suffix(')')?.split_once('(')?;
+        Some((offset.parse::<i32>().ok()?, find_reg(base)?))
+    };
+
+    let inst = match (op, args.len()) {
+        ("beqz", 2) => Inst::Beqz { rs: reg(0)?, label: label(1) },
+        ("bnez", 2) => Inst::Bnez { rs: reg(0)?, label: label(1) },
+        ("j", 1) => Inst::J { label: label(0) },
+        ("call", 1) => Inst::Call { label: label(0) },
+        ("ret", 0) => Inst::Ret,
+        ("lw", 2) => {
+            let (imm12, rs) = mem(1)?;
+            Inst::Lw { rd: reg(0)?, imm12, rs }
+        }
+        ("sw", 2) => {
+            let (imm12, rd) = mem(1)?;
+            Inst::Sw { rs: reg(0)?, imm12, rd }
+        }
+        ("li", 2) => Inst::Li { rd: reg(0)?, imm: imm(1)? },
+        ("la", 2) => Inst::La { rd: reg(0)?, label: label(1) },
+        ("mv", 2) => Inst::Mv { rd: reg(0)?, rs: reg(1)? },
+        ("seqz", 2) => Inst::Seqz { rd: reg(0)?, rs: reg(1)? },
+        ("snez", 2) => Inst::Snez { rd: reg(0)?, rs: reg(1)? },
+        ("sext.w", 2) => Inst::SextW { rd: reg(0)?, rs: reg(1)? },
+        ("addi", 3) => Inst::Addi { rd: reg(0)?, rs: reg(1)?, imm12: imm(2)? },
+        ("xori", 3) => Inst::Xori { rd: reg(0)?, rs: reg(1)?, imm12: imm(2)? },
+        ("ori", 3) => Inst::Ori { rd: reg(0)?, rs: reg(1)?, imm12: imm(2)? },
+        ("andi", 3) => Inst::Andi { rd: reg(0)?, rs: reg(1)?, imm12: imm(2)? },
+        (_, 3) => {
+            let (rd, rs1, rs2) = (reg(0)?, reg(1)?, reg(2)?);
+            match op {
+                "add" => Inst::Add { rd, rs1, rs2 },
+                "sub" => Inst::Sub { rd, rs1, rs2 },
+                "slt" => Inst::Slt { rd, rs1, rs2 },
+                "sgt" => Inst::Sgt { rd, rs1, rs2 },
+                "xor" => Inst::Xor { rd, rs1, rs2 },
+                "or" => Inst::Or { rd, rs1, rs2 },
+                "and" => Inst::And { rd, rs1, rs2 },
+                "sll" => Inst::Sll { rd, rs1, rs2 },
+                "srl" => Inst::Srl { rd, rs1, rs2 },
+                "sra" => Inst::Sra { rd, rs1, rs2 },
+                "mul" => Inst::Mul { rd, rs1, rs2 },
+                "div" => Inst::Div { rd, rs1, rs2 },
+                "rem" => Inst::Rem { rd, rs1, rs2 },
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some(inst)
+}
+
+/// (写的寄存器, 读的寄存器)；跳转、调用和返回单独处理
+fn regs(inst: &Inst) -> (Option<Reg>, Vec<Reg>) {
+    match *inst {
+        Inst::Beqz { rs, .. } | Inst::Bnez { rs, .. } => (None, vec![rs]),
+        Inst::J { .. } | Inst::Call { .. } | Inst::Ret => (None, Vec::new()),
+        Inst::Lw { rd, rs, .. } => (Some(rd), vec![rs]),
+        Inst::Sw { rs, rd, .. } => (None, vec![rs, rd]),
+        Inst::Add { rd, rs1, rs2 }
+        | Inst::Sub { rd, rs1, rs2 }
+        | Inst::Slt { rd, rs1, rs2 }
+        | Inst::Sgt { rd, rs1, rs2 }
+        | Inst::Xor { rd, rs1, rs2 }
+        | Inst::Or { rd, rs1, rs2 }
+        | Inst::And { rd, rs1, rs2 }
+        | Inst::Sll { rd, rs1, rs2 }
+        | Inst::Srl { rd, rs1, rs2 }
+        | Inst::Sra { rd, rs1, rs2 }
+        | Inst::Mul { rd, rs1, rs2 }
+        | Inst::Div { rd, rs1, rs2 }
+        | Inst::Rem { rd, rs1, rs2 } => (Some(rd), vec![rs1, rs2]),
+        Inst::Addi { rd, rs, .. }
+        | Inst::Xori { rd, rs, .. }
+        | Inst::Ori { rd, rs, .. }
+        | Inst::Andi { rd, rs, .. }
+        | Inst::Seqz { rd, rs }
+        | Inst::Snez { rd, rs }
+        | Inst::Mv { rd, rs }
+        | Inst::SextW { rd, rs } => (Some(rd), vec![rs]),
+        Inst::Li { rd, .. } | Inst::La { rd, .. } => (Some(rd), Vec::new()),
+        Inst::Raw(_) => unreachable!("解析不了的行是 Line::Raw"),
+    }
+}
+
+fn is_control(inst: &Inst) -> bool {
+    matches!(inst, Inst::Beqz { .. } | Inst::Bnez { .. } | Inst::J { .. } | Inst::Call { .. } | Inst::Ret)
+}
+
+fn is_comment(line: &Line) -> bool {
+    matches!(line, Line::Raw(text) if text.trim_start().starts_with('#'))
+}
+
+/// 跑一遍所有规则，有改动返回 true
+fn simplify(code: &mut Vec<Line>) -> bool {
+    let mut changed = false;
+
+    // 1) 从前往后改写，slots 记下每个栈槽（fp 偏移）当前和哪个寄存器相等
+    let mut out: Vec<Line> = Vec::with_capacity(code.len());
+    let mut slots: HashMap<i32, Reg> = HashMap::new();
+    for line in code.drain(..) {
+        if is_comment(&line) {
+            out.push(line);
+            continue;
+        }
+        let Line::Inst(inst) = line else {
+            slots.clear();
+            out.push(line);
+            continue;
+        };
+        let prev = match out.iter().rev().find(|line| !is_comment(line)) {
+            Some(Line::Inst(prev)) => Some(prev),
+            _ => None,
+        };
+        let new = rewrite(prev, inst.clone(), &slots);
+        changed |= new.as_ref() != Some(&inst);
+        let Some(inst) = new else { continue };
+
+        match inst {
+            Inst::Sw { rs, imm12, rd: "fp" } => {
+                slots.insert(imm12, rs);
+            }
+            // 经过指针的 sw 可能写到任何地方
+            Inst::Sw { .. } => slots.clear(),
+            _ if is_control(&inst) => slots.clear(),
+            _ => {
+                if let (Some(def), _) = regs(&inst) {
+                    if def == "fp" {
+                        slots.clear();
+                    }
+                    slots.retain(|_, reg| *reg != def);
+                }
+                if let Inst::Lw { rd, imm12, rs: "fp" } = inst {
+                    slots.insert(imm12, rd);
+                }
+            }
+        }
+        out.push(Line::Inst(inst));
+    }
+
+    // 2) 从后往前删掉没人读的临时寄存器写入，以及被后面的 sw 覆盖、中间没人读的 sw
+    let mut live: HashSet<Reg> = HashSet::new();
+    let mut overwritten: HashSet<i32> = HashSet::new();
+    for i in (0..out.len()).rev() {
+        if is_comment(&out[i]) {
+            continue;
+        }
+        let Line::Inst(inst) = &out[i] else {
+            // 不认识的行可能读任何东西
+            live.extend(TEMPS);
+            overwritten.clear();
+            continue;
+        };
+        let (def, uses) = regs(inst);
+        let dead = match *inst {
+            Inst::Sw { imm12, rd: "fp", .. } => !overwritten.insert(imm12),
+            Inst::Sw { .. } => false,
+            Inst::Lw { imm12, rs: "fp", .. } => {
+                overwritten.remove(&imm12);
+                def.is_some_and(|def| TEMPS.contains(&def) && !live.contains(def))
+            }
+            // 经过指针的 lw 可能读任何地方，也可能是有副作用的设备寄存器
+            Inst::Lw { .. } => {
+                overwritten.clear();
+                false
+            }
+            _ if is_control(inst) => {
+                live.clear();
+                overwritten.clear();
+                false
+            }
+            _ => def.is_some_and(|def| TEMPS.contains(&def) && !live.contains(def)),
+        };
+        if dead {
+            out.remove(i);
+            changed = true;
+            continue;
+        }
+        if let Some(def) = def {
+            live.remove(def);
+        }
+        live.extend(uses.into_iter().filter(|reg| TEMPS.contains(reg)));
+    }
+
+    *code = out;
+    changed
+}
+
+/// 单条指令的改写（prev 是紧挨着的前一条），None 表示删掉
+fn rewrite(prev: Option<&Inst>, inst: Inst, slots: &HashMap<i32, Reg>) -> Option<Inst> {
+    match (prev, inst) {
+        (_, Inst::Mv { rd, rs }) if rd == rs => None,
+        (Some(&Inst::Li { rd: temp, imm }), Inst::Add { rd, rs1, rs2 })
+            if (-2048..=2047).contains(&imm) && (rs1 == temp) != (rs2 == temp) =>
+        {
+            let rs = if rs1 == temp { rs2 } else { rs1 };
+            Some(Inst::Addi { rd, rs, imm12: imm })
+        }
+        (Some(&Inst::Mv { rd: temp, rs: src }), Inst::Mv { rd, rs }) if rs == temp && TEMPS.contains(&temp) => Some(Inst::Mv { rd, rs: src }),
+        (Some(&Inst::SextW { rd: extended, .. }), Inst::SextW { rd, rs }) if rs == extended => Some(Inst::Mv { rd, rs }),
+        (_, Inst::Lw { rd, imm12, rs: "fp" }) if slots.contains_key(&imm12) => Some(Inst::Mv { rd, rs: slots[&imm12] }),
+        (_, Inst::Sw { rs, imm12, rd: "fp" }) if slots.get(&imm12) == Some(&rs) => None,
+        (_, inst) => Some(inst),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peephole() {
+        let lines = [
+            "main:",
+            "  li\tt1, 5",
+            "  add\ts1, s2, t1",
+            "  mv\ts1, s1",
+            "  sw\ts1, 8(fp)",
+            "  lw\tt0, 8(fp)",
+            "  sw\tt0, 12(fp)",
+            "  sw\ts2, 12(fp)",
+            "  sext.w\ts3, s2",
+            "  sext.w\ts3, s3",
+            "  lw\tt0, 12(fp)",
+            "  mv\ta0, t0",
+            "  ret",
+        ];
+        let out = optimize(lines.iter().map(|line| line.to_string()).collect());
+        assert_eq!(
+            out,
+            [
+                "main:",
+                "  addi\ts1, s2, 5",
+                "  sw\ts1, 8(fp)",
+                "  sw\ts2, 12(fp)",
+                "  sext.w\ts3, s2",
+                "  mv\ta0, s2",
+                "  ret",
+            ]
+        );
+    }
+}
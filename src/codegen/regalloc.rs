@@ -0,0 +1,177 @@
+// 线性扫描寄存器分配（Poletto & Sarkar）：先对整个函数做活跃变量分析，给每个值算出一个
+// 覆盖它所有活跃位置的区间，再按区间起点依次分配 s1–s11；寄存器不够时，把区间终点最远的
+// 那个值溢出到栈上。s 寄存器是 callee-saved，值跨过 call 不会丢，
+// 函数用到的 s 寄存器由 build.rs 在 prologue 保存、在每个 ret 之前恢复。
+
+use std::collections::{HashMap, HashSet};
+
+use koopa::ir::types::TypeKind;
+use koopa::ir::{BasicBlock, FunctionData, Value, ValueKind};
+
+/// 可分配的寄存器（s0 是 fp）
+pub const ALLOCATABLE: [&str; 11] = ["s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11"];
+
+/// 需要一个位置（寄存器或栈槽）的指令：有结果的指令，alloc 除外——alloc 自己就是一块栈空间
+pub fn has_result(func_data: &FunctionData, inst: Value) -> bool {
+    let data = func_data.dfg().value(inst);
+    !matches!(data.kind(), ValueKind::Alloc(_)) && !matches!(data.ty().kind(), TypeKind::Unit)
+}
+
+/// 指令读到的所有值
+fn operands(kind: &ValueKind) -> Vec<Value> {
+    match kind {
+        ValueKind::Binary(bin) => vec![bin.lhs(), bin.rhs()],
+        ValueKind::Store(st) => vec![st.value(), st.dest()],
+        ValueKind::Load(ld) => vec![ld.src()],
+        ValueKind::GetPtr(gp) => vec![gp.src(), gp.index()],
+        ValueKind::GetElemPtr(gep) => vec![gep.src(), gep.index()],
+        ValueKind::Branch(br) => [br.cond()].into_iter().chain(br.true_args().iter().copied()).chain(br.false_args().iter().copied()).collect(),
+        ValueKind::Jump(jump) => jump.args().to_vec(),
+        ValueKind::Call(call) => call.args().to_vec(),
+        ValueKind::Return(ret) => ret.value().into_iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn successors(func_data: &FunctionData, bb: BasicBlock) -> Vec<BasicBlock> {
+    let last = func_data.layout().bbs().node(&bb).unwrap().insts().back_key().copied();
+    match last.map(|inst| func_data.dfg().value(inst).kind()) {
+        Some(ValueKind::Jump(jump)) => vec![jump.target()],
+        Some(ValueKind::Branch(br)) => vec![br.true_bb(), br.false_bb()],
+        _ => Vec::new(),
+    }
+}
+
+/// 给函数里的值分配寄存器；没出现在结果里的值放在栈上
+pub fn allocate(func_data: &FunctionData) -> HashMap<Value, &'static str> {
+    // 1) 参与分配的值，按出现顺序：函数参数，然后每个基本块的参数和有结果的指令
+    let bbs = func_data.layout().bbs().keys().copied().collect::<Vec<_>>();
+    let mut candidates = func_data.params().to_vec();
+    for &bb in &bbs {
+        candidates.extend(func_data.dfg().bbs()[&bb].params());
+        for &inst in func_data.layout().bbs().node(&bb).unwrap().insts().keys() {
+            if has_result(func_data, inst) {
+                candidates.push(inst);
+            }
+        }
+    }
+    let is_candidate = candidates.iter().copied().collect::<HashSet<_>>();
+
+    // 2) 活跃变量分析：live_in = use ∪ (live_out − def)，迭代到不动点
+    let mut uses = HashMap::<BasicBlock, HashSet<Value>>::new();
+    let mut defs = HashMap::<BasicBlock, HashSet<Value>>::new();
+    for &bb in &bbs {
+        let (use_set, def_set) = (uses.entry(bb).or_default(), defs.entry(bb).or_default());
+        def_set.extend(func_data.dfg().bbs()[&bb].params());
+        for &inst in func_data.layout().bbs().node(&bb).unwrap().insts().keys() {
+            for val in operands(func_data.dfg().value(inst).kind()) {
+                if is_candidate.contains(&val) && !def_set.contains(&val) {
+                    use_set.insert(val);
+                }
+            }
+            def_set.insert(inst);
+        }
+    }
+    let mut live_in = bbs.iter().map(|&bb| (bb, HashSet::new())).collect::<HashMap<_, _>>();
+    let mut live_out = live_in.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &bb in bbs.iter().rev() {
+            let out = successors(func_data, bb).iter().flat_map(|succ| live_in[succ].iter().copied()).collect::<HashSet<_>>();
+            let mut new_in = uses[&bb].clone();
+            new_in.extend(out.difference(&defs[&bb]));
+            if new_in != live_in[&bb] {
+                live_in.insert(bb, new_in);
+                changed = true;
+            }
+            live_out.insert(bb, out);
+        }
+    }
+
+    // 3) 活跃区间：指令按布局顺序编号，每个基本块开头额外占一个编号给它的参数
+    let mut ranges = HashMap::<Value, (usize, usize)>::new();
+    let mut extend = |val: Value, pos: usize| {
+        let range = ranges.entry(val).or_insert((pos, pos));
+        *range = (range.0.min(pos), range.1.max(pos));
+    };
+    for &param in func_data.params() {
+        extend(param, 0);
+    }
+    let mut pos = 0;
+    for &bb in &bbs {
+        pos += 1;
+        let start = pos;
+        for &param in func_data.dfg().bbs()[&bb].params() {
+            extend(param, start);
+        }
+        for &val in &live_in[&bb] {
+            extend(val, start);
+        }
+        for &inst in func_data.layout().bbs().node(&bb).unwrap().insts().keys() {
+            pos += 1;
+            if is_candidate.contains(&inst) {
+                extend(inst, pos);
+            }
+            for val in operands(func_data.dfg().value(inst).kind()) {
+                if is_candidate.contains(&val) {
+                    extend(val, pos);
+                }
+            }
+        }
+        for &val in &live_out[&bb] {
+            extend(val, pos);
+        }
+    }
+
+    // 4) 线性扫描。区间在端点重叠也算冲突：同一条 IR 指令的操作数和结果不会共用寄存器
+    let mut intervals = candidates.iter().filter_map(|val| ranges.get(val).map(|&(s, e)| (*val, s, e))).collect::<Vec<_>>();
+    intervals.sort_by_key(|&(_, start, end)| (start, end));
+    let mut assigned = HashMap::new();
+    let mut active: Vec<(Value, usize)> = Vec::new(); // (值, 区间终点)
+    let mut free = ALLOCATABLE.iter().rev().copied().collect::<Vec<_>>();
+    for (val, start, end) in intervals {
+        active.retain(|&(other, other_end)| {
+            let live = other_end >= start;
+            if !live {
+                free.push(assigned[&other]);
+            }
+            live
+        });
+        if let Some(reg) = free.pop() {
+            assigned.insert(val, reg);
+            active.push((val, end));
+            continue;
+        }
+        // 没有空闲寄存器：终点最远的值让出寄存器去栈上
+        let (i, &(victim, victim_end)) = active.iter().enumerate().max_by_key(|(_, entry)| entry.1).unwrap();
+        if victim_end > end {
+            let reg = assigned.remove(&victim).unwrap();
+            assigned.insert(val, reg);
+            active[i] = (val, end);
+        }
+    }
+    assigned
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_spill() {
+        // 13 个值同时活跃，s1–s11 不够用；它们还要跨过一次 call
+        let source = "int id(int x) { return x; }
+            int main() {
+                int a = 1; int b = a + 1; int c = b + 1; int d = c + 1; int e = d + 1; int f = e + 1; int g = f + 1;
+                int h = g + 1; int i = h + 1; int j = i + 1; int k = j + 1; int l = k + 1; int m = l + 1;
+                int n = id(m);
+                return a + b + c + d + e + f + g + h + i + j + k + l + m + n;
+            }";
+        let mut program = crate::irgen::build_ir(crate::parse(source).unwrap()).unwrap();
+        crate::irgen::opt::mem2reg(&mut program);
+        let asm = crate::codegen::build_asm(&program, crate::codegen::Target::default());
+        assert!(asm.contains("sd\ts11"), "{}", asm);
+        let (code, _) = crate::runner::build_and_run("regalloc", &asm, None, &crate::runner::test_dir()).unwrap();
+        // a = 1, b..m = 2..13, n = 13
+        assert_eq!(code, 91 + 13);
+    }
+}
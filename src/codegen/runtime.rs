@@ -0,0 +1,168 @@
+// SysY runtime library (getint/getch/getarray/putint/putch/putarray/starttime/stoptime) in RISC-V
+// assembly. runner.rs appends it to every program assembled by the built-in assembler, and links it
+// when using an external toolchain without $SYSY_RUNTIME, so compiled programs can do I/O under
+// -sim / -run / test.
+//
+// Input and output go through the UART (simulator/device.rs) at SERIAL_PORT; reading the line
+// status first tells EOF apart from a 0xff byte. The functions only touch t0-t6, a0-a5 and ra, and
+// keep their own state in the a registers their callees leave alone. starttime/stoptime do
+// nothing: the simulator reports cycles itself.
+
+pub const RUNTIME: &str = "\
+  .text
+  .globl getch
+getch:
+  lui t0, 0x50000
+  slli t0, t0, 1
+  addi t0, t0, 0x3f8
+  lbu a0, 5(t0)
+  andi a0, a0, 1
+  beqz a0, __rt_getch_eof
+  lbu a0, 0(t0)
+  ret
+__rt_getch_eof:
+  li a0, -1
+  ret
+
+  .globl putch
+putch:
+  lui t0, 0x50000
+  slli t0, t0, 1
+  addi t0, t0, 0x3f8
+  sb a0, 0(t0)
+  ret
+
+# skips anything before an optional '-' and the digits; the character after the number is consumed
+  .globl getint
+getint:
+  mv t3, ra
+  li t1, 0
+  li t2, 0
+__rt_getint_skip:
+  call getch
+  li t4, -1
+  beq a0, t4, __rt_getint_done
+  li t4, 45
+  beq a0, t4, __rt_getint_minus
+  addi t4, a0, -48
+  sltiu t5, t4, 10
+  bnez t5, __rt_getint_digit
+  j __rt_getint_skip
+__rt_getint_minus:
+  li t2, 1
+  call getch
+  addi t4, a0, -48
+  sltiu t5, t4, 10
+  beqz t5, __rt_getint_done
+__rt_getint_digit:
+  li t5, 10
+  mulw t1, t1, t5
+  addw t1, t1, t4
+  call getch
+  addi t4, a0, -48
+  sltiu t5, t4, 10
+  bnez t5, __rt_getint_digit
+__rt_getint_done:
+  mv a0, t1
+  beqz t2, __rt_getint_ret
+  negw a0, a0
+__rt_getint_ret:
+  mv ra, t3
+  ret
+
+# digits are written to a stack buffer lowest first, then printed backwards
+  .globl putint
+putint:
+  mv t3, ra
+  sext.w t1, a0
+  bgez t1, __rt_putint_digits
+  li a0, 45
+  call putch
+  neg t1, t1
+__rt_putint_digits:
+  addi sp, sp, -16
+  mv t2, sp
+  li t4, 10
+__rt_putint_div:
+  rem t6, t1, t4
+  addi t6, t6, 48
+  sb t6, 0(t2)
+  addi t2, t2, 1
+  div t1, t1, t4
+  bnez t1, __rt_putint_div
+__rt_putint_out:
+  addi t2, t2, -1
+  lbu a0, 0(t2)
+  call putch
+  bne t2, sp, __rt_putint_out
+  addi sp, sp, 16
+  li a0, 0
+  mv ra, t3
+  ret
+
+  .globl getarray
+getarray:
+  mv a4, ra
+  mv a1, a0
+  call getint
+  mv a2, a0
+  li a3, 0
+__rt_getarray_loop:
+  bge a3, a2, __rt_getarray_done
+  call getint
+  sw a0, 0(a1)
+  addi a1, a1, 4
+  addi a3, a3, 1
+  j __rt_getarray_loop
+__rt_getarray_done:
+  mv a0, a2
+  mv ra, a4
+  ret
+
+# n: a[0] a[1] ...
+  .globl putarray
+putarray:
+  mv a5, ra
+  mv a2, a0
+  mv a3, a1
+  call putint
+  li a0, 58
+  call putch
+  li a4, 0
+__rt_putarray_loop:
+  bge a4, a2, __rt_putarray_done
+  li a0, 32
+  call putch
+  lw a0, 0(a3)
+  call putint
+  addi a3, a3, 4
+  addi a4, a4, 1
+  j __rt_putarray_loop
+__rt_putarray_done:
+  li a0, 10
+  call putch
+  mv ra, a5
+  ret
+
+  .globl starttime
+starttime:
+  ret
+
+  .globl stoptime
+stoptime:
+  ret
+";
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_runtime_io() {
+        let source = "int a[4];
+            int main() { int n = getarray(a); int x = getint(); putint(x); putch(10); putarray(n, a); return getch() + 1; }";
+        let asm = crate::compile_to_riscv(source).unwrap();
+        let (code, output) = crate::runner::build_and_run("runtime", &asm, Some("3\n4 -5 6\n-2147483648\n"), &crate::runner::test_dir()).unwrap();
+        assert_eq!(output, "-2147483648\n3: 4 -5 6\n");
+        // the newline after the last number was consumed by getint, so getch sees EOF
+        assert_eq!(code, 0);
+    }
+}
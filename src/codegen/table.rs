@@ -39,7 +39,6 @@ impl DescriptorTable {
             return;
         }
         let idx = reg2idx(reg);
-        println!("free reg {}",idx2reg(idx));
         let val1 = self.reg2val[idx].expect("Double free");
         assert_eq!(val, val1, "Free on unmatched pair");
         self.reg2val[idx] = None;
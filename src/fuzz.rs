@@ -0,0 +1,400 @@
+// Random SysY program generator for differential testing (csmith-lite): `fuzz [N] [SEED]`.
+//
+// Programs only use int locals, arithmetic / comparison / logical expressions, if-else and bounded
+// while loops, so the generator can evaluate them itself. Each program prints its variables at the
+// end, compares them with the expected values and returns the 1-based index of the first wrong one
+// (0 if all match). Every program is compiled three ways, by the build backend at -O0 and after the
+// -O2 passes and by the stack backend; each run has to match the reference evaluation, and the runs
+// have to agree with each other on exit code and output. Failing programs are minimized by deleting
+// statements and written to fuzz-SEED.c.
+
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::{compile_to_koopa, compile_to_riscv, compile_to_riscv_opt, compile_to_riscv_stack};
+use crate::runner::{build_and_run, compile};
+
+const VARS: usize = 6;
+const MAX_DEPTH: u32 = 3;
+
+type Compile = fn(&str) -> Result<String, String>;
+
+const COMPILERS: [(&str, Compile); 3] = [
+    ("build -O0", compile_to_riscv),
+    ("build -O2", compile_o2),
+    ("stack", compile_to_riscv_stack),
+];
+
+fn compile_o2(source: &str) -> Result<String, String> {
+    compile_to_riscv_opt(source, 2)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BinOp {
+    Add, Sub, Mul, Div, Mod, Lt, Gt, Le, Ge, Eq, Ne, And, Or,
+}
+
+impl BinOp {
+    const ALL: [BinOp; 13] = [
+        BinOp::Add, BinOp::Sub, BinOp::Mul, BinOp::Div, BinOp::Mod, BinOp::Lt, BinOp::Gt,
+        BinOp::Le, BinOp::Ge, BinOp::Eq, BinOp::Ne, BinOp::And, BinOp::Or,
+    ];
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Lt => "<",
+            BinOp::Gt => ">",
+            BinOp::Le => "<=",
+            BinOp::Ge => ">=",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        }
+    }
+
+    // 32-bit wrapping semantics; divisors are never 0 or -1
+    fn apply(self, l: i32, r: i32) -> i32 {
+        match self {
+            BinOp::Add => l.wrapping_add(r),
+            BinOp::Sub => l.wrapping_sub(r),
+            BinOp::Mul => l.wrapping_mul(r),
+            BinOp::Div => l.wrapping_div(r),
+            BinOp::Mod => l.wrapping_rem(r),
+            BinOp::Lt => (l < r) as i32,
+            BinOp::Gt => (l > r) as i32,
+            BinOp::Le => (l <= r) as i32,
+            BinOp::Ge => (l >= r) as i32,
+            BinOp::Eq => (l == r) as i32,
+            BinOp::Ne => (l != r) as i32,
+            BinOp::And => (l != 0 && r != 0) as i32,
+            BinOp::Or => (l != 0 || r != 0) as i32,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(i32),
+    Var(usize),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Stmt {
+    Assign(usize, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    Loop(u32, Vec<Stmt>), // fixed trip count
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    init: [i32; VARS],
+    body: Vec<Stmt>,
+}
+
+fn eval(e: &Expr, vars: &[i32; VARS]) -> i32 {
+    match e {
+        Expr::Num(n) => *n,
+        Expr::Var(v) => vars[*v],
+        Expr::Neg(e) => eval(e, vars).wrapping_neg(),
+        Expr::Not(e) => (eval(e, vars) == 0) as i32,
+        Expr::Bin(op, l, r) => op.apply(eval(l, vars), eval(r, vars)),
+    }
+}
+
+fn exec(stmts: &[Stmt], vars: &mut [i32; VARS]) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign(v, e) => vars[*v] = eval(e, vars),
+            Stmt::If(cond, then, els) => {
+                if eval(cond, vars) != 0 { exec(then, vars) } else { exec(els, vars) }
+            }
+            Stmt::Loop(n, body) => {
+                for _ in 0..*n {
+                    exec(body, vars);
+                }
+            }
+        }
+    }
+}
+
+// SysY has no negative literals, and 2147483648 does not fit in an int
+fn literal(n: i32) -> String {
+    match n {
+        i32::MIN => String::from("(-2147483647 - 1)"),
+        n if n < 0 => format!("(-{})", n.unsigned_abs()),
+        n => n.to_string(),
+    }
+}
+
+fn render_expr(e: &Expr) -> String {
+    match e {
+        Expr::Num(n) => literal(*n),
+        Expr::Var(v) => format!("v{}", v),
+        Expr::Neg(e) => format!("(-{})", render_expr(e)),
+        Expr::Not(e) => format!("(!{})", render_expr(e)),
+        Expr::Bin(op, l, r) => format!("({} {} {})", render_expr(l), op.symbol(), render_expr(r)),
+    }
+}
+
+fn render_block(stmts: &[Stmt], depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth + 1);
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign(v, e) => out.push_str(&format!("{}v{} = {};\n", indent, v, render_expr(e))),
+            Stmt::If(cond, then, els) => {
+                out.push_str(&format!("{}if ({}) {{\n", indent, render_expr(cond)));
+                render_block(then, depth + 1, out);
+                out.push_str(&format!("{}}} else {{\n", indent));
+                render_block(els, depth + 1, out);
+                out.push_str(&format!("{}}}\n", indent));
+            }
+            Stmt::Loop(n, body) => {
+                // one counter per nesting level, scoped to its own block
+                out.push_str(&format!("{}{{\n{}  int i{} = 0;\n", indent, indent, depth));
+                out.push_str(&format!("{}  while (i{} < {}) {{\n", indent, depth, n));
+                render_block(body, depth + 2, out);
+                out.push_str(&format!("{}    i{} = i{} + 1;\n{}  }}\n{}}}\n", indent, depth, depth, indent, indent));
+            }
+        }
+    }
+}
+
+// delete the n-th statement in pre-order, returns false if there are fewer statements
+fn remove_nth(stmts: &mut Vec<Stmt>, n: &mut usize) -> bool {
+    for i in 0..stmts.len() {
+        if *n == 0 {
+            stmts.remove(i);
+            return true;
+        }
+        *n -= 1;
+        let removed = match &mut stmts[i] {
+            Stmt::Assign(..) => false,
+            Stmt::If(_, then, els) => remove_nth(then, n) || remove_nth(els, n),
+            Stmt::Loop(_, body) => remove_nth(body, n),
+        };
+        if removed {
+            return true;
+        }
+    }
+    false
+}
+
+impl Program {
+    pub fn expected(&self) -> [i32; VARS] {
+        let mut vars = self.init;
+        exec(&self.body, &mut vars);
+        vars
+    }
+
+    // what the program prints: its variables separated by spaces
+    pub fn expected_output(&self) -> String {
+        let values: Vec<String> = self.expected().iter().map(|v| v.to_string()).collect();
+        format!("{}\n", values.join(" "))
+    }
+
+    pub fn to_source(&self) -> String {
+        let mut out = String::from("int main() {\n");
+        for (i, v) in self.init.iter().enumerate() {
+            out.push_str(&format!("  int v{} = {};\n", i, literal(*v)));
+        }
+        render_block(&self.body, 0, &mut out);
+        for i in 0..VARS {
+            out.push_str(&format!("  putint(v{}); putch({});\n", i, if i + 1 < VARS { 32 } else { 10 }));
+        }
+        for (i, v) in self.expected().iter().enumerate() {
+            out.push_str(&format!("  if (v{} != {}) return {};\n", i, literal(*v), i + 1));
+        }
+        out.push_str("  return 0;\n}\n");
+        out
+    }
+}
+
+struct Generator {
+    rng: StdRng,
+}
+
+impl Generator {
+    fn literal(&mut self) -> i32 {
+        if self.rng.gen_bool(0.9) {
+            self.rng.gen_range(-100..=100)
+        } else {
+            self.rng.gen_range(-i32::MAX..=i32::MAX)
+        }
+    }
+
+    fn divisor(&mut self) -> i32 {
+        loop {
+            let d = self.rng.gen_range(-20..=20);
+            if d != 0 && d != -1 {
+                return d;
+            }
+        }
+    }
+
+    fn expr(&mut self, depth: u32) -> Expr {
+        if depth == 0 || self.rng.gen_bool(0.3) {
+            return if self.rng.gen_bool(0.6) {
+                Expr::Var(self.rng.gen_range(0..VARS))
+            } else {
+                Expr::Num(self.literal())
+            };
+        }
+        match self.rng.gen_range(0..10) {
+            0 => Expr::Neg(Box::new(self.expr(depth - 1))),
+            1 => Expr::Not(Box::new(self.expr(depth - 1))),
+            _ => {
+                let op = BinOp::ALL[self.rng.gen_range(0..BinOp::ALL.len())];
+                let l = Box::new(self.expr(depth - 1));
+                let r = match op {
+                    BinOp::Div | BinOp::Mod => Expr::Num(self.divisor()),
+                    _ => self.expr(depth - 1),
+                };
+                Expr::Bin(op, l, Box::new(r))
+            }
+        }
+    }
+
+    fn block(&mut self, depth: u32) -> Vec<Stmt> {
+        let len = self.rng.gen_range(1..=4);
+        (0..len).map(|_| self.stmt(depth)).collect()
+    }
+
+    fn stmt(&mut self, depth: u32) -> Stmt {
+        let kind = if depth == 0 { 0 } else { self.rng.gen_range(0..6) };
+        match kind {
+            0..=3 => Stmt::Assign(self.rng.gen_range(0..VARS), self.expr(3)),
+            4 => Stmt::If(self.expr(2), self.block(depth - 1), self.block(depth - 1)),
+            _ => Stmt::Loop(self.rng.gen_range(1..=4), self.block(depth - 1)),
+        }
+    }
+}
+
+pub fn generate(seed: u64) -> Program {
+    let mut gen = Generator { rng: StdRng::seed_from_u64(seed) };
+    let init = std::array::from_fn(|_| gen.literal());
+    let len = gen.rng.gen_range(3..=10);
+    let body = (0..len).map(|_| gen.stmt(MAX_DEPTH)).collect();
+    Program { init, body }
+}
+
+// Some(description) if a compiled program disagrees with the reference evaluation or with the
+// program from another compiler
+fn check(program: &Program, work: &Path) -> Option<String> {
+    let source = program.to_source();
+    match panic::catch_unwind(|| compile_to_koopa(&source)) {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => return Some(format!("Koopa IR generation failed: {}", e)),
+        Err(_) => return Some(String::from("Koopa IR generation panicked")),
+    }
+    let expected = program.expected_output();
+    let mut runs = Vec::new();
+    for (name, compile) in COMPILERS {
+        let asm = match panic::catch_unwind(|| compile(&source)) {
+            Ok(Ok(asm)) => asm,
+            Ok(Err(e)) => return Some(format!("{}: RISC-V generation failed: {}", name, e)),
+            Err(_) => return Some(format!("{}: RISC-V generation panicked", name)),
+        };
+        let (code, output) = match build_and_run("fuzz", &asm, None, work) {
+            Ok(run) => run,
+            Err(e) => return Some(format!("{}: {}", name, e)),
+        };
+        if code & 0xff != 0 {
+            return Some(format!("{}: v{} has the wrong value", name, (code & 0xff) - 1));
+        }
+        if output != expected {
+            return Some(format!("{}: prints {:?} instead of {:?}", name, output, expected));
+        }
+        runs.push((name, code));
+    }
+    // all of them printed the same and the low byte is 0 everywhere by now, the rest of the exit
+    // code still has to match
+    let (first, code) = runs[0];
+    runs[1..].iter()
+        .find(|&&(_, c)| c != code)
+        .map(|&(name, c)| format!("{} exits with {:#x}, {} with {:#x}", first, code, name, c))
+}
+
+// delete statements one at a time as long as the program keeps failing
+fn minimize(mut program: Program, work: &Path) -> (Program, String) {
+    let mut reason = check(&program, work).unwrap_or_default();
+    let mut n = 0;
+    loop {
+        let mut candidate = program.clone();
+        let mut index = n;
+        if !remove_nth(&mut candidate.body, &mut index) {
+            return (program, reason);
+        }
+        match check(&candidate, work) {
+            Some(r) => {
+                program = candidate;
+                reason = r;
+            }
+            None => n += 1,
+        }
+    }
+}
+
+// returns true if no disagreement was found
+pub fn run_fuzz(count: u64, seed: u64) -> io::Result<bool> {
+    let work = std::env::temp_dir().join("sysy-fuzz");
+    fs::create_dir_all(&work)?;
+
+    // make sure the toolchain works before blaming the compiler
+    let smoke = compile("int main() {\n  return 0;\n}\n")
+        .and_then(|asm| build_and_run("smoke", &asm, None, &work));
+    if let Err(e) = smoke {
+        return Err(io::Error::other(format!("cannot build a trivial program: {}", e)));
+    }
+
+    let mut failures = 0;
+    for i in 0..count {
+        let program_seed = seed.wrapping_add(i);
+        let program = generate(program_seed);
+        if check(&program, &work).is_none() {
+            continue;
+        }
+        let (reduced, reason) = minimize(program, &work);
+        let path = format!("fuzz-{}.c", program_seed);
+        fs::write(&path, reduced.to_source())?;
+        println!("seed {}: {} (minimized program written to {})", program_seed, reason, path);
+        failures += 1;
+    }
+    println!("{} programs, {} failures (seeds {}..{})", count, failures, seed, seed.wrapping_add(count));
+    Ok(failures == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_source() {
+        for seed in 0..20 {
+            let program = generate(seed);
+            let source = program.to_source();
+            crate::parse(&source).unwrap_or_else(|e| panic!("seed {}: {}\n{}", seed, e, source));
+            assert_eq!(program.expected_output().split_whitespace().count(), VARS);
+        }
+    }
+
+    // one program through all three compilers, checked against the reference evaluation
+    #[test]
+    fn test_check_fixed_seed() {
+        let program = generate(42);
+        assert_eq!(check(&program, &crate::runner::test_dir()), None, "\n{}", program.to_source());
+    }
+}
@@ -99,6 +99,37 @@ pub fn global_const_cur(env: &mut Env, dims: &Vec<i32>, init_val: &ConstInitVal,
 }
 
 
+// the elements of a const array as numbers, in row-major order; eval reads A[i] from these
+pub fn const_init_values(env: &mut Env, dims: &Vec<i32>, init_val: &ConstInitVal) -> Vec<i32> {
+    let mut vec = Vec::new();
+    let _ = const_values_cur(env, dims, init_val, 0, dims.len() as i32 + 1, &mut vec);
+    vec
+}
+
+// same walk as global_const_cur
+fn const_values_cur(env: &mut Env, dims: &Vec<i32>, init_val: &ConstInitVal, pre_len: i32, limit: i32, vec: &mut Vec<i32>) -> i32 {
+    let mut len = 0;
+    match init_val {
+        ConstInitVal::ConstExp(exp) => {
+            vec.push(exp.eval(env));
+            len += 1;
+        }
+        ConstInitVal::InitList(init_list) => {
+            let align = find_align(dims, pre_len, limit);
+
+            for init_val in init_list {
+                len += const_values_cur(env, dims, init_val, len, align, vec);
+            }
+
+            let total_len = dims.iter().rev().take(align as usize).fold(1, |acc, dim| acc * dim);
+            vec.resize(vec.len() + (total_len - len) as usize, 0);
+            len = total_len
+        }
+    }
+    len
+}
+
+
 pub fn global_var_init(env: &mut Env, dims: &Vec<i32>, init_val: &InitVal) -> Value {
 
     // return the product of dims
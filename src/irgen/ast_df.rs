@@ -1,3 +1,36 @@
+// 1-based line and column of a node in the source, for error messages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl std::fmt::Display for Pos {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+// byte offset -> Pos; the parser takes one so every node records where it starts
+pub struct LineIndex<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let starts = std::iter::once(0).chain(source.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        Self { source, starts }
+    }
+
+    pub fn pos(&self, offset: usize) -> Pos {
+        let line = self.starts.partition_point(|&start| start <= offset);
+        let start = self.starts[line - 1];
+        let col = self.source.get(start..offset).map_or(offset - start, |s| s.chars().count());
+        Pos { line, col: col + 1 }
+    }
+}
+
 #[derive(Debug)]
 pub struct CompUnit {
     pub items: Vec<GlobalItem>,
@@ -12,6 +45,7 @@ pub enum GlobalItem {
 #[derive(Debug)]  
 pub struct FuncDef {
     pub func_type: BType,
+    pub pos: Pos,
     pub ident: String,
     pub params: Vec<FuncFParam>,
     pub block: Block,
@@ -21,6 +55,7 @@ pub struct FuncDef {
 pub enum BType {
     Void,
     Int,
+    Float,
 }
 
 #[derive(Debug)]
@@ -28,12 +63,14 @@ pub struct FuncFParam {
     pub ty: BType,
     pub id: String,
     pub dims: Option<Vec<ConstExp>>,
+    pub pos: Pos,
 }
 
 #[derive(Debug)]
 pub struct FuncCall {
     pub id: String,
     pub args: Vec<Exp>,
+    pub pos: Pos,
 }
 
 #[derive(Debug)]
@@ -57,18 +94,39 @@ pub enum Stmt {
     Return(Return),
     If(If),
     While(While),
-    Break,
-    Continue,
+    Break(Pos),
+    Continue(Pos),
+}
+
+impl BlockItem {
+    // where the statement or declaration starts; None for `;` and expression statements
+    pub fn pos(&self) -> Option<Pos> {
+        match self {
+            BlockItem::Decl(Decl::Const(decl)) => Some(decl.pos),
+            BlockItem::Decl(Decl::Var(decl)) => Some(decl.pos),
+            BlockItem::Stmt(stmt) => match stmt {
+                Stmt::Assign(Assign { pos, .. })
+                | Stmt::Return(Return { pos, .. })
+                | Stmt::If(If { pos, .. })
+                | Stmt::While(While { pos, .. })
+                | Stmt::Break(pos)
+                | Stmt::Continue(pos) => Some(*pos),
+                Stmt::Empty | Stmt::Exp(_) | Stmt::Block(_) => None,
+            },
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct While {
+    pub pos: Pos,
     pub cond: Exp,
     pub stmt: Box<Stmt>,
 }
 
 #[derive(Debug)]
 pub struct If {
+    pub pos: Pos,
     pub cond: Exp,
     pub stmt: Box<Stmt>,
     pub else_stmt: Option<Box<Stmt>>,
@@ -76,6 +134,7 @@ pub struct If {
 
 #[derive(Debug)]
 pub struct Assign {
+    pub pos: Pos,
     pub lval: LVal,
     pub exp: Exp,
 }
@@ -83,6 +142,7 @@ pub struct Assign {
 #[derive(Debug)]
 pub struct Return {
     pub exp: Option<Exp>,
+    pub pos: Pos,
 }
 
 
@@ -98,7 +158,9 @@ pub enum Decl {
 
 #[derive(Debug)]
 pub struct ConstDecl {
+    pub pos: Pos,
     pub is_global: bool,
+    pub ty: BType,
     pub const_defs: Vec<ConstDef>,
 }
 
@@ -107,6 +169,7 @@ pub struct ConstDef {
     pub ident: String,
     pub init_val: ConstInitVal,
     pub dims: Option<Vec<ConstExp>>,
+    pub pos: Pos,
 }
 
 #[derive(Debug)]
@@ -117,7 +180,9 @@ pub enum ConstInitVal {
 
 #[derive(Debug)]
 pub struct VarDecl {
+    pub pos: Pos,
     pub is_global: bool,
+    pub ty: BType,
     pub defs: Vec<VarDef>,
 }
 
@@ -126,6 +191,7 @@ pub struct VarDef {
     pub ident: String,
     pub init_val: Option<InitVal>,
     pub dims: Option<Vec<ConstExp>>,
+    pub pos: Pos,
 }
 
 #[derive(Debug)]
@@ -140,8 +206,8 @@ pub enum InitVal {
  
 #[derive(Debug)]
 pub enum LVal {
-    Ident(String),
-    Array(String, Vec<Exp>),
+    Ident(String, Pos),
+    Array(String, Vec<Exp>, Pos),
 }
 
 ////////////////
@@ -153,11 +219,26 @@ pub enum Exp {
     LOrExp(LOrExp),
 }
 
+impl Exp {
+    // the variable if the expression is nothing but one (possibly in parentheses), like an array argument
+    pub fn as_lval(&self) -> Option<&LVal> {
+        let Exp::LOrExp(LOrExp::LAnd(LAndExp::Eq(EqExp::Rel(RelExp::Add(AddExp::Mul(MulExp::Unary(UnaryExp::PrimaryExp(primary)))))))) = self else {
+            return None;
+        };
+        match primary {
+            PrimaryExp::LVal(lval) => Some(lval),
+            PrimaryExp::Exp(exp) => exp.as_lval(),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum PrimaryExp {
     LVal(LVal),
     Exp(Box<Exp>),
     Num(i32),
+    Float(f32, Pos),
 }
 
 #[derive(Debug)]
@@ -240,4 +321,16 @@ pub enum RelOp {
 pub enum EqOp {
     Eq,
     Neq,
-}
\ No newline at end of file
+}
+
+// decimal ("1.5", ".5", "1e-3") or hexadecimal ("0x1.8p1") floating-point literal
+pub fn parse_float(text: &str) -> f32 {
+    let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) else {
+        return text.parse().unwrap();
+    };
+    let (mantissa, exp) = hex.split_once(['p', 'P']).unwrap();
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = u64::from_str_radix(&format!("{}{}", int, frac), 16).unwrap_or(0);
+    let exp = exp.parse::<i32>().unwrap() - 4 * frac.len() as i32;
+    (digits as f64 * 2f64.powi(exp)) as f32
+}
@@ -1,8 +1,12 @@
 use koopa::ir::{builder::{BasicBlockBuilder, GlobalInstBuilder, LocalInstBuilder, ValueBuilder}, BasicBlock, BinaryOp, Function, FunctionData, Program, Type, TypeKind, Value, ValueKind};
 
+use std::collections::HashMap;
+
 use super::loopstack::LoopStack;
-use super::scope::{Scope, Var, VarValue};
+use super::scope::{ArrayShape, Scope, Var, VarValue};
 use super::array;
+use super::ast_df::Exp;
+use super::generate::GenerateIR;
 
 macro_rules! insert_inst_into_bb {
     ($func_data:expr, $ctx:expr, $inst:expr) => {
@@ -11,6 +15,7 @@ macro_rules! insert_inst_into_bb {
             .bb_mut($ctx.block.expect("No block in context"))
             .insts_mut()
             .push_key_back($inst);
+        $ctx.lines.insert($inst, $ctx.line);
     };
 }
 
@@ -21,6 +26,8 @@ pub struct Context {
     pub func: Option<Function>,
     pub block: Option<BasicBlock>,
     pub block_count: usize,
+    pub line: usize, // source line of the statement being generated
+    pub lines: super::LineMap,
 }
 
 // Context, Symbol Table, ...
@@ -29,6 +36,7 @@ pub struct Env {
     pub ctx: Context,
     pub scope: Scope,
     pub loopstack: LoopStack,
+    pub const_arrays: HashMap<Value, Vec<i32>>, // flattened elements of const arrays, for eval
 } 
 
 impl Context {
@@ -60,6 +68,7 @@ impl Context {
             bb_mut(self.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.lines.insert(inst, self.line);
 
         inst 
     }
@@ -76,11 +85,17 @@ impl Context {
             bb_mut(self.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.lines.insert(inst, self.line);
             
         inst 
     }
 
     pub fn create_block(&mut self, bb_func: Option<String>) -> BasicBlock {
+        self.create_block_with_params(bb_func, vec![])
+    }
+
+    // a block taking arguments from the jumps into it, e.g. the join block of && / ||
+    pub fn create_block_with_params(&mut self, bb_func: Option<String>, params: Vec<Type>) -> BasicBlock {
         let func = self.func.expect("No function in context");
         let func_data = self.program.func_mut(func);
 
@@ -93,7 +108,7 @@ impl Context {
         let block = func_data.
             dfg_mut().
             new_bb().
-            basic_block(Some(bb_id));
+            basic_block_with_params(Some(bb_id), params);
 
         func_data.layout_mut().bbs_mut().extend([block]);
         
@@ -132,6 +147,7 @@ impl Env {
             bb_mut(self.ctx.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
         
         // insert var into symbol table
         let var = Var::new_normal(ident.clone(), Some(inst));
@@ -237,6 +253,7 @@ impl Env {
                     bb_mut(self.ctx.block.expect("No block in context")).
                     insts_mut().
                     push_key_back(inst);
+                self.ctx.lines.insert(inst, self.ctx.line);
                 
                 inst
             }
@@ -259,6 +276,7 @@ impl Env {
                     bb_mut(self.ctx.block.expect("No block in context")).
                     insts_mut().
                     push_key_back(inst);
+                self.ctx.lines.insert(inst, self.ctx.line);
             }
             VarValue::Func(_) => panic!("Func cannot be stored"),
         }
@@ -274,6 +292,7 @@ impl Env {
             bb_mut(self.ctx.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
     }
 
     pub fn load_val_by_addr(&mut self, addr: Value) -> Value {
@@ -286,6 +305,7 @@ impl Env {
             bb_mut(self.ctx.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
         inst 
     }
 
@@ -299,6 +319,7 @@ impl Env {
             bb_mut(self.ctx.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
     }
 
     pub fn ret_void_inst(&mut self) -> () {
@@ -311,6 +332,7 @@ impl Env {
             bb_mut(self.ctx.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
     }
 
     pub fn jump_inst(&mut self, bb: BasicBlock) -> () {
@@ -323,6 +345,26 @@ impl Env {
             bb_mut(self.ctx.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
+    }
+
+    pub fn jump_with_args_inst(&mut self, bb: BasicBlock, args: Vec<Value>) -> () {
+        let func = self.ctx.func.expect("No function in context");
+        let func_data = self.ctx.program.func_mut(func);
+        let inst = func_data.dfg_mut().new_value().jump_with_args(bb, args);
+
+        let _ = func_data.
+            layout_mut().
+            bb_mut(self.ctx.block.expect("No block in context")).
+            insts_mut().
+            push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
+    }
+
+    // the i-th parameter of a block made by create_block_with_params
+    pub fn block_param(&self, bb: BasicBlock, i: usize) -> Value {
+        let func = self.ctx.func.expect("No function in context");
+        self.ctx.program.func(func).dfg().bb(bb).params()[i]
     }
 
     pub fn branch_inst(&mut self, cond: Value, then_bb: BasicBlock, else_bb: BasicBlock) -> () {
@@ -335,6 +377,7 @@ impl Env {
             bb_mut(self.ctx.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
     }
 
     pub fn call_inst(&mut self, callee: Function, args: Vec<Value>) -> Value {
@@ -347,6 +390,7 @@ impl Env {
             bb_mut(self.ctx.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
 
         inst 
     }
@@ -362,6 +406,7 @@ impl Env {
             bb_mut(self.ctx.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
         
         inst
     }
@@ -377,7 +422,40 @@ impl Env {
             bb_mut(self.ctx.block.expect("No block in context")).
             insts_mut().
             push_key_back(inst);
+        self.ctx.lines.insert(inst, self.ctx.line);
 
         inst
     }
+
+    // address of `ident[indices...]`: a parameter is loaded and stepped with getptr first,
+    // every other subscript is a getelemptr
+    pub fn array_addr(&mut self, ident: &String, indices: &[Exp]) -> (Value, ArrayShape) {
+        let shape = self.scope.lookup_array(ident).unwrap_or_else(|| panic!("Variable {} is not an array", ident));
+        let mut addr = match shape.is_pointer {
+            true => self.load_var(ident),
+            false => self.scope.lookup_var_addr(ident).unwrap(),
+        };
+        for (i, index) in indices.iter().enumerate() {
+            let index = index.generate(self);
+            addr = match shape.is_pointer && i == 0 {
+                true => self.get_ptr_inst(addr, index),
+                false => self.get_elem_inst(addr, index),
+            };
+        }
+        (addr, shape)
+    }
+
+    // the value of `ident[indices...]` in an expression: the int once every dimension is indexed,
+    // otherwise a pointer to the first element of what is left, as array parameters take it
+    pub fn array_value(&mut self, ident: &String, indices: &[Exp]) -> Value {
+        let (addr, shape) = self.array_addr(ident, indices);
+        if indices.len() == shape.rank() {
+            return self.load_val_by_addr(addr);
+        }
+        if shape.is_pointer && indices.is_empty() {
+            return addr; // already the pointer the parameter was given
+        }
+        let zero = self.ctx.create_int_inst(0);
+        self.get_elem_inst(addr, zero)
+    }
 }
\ No newline at end of file
@@ -1,14 +1,24 @@
+use koopa::ir::BinaryOp;
+
 use super::ast_df::*;
+use super::opt::eval_binary;
 use super::{env::Env, scope::VarValue};
 // Calculate the value of a const expression
 pub trait EvalExp {
     fn eval(&self, env: &mut Env) -> i32;
+    // the value if the expression is constant, without panicking: None for variables, calls,
+    // division by zero and const array elements out of bounds
+    fn try_eval(&self, env: &mut Env) -> Option<i32>;
 }
 
 impl EvalExp for ConstExp {
     fn eval(&self, env: &mut Env) -> i32 {
         self.exp.eval(env)
     }
+
+    fn try_eval(&self, env: &mut Env) -> Option<i32> {
+        self.exp.try_eval(env)
+    }
 }
 
 impl EvalExp for Exp {
@@ -17,7 +27,27 @@ impl EvalExp for Exp {
             Exp::LOrExp(l_or_exp) => l_or_exp.eval(env),
         }
     }
-    
+
+    fn try_eval(&self, env: &mut Env) -> Option<i32> {
+        let Exp::LOrExp(l_or_exp) = self;
+        l_or_exp.try_eval(env)
+    }
+}
+
+// A[i][j] of a const array at constant indices within bounds
+pub fn const_element(env: &mut Env, ident: &String, indices: &[Exp]) -> Option<i32> {
+    let Some(VarValue::Alloc(Some(addr), Some(dims), _)) = env.scope.lookup_var(ident) else {
+        return None;
+    };
+    if indices.len() != dims.len() || !env.const_arrays.contains_key(&addr) {
+        return None;
+    }
+    let mut offset = 0;
+    for (index, &dim) in indices.iter().zip(&dims) {
+        let index = index.try_eval(env).filter(|index| (0..dim).contains(index))?;
+        offset = offset * dim + index;
+    }
+    Some(env.const_arrays[&addr][offset as usize])
 }
 
 
@@ -33,6 +63,17 @@ impl EvalExp for LOrExp {
             },
         }
     }
+
+    fn try_eval(&self, env: &mut Env) -> Option<i32> {
+        match self {
+            LOrExp::LAnd(l_and_exp) => l_and_exp.try_eval(env),
+            LOrExp::LOrLAnd(l_or_exp, l_and_exp) => {
+                let or_val = l_or_exp.try_eval(env)?;
+                let and_val = l_and_exp.try_eval(env)?;
+                Some((or_val != 0) as i32 | (and_val != 0) as i32)
+            },
+        }
+    }
 }
 
 impl EvalExp for LAndExp {
@@ -46,6 +87,17 @@ impl EvalExp for LAndExp {
             },
         }
     }
+
+    fn try_eval(&self, env: &mut Env) -> Option<i32> {
+        match self {
+            LAndExp::Eq(eq_exp) => eq_exp.try_eval(env),
+            LAndExp::LAndEq(l_and_exp, eq_exp) => {
+                let and_val = l_and_exp.try_eval(env)?;
+                let eq_val = eq_exp.try_eval(env)?;
+                Some((and_val != 0) as i32 & (eq_val != 0) as i32)
+            },
+        }
+    }
 }
 
 impl EvalExp for EqExp {
@@ -62,6 +114,19 @@ impl EvalExp for EqExp {
             },
         }
     }
+
+    fn try_eval(&self, env: &mut Env) -> Option<i32> {
+        match self {
+            EqExp::Rel(rel_exp) => rel_exp.try_eval(env),
+            EqExp::EqRel(eq_exp, eq_op, rel_exp) => {
+                let op = match eq_op {
+                    EqOp::Eq => BinaryOp::Eq,
+                    EqOp::Neq => BinaryOp::NotEq,
+                };
+                eval_binary(op, eq_exp.try_eval(env)?, rel_exp.try_eval(env)?)
+            },
+        }
+    }
 }
 
 impl EvalExp for RelExp {
@@ -80,6 +145,21 @@ impl EvalExp for RelExp {
             },
         }
     }
+
+    fn try_eval(&self, env: &mut Env) -> Option<i32> {
+        match self {
+            RelExp::Add(add_exp) => add_exp.try_eval(env),
+            RelExp::RelAdd(rel_exp, rel_op, add_exp) => {
+                let op = match rel_op {
+                    RelOp::Lt => BinaryOp::Lt,
+                    RelOp::Gt => BinaryOp::Gt,
+                    RelOp::Le => BinaryOp::Le,
+                    RelOp::Ge => BinaryOp::Ge,
+                };
+                eval_binary(op, rel_exp.try_eval(env)?, add_exp.try_eval(env)?)
+            },
+        }
+    }
 }
 
 impl EvalExp for AddExp {
@@ -96,6 +176,19 @@ impl EvalExp for AddExp {
             },
         }
     }
+
+    fn try_eval(&self, env: &mut Env) -> Option<i32> {
+        match self {
+            AddExp::Mul(mul_exp) => mul_exp.try_eval(env),
+            AddExp::AddMul(add_exp, add_op, mul_exp) => {
+                let op = match add_op {
+                    AddOp::Add => BinaryOp::Add,
+                    AddOp::Sub => BinaryOp::Sub,
+                };
+                eval_binary(op, add_exp.try_eval(env)?, mul_exp.try_eval(env)?)
+            },
+        }
+    }
 }
 
 impl EvalExp for MulExp {
@@ -113,6 +206,20 @@ impl EvalExp for MulExp {
             },
         }
     }
+
+    fn try_eval(&self, env: &mut Env) -> Option<i32> {
+        match self {
+            MulExp::Unary(unary_exp) => unary_exp.try_eval(env),
+            MulExp::MulUnary(mul_exp, mul_op, unary_exp) => {
+                let op = match mul_op {
+                    MulOp::Mul => BinaryOp::Mul,
+                    MulOp::Div => BinaryOp::Div,
+                    MulOp::Mod => BinaryOp::Mod,
+                };
+                eval_binary(op, mul_exp.try_eval(env)?, unary_exp.try_eval(env)?)
+            },
+        }
+    }
 }
 
 impl EvalExp for UnaryExp {
@@ -127,7 +234,22 @@ impl EvalExp for UnaryExp {
                     UnaryOp::Not => (unary_val == 0) as i32,
                 }
             },
-            UnaryExp::FuncCall(_) => panic!("Function call not implemented"),
+            UnaryExp::FuncCall(call) => panic!("{}: Function call not implemented", call.pos),
+        }
+    }
+
+    fn try_eval(&self, env: &mut Env) -> Option<i32> {
+        match self {
+            UnaryExp::PrimaryExp(primary_exp) => primary_exp.try_eval(env),
+            UnaryExp::Unary(unary_op, unary_exp) => {
+                let unary_val = unary_exp.try_eval(env)?;
+                match unary_op {
+                    UnaryOp::Plus => Some(unary_val),
+                    UnaryOp::Minus => Some(unary_val.wrapping_neg()),
+                    UnaryOp::Not => Some((unary_val == 0) as i32),
+                }
+            },
+            UnaryExp::FuncCall(_) => None,
         }
     }
 }
@@ -136,26 +258,76 @@ impl EvalExp for PrimaryExp {
     fn eval(&self, env: &mut Env) -> i32 {
         match self {
             PrimaryExp::Num(num) => num.clone(),
+            PrimaryExp::Float(..) => unreachable!("{}", super::NO_FLOAT),
             PrimaryExp::Exp(exp) => {
                 exp.eval(env)
             }
             PrimaryExp::LVal(lval) => {
                 match lval {
-                    LVal::Ident(ident) => {
+                    LVal::Ident(ident, pos) => {
                         if let Some(val) = env.scope.lookup_var(ident) {
                             match val {
                                 VarValue::Const(num) => num,
-                                VarValue::Alloc(..) => panic!("Variable {} is not const", ident),
-                                VarValue::Func(_) => panic!("Variable {} is a function", ident),
+                                VarValue::Alloc(..) => panic!("{}: Variable {} is not const", pos, ident),
+                                VarValue::Func(_) => panic!("{}: Variable {} is a function", pos, ident),
                             }
                         }
                         else {
-                            panic!("Variable {} not found in this scope", ident)
+                            panic!("{}: Variable {} not found in this scope", pos, ident)
                         }   
                     }
-                    LVal::Array(..) => panic!("Array does not occur in the constexpr"),
+                    LVal::Array(ident, indices, pos) => {
+                        // an element of a const array, at constant indices
+                        let Some(VarValue::Alloc(Some(addr), Some(dims), _)) = env.scope.lookup_var(ident) else {
+                            panic!("{}: Variable {} is not an array", pos, ident)
+                        };
+                        if indices.len() != dims.len() || !env.const_arrays.contains_key(&addr) {
+                            panic!("{}: {} is not a const array indexed in every dimension", pos, ident)
+                        }
+                        let mut offset = 0;
+                        for (index, &dim) in indices.iter().zip(&dims) {
+                            let index = index.eval(env);
+                            if index < 0 || index >= dim {
+                                panic!("{}: Index {} out of bounds for {}", pos, index, ident);
+                            }
+                            offset = offset * dim + index;
+                        }
+                        env.const_arrays[&addr][offset as usize]
+                    }
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    fn try_eval(&self, env: &mut Env) -> Option<i32> {
+        match self {
+            PrimaryExp::Num(num) => Some(*num),
+            PrimaryExp::Float(..) => None,
+            PrimaryExp::Exp(exp) => exp.try_eval(env),
+            PrimaryExp::LVal(LVal::Ident(ident, _)) => env.scope.is_const(ident),
+            PrimaryExp::LVal(LVal::Array(ident, indices, _)) => const_element(env, ident, indices),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{build_ir, ir_to_string};
+
+    #[test]
+    fn test_const_array_reads() {
+        // constant subscripts fold to the element, in array sizes, const initializers and expressions
+        let source = "int main() { const int A[3] = {4, 5, 6}; const int B[A[0] - 2] = {A[1] * 2}; return A[1] + B[0] + A[2 - 1]; }";
+        let program = build_ir(crate::parse(source).unwrap()).unwrap();
+        let ir = ir_to_string(&program);
+        assert!(!ir.contains("load"), "{}", ir);
+        assert!(ir.contains("[i32, 2]"), "{}", ir);
+
+        // a variable subscript still reads memory
+        let source = "const int A[4] = {1, 2, 3, 5}; int main() { int i = getint(); return A[i] + A[3]; }";
+        let program = build_ir(crate::parse(source).unwrap()).unwrap();
+        let asm = crate::codegen::build_asm(&program, crate::codegen::Target::default());
+        let (code, _) = crate::runner::build_and_run("const_array", &asm, Some("1"), &crate::runner::test_dir()).unwrap();
+        assert_eq!(code, 7);
+    }
+}
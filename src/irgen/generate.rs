@@ -11,14 +11,12 @@ use crate::irgen::array::global_var_init;
 use crate::irgen::array::local_const_init;
 use crate::irgen::array::local_var_init;
 use super::env::Env;
-use super::eval::EvalExp;
+use super::eval::{const_element, EvalExp};
 use super::opt;
 use super::builtin;
 use super::array;
 use super::ast_df::*;
-
-use rand::Rng;
-use rand::distributions::Alphanumeric;
+use super::NO_FLOAT;
 
 pub enum ControlFlow {
     Break,
@@ -70,11 +68,15 @@ impl FuncDef {
         let ret_type = match self.func_type {
             BType::Int  => Type::get_i32(),
             BType::Void => Type::get_unit(),
+            BType::Float => unreachable!("{}", NO_FLOAT),
         };
 
         let func_name = format!("{}{}", "@", self.ident);
         
         let params = self.params.iter().map(|param| {
+            if let BType::Float = param.ty {
+                unreachable!("{}", NO_FLOAT);
+            }
             let param_name = format!("{}{}", "@", param.id);
             let dims = match &param.dims {
                 None => None,
@@ -117,6 +119,7 @@ impl GenerateIR for FuncDef {
         let (params, func) = self.new_func(env);
                
         env.ctx.func = Some(func);
+        env.ctx.line = self.pos.line;
         env.scope.insert_func(&self.ident, func);
         let block = env.ctx.create_block(Some("entry".to_string()));
         env.ctx.block = Some(block);
@@ -165,7 +168,7 @@ impl GenerateIR for FuncCall {
 
     fn generate(&self, env: &mut Env) -> Value {
         let func_name = &self.id;
-        let func = env.scope.lookup_func(func_name).expect("Function not found");
+        let func = env.scope.lookup_func(func_name).unwrap_or_else(|| panic!("{}: Function {} not found", self.pos, func_name));
         let params = self.args.iter().map(|param| {
             let param_val = param.generate(env);
             param_val
@@ -203,7 +206,9 @@ impl GenerateIR for BlockItem {
     type RetType = CFType;
 
     fn generate(&self, env: &mut Env) -> CFType {
-        
+        if let Some(pos) = self.pos() {
+            env.ctx.line = pos.line;
+        }
         match self {
             BlockItem::Decl(decl) => {
                 decl.generate(env);
@@ -251,12 +256,12 @@ impl GenerateIR for Stmt {
                 let _ = while_stmt.generate(env);
                 Ok(())
             }
-            Stmt::Break => {
+            Stmt::Break(_) => {
                 let (_, exit) = env.loopstack.top();
                 env.jump_inst(exit);
                 Err(ControlFlow::Break)
             }
-            Stmt::Continue => {
+            Stmt::Continue(_) => {
                 let (entry, _) = env.loopstack.top();
                 env.jump_inst(entry);
                 Err(ControlFlow::Continue)
@@ -269,7 +274,7 @@ impl GenerateIR for Stmt {
 impl GenerateIR for While {
     type RetType = CFType;
     fn generate(&self, env: &mut Env) -> CFType {
-        let While { cond, stmt } = self;
+        let While { cond, stmt, .. } = self;
         let cond_block = env.ctx.create_block(Some("cond".to_string()));
         let body_block = env.ctx.create_block(Some("body".to_string()));
         let end_block = env.ctx.create_block(Some("end".to_string()));
@@ -306,7 +311,7 @@ impl GenerateIR for If {
     type RetType = CFType;
 
     fn generate(&self, env: &mut Env) -> CFType {
-        let If { cond, stmt, else_stmt } = self;
+        let If { cond, stmt, else_stmt, .. } = self;
         let cond_block = env.ctx.create_block(Some("cond".to_string()));
         let then_block = env.ctx.create_block(Some("then".to_string()));
         let else_block = env.ctx.create_block(Some("else".to_string()));
@@ -354,38 +359,27 @@ impl GenerateIR for If {
     }
 }
 
+// fail with the source position here rather than deep inside Env
+fn require_var(env: &Env, lval: &LVal) {
+    let (LVal::Ident(ident, pos) | LVal::Array(ident, _, pos)) = lval;
+    if env.scope.lookup_var(ident).is_none() {
+        panic!("{}: Var {} not found", pos, ident);
+    }
+}
+
 impl GenerateIR for Assign {
     type RetType = ();
 
     fn generate(&self, env: &mut Env) {
-        let Assign { lval, exp } = self;
+        let Assign { lval, exp, .. } = self;
+        require_var(env, lval);
         match lval {
-            LVal::Ident(ident) => {
+            LVal::Ident(ident, _) => {
                 let value = exp.generate(env);
                 env.store_var(&ident, value);
             },
-            LVal::Array(ident, dims,) => {
-                // Very important
-                let is_pointer = env.scope.lookup_is_pointer(ident).unwrap();
-                let mut addr = match is_pointer {
-                    true => env.load_var(ident),
-                    false => env.scope.lookup_var_addr(ident).unwrap(),
-                };
-
-                let mut iter = dims.iter();
-                if let Some(dim) = iter.next() {
-                    let index = dim.generate(env);
-                    match is_pointer {
-                        true => addr = env.get_ptr_inst(addr, index),
-                        false => addr = env.get_elem_inst(addr, index),
-                    }
-                }
-
-                for dim in iter {
-                    let dim_size = dim.generate(env);
-                    addr = env.get_elem_inst(addr, dim_size);
-                }
-
+            LVal::Array(ident, dims, _) => {
+                let (addr, _) = env.array_addr(ident, dims);
                 let value = exp.generate(env);
                 env.store_val_by_addr(addr, value);
 
@@ -398,7 +392,7 @@ impl GenerateIR for Return {
     type RetType = ();
 
     fn generate(&self, env: &mut Env) {
-        let Return { exp } = self;
+        let Return { exp, .. } = self;
         match exp {
             Some(exp) => {
                 let value = exp.generate(env);
@@ -431,6 +425,9 @@ impl GenerateIR for ConstDecl {
     type RetType = ();
 
     fn generate(&self, env: &mut Env) {
+        if let BType::Float = self.ty {
+            unreachable!("{}", NO_FLOAT);
+        }
         for const_def in &self.const_defs {
             match self.is_global {
                 true => global_const_decl_gen(env, const_def),
@@ -443,7 +440,7 @@ impl GenerateIR for ConstDecl {
 }
 
 pub fn global_const_decl_gen(env: &mut Env, const_def: &ConstDef) {
-    let ConstDef { ident, init_val, dims } = const_def;
+    let ConstDef { ident, init_val, dims, .. } = const_def;
     match dims {
         None => {
             let num = init_val.generate(env);
@@ -453,7 +450,9 @@ pub fn global_const_decl_gen(env: &mut Env, const_def: &ConstDef) {
             let dims_size = dims.iter().map(|dim| dim.eval(env)).collect::<Vec<_>>();
             
             let val = global_const_init(env, &dims_size, init_val);
-            env.alloc_global_array(ident, &dims_size, val); 
+            let inst = env.alloc_global_array(ident, &dims_size, val);
+            let values = array::const_init_values(env, &dims_size, init_val);
+            env.const_arrays.insert(inst, values);
         }
     };
 }
@@ -464,7 +463,7 @@ impl GenerateIR for ConstDef {
     type RetType = ();
 
     fn generate(&self, env: &mut Env) {
-        let ConstDef { ident, init_val, dims } = self;
+        let ConstDef { ident, init_val, dims, .. } = self;
         match dims {
             None => {
                 let num = init_val.generate(env);
@@ -478,6 +477,9 @@ impl GenerateIR for ConstDef {
                 let vec = local_const_init(env, &dims_size, init_val);
 
                 env.alloc_array(ident, &dims_size, Some(vec));
+                let addr = env.scope.lookup_var_addr(ident).expect("Array not allocated");
+                let values = array::const_init_values(env, &dims_size, init_val);
+                env.const_arrays.insert(addr, values);
             }
         }
     }
@@ -489,9 +491,12 @@ impl GenerateIR for ConstInitVal {
     fn generate(&self, env: &mut Env) -> i32 {
         match self {
             ConstInitVal::ConstExp(exp) => exp.eval(env),
-            ConstInitVal::InitList(..) => {
-                panic!("Not support");
-            }
+            // braces around a scalar, `const int c = {1};`; arrays go through array.rs
+            ConstInitVal::InitList(list) => match &list[..] {
+                [] => 0,
+                [init_val] => init_val.generate(env),
+                _ => panic!("Too many initializers for a scalar"),
+            },
         }
     }
 }
@@ -499,6 +504,9 @@ impl GenerateIR for ConstInitVal {
 impl GenerateIR for VarDecl {
     type RetType = ();
     fn generate(&self, env: &mut Env) -> () {
+        if let BType::Float = self.ty {
+            unreachable!("{}", NO_FLOAT);
+        }
         for var_def in &self.defs {
             match self.is_global {
                 true => global_var_decl_gen(env, var_def),
@@ -512,15 +520,13 @@ impl GenerateIR for VarDecl {
 
 // generate global var declaration
 pub fn global_var_decl_gen(env: &mut Env, var_def: &VarDef) {
-    let VarDef { ident, init_val, dims } = var_def;
+    let VarDef { ident, init_val, dims, .. } = var_def;
     match dims {
         None => {
-            let num = match init_val{
-                None => 0,
-                Some(init_val) => match init_val {
-                    InitVal::Exp(exp) => exp.eval(env),
-                    InitVal::InitList(_) => panic!("Global var can't be initialized by list"),
-                }
+            // folded at compile time: literals, const globals and elements of const arrays
+            let num = match init_val.as_ref().map(|init_val| scalar_init(init_val, ident)) {
+                Some(Some(exp)) => exp.eval(env),
+                _ => 0,
             };
             let _ = env.alloc_global_var(ident, num);
         }
@@ -541,11 +547,22 @@ pub fn global_var_decl_gen(env: &mut Env, var_def: &VarDef) {
     };
 }
 
+// `int x = {1};`: braces (possibly nested) around a scalar initializer; `{}` means 0
+fn scalar_init<'a>(init_val: &'a InitVal, ident: &str) -> Option<&'a Exp> {
+    match init_val {
+        InitVal::Exp(exp) => Some(exp),
+        InitVal::InitList(list) => match &list[..] {
+            [] => None,
+            [init_val] => scalar_init(init_val, ident),
+            _ => panic!("Too many initializers for scalar {}", ident),
+        },
+    }
+}
 
 impl GenerateIR for VarDef {
     type RetType = ();
     fn generate(&self, env: &mut Env) -> () {
-        let VarDef { ident, init_val, dims } = self;
+        let VarDef { ident, init_val, dims, .. } = self;
         match dims {
             None => {
                 match init_val {
@@ -585,7 +602,12 @@ impl GenerateIR for InitVal {
     fn generate(&self, env: &mut Env) -> Value {
         match self {
             InitVal::Exp(exp) => exp.generate(env),
-            InitVal::InitList(init_list) => todo!(),
+            // braces around a scalar, `int x = {1};`; arrays go through array.rs
+            InitVal::InitList(list) => match &list[..] {
+                [] => env.ctx.create_int_inst(0),
+                [init_val] => init_val.generate(env),
+                _ => panic!("Too many initializers for a scalar"),
+            },
         }
     }
 }
@@ -610,35 +632,31 @@ impl GenerateIR for LOrExp {
         match self {
             LOrExp::LAnd(l_and_exp) => l_and_exp.generate(env),
             LOrExp::LOrLAnd(l_or_exp, l_and_exp) => {
-
+                // short circuit: the result reaches the join block as its argument
+                //   br (lhs != 0), %true, %rhs
+                //   %true: jump %end(1)
+                //   %rhs:  jump %end(rhs != 0)
                 let zero = env.ctx.create_int_inst(0);
                 let one = env.ctx.create_int_inst(1);
-                // ShortCircuit: SimleImpl
-                
-                let cond_bb = env.ctx.create_block(Some("cond".to_string()));
-                let then_bb = env.ctx.create_block(Some("then".to_string()));
-                let end_bb = env.ctx.create_block(Some("end".to_string()));
-
-                env.jump_inst(cond_bb);
-                env.ctx.block = Some(cond_bb);
 
-                // tmporarily store the result of l_or_exp
-                let tmp_id = format!("tmpvar_{}_{}", env.ctx.block_count, generate_random_string(10));
-                let tmp_var = env.alloc_var(&tmp_id);
-                env.store_var(&tmp_id, one);
+                let true_bb = env.ctx.create_block(Some("true".to_string()));
+                let rhs_bb = env.ctx.create_block(Some("rhs".to_string()));
+                let end_bb = env.ctx.create_block_with_params(Some("end".to_string()), vec![Type::get_i32()]);
 
                 let mut l_or_val = l_or_exp.generate(env);
                 l_or_val = env.ctx.insert_bi_inst(BinaryOp::NotEq, l_or_val, zero);
-                env.branch_inst(l_or_val, end_bb, then_bb);
+                env.branch_inst(l_or_val, true_bb, rhs_bb);
 
-                env.ctx.block = Some(then_bb);
+                env.ctx.block = Some(true_bb);
+                env.jump_with_args_inst(end_bb, vec![one]);
+
+                env.ctx.block = Some(rhs_bb);
                 let mut l_and_val = l_and_exp.generate(env);
                 l_and_val = env.ctx.insert_bi_inst(BinaryOp::NotEq, l_and_val, zero);
-                env.store_var(&tmp_id, l_and_val);
-                env.jump_inst(end_bb);
+                env.jump_with_args_inst(end_bb, vec![l_and_val]);
 
                 env.ctx.block = Some(end_bb);
-                env.load_var(&tmp_id)
+                env.block_param(end_bb, 0)
             },
         }
     }
@@ -651,43 +669,27 @@ impl GenerateIR for LAndExp {
         match self {
             LAndExp::Eq(eq_exp) => eq_exp.generate(env),
             LAndExp::LAndEq(l_and_exp, eq_exp) => {
-                // let zero = env.ctx.create_int_inst(0);
-                // let one = env.ctx.create_int_inst(1);
-
-                // let mut l_and_val = l_and_exp.generate(env);
-                // l_and_val = env.ctx.insert_bi_inst(BinaryOp::NotEq, l_and_val, zero);
-
-                // let mut eq_val = eq_exp.generate(env);
-                // eq_val = env.ctx.insert_bi_inst(BinaryOp::NotEq, eq_val, zero);
-
-                // env.ctx.insert_bi_inst(BinaryOp::And, l_and_val, eq_val)
-
+                // same as ||, with the false side jumping to the join block with 0
                 let zero = env.ctx.create_int_inst(0);
-                
-                let cond_bb = env.ctx.create_block(Some("cond".to_string()));
-                let then_bb = env.ctx.create_block(Some("then".to_string()));
-                let end_bb = env.ctx.create_block(Some("end".to_string()));
 
-                env.jump_inst(cond_bb);
-                env.ctx.block = Some(cond_bb);
-
-                // tmporarily store the result of l_or_exp
-                let tmp_id = format!("tmpvar_{}_{}", env.ctx.block_count, generate_random_string(10));
-                let tmp_var = env.alloc_var(&tmp_id);
-                env.store_var(&tmp_id, zero);
+                let rhs_bb = env.ctx.create_block(Some("rhs".to_string()));
+                let false_bb = env.ctx.create_block(Some("false".to_string()));
+                let end_bb = env.ctx.create_block_with_params(Some("end".to_string()), vec![Type::get_i32()]);
 
                 let mut l_and_val = l_and_exp.generate(env);
                 l_and_val = env.ctx.insert_bi_inst(BinaryOp::NotEq, l_and_val, zero);
-                env.branch_inst(l_and_val, then_bb, end_bb);
+                env.branch_inst(l_and_val, rhs_bb, false_bb);
+
+                env.ctx.block = Some(false_bb);
+                env.jump_with_args_inst(end_bb, vec![zero]);
 
-                env.ctx.block = Some(then_bb);
+                env.ctx.block = Some(rhs_bb);
                 let mut eq_val = eq_exp.generate(env);
                 eq_val = env.ctx.insert_bi_inst(BinaryOp::NotEq, eq_val, zero);
-                env.store_var(&tmp_id, eq_val);
-                env.jump_inst(end_bb);
+                env.jump_with_args_inst(end_bb, vec![eq_val]);
 
                 env.ctx.block = Some(end_bb);
-                env.load_var(&tmp_id)
+                env.block_param(end_bb, 0)
             },
         }
     }
@@ -801,84 +803,32 @@ impl GenerateIR for PrimaryExp {
             PrimaryExp::Num(num) => {
                 env.ctx.create_int_inst(num.clone())
             },
+            PrimaryExp::Float(..) => unreachable!("{}", NO_FLOAT),
             PrimaryExp::Exp(exp) => {
                 exp.generate(env)
             },
             PrimaryExp::LVal(lval) => {
+                require_var(env, lval);
                 match lval {
-                    LVal::Ident(ident) => {
+                    LVal::Ident(ident, _) => {
                         let const_val = env.scope.is_const(&ident);
                         match const_val {
                             Some(num) => env.ctx.create_int_inst(num),
                             None => {
                                 // currently, load var from symbol table everytime
 
-                                let is_array = env.scope.is_array(ident);
-                                match is_array {
-                                    true => {
-                                        let is_pointer = env.scope.lookup_is_pointer(ident).unwrap();
-                                        let zero = env.ctx.create_int_inst(0);
-                                        let mut addr = match is_pointer {
-                                            true => env.load_var(ident),
-                                            false => {
-                                                let addr = env.scope.lookup_var_addr(ident).unwrap();
-                                                env.get_elem_inst(addr, zero)
-                                            }
-                                        };
-                                        addr
-                                    }
-                                    false => {
-                                        env.load_var(&ident)
-                                    }
+                                match env.scope.lookup_array(ident) {
+                                    Some(_) => env.array_value(ident, &[]),
+                                    None => env.load_var(&ident),
                                 }
                             }
                         }
                     },
-                    LVal::Array(ident, dims) => {
-                        let is_pointer = env.scope.lookup_is_pointer(ident).unwrap();
-                        let array_size = env.scope.lookup_dim_size(ident).unwrap();
-                        
-                        match is_pointer {
-                            true => {
-                                let mut addr = env.load_var(ident);
-                                let mut iter = dims.iter();
-                                if let Some(dim) = iter.next() {
-                                    let index = dim.generate(env);
-                                    addr = env.get_ptr_inst(addr, index);
-                                }
-
-                                for dim in iter {
-                                    let index = dim.generate(env);
-                                    addr = env.get_elem_inst(addr, index);
-                                }
-                                
-                                if array_size + 1 == dims.len() { // Value
-                                    env.load_val_by_addr(addr)
-                                }
-                                else { //Pointer
-                                    let zero = env.ctx.create_int_inst(0);
-                                    addr = env.get_elem_inst(addr, zero);
-                                    addr
-                                }
-                            },
-                            false => {
-                                let mut addr = env.scope.lookup_var_addr(ident).unwrap();
-                                for dim in dims.iter() {
-                                    let index = dim.generate(env);
-                                    addr = env.get_elem_inst(addr, index);
-                                }
-
-                                if array_size == dims.len() {
-                                    env.load_val_by_addr(addr)
-                                }
-                                else {
-                                    let zero = env.ctx.create_int_inst(0);
-                                    addr = env.get_elem_inst(addr, zero);
-                                    addr
-                                }
-                            },
-                        }
-                    }
+                    // an element of a const array at constant indices is just a number
+                    LVal::Array(ident, dims, _) => match const_element(env, ident, dims) {
+                        Some(num) => env.ctx.create_int_inst(num),
+                        None => env.array_value(ident, dims),
+                    },
                 }
 
             }
@@ -886,9 +836,3 @@ impl GenerateIR for PrimaryExp {
     }
 }
 
-
-pub fn generate_random_string(length: usize) -> String {
-    let rng = rand::thread_rng();
-    String::from_utf8_lossy(&rng.sample_iter(&Alphanumeric).take(length).collect::<Vec<u8>>()).to_string()
-}
-
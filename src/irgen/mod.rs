@@ -7,7 +7,8 @@ mod env;
 mod scope;
 mod eval;
 mod loopstack;
-mod opt;
+pub mod opt;
+pub mod verify;
 mod builtin;
 mod array;
 pub mod ast_df;
@@ -21,18 +22,36 @@ use std::fs::File;
 
 use ast_df::*;
 
+// The compiler does not lower float. Koopa IR has no floating-point type, and carrying floats as
+// i32 bit patterns would need typed expressions throughout irgen plus F instructions in both
+// backends, which this code does not have. Only the parser, the built-in assembler and the
+// simulator (RV64F) know about float; sema reports every use with this message, and irgen, which
+// only sees programs sema accepted, treats float as unreachable.
+pub(crate) const NO_FLOAT: &str = "float is not supported: the compiler does not generate code for floating-point values";
+
+// source line of each generated instruction; instructions added later by the optimizer have none
+pub type LineMap = std::collections::HashMap<Value, usize>;
+
 pub fn build_ir(ast: CompUnit) -> Option<Program> {
+    build_ir_with_lines(ast).map(|(program, _)| program)
+}
+
+pub fn build_ir_with_lines(ast: CompUnit) -> Option<(Program, LineMap)> {
     let mut env = env::Env::default();
     let _ = ast.generate(&mut env);
-    Some(env.ctx.program)
+    Some((env.ctx.program, env.ctx.lines))
+}
+
+// Koopa IR in text form
+pub fn ir_to_string(program: &Program) -> String {
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(program).unwrap();
+    std::str::from_utf8(&gen.writer()).unwrap().to_string()
 }
 
 pub fn emit_ir(program: Program, output: String) {
     // convert to text form
-    let mut gen = KoopaGenerator::new(Vec::new());
-    gen.generate_on(&program).unwrap();
-    
-    let text_form_ir = std::str::from_utf8(&gen.writer()).unwrap().to_string();
+    let text_form_ir = ir_to_string(&program);
     println!("{}", text_form_ir);
 
     let mut file =  File::create(output).expect("Create file failed");
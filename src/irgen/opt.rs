@@ -1,10 +1,13 @@
-use koopa::ir::ValueKind;
+use std::collections::{HashMap, HashSet};
+
+use koopa::ir::builder::{LocalInstBuilder, ValueBuilder};
+use koopa::ir::{BasicBlock, BinaryOp, FunctionData, Program, Type, TypeKind, Value, ValueKind};
 
 use super::ast_df::*;
 
 use super::env::Env;
 
-pub fn check_ir(env: &mut Env, ret_type: BType) {
+pub(crate) fn check_ir(env: &mut Env, ret_type: BType) {
     // check the last inst of the blocks           
     let func = env.ctx.func.expect("No function in context");
     let func_data = env.ctx.program.func_mut(func);
@@ -37,6 +40,705 @@ pub fn check_ir(env: &mut Env, ret_type: BType) {
             BType::Void => {
                 env.ret_void_inst();
             },
+            BType::Float => unreachable!("{}", super::NO_FLOAT),
         }
     });
-}
\ No newline at end of file
+
+    let func_data = env.ctx.program.func_mut(func);
+    simplify_cfg(func_data);
+}
+
+// the control flow graph as generated has blocks nothing jumps to (the end block of an if whose
+// branches all return, code after break), blocks that only jump on (the end of a nested if) and
+// chains of blocks joined by plain jumps; drop the first, bypass the second and merge the third
+fn simplify_cfg(func_data: &mut FunctionData) {
+    remove_unreachable(func_data);
+    while skip_forwarders(func_data) | merge_blocks(func_data) {}
+    remove_unreachable(func_data);
+}
+
+fn predecessors(func_data: &FunctionData) -> HashMap<BasicBlock, Vec<BasicBlock>> {
+    let mut preds = HashMap::<_, Vec<_>>::new();
+    for &bb in func_data.layout().bbs().keys() {
+        for succ in successors(func_data, bb) {
+            preds.entry(succ).or_default().push(bb);
+        }
+    }
+    preds
+}
+
+// the target of a block other than the entry that is nothing but `jump target`, without arguments
+fn forward_target(func_data: &FunctionData, bb: BasicBlock) -> Option<BasicBlock> {
+    let insts = func_data.layout().bbs().node(&bb)?.insts();
+    if Some(bb) == func_data.layout().entry_bb() || insts.len() != 1 || !func_data.dfg().bb(bb).params().is_empty() {
+        return None;
+    }
+    match func_data.dfg().value(*insts.back_key()?).kind() {
+        ValueKind::Jump(jump) if jump.args().is_empty() && jump.target() != bb => Some(jump.target()),
+        _ => None,
+    }
+}
+
+// point the jumps and branches into one such block at its target instead;
+// a chain of them is shortened from the far end, and a loop of them is left alone
+fn skip_forwarders(func_data: &mut FunctionData) -> bool {
+    let preds = predecessors(func_data);
+    let forwarder = func_data.layout().bbs().keys().copied().find_map(|bb| {
+        let target = forward_target(func_data, bb)?;
+        (forward_target(func_data, target).is_none() && preds.contains_key(&bb)).then_some((bb, target))
+    });
+    let Some((bb, target)) = forwarder else {
+        return false;
+    };
+    let sub = |b: BasicBlock| if b == bb { target } else { b };
+    for &pred in &preds[&bb] {
+        let last = terminator(func_data, pred).unwrap();
+        let kind = func_data.dfg().value(last).kind().clone();
+        let builder = func_data.dfg_mut().replace_value_with(last);
+        let _ = match kind {
+            ValueKind::Jump(jump) => builder.jump_with_args(sub(jump.target()), jump.args().to_vec()),
+            ValueKind::Branch(br) => builder.branch_with_args(
+                br.cond(), sub(br.true_bb()), sub(br.false_bb()), br.true_args().to_vec(), br.false_args().to_vec(),
+            ),
+            _ => unreachable!("Predecessor without a jump or branch"),
+        };
+    }
+    remove_unreachable(func_data);
+    true
+}
+
+// append the single successor of a block ending in a plain jump, if this block is its only predecessor
+fn merge_blocks(func_data: &mut FunctionData) -> bool {
+    let preds = predecessors(func_data);
+    let entry = func_data.layout().entry_bb();
+    let pair = func_data.layout().bbs().keys().copied().find_map(|bb| {
+        let last = terminator(func_data, bb)?;
+        let ValueKind::Jump(jump) = func_data.dfg().value(last).kind() else {
+            return None;
+        };
+        let next = jump.target();
+        let single = preds.get(&next).is_some_and(|p| p.len() == 1);
+        (next != bb && Some(next) != entry && single && jump.args().is_empty()).then_some((bb, last, next))
+    });
+    let Some((bb, jump, next)) = pair else {
+        return false;
+    };
+    remove_inst(func_data, bb, jump);
+    let moved = func_data.layout().bbs().node(&next).unwrap().insts().keys().copied().collect::<Vec<_>>();
+    for inst in moved {
+        func_data.layout_mut().bb_mut(next).insts_mut().remove(&inst);
+        let _ = func_data.layout_mut().bb_mut(bb).insts_mut().push_key_back(inst);
+    }
+    func_data.layout_mut().bbs_mut().remove(&next);
+    true
+}
+
+// -O1: constant folding and propagation, repeated until nothing changes
+//   binary ops on two integer constants become the constant;
+//   loads from a local int that is stored at most once, with a constant, become that constant
+//   (0 if it is never written: reading a variable before it is written is undefined in SysY);
+//   branches on a constant become jumps, and blocks that can no longer be reached are dropped
+pub fn const_fold(program: &mut Program) {
+    for func in program.func_layout().to_vec() {
+        let func_data = program.func_mut(func);
+        if func_data.layout().entry_bb().is_none() {
+            continue; // library declaration
+        }
+        while fold_binaries(func_data) | fold_loads(func_data) | fold_branches(func_data) {}
+    }
+}
+
+// (block, instruction) pairs in layout order
+fn insts(func_data: &FunctionData) -> Vec<(BasicBlock, Value)> {
+    func_data.layout().bbs().iter()
+        .flat_map(|(&bb, node)| node.insts().keys().map(move |&inst| (bb, inst)))
+        .collect()
+}
+
+// globals are not in the function's dfg
+fn int_value(func_data: &FunctionData, val: Value) -> Option<i32> {
+    match func_data.dfg().values().get(&val)?.kind() {
+        ValueKind::Integer(int) => Some(int.value()),
+        _ => None,
+    }
+}
+
+// None for division by zero, which is left to happen at runtime
+pub(super) fn eval_binary(op: BinaryOp, lhs: i32, rhs: i32) -> Option<i32> {
+    let num = match op {
+        BinaryOp::NotEq => (lhs != rhs) as i32,
+        BinaryOp::Eq => (lhs == rhs) as i32,
+        BinaryOp::Gt => (lhs > rhs) as i32,
+        BinaryOp::Lt => (lhs < rhs) as i32,
+        BinaryOp::Ge => (lhs >= rhs) as i32,
+        BinaryOp::Le => (lhs <= rhs) as i32,
+        BinaryOp::Add => lhs.wrapping_add(rhs),
+        BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        BinaryOp::Mul => lhs.wrapping_mul(rhs),
+        BinaryOp::Div if rhs == 0 => return None,
+        BinaryOp::Div => lhs.wrapping_div(rhs),
+        BinaryOp::Mod if rhs == 0 => return None,
+        BinaryOp::Mod => lhs.wrapping_rem(rhs),
+        BinaryOp::And => lhs & rhs,
+        BinaryOp::Or => lhs | rhs,
+        BinaryOp::Xor => lhs ^ rhs,
+        BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+        BinaryOp::Shr => (lhs as u32).wrapping_shr(rhs as u32) as i32,
+        BinaryOp::Sar => lhs.wrapping_shr(rhs as u32),
+    };
+    Some(num)
+}
+
+// rewrite every user of `from` to use `to` instead
+fn replace_uses(func_data: &mut FunctionData, from: Value, to: Value) {
+    let users = func_data.dfg().value(from).used_by().iter().copied().collect::<Vec<_>>();
+    let sub = |val: Value| if val == from { to } else { val };
+    for user in users {
+        let kind = func_data.dfg().value(user).kind().clone();
+        let builder = func_data.dfg_mut().replace_value_with(user);
+        let _ = match kind {
+            ValueKind::Binary(bin) => builder.binary(bin.op(), sub(bin.lhs()), sub(bin.rhs())),
+            ValueKind::Store(store) => builder.store(sub(store.value()), sub(store.dest())),
+            ValueKind::Load(load) => builder.load(sub(load.src())),
+            ValueKind::GetPtr(ptr) => builder.get_ptr(sub(ptr.src()), sub(ptr.index())),
+            ValueKind::GetElemPtr(ptr) => builder.get_elem_ptr(sub(ptr.src()), sub(ptr.index())),
+            ValueKind::Branch(br) => builder.branch_with_args(
+                sub(br.cond()), br.true_bb(), br.false_bb(),
+                br.true_args().iter().map(|&arg| sub(arg)).collect(),
+                br.false_args().iter().map(|&arg| sub(arg)).collect(),
+            ),
+            ValueKind::Jump(jump) => builder.jump_with_args(jump.target(), jump.args().iter().map(|&arg| sub(arg)).collect()),
+            ValueKind::Call(call) => builder.call(call.callee(), call.args().iter().map(|&arg| sub(arg)).collect()),
+            ValueKind::Return(ret) => builder.ret(ret.value().map(sub)),
+            _ => unreachable!("Unexpected user of a folded value"),
+        };
+    }
+}
+
+fn remove_inst(func_data: &mut FunctionData, bb: BasicBlock, inst: Value) {
+    func_data.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+    func_data.dfg_mut().remove_value(inst);
+}
+
+fn fold_binaries(func_data: &mut FunctionData) -> bool {
+    let mut changed = false;
+    for (bb, inst) in insts(func_data) {
+        let folded = match func_data.dfg().value(inst).kind() {
+            ValueKind::Binary(bin) => match (int_value(func_data, bin.lhs()), int_value(func_data, bin.rhs())) {
+                (Some(lhs), Some(rhs)) => eval_binary(bin.op(), lhs, rhs),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(num) = folded {
+            let int = func_data.dfg_mut().new_value().integer(num);
+            replace_uses(func_data, inst, int);
+            remove_inst(func_data, bb, inst);
+            changed = true;
+        }
+    }
+    changed
+}
+
+// alloc i32, the only kind of variable that can be folded or promoted
+fn scalar_alloc(func_data: &FunctionData, inst: Value) -> bool {
+    let data = func_data.dfg().value(inst);
+    matches!(data.kind(), ValueKind::Alloc(_))
+        && matches!(data.ty().kind(), TypeKind::Pointer(base) if *base == Type::get_i32())
+}
+
+fn fold_loads(func_data: &mut FunctionData) -> bool {
+    let insts = insts(func_data);
+    let parent = insts.iter().map(|&(bb, inst)| (inst, bb)).collect::<HashMap<_, _>>();
+    let allocs = insts.iter().copied().filter(|&(_, inst)| scalar_alloc(func_data, inst)).collect::<Vec<_>>();
+    let mut changed = false;
+
+    for (bb, alloc) in allocs {
+        let data = func_data.dfg().value(alloc);
+        // only loads and stores of the variable itself, all of them still in the layout
+        let (mut loads, mut stores) = (Vec::new(), Vec::new());
+        let mut foldable = true;
+        for &user in data.used_by() {
+            match func_data.dfg().value(user).kind() {
+                _ if !parent.contains_key(&user) => foldable = false,
+                ValueKind::Load(_) => loads.push(user),
+                ValueKind::Store(store) if store.dest() == alloc => stores.push((user, store.value())),
+                _ => foldable = false,
+            }
+        }
+        let num = match stores[..] {
+            [] => Some(0),
+            [(_, val)] => int_value(func_data, val),
+            _ => None,
+        };
+        let Some(num) = num.filter(|_| foldable) else { continue };
+
+        for load in loads {
+            let int = func_data.dfg_mut().new_value().integer(num);
+            replace_uses(func_data, load, int);
+            remove_inst(func_data, parent[&load], load);
+        }
+        for (store, _) in stores {
+            remove_inst(func_data, parent[&store], store);
+        }
+        remove_inst(func_data, bb, alloc);
+        changed = true;
+    }
+    changed
+}
+
+fn fold_branches(func_data: &mut FunctionData) -> bool {
+    let mut changed = false;
+    for (_, inst) in insts(func_data) {
+        let target = match func_data.dfg().value(inst).kind() {
+            ValueKind::Branch(br) => match int_value(func_data, br.cond()) {
+                Some(0) => Some((br.false_bb(), br.false_args().to_vec())),
+                Some(_) => Some((br.true_bb(), br.true_args().to_vec())),
+                None => None,
+            },
+            _ => None,
+        };
+        if let Some((target, args)) = target {
+            let _ = func_data.dfg_mut().replace_value_with(inst).jump_with_args(target, args);
+            changed = true;
+        }
+    }
+    remove_unreachable(func_data) || changed
+}
+
+fn terminator(func_data: &FunctionData, bb: BasicBlock) -> Option<Value> {
+    func_data.layout().bbs().node(&bb).unwrap().insts().back_key().copied()
+}
+
+fn successors(func_data: &FunctionData, bb: BasicBlock) -> Vec<BasicBlock> {
+    match terminator(func_data, bb).map(|inst| func_data.dfg().value(inst).kind()) {
+        Some(ValueKind::Jump(jump)) => vec![jump.target()],
+        Some(ValueKind::Branch(br)) => vec![br.true_bb(), br.false_bb()],
+        _ => vec![],
+    }
+}
+
+// drop blocks the entry cannot reach, and then whatever in them is no longer used
+fn remove_unreachable(func_data: &mut FunctionData) -> bool {
+    let entry = func_data.layout().entry_bb().unwrap();
+    let mut reached = HashSet::from([entry]);
+    let mut work = vec![entry];
+    while let Some(bb) = work.pop() {
+        for target in successors(func_data, bb) {
+            if reached.insert(target) {
+                work.push(target);
+            }
+        }
+    }
+
+    let dead = func_data.layout().bbs().keys().filter(|bb| !reached.contains(bb)).copied().collect::<Vec<_>>();
+    let mut dead_insts = Vec::new();
+    for bb in &dead {
+        dead_insts.extend(func_data.layout().bbs().node(bb).unwrap().insts().keys().copied());
+        func_data.layout_mut().bbs_mut().remove(bb);
+    }
+    drop_values(func_data, dead_insts);
+    !dead.is_empty()
+}
+
+// delete values already taken out of the layout, users first;
+// a value still used from elsewhere stays in the dfg
+fn drop_values(func_data: &mut FunctionData, mut values: Vec<Value>) {
+    while let Some(i) = values.iter().position(|&val| func_data.dfg().value(val).used_by().is_empty()) {
+        func_data.dfg_mut().remove_value(values.swap_remove(i));
+    }
+}
+
+// -O1 cleanup after folding: whatever follows the first return / jump / branch of a block,
+// blocks the entry cannot reach, values nobody uses, and variables that are only written
+pub fn eliminate_dead_code(program: &mut Program) {
+    for func in program.func_layout().to_vec() {
+        let func_data = program.func_mut(func);
+        if func_data.layout().entry_bb().is_none() {
+            continue; // library declaration
+        }
+        strip_after_terminators(func_data);
+        remove_unreachable(func_data);
+        while remove_unused(func_data) {}
+    }
+}
+
+fn strip_after_terminators(func_data: &mut FunctionData) {
+    for bb in func_data.layout().bbs().keys().copied().collect::<Vec<_>>() {
+        let bb_insts = func_data.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect::<Vec<_>>();
+        let end = bb_insts.iter().position(|&inst| {
+            matches!(func_data.dfg().value(inst).kind(), ValueKind::Return(_) | ValueKind::Jump(_) | ValueKind::Branch(_))
+        });
+        if let Some(end) = end {
+            let tail = bb_insts[end + 1..].to_vec();
+            for inst in &tail {
+                func_data.layout_mut().bb_mut(bb).insts_mut().remove(inst);
+            }
+            drop_values(func_data, tail);
+        }
+    }
+}
+
+// a local that is stored to but never read, directly or through element pointers:
+// the stores and pointers to remove with it, users first
+fn written_only(func_data: &FunctionData, alloc: Value, parent: &HashMap<Value, BasicBlock>) -> Option<Vec<Value>> {
+    if !matches!(func_data.dfg().value(alloc).kind(), ValueKind::Alloc(_)) {
+        return None;
+    }
+    let (mut stores, mut ptrs) = (Vec::new(), vec![alloc]);
+    let mut i = 0;
+    while let Some(&ptr) = ptrs.get(i) {
+        for &user in func_data.dfg().value(ptr).used_by() {
+            if !parent.contains_key(&user) {
+                return None;
+            }
+            match func_data.dfg().value(user).kind() {
+                ValueKind::Store(store) if store.dest() == ptr && store.value() != ptr => stores.push(user),
+                ValueKind::GetElemPtr(gep) if gep.src() == ptr => ptrs.push(user),
+                ValueKind::GetPtr(gp) if gp.src() == ptr => ptrs.push(user),
+                _ => return None,
+            }
+        }
+        i += 1;
+    }
+    stores.extend(ptrs.drain(1..).rev());
+    Some(stores)
+}
+
+fn remove_unused(func_data: &mut FunctionData) -> bool {
+    let insts = insts(func_data);
+    let parent = insts.iter().map(|&(bb, inst)| (inst, bb)).collect::<HashMap<_, _>>();
+    let mut changed = false;
+    for (bb, inst) in insts {
+        let Some(data) = func_data.dfg().values().get(&inst) else {
+            continue; // removed along with its variable
+        };
+        // calls and stores stay even when nothing uses them
+        let pure = matches!(data.kind(), ValueKind::Integer(_) | ValueKind::Binary(_) | ValueKind::Load(_)
+            | ValueKind::GetPtr(_) | ValueKind::GetElemPtr(_) | ValueKind::Alloc(_));
+        if pure && data.used_by().is_empty() {
+            remove_inst(func_data, bb, inst);
+            changed = true;
+            continue;
+        }
+        if let Some(users) = written_only(func_data, inst, &parent) {
+            for user in users {
+                remove_inst(func_data, parent[&user], user);
+            }
+            remove_inst(func_data, bb, inst);
+            changed = true;
+        }
+    }
+    changed
+}
+
+// -O2: promote local ints to SSA values (mem2reg). A variable that is only loaded and stored
+// gets a block parameter wherever different definitions meet (the iterated dominance frontier of
+// its stores), and every load is replaced by the value that reaches it. Blocks are rebuilt with
+// the parameters they need, and a branch edge into a block with parameters gets a block of its
+// own holding the jump, so only jumps carry arguments.
+pub fn mem2reg(program: &mut Program) {
+    for func in program.func_layout().to_vec() {
+        let func_data = program.func_mut(func);
+        if func_data.layout().entry_bb().is_none() {
+            continue; // library declaration
+        }
+        remove_unreachable(func_data);
+        promote(func_data);
+    }
+}
+
+// control flow between the blocks in the layout, which must all be reachable
+pub(super) struct Cfg {
+    order: Vec<BasicBlock>, // reverse postorder, the entry first
+    preds: HashMap<BasicBlock, Vec<BasicBlock>>,
+    pub(super) idom: HashMap<BasicBlock, BasicBlock>, // the entry is its own
+}
+
+impl Cfg {
+    pub(super) fn new(func_data: &FunctionData) -> Self {
+        let entry = func_data.layout().entry_bb().unwrap();
+        let mut preds = HashMap::<_, Vec<_>>::new();
+        let mut post = Vec::new();
+        let mut visited = HashSet::from([entry]);
+        let mut stack = vec![(entry, successors(func_data, entry), 0)];
+        while let Some((bb, succs, i)) = stack.pop() {
+            preds.entry(bb).or_default();
+            match succs.get(i).copied() {
+                Some(succ) => {
+                    preds.entry(succ).or_default().push(bb);
+                    stack.push((bb, succs, i + 1));
+                    if visited.insert(succ) {
+                        stack.push((succ, successors(func_data, succ), 0));
+                    }
+                }
+                None => post.push(bb),
+            }
+        }
+        post.reverse();
+
+        // Cooper, Harvey and Kennedy, "A Simple, Fast Dominance Algorithm"
+        let number = post.iter().enumerate().map(|(i, &bb)| (bb, i)).collect::<HashMap<_, _>>();
+        let mut idom = HashMap::from([(entry, entry)]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &bb in &post[1..] {
+                let new_idom = preds[&bb].iter().copied().filter(|p| idom.contains_key(p)).reduce(|mut a, mut b| {
+                    while a != b {
+                        while number[&a] > number[&b] {
+                            a = idom[&a];
+                        }
+                        while number[&b] > number[&a] {
+                            b = idom[&b];
+                        }
+                    }
+                    a
+                }).unwrap();
+                if idom.insert(bb, new_idom) != Some(new_idom) {
+                    changed = true;
+                }
+            }
+        }
+        Cfg { order: post, preds, idom }
+    }
+
+    fn frontier(&self) -> HashMap<BasicBlock, HashSet<BasicBlock>> {
+        let mut frontier = self.order.iter().map(|&bb| (bb, HashSet::new())).collect::<HashMap<_, _>>();
+        for (&bb, preds) in &self.preds {
+            if preds.len() < 2 {
+                continue;
+            }
+            for &pred in preds {
+                let mut runner = pred;
+                while runner != self.idom[&bb] {
+                    frontier.get_mut(&runner).unwrap().insert(bb);
+                    runner = self.idom[&runner];
+                }
+            }
+        }
+        frontier
+    }
+}
+
+fn promote(func_data: &mut FunctionData) {
+    let insts = insts(func_data);
+    let parent = insts.iter().map(|&(bb, inst)| (inst, bb)).collect::<HashMap<_, _>>();
+    // only loads and stores of the variable itself, all of them still in the layout
+    let vars = insts.iter().map(|&(_, inst)| inst).filter(|&alloc| {
+        scalar_alloc(func_data, alloc)
+            && func_data.dfg().value(alloc).used_by().iter().all(|user| {
+                parent.contains_key(user) && match func_data.dfg().value(*user).kind() {
+                    ValueKind::Load(_) => true,
+                    ValueKind::Store(store) => store.dest() == alloc,
+                    _ => false,
+                }
+            })
+    }).collect::<Vec<_>>();
+    if vars.is_empty() {
+        return;
+    }
+    let index = vars.iter().enumerate().map(|(i, &var)| (var, i)).collect::<HashMap<_, _>>();
+    let cfg = Cfg::new(func_data);
+    let entry = cfg.order[0];
+
+    // the variables each block takes as parameters, in `vars` order
+    let frontier = cfg.frontier();
+    let mut params = HashMap::<BasicBlock, Vec<Value>>::new();
+    for &var in &vars {
+        let mut work = func_data.dfg().value(var).used_by().iter()
+            .filter(|user| matches!(func_data.dfg().value(**user).kind(), ValueKind::Store(_)))
+            .map(|store| parent[store])
+            .collect::<Vec<_>>();
+        let mut placed = HashSet::new();
+        while let Some(bb) = work.pop() {
+            for &join in &frontier[&bb] {
+                if join != entry && placed.insert(join) {
+                    params.entry(join).or_default().push(var);
+                    work.push(join);
+                }
+            }
+        }
+    }
+
+    // parameters can only be given to a new block, so every block but the entry is rebuilt;
+    // a block's own parameters (the join block of && / ||) stay in front of the new ones
+    let mut rebuilt = HashMap::from([(entry, entry)]);
+    let mut own = HashMap::new();
+    for bb in func_data.layout().bbs().keys().copied().collect::<Vec<_>>() {
+        if bb == entry {
+            continue;
+        }
+        let name = func_data.dfg().bbs()[&bb].name().clone();
+        let old_params = func_data.dfg().bbs()[&bb].params().to_vec();
+        let mut tys = old_params.iter().map(|&param| func_data.dfg().value(param).ty().clone()).collect::<Vec<_>>();
+        tys.extend(vec![Type::get_i32(); params.get(&bb).map_or(0, Vec::len)]);
+        let new_bb = func_data.dfg_mut().new_bb().basic_block_with_params(name, tys);
+        for (i, &old) in old_params.iter().enumerate() {
+            let new = func_data.dfg().bbs()[&new_bb].params()[i];
+            replace_uses(func_data, old, new);
+        }
+        own.insert(bb, old_params.len());
+        let bb_insts = func_data.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect::<Vec<_>>();
+        for inst in &bb_insts {
+            func_data.layout_mut().bb_mut(bb).insts_mut().remove(inst);
+        }
+        func_data.layout_mut().bbs_mut().remove(&bb);
+        func_data.layout_mut().bbs_mut().extend([new_bb]);
+        for inst in bb_insts {
+            let _ = func_data.layout_mut().bb_mut(new_bb).insts_mut().push_key_back(inst);
+        }
+        rebuilt.insert(bb, new_bb);
+    }
+
+    // rename along the dominator tree, carrying the current value of each variable
+    let mut children = HashMap::<BasicBlock, Vec<BasicBlock>>::new();
+    for &bb in &cfg.order[1..] {
+        children.entry(cfg.idom[&bb]).or_default().push(bb);
+    }
+    let undef = func_data.dfg_mut().new_value().integer(0); // read before written
+    let mut work = vec![(entry, vec![undef; vars.len()])];
+    let mut edges = 0;
+    while let Some((bb, mut current)) = work.pop() {
+        let block = rebuilt[&bb];
+        for (i, var) in params.get(&bb).into_iter().flatten().enumerate() {
+            current[index[var]] = func_data.dfg().bbs()[&block].params()[own[&bb] + i];
+        }
+        let block_insts = func_data.layout().bbs().node(&block).unwrap().insts().keys().copied().collect::<Vec<_>>();
+        for inst in block_insts {
+            match func_data.dfg().value(inst).kind().clone() {
+                ValueKind::Load(load) if index.contains_key(&load.src()) => {
+                    replace_uses(func_data, inst, current[index[&load.src()]]);
+                    remove_inst(func_data, block, inst);
+                }
+                ValueKind::Store(store) if index.contains_key(&store.dest()) => {
+                    current[index[&store.dest()]] = store.value();
+                    remove_inst(func_data, block, inst);
+                }
+                _ => {}
+            }
+        }
+
+        // point the terminator at the rebuilt blocks, passing the current values
+        let args = |target: &BasicBlock| -> Vec<Value> {
+            params.get(target).into_iter().flatten().map(|var| current[index[var]]).collect()
+        };
+        if let Some(last) = terminator(func_data, block) {
+            match func_data.dfg().value(last).kind().clone() {
+                ValueKind::Jump(jump) => {
+                    let target = rebuilt[&jump.target()];
+                    let args = jump.args().iter().copied().chain(args(&jump.target())).collect();
+                    let _ = func_data.dfg_mut().replace_value_with(last).jump_with_args(target, args);
+                }
+                ValueKind::Branch(br) => {
+                    // (block, arguments) for one side of the branch
+                    let mut edge = |target: BasicBlock, own_args: &[Value]| {
+                        let args = own_args.iter().copied().chain(args(&target)).collect::<Vec<_>>();
+                        if args.len() == own_args.len() {
+                            return (rebuilt[&target], args);
+                        }
+                        let name = func_data.dfg().bbs()[&target].name().as_ref().map(|name| format!("{}_edge{}", name, edges));
+                        edges += 1;
+                        let edge = func_data.dfg_mut().new_bb().basic_block(name);
+                        func_data.layout_mut().bbs_mut().extend([edge]);
+                        let jump = func_data.dfg_mut().new_value().jump_with_args(rebuilt[&target], args);
+                        let _ = func_data.layout_mut().bb_mut(edge).insts_mut().push_key_back(jump);
+                        (edge, Vec::new())
+                    };
+                    let (true_bb, true_args) = edge(br.true_bb(), br.true_args());
+                    let (false_bb, false_args) = edge(br.false_bb(), br.false_args());
+                    let _ = func_data.dfg_mut().replace_value_with(last).branch_with_args(br.cond(), true_bb, false_bb, true_args, false_args);
+                }
+                _ => {}
+            }
+        }
+        for &child in children.get(&bb).into_iter().flatten() {
+            work.push((child, current.clone()));
+        }
+    }
+
+    for var in vars {
+        let bb = rebuilt[&parent[&var]];
+        remove_inst(func_data, bb, var);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_const_fold() {
+        let source = "int main() { int x = 2; int y; if (x * 3 > 5) { return x + y + 1; } return x / 0; }";
+        let mut program = super::super::build_ir(crate::parse(source).unwrap()).unwrap();
+        const_fold(&mut program);
+        let ir = super::super::ir_to_string(&program);
+        // everything folds into the taken branch; the division by zero goes with the dead block
+        assert!(ir.contains("ret 3"), "{}", ir);
+        for gone in ["alloc", "load", "store", "br ", "div"] {
+            assert!(!ir.contains(gone), "{}", ir);
+        }
+    }
+
+    #[test]
+    fn test_eliminate_dead_code() {
+        let source = "int main() { int a[4]; int x = getint(); a[0] = 1; x + 1; if (x) return x; else return 1; return 0; }";
+        let mut program = super::super::build_ir(crate::parse(source).unwrap()).unwrap();
+        eliminate_dead_code(&mut program);
+        let ir = super::super::ir_to_string(&program);
+        // the array is only written, x + 1 is unused, and nothing jumps to the last return
+        for gone in ["[i32, 4]", "add", "ret 0"] {
+            assert!(!ir.contains(gone), "{}", ir);
+        }
+        assert!(ir.contains("call @getint"), "{}", ir);
+    }
+
+    #[test]
+    fn test_mem2reg() {
+        let source = "int main() { int i = 0; int s = 0; while (i < 10) { if (i % 2) s = s + i; i = i + 1; } return s; }";
+        let mut program = super::super::build_ir(crate::parse(source).unwrap()).unwrap();
+        mem2reg(&mut program);
+        let ir = super::super::ir_to_string(&program);
+        // i and s live in block parameters now
+        for gone in ["alloc", "load", "store"] {
+            assert!(!ir.contains(gone), "{}", ir);
+        }
+        assert!(ir.contains(": i32, "), "{}", ir);
+        // and the program still computes 1 + 3 + 5 + 7 + 9
+        let asm = crate::codegen::build_asm(&program, crate::codegen::Target::default());
+        let (code, _) = crate::runner::build_and_run("mem2reg", &asm, None, &crate::runner::test_dir()).unwrap();
+        assert_eq!(code, 25);
+    }
+
+    #[test]
+    fn test_simplify_cfg() {
+        let source = "int main() { int a = getint(); if (a) { if (a > 1) return 2; else return 3; } while (a) return 4; return 0; }";
+        let program = super::super::build_ir(crate::parse(source).unwrap()).unwrap();
+        assert_eq!(super::super::verify::verify(&program), Vec::<String>::new());
+        let ir = super::super::ir_to_string(&program);
+        // no block is only a jump: the end blocks of both ifs are gone
+        let lines = ir.lines().collect::<Vec<_>>();
+        for pair in lines.windows(2) {
+            assert!(!(pair[0].ends_with(':') && pair[1].trim_start().starts_with("jump")), "{}", ir);
+        }
+        let asm = crate::codegen::build_asm(&program, crate::codegen::Target::default());
+        for (input, expected) in [("0", 0), ("1", 3), ("5", 2)] {
+            let (code, _) = crate::runner::build_and_run("simplify_cfg", &asm, Some(input), &crate::runner::test_dir()).unwrap();
+            assert_eq!(code, expected);
+        }
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        // && and || hand their result to the join block as an argument; mem2reg keeps it in front
+        let source = "int f(int x) { putint(x); return x; }
+            int main() { int a = 0; if (f(1) || f(2)) a = a + 1; if (f(0) && f(3)) a = a + 10; return a + (f(4) && f(5)); }";
+        let mut program = super::super::build_ir(crate::parse(source).unwrap()).unwrap();
+        let ir = super::super::ir_to_string(&program);
+        assert_eq!(ir.matches("alloc").count(), 2, "{}", ir); // x and a only
+        mem2reg(&mut program);
+        let asm = crate::codegen::build_asm(&program, crate::codegen::Target::default());
+        let (code, output) = crate::runner::build_and_run("short_circuit", &asm, None, &crate::runner::test_dir()).unwrap();
+        assert_eq!((code, output.as_str()), (2, "1045"));
+    }
+}
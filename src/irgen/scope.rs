@@ -68,38 +68,17 @@ impl Scope {
         None
     }
 
-    pub fn lookup_dim_size(&self, ident: &Ident) -> Option<usize> {
-        for scope in self.scopes.iter().rev() {
-            if let Some(var) = scope.table.vars.borrow().get(ident) {
-                if let VarValue::Alloc(_, Some(dims), _) = var {
-                    return Some(dims.len());
-                }
-            }
-        }
-        None
-    }
-
-    pub fn lookup_is_pointer(&self, ident: &Ident) -> Option<bool> {
-        for scope in self.scopes.iter().rev() {
-            if let Some(var) = scope.table.vars.borrow().get(ident) {
-                if let VarValue::Alloc(_, _, is_pointer) = var {
-                    return Some(is_pointer.unwrap());
-                }
-            }
-        }
-        None
-    }
-
-    pub fn is_array(&self, ident: &Ident) -> bool {
+    // None if `ident` is not an array (or array parameter) in the innermost scope that has it
+    pub fn lookup_array(&self, ident: &Ident) -> Option<ArrayShape> {
         for scope in self.scopes.iter().rev() {
             if let Some(val) = scope.table.vars.borrow().get(ident) {
                 return match val {
-                    VarValue::Alloc(_, Some(..), _) => true,
-                    _ => false,
+                    VarValue::Alloc(_, Some(dims), is_pointer) => Some(ArrayShape { dims: dims.clone(), is_pointer: is_pointer.unwrap_or(false) }),
+                    _ => None,
                 };
             }
         }
-        false
+        None
     }
 
     // lookup for var (not function)
@@ -168,6 +147,22 @@ pub struct ArrayVar {
     value: Option<Value>, // alloc in Koopa IR
 }
 
+// An array as irgen sees it. A local or global `int a[2][3]` has dims [2, 3] and is an alloc of
+// [[i32, 3], 2]; a parameter `int a[][3]` has dims [3] and is_pointer set, an alloc holding a
+// *[i32, 3]. The first subscript of a parameter is a getptr, every other one a getelemptr.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayShape {
+    pub dims: Vec<i32>,
+    pub is_pointer: bool,
+}
+
+impl ArrayShape {
+    // subscripts that reach an int
+    pub fn rank(&self) -> usize {
+        self.dims.len() + self.is_pointer as usize
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum VarValue {
     Const(i32),
@@ -279,4 +274,30 @@ impl Var {
             Var::Array(_) => None,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_array_params() {
+        let local = ArrayShape { dims: vec![2, 3, 4], is_pointer: false };
+        let param = ArrayShape { dims: vec![3, 4], is_pointer: true };
+        assert_eq!((local.rank(), param.rank()), (3, 3));
+
+        // parameters indexed part of the way are passed on to parameters of lower rank
+        let source = "int row(int r[], int n) { int s = 0; int i = 0; while (i < n) { s = s + r[i]; i = i + 1; } return s; }
+            int plane(int p[][3]) { return row(p[0], 3) + row(p[1], 3); }
+            int cube(int c[][2][3], int k) { return plane(c[k]); }
+            int main() { int a[2][2][3] = {{{1, 2, 3}, {4, 5, 6}}, {{7, 8, 9}, {10, 11, 12}}}; a[1][1][2] = 20;
+                return cube(a, 0) + cube(a, 1) + row(a[1][0], 3); }";
+        let program = super::super::build_ir(crate::parse(source).unwrap()).unwrap();
+        let asm = crate::codegen::build_asm(&program, crate::codegen::Target::Rv64);
+        let (code, _) = crate::runner::build_and_run("array_params", &asm, None, &crate::runner::test_dir()).unwrap();
+        assert_eq!(code, 21 + 65 + 24);
+
+        // the same number of dimensions with different sizes is reported by sema, before irgen
+        let source = "int f(int a[][3]) { return a[0][0]; } int main() { int b[2][4]; return f(b); }";
+        assert_eq!(crate::analyze(source).unwrap_err(), "1:74: error: argument 1 of 'f' should be int[][3], got int[][4]");
+    }
+}
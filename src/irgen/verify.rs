@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use koopa::ir::{BasicBlock, FunctionData, Program, Value, ValueKind};
+
+use super::opt::Cfg;
+
+// Koopa IR verifier, run after build_ir and after every optimization pass (-check runs only this):
+//   every block ends with exactly one ret / jump / br;
+//   jumps and branches go to blocks of the same function, with one argument per block parameter;
+//   every value is defined before it is used: earlier in the same block, or in a block that
+//   dominates the use (blocks the entry cannot reach never run and are not checked for this)
+pub fn verify(program: &Program) -> Vec<String> {
+    let mut errors = Vec::new();
+    for &func in program.func_layout() {
+        let func_data = program.func(func);
+        if func_data.layout().entry_bb().is_none() {
+            continue; // library declaration
+        }
+        verify_func(func_data, &mut errors);
+    }
+    errors
+}
+
+// the diagnostics as one error, naming the step that produced the program
+pub fn check(program: &Program, after: &str) -> Result<(), String> {
+    let errors = verify(program);
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(format!("malformed Koopa IR after {}:\n{}", after, errors.join("\n")))
+}
+
+fn is_terminator(kind: &ValueKind) -> bool {
+    matches!(kind, ValueKind::Return(_) | ValueKind::Jump(_) | ValueKind::Branch(_))
+}
+
+// (target, arguments) of a jump or branch
+fn edges(kind: &ValueKind) -> Vec<(BasicBlock, usize)> {
+    match kind {
+        ValueKind::Jump(jump) => vec![(jump.target(), jump.args().len())],
+        ValueKind::Branch(br) => vec![(br.true_bb(), br.true_args().len()), (br.false_bb(), br.false_args().len())],
+        _ => vec![],
+    }
+}
+
+fn kind_name(kind: &ValueKind) -> &'static str {
+    match kind {
+        ValueKind::Alloc(_) => "alloc",
+        ValueKind::Load(_) => "load",
+        ValueKind::Store(_) => "store",
+        ValueKind::GetPtr(_) => "getptr",
+        ValueKind::GetElemPtr(_) => "getelemptr",
+        ValueKind::Binary(_) => "binary",
+        ValueKind::Branch(_) => "br",
+        ValueKind::Jump(_) => "jump",
+        ValueKind::Call(_) => "call",
+        ValueKind::Return(_) => "ret",
+        _ => "value",
+    }
+}
+
+fn block_name(func_data: &FunctionData, bb: BasicBlock) -> String {
+    func_data.dfg().bb(bb).name().clone().unwrap_or_else(|| String::from("%<unnamed>"))
+}
+
+// its name, or what kind of instruction it is
+fn value_name(func_data: &FunctionData, val: Value) -> String {
+    let data = func_data.dfg().value(val);
+    data.name().clone().unwrap_or_else(|| kind_name(data.kind()).to_string())
+}
+
+fn verify_func(func_data: &FunctionData, errors: &mut Vec<String>) {
+    let func = func_data.name();
+    let blocks = func_data.layout().bbs();
+    let mut position = HashMap::new(); // instruction -> (block, index)
+    let mut owner = HashMap::new(); // block parameter -> block
+    let mut targets_ok = true;
+    for (&bb, node) in blocks.iter() {
+        let at = format!("{}, {}", func, block_name(func_data, bb));
+        for &param in func_data.dfg().bb(bb).params() {
+            owner.insert(param, bb);
+        }
+        let insts = node.insts().keys().copied().collect::<Vec<_>>();
+        if !insts.last().is_some_and(|&last| is_terminator(func_data.dfg().value(last).kind())) {
+            errors.push(format!("{}: block does not end with ret, jump or br", at));
+        }
+        for (i, &inst) in insts.iter().enumerate() {
+            position.insert(inst, (bb, i));
+            let kind = func_data.dfg().value(inst).kind();
+            if is_terminator(kind) && i + 1 < insts.len() {
+                errors.push(format!("{}: {} at instruction {} is not the last one of the block", at, kind_name(kind), i));
+            }
+            for (target, args) in edges(kind) {
+                if blocks.node(&target).is_none() {
+                    errors.push(format!("{}: {} goes to a block that is not in the function", at, kind_name(kind)));
+                    targets_ok = false;
+                } else if func_data.dfg().bb(target).params().len() != args {
+                    errors.push(format!("{}: {} passes {} arguments to {}, which has {} parameters",
+                        at, kind_name(kind), args, block_name(func_data, target), func_data.dfg().bb(target).params().len()));
+                }
+            }
+        }
+    }
+    if !targets_ok {
+        return; // no control flow graph to check the definitions against
+    }
+
+    let cfg = Cfg::new(func_data);
+    let dominates = |a: BasicBlock, mut b: BasicBlock| loop {
+        if a == b {
+            return true;
+        }
+        let up = cfg.idom[&b];
+        if up == b {
+            return false;
+        }
+        b = up;
+    };
+    for (&bb, node) in blocks.iter() {
+        if !cfg.idom.contains_key(&bb) {
+            continue; // unreachable
+        }
+        for (i, &inst) in node.insts().keys().enumerate() {
+            for used in func_data.dfg().value(inst).kind().value_uses() {
+                let Some(data) = func_data.dfg().values().get(&used) else {
+                    continue; // globals are not in the function's dfg
+                };
+                let defined = match (position.get(&used), owner.get(&used)) {
+                    (Some(&(def_bb, j)), _) if def_bb == bb => j < i,
+                    (Some(&(def_bb, _)), _) => dominates(def_bb, bb),
+                    (None, Some(&param_bb)) => dominates(param_bb, bb),
+                    (None, None) => !data.kind().is_local_inst(), // constants and function parameters
+                };
+                if !defined {
+                    errors.push(format!("{}, {}: {} at instruction {} uses {} before it is defined",
+                        func, block_name(func_data, bb), value_name(func_data, inst), i, value_name(func_data, used)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::opt::mem2reg;
+
+    #[test]
+    fn test_verify() {
+        let source = "int main() { int x = getint(); if (x) return 1; return x + 2; }";
+        let mut program = super::super::build_ir(crate::parse(source).unwrap()).unwrap();
+        assert_eq!(verify(&program), Vec::<String>::new());
+        mem2reg(&mut program);
+        assert_eq!(verify(&program), Vec::<String>::new());
+
+        // take the call out of the entry block: x is used without being defined anywhere
+        let main = program.func_layout().iter().copied().find(|&f| program.func(f).name() == "@main").unwrap();
+        let func_data = program.func_mut(main);
+        let entry = func_data.layout().entry_bb().unwrap();
+        let call = func_data.layout().bbs().node(&entry).unwrap().insts().keys().copied()
+            .find(|&inst| matches!(func_data.dfg().value(inst).kind(), ValueKind::Call(_))).unwrap();
+        func_data.layout_mut().bb_mut(entry).insts_mut().remove(&call);
+        let errors = verify(&program);
+        assert!(errors.iter().any(|e| e.contains("br at instruction") && e.contains("uses call before it is defined")), "{:?}", errors);
+
+        // and without its jump the entry block no longer ends
+        let func_data = program.func_mut(main);
+        let jump = *func_data.layout().bbs().node(&entry).unwrap().insts().back_key().unwrap();
+        func_data.layout_mut().bb_mut(entry).insts_mut().remove(&jump);
+        assert!(verify(&program).iter().any(|e| e.contains("does not end with")));
+        assert!(check(&program, "test").unwrap_err().starts_with("malformed Koopa IR after test:"));
+    }
+}
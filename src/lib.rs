@@ -0,0 +1,165 @@
+// SysY toolchain as a library: SysY -> Koopa IR -> RISC-V assembly, plus the pipeline simulator.
+//
+//   let asm = compile_to_riscv("int main() { return 0; }")?;
+//   let mut sim = Simulator::load("testcase/bin/main.bin")?;
+//   let code = sim.run(None);
+
+pub mod codegen;
+pub mod irgen;
+pub mod sema;
+pub mod simulator;
+pub mod runner;
+pub mod fuzz;
+#[cfg(feature = "gui")]
+mod shell;
+
+use lalrpop_util::{lalrpop_mod, ParseError};
+lalrpop_mod! {
+    #[allow(clippy::all)]
+    pub sysy
+}
+
+pub use irgen::ast_df::CompUnit;
+use irgen::ast_df::LineIndex;
+pub use simulator::Simulator;
+
+// parse SysY source into an AST; errors read "Parse error at LINE:COL: ..."
+pub fn parse(source: &str) -> Result<CompUnit, String> {
+    let lines = LineIndex::new(source);
+    sysy::CompUnitParser::new()
+        .parse(&lines, source)
+        .map_err(|e| parse_error(&lines, e))
+}
+
+fn parse_error<T: std::fmt::Display>(lines: &LineIndex, error: ParseError<usize, T, &str>) -> String {
+    // lalrpop names regex terminals by their pattern, which says little to the user
+    let expected = |expected: Vec<String>| {
+        let mut names: Vec<String> = expected
+            .into_iter()
+            .map(|e| match e.strip_prefix("r#\"") {
+                Some(re) if re.starts_with("[_a-zA-Z]") => String::from("identifier"),
+                Some(_) => String::from("number"),
+                None => e,
+            })
+            .collect();
+        names.dedup();
+        match names.len() {
+            0 => String::new(),
+            _ => format!(", expected one of {}", names.join(", ")),
+        }
+    };
+    let (offset, message) = match error {
+        ParseError::InvalidToken { location } => (location, String::from("invalid token")),
+        ParseError::UnrecognizedEof { location, expected: e } => (location, format!("unexpected end of file{}", expected(e))),
+        ParseError::UnrecognizedToken { token: (start, token, _), expected: e } => (start, format!("unexpected `{}`{}", token, expected(e))),
+        ParseError::ExtraToken { token: (start, token, _) } => (start, format!("extra token `{}`", token)),
+        ParseError::User { error } => (0, error.to_string()),
+    };
+    format!("Parse error at {}: {}", lines.pos(offset), message)
+}
+
+// parse and check SysY source; semantic errors come back one per line as "LINE:COL: error: ..."
+pub fn analyze(source: &str) -> Result<CompUnit, String> {
+    let ast = parse(source)?;
+    let diags = sema::check(&ast);
+    if !diags.is_empty() {
+        return Err(diags.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"));
+    }
+    Ok(ast)
+}
+
+// Koopa IR for SysY source, checked by the IR verifier
+fn build_checked(source: &str) -> Result<(koopa::ir::Program, irgen::LineMap), String> {
+    let (program, lines) = irgen::build_ir_with_lines(analyze(source)?).ok_or("IR generation failed")?;
+    irgen::verify::check(&program, "IR generation")?;
+    Ok((program, lines))
+}
+
+// compile SysY source to Koopa IR text
+pub fn compile_to_koopa(source: &str) -> Result<String, String> {
+    let (program, _) = build_checked(source)?;
+    Ok(irgen::ir_to_string(&program))
+}
+
+// the -O passes: mem2reg from -O2, constant folding and dead code elimination from -O1; the
+// verifier runs after every pass, so a malformed program is reported where it appears
+pub fn optimize(program: &mut koopa::ir::Program, opt_level: u8) -> Result<(), String> {
+    if opt_level > 1 {
+        irgen::opt::mem2reg(program);
+        irgen::verify::check(program, "mem2reg")?;
+    }
+    if opt_level > 0 {
+        irgen::opt::const_fold(program);
+        irgen::verify::check(program, "constant folding")?;
+        irgen::opt::eliminate_dead_code(program);
+        irgen::verify::check(program, "dead code elimination")?;
+    }
+    Ok(())
+}
+
+// compile SysY source to RISC-V assembly
+pub fn compile_to_riscv(source: &str) -> Result<String, String> {
+    let (program, _) = build_checked(source)?;
+    Ok(codegen::build_asm(&program, codegen::Target::default()))
+}
+
+// same after the -O`opt_level` passes
+pub fn compile_to_riscv_opt(source: &str, opt_level: u8) -> Result<String, String> {
+    let (mut program, _) = build_checked(source)?;
+    optimize(&mut program, opt_level)?;
+    Ok(codegen::build_asm(&program, codegen::Target::default()))
+}
+
+// same with the stack backend, whose output brings its own _start/_trm_init prologue and stack
+pub fn compile_to_riscv_stack(source: &str) -> Result<String, String> {
+    use codegen::Backend;
+    let (program, _) = build_checked(source)?;
+    Ok(codegen::StackBackend.lower(&program).emit_asm())
+}
+
+// same, with each instruction group preceded by its SysY source line and Koopa IR (-g)
+pub fn compile_to_riscv_debug(source: &str) -> Result<String, String> {
+    let (program, lines) = build_checked(source)?;
+    let debug = codegen::DebugInfo { lines: &lines, source };
+    Ok(codegen::build_asm_with_debug(&program, codegen::Target::default(), &debug))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // golden files testcase/golden/NAME.{koopa,s} lock down the output for testcase/c/NAME.c;
+    // a missing or different file fails, run with UPDATE_GOLDEN=1 to record an intended codegen change
+    const GOLDEN_PROGRAMS: [&str; 4] = ["dummy", "hello", "exp", "ackermann"];
+
+    fn check_golden(path: &str, actual: &str, update: bool) -> Result<(), String> {
+        match fs::read_to_string(path) {
+            Ok(expected) if expected == actual => Ok(()),
+            Ok(_) if !update => Err(format!("{} differs from the compiler output", path)),
+            Err(_) if !update => Err(format!("{} is missing, run with UPDATE_GOLDEN=1 to record it", path)),
+            _ => {
+                fs::write(path, actual).map_err(|e| e.to_string())?;
+                println!("recorded {}", path);
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_golden_output() {
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let mut failures = Vec::new();
+        for name in GOLDEN_PROGRAMS {
+            let source = fs::read_to_string(format!("testcase/c/{}.c", name)).unwrap();
+            let koopa = compile_to_koopa(&source).unwrap();
+            let asm = compile_to_riscv(&source).unwrap();
+            for (ext, actual) in [("koopa", koopa), ("s", asm)] {
+                if let Err(e) = check_golden(&format!("testcase/golden/{}.{}", name, ext), &actual, update) {
+                    failures.push(e);
+                }
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
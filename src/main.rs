@@ -1,17 +1,221 @@
-mod codegen;
-mod irgen;
-mod simulator;
-// use koopa::back::KoopaGenerator;
-use lalrpop_util::lalrpop_mod;
-use std::{env::args};
 use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
-use irgen::{build_ir, emit_ir};
-use codegen::{build_asm, emit_asm};
-lalrpop_mod! {
-    #[allow(clippy::all)]
-    sysy
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use naive_sysy::{optimize, parse, sema, simulator};
+use naive_sysy::runner::{build_image_with, run_source, run_tests};
+use naive_sysy::fuzz::run_fuzz;
+use naive_sysy::irgen::{build_ir_with_lines, emit_ir};
+use naive_sysy::irgen::verify;
+use naive_sysy::codegen::{emit_asm, Backend, BackendKind, BuildBackend, DebugInfo, StackBackend, Target};
+
+// Inputs and outputs are ordinary paths. For compatibility with the old layout, an input that does
+// not exist is looked up in testcase/c, and then a bare output name goes to testcase/koopa,
+// testcase/riscv or testcase/bin as before: `-koopa hello.c -o hello.koopa` still works.
+#[derive(Parser)]
+#[command(name = "Naive-SysY-Simulator", about = "SysY compiler and pipelined RISC-V simulator")]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    tool: Option<Tool>,
+
+    /// What to do with the input (also -koopa / -riscv / -sim / -run / -check)
+    #[arg(long, value_enum)]
+    mode: Option<Mode>,
+
+    /// SysY source file
+    #[arg(long, short = 'i')]
+    input: Option<String>,
+
+    /// SysY source file, same as --input
+    source: Option<String>,
+
+    /// Output file; for -sim, the image to load (- reopens the last one, none builds the source)
+    #[arg(long, short = 'o')]
+    output: Option<String>,
+
+    /// Optimization level: 1 folds constants and removes dead code in the IR, 2 also promotes variables to SSA values
+    #[arg(short = 'O', default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2))]
+    opt_level: u8,
+
+    /// Code generation target for -riscv: rv64 (8-byte pointers, addw/subw/..., what the simulator runs) or rv32 (4-byte values, for 32-bit toolchains)
+    #[arg(long, default_value = "rv64")]
+    target: Target,
+
+    /// Backend for -riscv and for -sim from source: build (register allocation, peephole; honours --target and -g) or stack (the original one)
+    #[arg(long, default_value = "build")]
+    backend: BackendKind,
+
+    /// Annotate -riscv output with the SysY source line and Koopa IR of each instruction group
+    #[arg(short = 'g')]
+    debug: bool,
+
+    #[command(flatten)]
+    sim: SimArgs,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Mode {
+    Koopa,
+    Riscv,
+    Sim,
+    Run,
+    Check,
+}
+
+impl Mode {
+    // where bare output names go in the old layout
+    fn testcase_dir(self) -> &'static str {
+        match self {
+            Mode::Koopa | Mode::Check => "koopa",
+            Mode::Riscv => "riscv",
+            Mode::Sim | Mode::Run => "bin",
+        }
+    }
+}
+
+// simulator options shared by -sim, -run, bench and profile
+#[derive(Args)]
+struct SimArgs {
+    /// Simulator configuration file (see simulator/config.rs)
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Override one configuration field
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
+    /// Simulated cycles per second in free-run mode
+    #[arg(long, value_parser = parse_hz)]
+    hz: Option<f64>,
+
+    /// Use the terminal UI
+    #[arg(long)]
+    tui: bool,
+
+    /// Use the simplified GUI
+    #[arg(long)]
+    shell: bool,
+
+    /// Use the sim> REPL
+    #[arg(long)]
+    cli: bool,
+
+    /// Drive the simulator over TCP
+    #[arg(long, value_name = "PORT")]
+    server: Option<u16>,
+
+    /// Run -sim to the end without any UI and exit with the guest's exit code
+    #[arg(long)]
+    headless: bool,
+
+    /// Run the debugger commands in FILE on -sim, then exit (status 1 if an assert fails)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["headless", "tui", "shell", "server"])]
+    script: Option<String>,
+
+    /// Queue FILE on the UART as the guest's input (getint/getch) before the program starts
+    #[arg(long, value_name = "FILE")]
+    stdin: Option<String>,
+
+    /// Stop a --headless run after N cycles
+    #[arg(long, value_name = "N", requires = "headless")]
+    max_cycles: Option<u64>,
+
+    /// Run on the single-cycle model instead of the pipeline (-run)
+    #[arg(long)]
+    single_cycle: bool,
+
+    /// Log every committed instruction
+    #[arg(long)]
+    itrace: bool,
+
+    /// Log every load and store
+    #[arg(long)]
+    mtrace: bool,
+
+    /// Log function calls and returns
+    #[arg(long)]
+    ftrace: bool,
+
+    /// Write traces to FILE instead of stderr
+    #[arg(long, value_name = "FILE")]
+    trace_log: Option<String>,
+
+    /// Load the image as ELF even when it is named .bin
+    #[arg(long)]
+    elf: bool,
+
+    /// Add a RAM or ROM region to the memory map, e.g. rom:0x1000:0x10000
+    #[arg(long = "region", value_name = "KIND:BASE:SIZE")]
+    regions: Vec<String>,
+}
+
+impl SimArgs {
+    fn load_config(&self) -> Result<simulator::SimConfig, String> {
+        let mut config = simulator::SimConfig::load(self.config.as_deref(), &self.overrides)?;
+        if self.hz.is_some() {
+            config.pipeline.hz = self.hz; // --hz wins over the config file
+        }
+        config.trace.itrace |= self.itrace;
+        config.trace.mtrace |= self.mtrace;
+        config.trace.ftrace |= self.ftrace;
+        config.memory.elf |= self.elf;
+        for region in &self.regions {
+            config.memory.regions.push(region.parse()?);
+        }
+        config.memory_map()?;
+        if self.trace_log.is_some() {
+            config.trace.log = self.trace_log.clone();
+        }
+        if self.stdin.is_some() {
+            config.console.stdin = self.stdin.clone();
+        }
+        Ok(config)
+    }
+
+    fn frontend(&self) -> simulator::Frontend {
+        match self.server {
+            Some(port) => simulator::Frontend::Server(port),
+            None if self.tui => simulator::Frontend::Tui,
+            None if self.shell => simulator::Frontend::Shell,
+            None if self.cli => simulator::Frontend::Cli,
+            None => simulator::Frontend::Gui,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Tool {
+    /// Compile and run every .c in DIR, compare against the .out files
+    Test { dir: PathBuf },
+    /// Compare the single-cycle model with pipeline configurations
+    Bench {
+        image: String,
+        #[command(flatten)]
+        sim: SimArgs,
+    },
+    /// Run to completion and report the N hottest basic blocks
+    Profile {
+        image: String,
+        #[arg(default_value_t = 10)]
+        top: usize,
+        #[command(flatten)]
+        sim: SimArgs,
+    },
+    /// Run the pipeline against a reference interpreter, report the first divergence
+    Difftest {
+        image: String,
+        #[command(flatten)]
+        sim: SimArgs,
+    },
+    /// Compile and run N random programs, report disagreements
+    Fuzz {
+        #[arg(default_value_t = 100)]
+        count: u64,
+        seed: Option<u64>,
+    },
 }
 
 fn main() {
@@ -22,52 +226,137 @@ fn main() {
     }
 }
 
-#[allow(unused)]
+// --hz: the run loop sleeps 1/hz between cycles, so it has to be positive
+fn parse_hz(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(hz) if hz > 0.0 && hz.is_finite() => Ok(hz),
+        Ok(_) => Err(String::from("expected a positive number of cycles per second")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// the old single-dash modes: -koopa IN -o OUT
+fn legacy_args() -> Vec<String> {
+    std::env::args()
+        .map(|arg| match arg.as_str() {
+            "-koopa" | "-riscv" | "-sim" | "-run" | "-check" => format!("--mode={}", &arg[1..]),
+            _ => arg,
+        })
+        .collect()
+}
+
+// (path, whether it was found in testcase/c)
+fn resolve_input(input: &str) -> (PathBuf, bool) {
+    let path = PathBuf::from(input);
+    let fallback = Path::new("testcase/c").join(input);
+    if !path.exists() && fallback.exists() { (fallback, true) } else { (path, false) }
+}
+
+fn resolve_output(output: &str, mode: Mode, legacy: bool) -> String {
+    let bare = Path::new(output).parent().is_none_or(|p| p.as_os_str().is_empty());
+    if legacy && bare { format!("testcase/{}/{}", mode.testcase_dir(), output) } else { output.to_string() }
+}
+
 fn try_main() -> Result<(), Box<dyn std::error::Error>> {
-    // parse arguments
-    let mut args = args();
-    args.next();
-    let mode = args.next().unwrap();
-    let input = args.next().unwrap();
-    args.next();
-    let output = args.next().unwrap();
-    
-    // add dir prefix
-    let input = format!("{}/{}", "testcase/c", input); 
-    // println!("input: {}, output: {}", input, output);
-
-    // read input file
-    let input = read_to_string(input)?;
-
-    // generate AST
-    let ast = sysy::CompUnitParser::new()
-        .parse(&input)
-        .expect("Parse error");
-
-    // println!("{:#?}", ast);
-
-    // generate IR
-    let program = build_ir(ast).unwrap();
-
-    match mode.as_str() {
-        "-koopa" => {
-            let output = format!("{}/{}/{}", "testcase", "koopa", output);
-            emit_ir(program, output);
+    let cli = Cli::parse_from(legacy_args());
+
+    match cli.tool {
+        Some(Tool::Test { dir }) => {
+            if !run_tests(&dir)? {
+                exit(1);
+            }
+            return Ok(());
+        }
+        Some(Tool::Bench { image, sim }) => {
+            simulator::run_bench(&image, &sim.load_config()?)?;
+            return Ok(());
         }
-        "-riscv" => {
-            // generate ASM
-            let mut asm_program = build_asm(&program);
-            let output = format!("{}/{}/{}", "testcase", "riscv", output);
-            emit_asm(asm_program, output);
+        Some(Tool::Profile { image, top, sim }) => {
+            simulator::run_profile(&image, &sim.load_config()?, top)?;
+            return Ok(());
         }
-        "-sim" => {
-            // let mut asm_program = build_asm(&program);
-            // let output = format!("{}/{}/{}.s", "testcase", "riscv", output);
-            // emit_asm(asm_program, output);
-            let output = format!("{}/{}/{}", "testcase", "bin", output);
-            simulator::pipe_exc(output);
+        Some(Tool::Difftest { image, sim }) => {
+            simulator::run_difftest(&image, &sim.load_config()?)?;
+            return Ok(());
+        }
+        Some(Tool::Fuzz { count, seed }) => {
+            let seed = match seed {
+                Some(seed) => seed,
+                None => std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs(),
+            };
+            if !run_fuzz(count, seed)? {
+                exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let mode = cli.mode.ok_or("No mode given, use --mode (or -koopa / -riscv / -sim / -run / -check) or a subcommand; see --help")?;
+    let input = cli.input.or(cli.source).map(|input| resolve_input(&input));
+
+    match mode {
+        Mode::Koopa | Mode::Riscv | Mode::Check => {
+            let (input, legacy) = input.ok_or("No input file given")?;
+            let output = match mode {
+                Mode::Check => String::new(), // -check writes nothing
+                _ => resolve_output(&cli.output.ok_or("No output file given, use -o FILE")?, mode, legacy),
+            };
+
+            // generate AST, then IR
+            let source = read_to_string(&input).map_err(|e| format!("{}: {}", input.display(), e))?;
+            let ast = parse(&source)?;
+            let diags = sema::check(&ast);
+            if !diags.is_empty() {
+                let lines: Vec<String> = diags.iter().map(|d| format!("{}:{}", input.display(), d)).collect();
+                return Err(lines.join("\n").into());
+            }
+            // the verifier runs after every step, so a malformed program is reported where it appears
+            let (mut program, lines) = build_ir_with_lines(ast).unwrap();
+            verify::check(&program, "IR generation")?;
+            optimize(&mut program, cli.opt_level)?;
+
+            if mode == Mode::Check {
+                let funcs = program.func_layout().iter().filter(|&&f| program.func(f).layout().entry_bb().is_some()).count();
+                println!("{}: Koopa IR OK, {} functions", input.display(), funcs);
+            } else if mode == Mode::Koopa {
+                emit_ir(program, output);
+            } else {
+                // generate ASM
+                let debug = DebugInfo { lines: &lines, source: &source };
+                let backend: Box<dyn Backend> = match cli.backend {
+                    BackendKind::Build => Box::new(BuildBackend { target: cli.target, debug: cli.debug.then_some(&debug) }),
+                    BackendKind::Stack => Box::new(StackBackend),
+                };
+                emit_asm(backend.lower(&program), output);
+            }
+        }
+        Mode::Sim => {
+            // "-" reopens the image from the last GUI session;
+            // without -o the source is compiled and assembled first, and the result is debugged
+            let config = cli.sim.load_config()?;
+            let image = match (cli.output, input) {
+                (Some(output), _) if output == "-" => String::new(),
+                (Some(output), _) if Path::new(&output).exists() => output,
+                (Some(output), _) => resolve_output(&output, mode, true),
+                // the debugger's source view needs the line info that only -O0 keeps
+                (None, Some(_)) if cli.opt_level > 0 => return Err("-sim builds the source at -O0, use -riscv -O to get an optimized image".into()),
+                (None, Some((source, _))) => build_image_with(&source, config.memory.base, cli.backend)?.to_string_lossy().into_owned(),
+                (None, None) => return Err("No image or source given, use -o IMAGE or a .c file".into()),
+            };
+            if cli.sim.headless {
+                exit(simulator::pipe_exc_once(&image, &config, cli.sim.max_cycles)?);
+            }
+            if let Some(script) = &cli.sim.script {
+                exit(simulator::run_script(image, config, script)?);
+            }
+            simulator::pipe_exc(image, config, cli.sim.frontend());
+        }
+        Mode::Run => {
+            let (input, _) = input.ok_or("No input file given")?;
+            let model = if cli.sim.single_cycle { simulator::CpuModel::SingleCycle } else { simulator::CpuModel::Pipeline };
+            run_source(&input, &cli.sim.load_config()?, model, cli.opt_level)?;
         }
-        _ => panic!("Unsupported Mode"),
     }
     Ok(())
-}
\ No newline at end of file
+}
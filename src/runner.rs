@@ -0,0 +1,263 @@
+// End-to-end test runner: `test DIR` compiles every DIR/*.c, runs it on the simulator and compares
+// the guest output plus exit code (on its own last line) against DIR/*.out. DIR/*.in, if present,
+// is fed to the guest UART. `-run FILE` does the same for a single program and prints its output
+// and statistics instead of comparing.
+//
+// Programs are assembled into a flat image by the built-in assembler (codegen::encode). Setting
+// $RISCV_CC (e.g. riscv64-unknown-elf-gcc) or $SYSY_RUNTIME (runtime objects to link, space
+// separated) switches to assembling and linking with an external RISC-V toolchain instead.
+// Either way the SysY library comes from codegen::runtime unless $SYSY_RUNTIME replaces it.
+
+use std::fs;
+use std::io::{self, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::simulator::{run_model, CpuModel, SimConfig, Simulator};
+use crate::{compile_to_riscv, compile_to_riscv_debug, compile_to_riscv_opt, compile_to_riscv_stack};
+use crate::codegen::{encode, runtime, BackendKind};
+
+const MAX_CYCLES: u64 = 500_000_000;
+const LINKER_SCRIPT: &str = "build/scripts/linker.ld";
+
+fn link(inputs: &[&Path], elf: &Path) -> Result<(), String> {
+    let cc = std::env::var("RISCV_CC").unwrap_or_else(|_| String::from("riscv64-unknown-elf-gcc"));
+    let objects = std::env::var("SYSY_RUNTIME").unwrap_or_default();
+    let builtin = elf.with_file_name("sysy_runtime.s");
+    if objects.is_empty() {
+        fs::write(&builtin, runtime::RUNTIME).map_err(|e| e.to_string())?;
+    }
+    let output = Command::new(&cc)
+        .args(["-nostdlib", "-static", "-march=rv64im", "-mabi=lp64", "-T", LINKER_SCRIPT, "-o"])
+        .arg(elf)
+        .args(inputs)
+        .args(objects.split_whitespace())
+        .args(objects.is_empty().then_some(&builtin))
+        .output()
+        .map_err(|e| format!("cannot run {}: {}", cc, e))?;
+    if !output.status.success() {
+        return Err(format!("link failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+// first line where the two outputs differ
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut exp = expected.lines();
+    let mut act = actual.lines();
+    let mut line = 1;
+    loop {
+        match (exp.next(), act.next()) {
+            (Some(e), Some(a)) if e == a => line += 1,
+            (e, a) => {
+                return format!("line {}: expected {:?}, got {:?}", line, e.unwrap_or("<eof>"), a.unwrap_or("<eof>"));
+            }
+        }
+    }
+}
+
+fn external_toolchain() -> bool {
+    std::env::var_os("RISCV_CC").is_some() || std::env::var_os("SYSY_RUNTIME").is_some()
+}
+
+// write out and assemble a compiled program for memory at `base`, returns the image path
+fn assemble(name: &str, asm: &str, work: &Path, source: &Path, base: u64) -> Result<PathBuf, String> {
+    let asm_path = work.join(format!("{}.s", name));
+    fs::write(&asm_path, asm).map_err(|e| e.to_string())?;
+    if external_toolchain() {
+        let elf_path = work.join(format!("{}.elf", name));
+        link(&[&asm_path], &elf_path)?;
+        return Ok(elf_path);
+    }
+    let bin_path = work.join(format!("{}.bin", name));
+    encode::assemble(&format!("{}\n{}", asm, runtime::RUNTIME), base)?.write(&bin_path, source)?;
+    Ok(bin_path)
+}
+
+// assemble and run a compiled program, returns its exit code and UART output
+pub(crate) fn build_and_run(name: &str, asm: &str, input: Option<&str>, work: &Path) -> Result<(u64, String), String> {
+    let config = SimConfig::default();
+    let image = assemble(name, asm, work, &work.join(format!("{}.c", name)), config.memory.base)?;
+    let mut sim = Simulator::load(&image.to_string_lossy()).map_err(|e| e.to_string())?;
+    if let Some(input) = input {
+        sim.input(input);
+    }
+    let code = match (sim.run(Some(MAX_CYCLES)), sim.fault()) {
+        (Some(code), _) => code,
+        (None, Some(fault)) => return Err(fault.to_string()),
+        (None, None) => return Err(format!("no exit after {} cycles", MAX_CYCLES)),
+    };
+    Ok((code, String::from_utf8_lossy(sim.output()).into_owned()))
+}
+
+// a fresh directory for one test to write into, so tests running in parallel (or two test
+// binaries at once) never share a file
+#[cfg(test)]
+pub(crate) fn test_dir() -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let dir = std::env::temp_dir().join(format!("sysy-test-{}-{}", std::process::id(), n));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// assemble `asm` at the reset vector into test.bin (and test.map) in a test_dir(), returns the
+// image path the loaders take
+#[cfg(test)]
+pub(crate) fn test_image(asm: &str) -> String {
+    let path = test_dir().join("test.bin");
+    encode::assemble(asm, SimConfig::default().memory.base).unwrap().write(&path, Path::new("test.s")).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+// compile without letting a compiler panic take down the whole run
+pub(crate) fn compile(source: &str) -> Result<String, String> {
+    panic::catch_unwind(|| compile_to_riscv(source)).map_err(|_| String::from("compiler panicked"))?
+}
+
+fn run_case(source: &Path, work: &Path) -> Result<(), String> {
+    let stem = source.file_stem().unwrap().to_string_lossy().into_owned();
+    let text = fs::read_to_string(source).map_err(|e| e.to_string())?;
+    let asm = compile(&text)?;
+    let input = fs::read_to_string(source.with_extension("in")).ok();
+    let (code, mut actual) = build_and_run(&stem, &asm, input.as_deref(), work)?;
+
+    if !actual.is_empty() && !actual.ends_with('\n') {
+        actual.push('\n');
+    }
+    actual.push_str(&format!("{}\n", code & 0xff));
+
+    let expected = fs::read_to_string(source.with_extension("out")).map_err(|e| format!("missing .out: {}", e))?;
+    if expected.trim_end() == actual.trim_end() {
+        Ok(())
+    } else {
+        Err(first_difference(&expected, &actual))
+    }
+}
+
+// compile at -O`opt_level` and assemble one program for memory at `base` into a temporary
+// directory, returns the image path. Only -O0 keeps the -g line comments that the image's .map
+// needs for the debugger to show the SysY source: the optimizer passes add instructions without a
+// source line.
+pub fn build_image(source: &Path, base: u64, opt_level: u8) -> Result<PathBuf, String> {
+    match opt_level {
+        0 => build_image_from(source, base, compile_to_riscv_debug),
+        _ => build_image_from(source, base, |text| compile_to_riscv_opt(text, opt_level)),
+    }
+}
+
+// same at -O0 with `backend`; the stack backend's own _start is kept and sets up its own stack
+pub fn build_image_with(source: &Path, base: u64, backend: BackendKind) -> Result<PathBuf, String> {
+    match backend {
+        BackendKind::Build => build_image(source, base, 0),
+        BackendKind::Stack => build_image_from(source, base, compile_to_riscv_stack),
+    }
+}
+
+fn build_image_from<F>(source: &Path, base: u64, compile: F) -> Result<PathBuf, String>
+where
+    F: Fn(&str) -> Result<String, String> + panic::RefUnwindSafe,
+{
+    let text = fs::read_to_string(source).map_err(|e| format!("{}: {}", source.display(), e))?;
+    let asm = panic::catch_unwind(|| compile(&text)).map_err(|_| String::from("compiler panicked"))??;
+    let work = std::env::temp_dir().join("sysy-run");
+    fs::create_dir_all(&work).map_err(|e| e.to_string())?;
+    let stem = source.file_stem().map_or(String::from("main"), |s| s.to_string_lossy().into_owned());
+    assemble(&stem, &asm, &work, source, base)
+}
+
+// compile at -O`opt_level`, assemble and run one program on `model`, print its output and
+// statistics; returns the exit code
+pub fn run_source(source: &Path, config: &SimConfig, model: CpuModel, opt_level: u8) -> Result<u64, String> {
+    let image = build_image(source, config.memory.base, opt_level)?;
+
+    // --stdin is queued by the model itself and takes the place of the .in file
+    let input = match config.console.stdin {
+        Some(_) => None,
+        None => fs::read_to_string(source.with_extension("in")).ok(),
+    };
+    let run = run_model(&image.to_string_lossy(), config, model, input.as_deref())?;
+
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(&run.output);
+    if !run.output.is_empty() && !run.output.ends_with(b"\n") {
+        println!();
+    }
+    println!("--- {} ---", run.model);
+    println!("Exit code: {}", run.exit_code & 0xff);
+    println!("Cycles: {}, instructions: {}, CPI: {:.3}", run.cycles, run.insts, run.cycles as f64 / run.insts.max(1) as f64);
+    if !run.note.is_empty() {
+        println!("{}", run.note);
+    }
+    Ok(run.exit_code)
+}
+
+// returns true if every test passed
+pub fn run_tests(dir: &Path) -> io::Result<bool> {
+    let mut sources: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "c"))
+        .collect();
+    sources.sort();
+
+    let work = std::env::temp_dir().join("sysy-test");
+    fs::create_dir_all(&work)?;
+
+    let mut failed = Vec::new();
+    for source in &sources {
+        let name = source.file_name().unwrap().to_string_lossy();
+        match run_case(source, &work) {
+            Ok(()) => println!("PASS {}", name),
+            Err(e) => {
+                println!("FAIL {}: {}", name, e);
+                failed.push(name.into_owned());
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", sources.len() - failed.len(), failed.len());
+    for name in &failed {
+        println!("  {}", name);
+    }
+    Ok(failed.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::{build_asm, Target};
+
+    // rv64 code called from gcc-compiled C (testcase/abi): stack arguments, array pointers and
+    // callee-saved registers have to follow the standard calling convention. Needs $RISCV_CC.
+    #[test]
+    fn test_abi_gcc_caller() {
+        if std::env::var_os("RISCV_CC").is_none() {
+            return;
+        }
+        let source = fs::read_to_string("testcase/abi/callee.c").unwrap();
+        let program = crate::irgen::build_ir(crate::analyze(&source).unwrap()).unwrap();
+        let work = test_dir();
+        let asm_path = work.join("abi_callee.s");
+        fs::write(&asm_path, build_asm(&program, Target::Rv64)).unwrap();
+        let elf_path = work.join("abi.elf");
+        link(&[Path::new("testcase/abi/caller.c"), &asm_path], &elf_path).unwrap();
+        let mut sim = Simulator::load(&elf_path.to_string_lossy()).map_err(|e| e.to_string()).unwrap();
+        assert_eq!(sim.run(Some(MAX_CYCLES)), Some(0));
+    }
+
+    // -run -O2 goes through mem2reg: the loop counters live in registers instead of stack slots
+    #[test]
+    fn test_build_image_opt_level() {
+        let source = test_dir().join("opt_level.c");
+        fs::write(&source, "int main() { int s = 0; int i = 0; while (i < 10) { s = s + i; i = i + 1; } return s; }").unwrap();
+        for opt_level in 0..=2 {
+            let image = build_image(&source, SimConfig::default().memory.base, opt_level).unwrap();
+            let asm = fs::read_to_string(image.with_extension("s")).unwrap();
+            assert_eq!(asm.contains("lw"), opt_level < 2, "-O{}:\n{}", opt_level, asm);
+            let mut sim = Simulator::load(&image.to_string_lossy()).map_err(|e| e.to_string()).unwrap();
+            assert_eq!(sim.run(Some(MAX_CYCLES)), Some(45));
+        }
+    }
+}
@@ -0,0 +1,666 @@
+// Semantic analysis on the AST, run before IR generation. irgen assumes a well-formed program
+// and panics otherwise; this pass finds the problems first and reports all of them with their
+// positions: undeclared or redefined names, wrong call arity or argument shape (the number of
+// dimensions of an array argument, and its sizes after the first), assignments to
+// constants, misused arrays and void values, return type mismatches, break / continue outside a
+// loop, and non-constant expressions where SysY requires constants (array sizes, const and
+// global initializers). Constant expressions are evaluated the way irgen evaluates them, so
+// array sizes can be compared and const array reads checked against the bounds.
+
+use std::collections::HashMap;
+
+use crate::irgen::ast_df::*;
+use crate::irgen::NO_FLOAT;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub pos: Pos,
+    pub message: String,
+}
+
+// "LINE:COL: error: MESSAGE"
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: error: {}", self.pos, self.message)
+    }
+}
+
+// int values, arrays (and array parameters) by number of dimensions, void call results
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Ty {
+    Int,
+    Array(usize),
+    Void,
+}
+
+// the size of every dimension, None where it is not known (the first one of an array parameter)
+type Dims = Vec<Option<i32>>;
+
+#[derive(Debug, Clone)]
+enum Symbol {
+    Var { dims: Dims },
+    Const { dims: Dims, values: Option<Vec<i32>> }, // elements in row-major order, if known
+    Func { ret: Ty, params: Vec<Dims> },
+}
+
+// the type of an expression, where to point if it is used wrongly (None for literals), and its
+// value if it is a constant expression
+#[derive(Debug, Clone, Copy)]
+struct Val {
+    ty: Ty,
+    pos: Option<Pos>,
+    value: Option<i32>,
+}
+
+impl Val {
+    const INT: Val = Val { ty: Ty::Int, pos: None, value: None };
+
+    fn num(value: Option<i32>) -> Val {
+        Val { value, ..Val::INT }
+    }
+}
+
+// the runtime library, as irgen declares it
+const BUILTINS: [(&str, Ty, &[usize]); 8] = [
+    ("getint", Ty::Int, &[]),
+    ("getch", Ty::Int, &[]),
+    ("getarray", Ty::Int, &[1]),
+    ("putint", Ty::Int, &[0]),
+    ("putch", Ty::Void, &[0]),
+    ("putarray", Ty::Void, &[0, 1]),
+    ("starttime", Ty::Void, &[]),
+    ("stoptime", Ty::Void, &[]),
+];
+
+struct Checker {
+    scopes: Vec<HashMap<String, Symbol>>, // [0] holds globals and functions
+    diags: Vec<Diagnostic>,
+    func: String,
+    ret: Ty,
+    loops: usize,
+}
+
+// all problems in the program, in source order; empty if it can go to irgen
+pub fn check(ast: &CompUnit) -> Vec<Diagnostic> {
+    let mut globals = HashMap::new();
+    for (name, ret, params) in BUILTINS {
+        let params = params.iter().map(|&rank| vec![None; rank]).collect();
+        globals.insert(name.to_string(), Symbol::Func { ret, params });
+    }
+    let mut checker = Checker { scopes: vec![globals], diags: Vec::new(), func: String::new(), ret: Ty::Void, loops: 0 };
+
+    for item in &ast.items {
+        match item {
+            GlobalItem::Decl(decl) => checker.decl(decl),
+            GlobalItem::FuncDef(func) => checker.func_def(func),
+        }
+    }
+    match checker.scopes[0].get("main") {
+        Some(Symbol::Func { ret: Ty::Int, params }) if params.is_empty() => {}
+        Some(_) => {
+            let pos = ast.items.iter().find_map(|item| match item {
+                GlobalItem::FuncDef(f) if f.ident == "main" => Some(f.pos),
+                _ => None,
+            });
+            checker.error(pos.unwrap_or_default(), "'main' must be declared as 'int main()'");
+        }
+        None => checker.error(Pos { line: 1, col: 1 }, "no 'main' function"),
+    }
+    checker.diags.sort_by_key(|d| d.pos);
+    checker.diags
+}
+
+fn rank_name(rank: usize) -> String {
+    match rank {
+        0 => String::from("an int"),
+        1 => String::from("a 1-dimensional array"),
+        n => format!("a {}-dimensional array", n),
+    }
+}
+
+// int[][3][4], as an array parameter is declared
+fn array_type_name(dims: &[Option<i32>]) -> String {
+    let sizes: String = dims.iter().skip(1).map(|dim| dim.map_or(String::from("[]"), |dim| format!("[{}]", dim))).collect();
+    format!("int[]{}", sizes)
+}
+
+// how many trailing dimensions a nested initializer list starting at element `len` fills, as in
+// irgen (array::find_align)
+fn find_align(dims: &[i32], mut len: i32, limit: usize) -> usize {
+    let mut align = 0;
+    for dim in dims.iter().rev() {
+        if len % dim != 0 {
+            break;
+        }
+        align += 1;
+        len /= dim;
+    }
+    align.min(limit - 1)
+}
+
+// the elements of a const array laid out the way irgen lays them out (array::const_init_values),
+// from the values of its initializers in source order; None if a size or an element is not known
+fn const_values(dims: &[Option<i32>], init: &ConstInitVal, leaves: Vec<Option<i32>>) -> Option<Vec<i32>> {
+    let dims: Vec<i32> = dims.iter().copied().collect::<Option<_>>()?;
+    let mut leaves = leaves.into_iter().collect::<Option<Vec<i32>>>()?.into_iter();
+    if dims.iter().any(|&dim| dim <= 0) {
+        return None;
+    }
+    if dims.is_empty() {
+        return Some(vec![leaves.next().unwrap_or(0)]); // `{}` is 0
+    }
+    let mut values = Vec::new();
+    layout(&dims, init, &mut leaves, 0, dims.len() + 1, &mut values);
+    Some(values)
+}
+
+fn layout(dims: &[i32], init: &ConstInitVal, leaves: &mut impl Iterator<Item = i32>, pre_len: i32, limit: usize, values: &mut Vec<i32>) -> i32 {
+    match init {
+        ConstInitVal::ConstExp(_) => {
+            values.push(leaves.next().unwrap_or(0));
+            1
+        }
+        ConstInitVal::InitList(list) => {
+            let align = find_align(dims, pre_len, limit);
+            let mut len = 0;
+            for item in list {
+                len += layout(dims, item, leaves, len, align, values);
+            }
+            let total: i32 = dims.iter().rev().take(align).product();
+            values.resize(values.len() + (total - len).max(0) as usize, 0);
+            total
+        }
+    }
+}
+
+// A[i][j] of a const array, or a scalar constant, at known indices within bounds
+fn element(dims: &[Option<i32>], values: Option<&[i32]>, indices: &[Option<i32>]) -> Option<i32> {
+    if indices.len() != dims.len() {
+        return None;
+    }
+    let mut offset = 0;
+    for (&index, &dim) in indices.iter().zip(dims) {
+        let (index, dim) = (index?, dim?);
+        if !(0..dim).contains(&index) {
+            return None;
+        }
+        offset = offset * dim + index;
+    }
+    values?.get(offset as usize).copied()
+}
+
+impl Checker {
+    fn error(&mut self, pos: Pos, message: impl Into<String>) {
+        self.diags.push(Diagnostic { pos, message: message.into() });
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn declare(&mut self, name: &str, symbol: Symbol, pos: Pos) {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.contains_key(name) {
+            self.error(pos, format!("redefinition of '{}'", name));
+        } else {
+            scope.insert(name.to_string(), symbol);
+        }
+    }
+
+    // int / void are fine for functions, only int for anything else
+    fn base_type(&mut self, ty: &BType, pos: Pos, what: &str) -> bool {
+        match ty {
+            BType::Int => true,
+            BType::Void => {
+                self.error(pos, format!("{} cannot be void", what));
+                false
+            }
+            BType::Float => {
+                self.error(pos, NO_FLOAT);
+                false
+            }
+        }
+    }
+
+    fn func_def(&mut self, func: &FuncDef) {
+        let ret = match func.func_type {
+            BType::Int => Ty::Int,
+            BType::Void => Ty::Void,
+            BType::Float => {
+                self.error(func.pos, NO_FLOAT);
+                Ty::Int
+            }
+        };
+        let mut params = Vec::new();
+        for param in &func.params {
+            self.base_type(&param.ty, param.pos, &format!("parameter '{}'", param.id));
+            let dims: Dims = match &param.dims {
+                Some(dims) => std::iter::once(None).chain(dims.iter().map(|dim| self.const_exp(&dim.exp))).collect(),
+                None => Vec::new(),
+            };
+            params.push(dims);
+        }
+        // declared before the body, so it can call itself
+        self.declare(&func.ident, Symbol::Func { ret, params: params.clone() }, func.pos);
+
+        self.func = func.ident.clone();
+        self.ret = ret;
+        self.scopes.push(HashMap::new());
+        for (param, dims) in func.params.iter().zip(params) {
+            self.declare(&param.id, Symbol::Var { dims }, param.pos);
+        }
+        self.block(&func.block);
+        self.scopes.pop();
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        for item in &block.items {
+            match item {
+                BlockItem::Decl(decl) => self.decl(decl),
+                BlockItem::Stmt(stmt) => self.stmt(stmt),
+            }
+        }
+        self.scopes.pop();
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Const(decl) => {
+                for def in &decl.const_defs {
+                    self.base_type(&decl.ty, def.pos, &format!("constant '{}'", def.ident));
+                    let dims = self.dims(&def.dims);
+                    let mut leaves = Vec::new();
+                    self.const_init(&def.init_val, dims.len(), def.pos, true, &mut leaves);
+                    let values = const_values(&dims, &def.init_val, leaves);
+                    self.declare(&def.ident, Symbol::Const { dims, values }, def.pos);
+                }
+            }
+            Decl::Var(decl) => {
+                for def in &decl.defs {
+                    self.base_type(&decl.ty, def.pos, &format!("variable '{}'", def.ident));
+                    let dims = self.dims(&def.dims);
+                    if let Some(init) = &def.init_val {
+                        self.init(init, dims.len(), def.pos, decl.is_global, true);
+                    }
+                    self.declare(&def.ident, Symbol::Var { dims }, def.pos);
+                }
+            }
+        }
+    }
+
+    // array sizes must be constant
+    fn dims(&mut self, dims: &Option<Vec<ConstExp>>) -> Dims {
+        let dims = dims.as_deref().unwrap_or(&[]);
+        dims.iter().map(|dim| self.const_exp(&dim.exp)).collect()
+    }
+
+    // `leaves` gets the value of every initializer, in source order
+    fn const_init(&mut self, init: &ConstInitVal, rank: usize, pos: Pos, top: bool, leaves: &mut Vec<Option<i32>>) {
+        match init {
+            ConstInitVal::ConstExp(exp) if top && rank > 0 => {
+                self.error(pos, "an array needs an initializer list");
+                self.const_exp(&exp.exp);
+            }
+            ConstInitVal::ConstExp(exp) => {
+                let value = self.const_exp(&exp.exp);
+                leaves.push(value);
+            }
+            // braces around a scalar: `const int c = {1};`, `const int a[2] = {{1}, 2};`
+            ConstInitVal::InitList(list) if rank == 0 => match &list[..] {
+                [] => {}
+                [item] => self.const_init(item, 0, pos, false, leaves),
+                _ => self.error(pos, "too many initializers for a scalar"),
+            },
+            ConstInitVal::InitList(list) => list.iter().for_each(|item| self.const_init(item, rank - 1, pos, false, leaves)),
+        }
+    }
+
+    fn init(&mut self, init: &InitVal, rank: usize, pos: Pos, constant: bool, top: bool) {
+        match init {
+            InitVal::Exp(exp) if top && rank > 0 => {
+                self.error(pos, "an array needs an initializer list");
+                self.exp(exp, constant);
+            }
+            InitVal::Exp(exp) => {
+                let val = self.exp(exp, constant);
+                self.expect_int(val, "initializer");
+            }
+            InitVal::InitList(list) if rank == 0 => match &list[..] {
+                [] => {}
+                [item] => self.init(item, 0, pos, constant, false),
+                _ => self.error(pos, "too many initializers for a scalar"),
+            },
+            InitVal::InitList(list) => list.iter().for_each(|item| self.init(item, rank - 1, pos, constant, false)),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Empty => {}
+            Stmt::Assign(assign) => {
+                let (name, pos) = match &assign.lval {
+                    LVal::Ident(name, pos) | LVal::Array(name, _, pos) => (name, *pos),
+                };
+                match self.lookup(name) {
+                    Some(Symbol::Const { .. }) => self.error(pos, format!("cannot assign to constant '{}'", name)),
+                    _ => {
+                        let target = self.lval(&assign.lval, false);
+                        if let Ty::Array(_) = target.ty {
+                            self.error(pos, format!("cannot assign to array '{}'", name));
+                        }
+                    }
+                }
+                let val = self.exp(&assign.exp, false);
+                self.expect_int(val, "assigned value");
+            }
+            Stmt::Exp(exp) => {
+                self.exp(exp, false);
+            }
+            Stmt::Block(block) => self.block(block),
+            Stmt::Return(ret) => match (&ret.exp, self.ret) {
+                (Some(exp), Ty::Void) => {
+                    self.exp(exp, false);
+                    self.error(ret.pos, format!("void function '{}' should not return a value", self.func));
+                }
+                (Some(exp), _) => {
+                    let val = self.exp(exp, false);
+                    self.expect_int(val, "return value");
+                }
+                (None, Ty::Int) => self.error(ret.pos, format!("function '{}' should return a value", self.func)),
+                (None, _) => {}
+            },
+            Stmt::If(stmt) => {
+                let cond = self.exp(&stmt.cond, false);
+                self.expect_int(cond, "condition");
+                self.stmt(&stmt.stmt);
+                if let Some(else_stmt) = &stmt.else_stmt {
+                    self.stmt(else_stmt);
+                }
+            }
+            Stmt::While(stmt) => {
+                let cond = self.exp(&stmt.cond, false);
+                self.expect_int(cond, "condition");
+                self.loops += 1;
+                self.stmt(&stmt.stmt);
+                self.loops -= 1;
+            }
+            Stmt::Break(pos) if self.loops == 0 => self.error(*pos, "'break' outside a loop"),
+            Stmt::Continue(pos) if self.loops == 0 => self.error(*pos, "'continue' outside a loop"),
+            Stmt::Break(_) | Stmt::Continue(_) => {}
+        }
+    }
+
+    fn expect_int(&mut self, val: Val, what: &str) {
+        match (val.ty, val.pos) {
+            (Ty::Int, _) | (_, None) => {}
+            (Ty::Void, Some(pos)) => self.error(pos, format!("{} is a void function call", what)),
+            (Ty::Array(_), Some(pos)) => self.error(pos, format!("{} is an array, not an int", what)),
+        }
+    }
+
+    fn const_exp(&mut self, exp: &Exp) -> Option<i32> {
+        let val = self.exp(exp, true);
+        self.expect_int(val, "constant expression");
+        val.value
+    }
+
+    // `constant`: only literals and scalar constants may appear (what irgen can evaluate)
+    fn exp(&mut self, exp: &Exp, constant: bool) -> Val {
+        let Exp::LOrExp(exp) = exp;
+        self.lor(exp, constant)
+    }
+
+    // `op` folds the two values, None where irgen would not fold it (division by zero)
+    fn operands(&mut self, a: Val, b: Val, op: impl Fn(i32, i32) -> Option<i32>) -> Val {
+        self.expect_int(a, "operand");
+        self.expect_int(b, "operand");
+        Val::num(a.value.zip(b.value).and_then(|(a, b)| op(a, b)))
+    }
+
+    fn lor(&mut self, exp: &LOrExp, c: bool) -> Val {
+        match exp {
+            LOrExp::LAnd(e) => self.land(e, c),
+            LOrExp::LOrLAnd(a, b) => {
+                let (a, b) = (self.lor(a, c), self.land(b, c));
+                self.operands(a, b, |a, b| Some((a != 0 || b != 0) as i32))
+            }
+        }
+    }
+
+    fn land(&mut self, exp: &LAndExp, c: bool) -> Val {
+        match exp {
+            LAndExp::Eq(e) => self.eq(e, c),
+            LAndExp::LAndEq(a, b) => {
+                let (a, b) = (self.land(a, c), self.eq(b, c));
+                self.operands(a, b, |a, b| Some((a != 0 && b != 0) as i32))
+            }
+        }
+    }
+
+    fn eq(&mut self, exp: &EqExp, c: bool) -> Val {
+        match exp {
+            EqExp::Rel(e) => self.rel(e, c),
+            EqExp::EqRel(a, op, b) => {
+                let (a, b) = (self.eq(a, c), self.rel(b, c));
+                let eq = matches!(op, EqOp::Eq);
+                self.operands(a, b, |a, b| Some(((a == b) == eq) as i32))
+            }
+        }
+    }
+
+    fn rel(&mut self, exp: &RelExp, c: bool) -> Val {
+        match exp {
+            RelExp::Add(e) => self.add(e, c),
+            RelExp::RelAdd(a, op, b) => {
+                let (a, b) = (self.rel(a, c), self.add(b, c));
+                self.operands(a, b, |a, b| Some(match op {
+                    RelOp::Lt => a < b,
+                    RelOp::Gt => a > b,
+                    RelOp::Le => a <= b,
+                    RelOp::Ge => a >= b,
+                } as i32))
+            }
+        }
+    }
+
+    fn add(&mut self, exp: &AddExp, c: bool) -> Val {
+        match exp {
+            AddExp::Mul(e) => self.mul(e, c),
+            AddExp::AddMul(a, op, b) => {
+                let (a, b) = (self.add(a, c), self.mul(b, c));
+                self.operands(a, b, |a, b| Some(match op {
+                    AddOp::Add => a.wrapping_add(b),
+                    AddOp::Sub => a.wrapping_sub(b),
+                }))
+            }
+        }
+    }
+
+    fn mul(&mut self, exp: &MulExp, c: bool) -> Val {
+        match exp {
+            MulExp::Unary(e) => self.unary(e, c),
+            MulExp::MulUnary(a, op, b) => {
+                let (a, b) = (self.mul(a, c), self.unary(b, c));
+                self.operands(a, b, |a, b| match op {
+                    MulOp::Mul => Some(a.wrapping_mul(b)),
+                    MulOp::Div => (b != 0).then(|| a.wrapping_div(b)),
+                    MulOp::Mod => (b != 0).then(|| a.wrapping_rem(b)),
+                })
+            }
+        }
+    }
+
+    fn unary(&mut self, exp: &UnaryExp, c: bool) -> Val {
+        match exp {
+            UnaryExp::PrimaryExp(PrimaryExp::Num(num)) => Val::num(Some(*num)),
+            UnaryExp::PrimaryExp(PrimaryExp::Float(_, pos)) => {
+                self.error(*pos, NO_FLOAT);
+                Val::INT
+            }
+            UnaryExp::PrimaryExp(PrimaryExp::Exp(e)) => self.exp(e, c),
+            UnaryExp::PrimaryExp(PrimaryExp::LVal(lval)) => self.lval(lval, c),
+            UnaryExp::Unary(op, e) => {
+                let val = self.unary(e, c);
+                self.expect_int(val, "operand");
+                Val::num(val.value.map(|v| match op {
+                    UnaryOp::Plus => v,
+                    UnaryOp::Minus => v.wrapping_neg(),
+                    UnaryOp::Not => (v == 0) as i32,
+                }))
+            }
+            UnaryExp::FuncCall(call) => {
+                if c {
+                    self.error(call.pos, format!("call to '{}' in a constant expression", call.id));
+                }
+                self.call(call)
+            }
+        }
+    }
+
+    fn lval(&mut self, lval: &LVal, constant: bool) -> Val {
+        let (name, indices, pos) = match lval {
+            LVal::Ident(name, pos) => (name, &[][..], *pos),
+            LVal::Array(name, indices, pos) => (name, &indices[..], *pos),
+        };
+        let mut index_values = Vec::new();
+        for index in indices {
+            let val = self.exp(index, constant);
+            self.expect_int(val, "array index");
+            index_values.push(val.value);
+        }
+        let (rank, value) = match self.lookup(name) {
+            None => {
+                self.error(pos, format!("'{}' was not declared", name));
+                return Val::INT;
+            }
+            Some(Symbol::Func { .. }) => {
+                self.error(pos, format!("'{}' is a function, not a variable", name));
+                return Val::INT;
+            }
+            Some(Symbol::Var { .. }) if constant => {
+                self.error(pos, format!("'{}' is not a constant", name));
+                return Val::INT;
+            }
+            // an element of a const array is fine, A[1] * 2, the array itself is not
+            Some(Symbol::Const { dims, .. }) if constant && dims.len() > indices.len() => {
+                self.error(pos, format!("array '{}' cannot be used in a constant expression", name));
+                return Val::INT;
+            }
+            // irgen evaluates these at compile time and cannot read past the end
+            Some(Symbol::Const { dims, .. }) if constant && index_values.iter().zip(dims)
+                .any(|(&index, &dim)| matches!((index, dim), (Some(index), Some(dim)) if !(0..dim).contains(&index))) => {
+                self.error(pos, format!("index out of bounds for '{}' in a constant expression", name));
+                return Val::INT;
+            }
+            Some(Symbol::Var { dims }) => (dims.len(), None),
+            Some(Symbol::Const { dims, values }) => (dims.len(), element(dims, values.as_deref(), &index_values)),
+        };
+        if indices.len() > rank {
+            let message = match rank {
+                0 => format!("'{}' is not an array", name),
+                _ => format!("'{}' has {} dimension(s), indexed with {}", name, rank, indices.len()),
+            };
+            self.error(pos, message);
+            return Val::INT;
+        }
+        let ty = if indices.len() == rank { Ty::Int } else { Ty::Array(rank - indices.len()) };
+        Val { ty, pos: Some(pos), value }
+    }
+
+    // the dims left of an array passed as an argument, `a` or `a[i]`
+    fn arg_dims(&self, arg: &Exp) -> Option<Dims> {
+        let (name, indexed) = match arg.as_lval()? {
+            LVal::Ident(name, _) => (name, 0),
+            LVal::Array(name, indices, _) => (name, indices.len()),
+        };
+        match self.lookup(name)? {
+            Symbol::Var { dims } | Symbol::Const { dims, .. } => dims.get(indexed..).map(<[_]>::to_vec),
+            Symbol::Func { .. } => None,
+        }
+    }
+
+    fn call(&mut self, call: &FuncCall) -> Val {
+        let args: Vec<Val> = call.args.iter().map(|arg| self.exp(arg, false)).collect();
+        let (ret, params) = match self.lookup(&call.id) {
+            Some(Symbol::Func { ret, params }) => (*ret, params.clone()),
+            Some(_) => {
+                self.error(call.pos, format!("'{}' is not a function", call.id));
+                return Val::INT;
+            }
+            None => {
+                self.error(call.pos, format!("function '{}' was not declared", call.id));
+                return Val::INT;
+            }
+        };
+        if args.len() != params.len() {
+            self.error(call.pos, format!("'{}' expects {} argument(s), got {}", call.id, params.len(), args.len()));
+        } else {
+            for (i, (arg, dims)) in args.iter().zip(params).enumerate() {
+                let rank = dims.len();
+                let actual = match arg.ty {
+                    Ty::Int => 0,
+                    Ty::Array(n) => n,
+                    Ty::Void => {
+                        self.expect_int(*arg, "argument");
+                        continue;
+                    }
+                };
+                let pos = arg.pos.unwrap_or(call.pos);
+                if actual != rank {
+                    self.error(pos, format!("argument {} of '{}' should be {}, got {}", i + 1, call.id, rank_name(rank), rank_name(actual)));
+                    continue;
+                }
+                // the sizes after the first must match as well, where both are known
+                let Some(got) = self.arg_dims(&call.args[i]).filter(|_| rank > 0) else {
+                    continue;
+                };
+                if got.iter().zip(&dims).skip(1).any(|(a, b)| matches!((a, b), (Some(a), Some(b)) if a != b)) {
+                    self.error(pos, format!("argument {} of '{}' should be {}, got {}", i + 1, call.id, array_type_name(&dims), array_type_name(&got)));
+                }
+            }
+        }
+        Val { ty: ret, pos: Some(call.pos), value: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn messages(source: &str) -> Vec<String> {
+        check(&parse(source).unwrap()).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sema() {
+        assert!(messages("const int N = 2; int a[N][3]; int f(int x[][3]) { return x[1][2]; }\nint main() { return f(a) + getint(); }").is_empty());
+
+        let source = "int g;\nvoid f(int a[]) { return 1; }\nint main() {\n  const int c = g;\n  c = 2;\n  int b[2];\n  f(b[0]);\n  f(b, 1);\n  x = f(b);\n  break;\n}\n";
+        assert_eq!(messages(source), [
+            "2:19: error: void function 'f' should not return a value",
+            "4:17: error: 'g' is not a constant",
+            "5:3: error: cannot assign to constant 'c'",
+            "7:5: error: argument 1 of 'f' should be a 1-dimensional array, got an int",
+            "8:3: error: 'f' expects 1 argument(s), got 2",
+            "9:3: error: 'x' was not declared",
+            "9:7: error: assigned value is a void function call",
+            "10:3: error: 'break' outside a loop",
+        ]);
+        assert_eq!(messages("int f() { return 0; }"), ["1:1: error: no 'main' function"]);
+        assert!(messages("int x = {1}; int main() { const int c = {{2}}; int y = {}; int a[2][2] = {1, {2}, 3}; return x + c + y; }").is_empty());
+        assert_eq!(messages("int x = {1, 2}; int main() { return 0; }"), ["1:5: error: too many initializers for a scalar"]);
+        assert!(messages("const int A[2][2] = {{1, 2}, 3}; const int K = 1; int n = A[1][0] * 2 + K;\nint main() { return n; }").is_empty());
+
+        // array arguments: sizes after the first are compared, also when they come from constants
+        let source = "const int N = 3; const int A[2] = {1, N + 1};\nint f(int a[][N]) { return a[0][0]; }\nint main() { int b[2][A[1]]; int c[4][3]; return f(b) + f(c) + A[2 - 1 * 2]; }";
+        assert_eq!(messages(source), ["3:52: error: argument 1 of 'f' should be int[][3], got int[][4]"]);
+        assert_eq!(messages("const int A[2] = {1};\nint x = A[2];\nint main() { return x; }"), ["2:9: error: index out of bounds for 'A' in a constant expression"]);
+
+        // float is parsed but stops here, irgen never sees it
+        let source = "float f(float x) { return 1.5; }\nint main() { const float c = 2; float v[2]; return 0; }";
+        let expected: Vec<String> = ["1:7", "1:15", "1:27", "2:26", "2:39"].iter().map(|pos| format!("{}: error: {}", pos, NO_FLOAT)).collect();
+        assert_eq!(messages(source), expected);
+    }
+}
@@ -1,7 +1,11 @@
 use eframe::egui;
-use crate::simulator::{Pipeline, Memory, parse_hex_address};
+use crate::simulator::{Pipeline, Memory, SimConfig, parse_hex_address, step_cycles, MAX_EXAMINE_COUNT, REG_NAMES};
+use crate::simulator::{History, HistorySearch, HISTORY_FILE, command_line};
 
+// 简化版图形界面：控制栏 + 寄存器/控制台 + 内存查看器，使用 --shell 启动
 pub struct GraphicalShell {
+    image: String,
+    config: SimConfig,
     prog: Pipeline,
     mem: Memory,
     running: bool,
@@ -11,28 +15,32 @@ pub struct GraphicalShell {
     mem_count: String,
     mem_view: Vec<(u64, u64)>,
     console_output: String,
+    command_input: String,
+    history: History,
+    history_search: Option<HistorySearch>,
 }
 
-impl Default for GraphicalShell {
-    fn default() -> Self {
+impl GraphicalShell {
+    pub fn new(image: String, config: SimConfig) -> Self {
+        let (prog, mem) = config.build(&image).unwrap();
+
         Self {
-            prog: Pipeline::new(),
-            mem: Memory::new(),
+            image,
+            config,
+            prog,
+            mem,
             running: true,
             debug_mode: true,
             step_count: "1".to_string(),
-            mem_addr: "0x1000".to_string(),
+            mem_addr: "0x80000000".to_string(),
             mem_count: "10".to_string(),
             mem_view: Vec::new(),
             console_output: "Simulator ready. Type 'help' for commands.\n".to_string(),
+            command_input: String::new(),
+            history: History::load(HISTORY_FILE),
+            history_search: None,
         }
     }
-}
-
-impl GraphicalShell {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        Default::default()
-    }
 
     fn add_console_output(&mut self, text: &str) {
         self.console_output.push_str(text);
@@ -65,18 +73,22 @@ impl GraphicalShell {
                     None => 1,
                 };
 
-                for _ in 0..n {
-                    if !self.running { break; }
-                    self.prog.step(&mut self.mem);
-                    self.running = self.prog.cpu.running;
+                if !self.running {
+                    self.add_console_output("The program is not running");
+                    return;
                 }
-                self.add_console_output("Step executed");
+                let (_, message) = step_cycles(&mut self.prog, &mut self.mem, n);
+                self.running = self.prog.cpu.running;
+                self.add_console_output(message.trim_end());
             },
             Some("info") => {
                 match parts.next() {
                     Some("r") => {
-                        let state = self.prog.print_state(&self.mem);
-                        self.add_console_output(&state);
+                        self.add_console_output(&format!("pc: 0x{:016x}", self.prog.cpu.pc));
+                        for (name, value) in REG_NAMES.iter().zip(self.prog.cpu.reg) {
+                            let line = format!("{:>4}: 0x{:016x}", name, value);
+                            self.add_console_output(&line);
+                        }
                     },
                     Some(_) => self.add_console_output("Invalid info subcommand"),
                     None => self.add_console_output("Missing subcommand for info"),
@@ -85,7 +97,11 @@ impl GraphicalShell {
             Some("x") => {
                 let n = match parts.next() {
                     Some(n_str) => match n_str.parse::<usize>() {
-                        Ok(n) => n,
+                        Ok(n) if n <= MAX_EXAMINE_COUNT => n,
+                        Ok(_) => {
+                            self.add_console_output(&format!("x prints at most {} words at a time", MAX_EXAMINE_COUNT));
+                            return;
+                        }
                         Err(_) => {
                             self.add_console_output("Invalid number");
                             return;
@@ -109,9 +125,13 @@ impl GraphicalShell {
                     Ok(addr) => {
                         self.mem_view.clear();
                         for i in 0..n {
-                            let current_addr = addr + (i * 4) as u64;
-                            if let Ok(data) = self.mem.mem_read(current_addr, 4) {
-                                self.mem_view.push((current_addr, data));
+                            let current_addr = addr.wrapping_add((i * 4) as u64);
+                            match self.mem.mem_read(current_addr, 4) {
+                                Ok(data) => self.mem_view.push((current_addr, data)),
+                                Err(e) => {
+                                    self.add_console_output(&format!("0x{:08x}: {}", current_addr, e));
+                                    break;
+                                }
                             }
                         }
                     },
@@ -142,117 +162,128 @@ impl eframe::App for GraphicalShell {
         if !self.debug_mode && self.running {
             self.prog.step(&mut self.mem);
             self.running = self.prog.cpu.running;
+            if !self.running {
+                self.add_console_output(&format!("Program stopped after {} cycles, a0 = {}",
+                    self.prog.cpu.cycle_count, self.prog.cpu.reg[10] as i64));
+            }
         }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            // 顶部控制栏
-            egui::TopBottomPanel::top("control_panel").show(ctx, |ui| {
-                ui.horizontal(|ui| {
-                    if ui.button("Run").clicked() {
-                        self.debug_mode = false;
-                        self.add_console_output("Continuing execution");
-                    }
-                    if ui.button("Pause").clicked() {
-                        self.debug_mode = true;
-                        self.add_console_output("Entering debug mode");
-                    }
-                    if ui.button("Step").clicked() {
-                        self.execute_command("si");
-                    }
-                    
-                    ui.label("Steps:");
-                    ui.text_edit_singleline(&mut self.step_count);
-                    if ui.button("Step N").clicked() {
-                        self.execute_command(&format!("si {}", self.step_count));
-                    }
-                    
-                    if ui.button("Reset").clicked() {
-                        *self = Self::default();
-                    }
-                    
-                    ui.separator();
-                    
-                    ui.label("Status:");
-                    ui.label(if self.running {
-                        "Running"
-                    } else {
-                        "Stopped"
-                    });
+        // 顶部控制栏
+        egui::TopBottomPanel::top("control_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Run").clicked() {
+                    self.debug_mode = false;
+                    self.add_console_output("Continuing execution");
+                }
+                if ui.button("Pause").clicked() {
+                    self.debug_mode = true;
+                    self.add_console_output("Entering debug mode");
+                }
+                if ui.button("Step").clicked() {
+                    self.execute_command("si");
+                }
+
+                ui.label("Steps:");
+                ui.add(egui::TextEdit::singleline(&mut self.step_count).desired_width(60.0));
+                if ui.button("Step N").clicked() {
+                    self.execute_command(&format!("si {}", self.step_count));
+                }
+
+                if ui.button("Reset").clicked() {
+                    *self = Self::new(std::mem::take(&mut self.image), self.config.clone());
+                }
+
+                ui.separator();
+
+                ui.label("Status:");
+                ui.label(if self.running {
+                    "Running"
+                } else {
+                    "Stopped"
                 });
             });
+        });
 
-            // 主内容区域
+        // 底部命令行
+        egui::TopBottomPanel::bottom("command_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                // 左侧面板：寄存器和状态
-                egui::SidePanel::left("register_panel").show_inside(ui, |ui| {
-                    ui.vertical(|ui| {
-                        ui.heading("CPU State");
-                        if ui.button("Refresh State").clicked() {
-                            self.execute_command("info r");
-                        }
-                        egui::ScrollArea::vertical().show(ui, |ui| {
-                            ui.monospace(&self.console_output);
-                        });
-                    });
-                });
+                let submitted = command_line(ui, &mut self.command_input, &mut self.history, &mut self.history_search);
+                if ui.button("Execute").clicked() || submitted {
+                    let cmd = std::mem::take(&mut self.command_input);
+                    if !cmd.trim().is_empty() {
+                        self.history.push(&cmd);
+                        self.execute_command(&cmd);
+                    }
+                }
+            });
+        });
 
-                // 中间分隔线
-                ui.separator();
+        // 右侧面板：内存查看器
+        egui::SidePanel::right("memory_panel").show(ctx, |ui| {
+            ui.heading("Memory Viewer");
 
-                // 右侧面板：内存查看器
-                egui::SidePanel::right("memory_panel").show_inside(ui, |ui| {
-                    ui.vertical(|ui| {
-                        ui.heading("Memory Viewer");
-                        
-                        ui.horizontal(|ui| {
-                            ui.label("Address:");
-                            ui.text_edit_singleline(&mut self.mem_addr);
-                            
-                            ui.label("Count:");
-                            ui.text_edit_singleline(&mut self.mem_count);
-                            
-                            if ui.button("Examine").clicked() {
-                                self.execute_command(&format!("x {} {}", self.mem_count, self.mem_addr));
-                            }
-                        });
-                        
-                        egui::ScrollArea::vertical().show(ui, |ui| {
-                            egui::Grid::new("memory_grid")
-                                .num_columns(2)
-                                .striped(true)
-                                .show(ui, |ui| {
-                                    ui.label("Address");
-                                    ui.label("Value");
-                                    ui.end_row();
-                                    
-                                    for (addr, value) in &self.mem_view {
-                                        ui.label(format!("0x{:08x}", addr));
-                                        ui.label(format!("0x{:08x}", value));
-                                        ui.end_row();
-                                    }
-                                });
-                        });
-                    });
-                });
+            ui.horizontal(|ui| {
+                ui.label("Address:");
+                ui.add(egui::TextEdit::singleline(&mut self.mem_addr).desired_width(100.0));
+
+                ui.label("Count:");
+                ui.add(egui::TextEdit::singleline(&mut self.mem_count).desired_width(40.0));
+
+                if ui.button("Examine").clicked() {
+                    self.execute_command(&format!("x {} {}", self.mem_count, self.mem_addr));
+                }
             });
 
-            // 底部命令行
-            egui::TopBottomPanel::bottom("command_panel").show(ctx, |ui| {
-                ui.horizontal(|ui| {
-                    let command_input = ui.text_edit_singleline(&mut String::new());
-                    if ui.button("Execute").clicked() || command_input.lost_focus() {
-                        if let Some(cmd) = command_input.text() {
-                            if !cmd.trim().is_empty() {
-                                self.execute_command(cmd);
-                                command_input.clear();
-                            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("memory_grid")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Address");
+                        ui.label("Value");
+                        ui.end_row();
+
+                        for (addr, value) in &self.mem_view {
+                            ui.label(format!("0x{:08x}", addr));
+                            ui.label(format!("0x{:08x}", value));
+                            ui.end_row();
                         }
-                    }
+                    });
+            });
+        });
+
+        // 中间：寄存器和控制台输出
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("CPU State");
+            if ui.button("Refresh State").clicked() {
+                self.execute_command("info r");
+            }
+            // 程序通过 UART 输出的内容单独显示在控制台上方
+            if !self.mem.uart.tx.is_empty() {
+                ui.label("Guest output:");
+                egui::ScrollArea::vertical().id_source("guest_output").max_height(120.0).stick_to_bottom(true).show(ui, |ui| {
+                    ui.monospace(String::from_utf8_lossy(&self.mem.uart.tx));
                 });
+                ui.separator();
+            }
+            egui::ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
+                ui.monospace(&self.console_output);
             });
         });
 
         // 请求重绘以保持动画
         ctx.request_repaint();
     }
-}
\ No newline at end of file
+}
+// 运行简化版图形界面
+pub fn run_shell(image: String, config: SimConfig) -> Result<(), eframe::Error> {
+    eframe::run_native(
+        "Simulator Shell",
+        eframe::NativeOptions {
+            viewport: egui::ViewportBuilder::default()
+                .with_inner_size([900.0, 600.0]),
+            ..Default::default()
+        },
+        Box::new(move |_cc| Box::new(GraphicalShell::new(image, config))),
+    )
+}
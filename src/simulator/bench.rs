@@ -0,0 +1,151 @@
+// CPU models, and comparing them on one image: `bench IMAGE`.
+// The single-cycle model pushes each instruction through all five stages in one long cycle,
+// so its clock period is taken as five pipeline stage delays when computing speedup.
+
+use super::compressed;
+use super::config::SimConfig;
+use super::cpu::{CPUState, IDEXReg, IFIDReg, IRQ_M_TIMER};
+use super::decode::{decode_stage, execute_stage, memory_stage, writeback_stage};
+use super::perf::InstClass;
+
+const MAX_CYCLES: u64 = 500_000_000;
+const STAGES: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuModel {
+    SingleCycle,
+    Pipeline, // caches as configured
+}
+
+// result of running an image to completion on one model
+#[derive(Debug, Clone)]
+pub struct ModelRun {
+    pub model: &'static str,
+    pub cycles: u64,
+    pub insts: u64,
+    pub exit_code: u64,
+    pub stage_delays: u64, // cycles * clock period
+    pub note: String,
+    pub output: Vec<u8>, // bytes written to the UART
+}
+
+fn single_cycle(image: &str, config: &SimConfig, input: Option<&str>) -> Result<ModelRun, String> {
+    let mut mem = config.new_memory();
+    mem.load_image(image).map_err(|e| e.to_string())?;
+    config.queue_input(&mut mem)?;
+    mem.uart.quiet = true;
+    if let Some(input) = input {
+        mem.uart.push_input(input);
+    }
+    let mut cpu = CPUState::new();
+    cpu.pc = mem.entry();
+    cpu.reg[2] = config.stack_top();
+    cpu.running = true;
+    cpu.quiet = true;
+
+    let mut cycles = 0;
+    while cpu.running {
+        if cycles == MAX_CYCLES {
+            return Err(format!("single-cycle: no exit after {} cycles", MAX_CYCLES));
+        }
+        cpu.cycle_count += 1; // for rdcycle
+        mem.clint.tick();
+        cpu.set_timer_pending(mem.clint.timer_pending());
+        if cpu.interrupt_pending() {
+            cpu.pc = cpu.trap(cpu.pc, IRQ_M_TIMER);
+        }
+        let (inst, compressed) = compressed::fetch(&mem, cpu.pc).map_err(|e| e.to_string())?;
+        let fetched = IFIDReg { pc: cpu.pc, inst, seq: 1, compressed };
+        // with a trap handler installed, an illegal instruction traps in execute_stage
+        let decoded = match decode_stage(&cpu, &fetched) {
+            Ok(decoded) => decoded,
+            Err(_) if cpu.csr.mtvec != 0 => IDEXReg { pc: cpu.pc, inst, seq: 1, compressed, ..IDEXReg::default() },
+            Err(e) => return Err(e.to_string()),
+        };
+        cpu.next_pc = cpu.pc.wrapping_add(if compressed { 2 } else { 4 });
+        let executed = execute_stage(&mut cpu, &decoded, &mut mem);
+        if let Some(handler) = cpu.trap_pc.take() {
+            cpu.next_pc = handler;
+        }
+        let accessed = memory_stage(&mut cpu, &executed, &mut mem);
+        writeback_stage(&mut cpu, &accessed).map_err(|e| e.to_string())?;
+        if executed.seq != 0 {
+            cpu.hpm[InstClass::of(inst) as usize] += 1; // no stalls or mispredictions here
+        }
+        cpu.pc = cpu.next_pc;
+        cycles += 1;
+    }
+    Ok(ModelRun {
+        model: "single-cycle",
+        cycles,
+        insts: cpu.inst_count as u64,
+        exit_code: cpu.reg[10],
+        stage_delays: cycles * STAGES,
+        note: String::new(),
+        output: mem.uart.tx,
+    })
+}
+
+fn pipelined(image: &str, config: &SimConfig, model: &'static str, caches: bool, input: Option<&str>) -> Result<ModelRun, String> {
+    let (mut pipeline, mut mem) = config.build(image)?;
+    pipeline.set_quiet(&mut mem, true);
+    pipeline.enable_caches(caches);
+    if let Some(input) = input {
+        mem.uart.push_input(input);
+    }
+
+    while pipeline.cpu.running {
+        if pipeline.cpu.cycle_count as u64 == MAX_CYCLES {
+            return Err(format!("{}: no exit after {} cycles", model, MAX_CYCLES));
+        }
+        pipeline.step(&mut mem);
+    }
+    if let Some(fault) = &pipeline.fault {
+        return Err(format!("{}: {}", model, fault));
+    }
+
+    let counters = pipeline.perf_counters();
+    let mut note = format!("{} load-use stalls, {} mispredicts", counters.load_use_stalls, counters.mispredicts);
+    for cache in pipeline.icache.iter().chain(pipeline.dcache.iter()) {
+        note.push_str(&format!(", {} {:.1}% hit", cache.name, cache.hit_rate() * 100.0));
+    }
+    Ok(ModelRun {
+        model,
+        cycles: counters.cycles,
+        insts: counters.insts,
+        exit_code: pipeline.cpu.reg[10],
+        stage_delays: counters.cycles,
+        note,
+        output: mem.uart.tx,
+    })
+}
+
+// run `image` to completion on `model`, with `input` queued on the UART
+pub fn run_model(image: &str, config: &SimConfig, model: CpuModel, input: Option<&str>) -> Result<ModelRun, String> {
+    match model {
+        CpuModel::SingleCycle => single_cycle(image, config, input),
+        CpuModel::Pipeline if config.cache.enabled => pipelined(image, config, "pipeline + I/D caches", true, input),
+        CpuModel::Pipeline => pipelined(image, config, "pipeline, predict not-taken", false, input),
+    }
+}
+
+// cache geometry and memory map come from `config`
+pub fn run_bench(image: &str, config: &SimConfig) -> Result<(), String> {
+    let rows = [
+        single_cycle(image, config, None)?,
+        pipelined(image, config, "pipeline, predict not-taken", false, None)?,
+        pipelined(image, config, "pipeline + I/D caches", true, None)?,
+    ];
+
+    let base = rows[0].stage_delays as f64;
+    println!("{:<28} {:>12} {:>12} {:>7} {:>14} {:>8}  Notes", "Model", "Cycles", "Insts", "CPI", "Stage delays", "Speedup");
+    for row in &rows {
+        println!("{:<28} {:>12} {:>12} {:>7.3} {:>14} {:>7.2}x  {}",
+            row.model, row.cycles, row.insts, row.cycles as f64 / row.insts.max(1) as f64,
+            row.stage_delays, base / row.stage_delays.max(1) as f64, row.note);
+    }
+    if rows.iter().any(|r| r.exit_code != rows[0].exit_code) {
+        println!("warning: models disagree on the exit code");
+    }
+    Ok(())
+}
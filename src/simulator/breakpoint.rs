@@ -0,0 +1,405 @@
+// Numbered breakpoints and watchpoints shared by every debugger frontend. Pipeline::step consults
+// the manager each cycle and leaves what fired in `Pipeline::breakpoint_hit`, so a frontend only
+// has to stop running when it is set.
+//
+// A breakpoint fires when the instruction at its address reaches the MEM/WB latch, the point at
+// which step/next/finish consider it committed. Checking the fetch pc instead would also stop on
+// wrong-path instructions behind a mispredicted branch. The instruction has not written back yet,
+// so a condition sees the registers as they were right before it.
+//
+// Watchpoints are checked in the memory stage against every load and store that overlaps their
+// range. The access has already happened when the program stops, at the same commit point.
+
+use std::fmt;
+
+use super::cpu::{CPUState, REG_NAMES};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    // two-character operators first so "<=" is not read as "<"
+    const ALL: [(&'static str, CmpOp); 6] = [
+        ("==", CmpOp::Eq), ("!=", CmpOp::Ne), ("<=", CmpOp::Le),
+        (">=", CmpOp::Ge), ("<", CmpOp::Lt), (">", CmpOp::Gt),
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        Self::ALL.iter().find(|(_, op)| *op == self).map_or("?", |(s, _)| s)
+    }
+
+    // "LHS OP RHS" split at the first operator
+    pub fn split(text: &str) -> Option<(&str, CmpOp, &str)> {
+        let (pos, sym, op) = Self::ALL.iter()
+            .filter_map(|&(sym, op)| text.find(sym).map(|pos| (pos, sym, op)))
+            .min_by_key(|&(pos, _, _)| pos)?;
+        Some((text[..pos].trim(), op, text[pos + sym.len()..].trim()))
+    }
+
+    pub fn compare(self, a: i64, b: i64) -> bool {
+        match self {
+            CmpOp::Eq => a == b,
+            CmpOp::Ne => a != b,
+            CmpOp::Lt => a < b,
+            CmpOp::Le => a <= b,
+            CmpOp::Gt => a > b,
+            CmpOp::Ge => a >= b,
+        }
+    }
+}
+
+// `REG OP VALUE`, e.g. `$a0 == 5` or `x11 < 0x10`; compared as signed 64-bit integers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Condition {
+    pub reg: usize,
+    pub op: CmpOp,
+    pub value: i64,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (reg, op, value) = CmpOp::split(text)
+            .ok_or_else(|| format!("Invalid condition '{}', expected REG OP VALUE", text.trim()))?;
+        Ok(Self { reg: parse_register(reg)?, op, value: parse_value(value)? })
+    }
+
+    pub fn holds(&self, cpu: &CPUState) -> bool {
+        self.op.compare(cpu.reg[self.reg] as i64, self.value)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "${} {} {}", REG_NAMES[self.reg], self.op.symbol(), self.value)
+    }
+}
+
+// a0 / $a0 / x10
+fn parse_register(name: &str) -> Result<usize, String> {
+    let name = name.trim_start_matches('$');
+    REG_NAMES.iter().position(|r| *r == name)
+        .or_else(|| name.strip_prefix('x').and_then(|n| n.parse::<usize>().ok()).filter(|i| *i < 32))
+        .ok_or_else(|| format!("Unknown register '{}'", name))
+}
+
+// decimal or 0x hex, optionally negative
+fn parse_value(s: &str) -> Result<i64, String> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).map(|v| v as i64),
+        None => digits.parse::<i64>(),
+    }.map_err(|_| format!("Invalid value '{}'", s))?;
+    Ok(if negative { value.wrapping_neg() } else { value })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub addr: u64,
+    pub temporary: bool, // deleted after it fires once
+    pub condition: Option<Condition>,
+    pub hits: u64,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = if self.temporary { "Temporary breakpoint" } else { "Breakpoint" };
+        write!(f, "{} {} at 0x{:08x}", kind, self.id, self.addr)?;
+        if let Some(cond) = &self.condition {
+            write!(f, " if {}", cond)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access, // either
+}
+
+impl WatchKind {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "r" => Some(WatchKind::Read),
+            "w" => Some(WatchKind::Write),
+            "rw" => Some(WatchKind::Access),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub addr: u64,
+    pub len: u64,
+    pub kind: WatchKind,
+    pub hits: u64,
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Watchpoint {} ({}) on [0x{:08x}, 0x{:08x})", self.id, self.kind.name(), self.addr, self.addr + self.len)
+    }
+}
+
+// a load or store that touched a watchpoint's range; `old` and `new` are the `size` bytes at
+// `addr` before and after the access, so they are equal for a read. An atomic read-modify-write
+// is both a read and a write
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchHit {
+    pub id: usize,
+    pub pc: u64,
+    pub addr: u64,
+    pub size: usize,
+    pub read: bool,
+    pub write: bool,
+    pub old: u64,
+    pub new: u64,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.size * 2;
+        if self.write {
+            write!(f, "Watchpoint {}: write to 0x{:08x} at pc 0x{:08x}, old value = 0x{:0width$x}, new value = 0x{:0width$x}",
+                self.id, self.addr, self.pc, self.old, self.new)
+        } else {
+            write!(f, "Watchpoint {}: read from 0x{:08x} at pc 0x{:08x}, value = 0x{:0width$x}", self.id, self.addr, self.pc, self.new)
+        }
+    }
+}
+
+// what stopped the program in the last cycle
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hit {
+    Breakpoint(Breakpoint),
+    Watchpoint(WatchHit),
+}
+
+impl Hit {
+    // the instruction that caused the stop
+    pub fn pc(&self) -> u64 {
+        match self {
+            Hit::Breakpoint(b) => b.addr,
+            Hit::Watchpoint(w) => w.pc,
+        }
+    }
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hit::Breakpoint(b) => b.fmt(f),
+            Hit::Watchpoint(w) => w.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BreakpointManager {
+    list: Vec<Breakpoint>, // in id order
+    watches: Vec<Watchpoint>,
+    last_id: usize, // breakpoints and watchpoints share the numbering
+}
+
+impl BreakpointManager {
+    // returns the new breakpoint's number, counting from 1
+    pub fn add(&mut self, addr: u64, temporary: bool, condition: Option<Condition>) -> usize {
+        self.last_id += 1;
+        self.list.push(Breakpoint { id: self.last_id, addr, temporary, condition, hits: 0 });
+        self.last_id
+    }
+
+    pub fn watch(&mut self, addr: u64, len: u64, kind: WatchKind) -> usize {
+        self.last_id += 1;
+        self.watches.push(Watchpoint { id: self.last_id, addr, len, kind, hits: 0 });
+        self.last_id
+    }
+
+    // deletes breakpoint or watchpoint `id`, returns whether it existed
+    pub fn delete(&mut self, id: usize) -> bool {
+        let before = self.list.len() + self.watches.len();
+        self.list.retain(|b| b.id != id);
+        self.watches.retain(|w| w.id != id);
+        self.list.len() + self.watches.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.watches.clear();
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        self.list.iter().any(|b| b.addr == addr)
+    }
+
+    // removes every breakpoint at `addr`, returns whether there was one
+    pub fn remove_at(&mut self, addr: u64) -> bool {
+        let before = self.list.len();
+        self.list.retain(|b| b.addr != addr);
+        self.list.len() != before
+    }
+
+    // for clicking a line in the GUI: removes the breakpoints at `addr`, or sets a plain one
+    pub fn toggle(&mut self, addr: u64) {
+        if !self.remove_at(addr) {
+            self.add(addr, false, None);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.list.iter()
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watches.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty() && self.watches.is_empty()
+    }
+
+    pub fn has_watchpoints(&self) -> bool {
+        !self.watches.is_empty()
+    }
+
+    // the breakpoint at `pc` whose condition holds, if any; temporary ones are deleted here
+    pub fn check(&mut self, pc: u64, cpu: &CPUState) -> Option<Breakpoint> {
+        let index = self.list.iter()
+            .position(|b| b.addr == pc && b.condition.is_none_or(|c| c.holds(cpu)))?;
+        self.list[index].hits += 1;
+        let hit = self.list[index].clone();
+        if hit.temporary {
+            self.list.remove(index);
+        }
+        Some(hit)
+    }
+
+    // `access` with the number of the first watchpoint it overlaps and matches the kind of
+    pub fn check_access(&mut self, access: WatchHit) -> Option<WatchHit> {
+        let end = access.addr.wrapping_add(access.size as u64);
+        let w = self.watches.iter_mut().find(|w| {
+            let kind = match w.kind {
+                WatchKind::Read => access.read,
+                WatchKind::Write => access.write,
+                WatchKind::Access => access.read || access.write,
+            };
+            kind && access.addr < w.addr + w.len && w.addr < end
+        })?;
+        w.hits += 1;
+        Some(WatchHit { id: w.id, ..access })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::embed::Simulator;
+
+    #[test]
+    fn test_condition_parse() {
+        let cond = Condition::parse("$a0==5").unwrap();
+        assert_eq!(cond, Condition { reg: 10, op: CmpOp::Eq, value: 5 });
+        assert_eq!(Condition::parse("x11 <= -0x10").unwrap(), Condition { reg: 11, op: CmpOp::Le, value: -16 });
+        assert_eq!(cond.to_string(), "$a0 == 5");
+        assert!(Condition::parse("a0 5").is_err());
+        assert!(Condition::parse("q0 == 5").is_err());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // a0 counts 1..=5, the addi at `again` commits once per iteration
+        let asm = "main:
+  li a0, 0
+again:
+  addi a0, a0, 1
+  li t0, 5
+  blt a0, t0, again
+  ret
+";
+        let path = crate::runner::test_image(asm);
+        let image = crate::codegen::encode::assemble(asm, 0x8000_0000).unwrap();
+        let addi = image.symbols["again"];
+        let mut sim = Simulator::load(&path).unwrap();
+
+        let run = |sim: &mut Simulator| loop {
+            sim.pipeline.step(&mut sim.mem);
+            if let Some(hit) = sim.pipeline.breakpoint_hit.take() {
+                return Some(hit);
+            }
+            if !sim.pipeline.cpu.running {
+                return None;
+            }
+        };
+
+        let id = sim.pipeline.breakpoints.add(addi, false, Some(Condition::parse("a0 == 3").unwrap()));
+        let Some(Hit::Breakpoint(hit)) = run(&mut sim) else { panic!("breakpoint not hit") };
+        assert_eq!((hit.id, hit.hits), (id, 1));
+        // a0 still holds the value from before the addi
+        assert_eq!(sim.pipeline.cpu.reg[10], 3);
+
+        sim.pipeline.breakpoints.delete(id);
+        sim.pipeline.breakpoints.add(addi, true, None);
+        assert!(run(&mut sim).is_some());
+        assert!(sim.pipeline.breakpoints.is_empty());
+        assert!(run(&mut sim).is_none());
+        assert_eq!(sim.pipeline.cpu.reg[10], 5);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let asm = "  .data
+arr:
+  .word 1, 2, 3, 4
+  .text
+main:
+  la t0, arr
+  lw a1, 4(t0)
+  li t1, 7
+  sw t1, 8(t0)
+  lw a0, 8(t0)
+  ret
+";
+        let path = crate::runner::test_image(asm);
+        let image = crate::codegen::encode::assemble(asm, 0x8000_0000).unwrap();
+        let arr = image.symbols["arr"];
+        let mut sim = Simulator::load(&path).unwrap();
+        sim.pipeline.breakpoints.watch(arr + 8, 4, WatchKind::Write);
+        sim.pipeline.breakpoints.watch(arr, 8, WatchKind::Read);
+
+        let mut hits = Vec::new();
+        while sim.pipeline.cpu.running {
+            sim.pipeline.step(&mut sim.mem);
+            if let Some(Hit::Watchpoint(hit)) = sim.pipeline.breakpoint_hit.take() {
+                hits.push(hit);
+            }
+        }
+        assert_eq!(hits.len(), 2, "{:?}", hits);
+        assert_eq!((hits[0].id, hits[0].addr, hits[0].write, hits[0].new), (2, arr + 4, false, 2));
+        assert_eq!((hits[1].id, hits[1].old, hits[1].new), (1, 3, 7));
+        assert!(hits[1].to_string().contains("old value = 0x00000003, new value = 0x00000007"), "{}", hits[1]);
+        assert_eq!(sim.pipeline.cpu.reg[10], 7);
+    }
+}
@@ -0,0 +1,259 @@
+// Set-associative cache model (LRU; write-back with write-allocate, or write-through without).
+// It only tracks tags and statistics; data is always read from Memory. Each miss stalls the
+// whole pipeline for `miss_penalty` cycles, write-through stores go to memory without stalling.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+const RECENT_ACCESSES: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WritePolicy {
+    // dirty lines are written back when evicted; a write miss allocates the line
+    #[default]
+    WriteBack,
+    // every store goes to memory; a write miss leaves the cache alone
+    WriteThrough,
+}
+
+impl WritePolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            WritePolicy::WriteBack => "write-back",
+            WritePolicy::WriteThrough => "write-through",
+        }
+    }
+}
+
+// cache shape and policy, configurable per cache in the [cache] section of the config file
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheGeometry {
+    pub sets: usize,
+    pub ways: usize,
+    pub line_size: usize,
+    pub write_policy: WritePolicy,
+    pub miss_penalty: u64, // stall cycles per miss
+}
+
+impl Default for CacheGeometry {
+    fn default() -> Self {
+        Self { sets: 16, ways: 2, line_size: 16, write_policy: WritePolicy::WriteBack, miss_penalty: 10 }
+    }
+}
+
+impl CacheGeometry {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.sets.is_power_of_two() || !self.line_size.is_power_of_two() || self.ways == 0 {
+            return Err(format!("invalid cache geometry {:?}: sets and line_size must be powers of two, ways > 0", self));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct CacheLine {
+    pub valid: bool,
+    pub dirty: bool,
+    pub tag: u64,
+    lru: u64, // last access stamp
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheAccess {
+    pub set: usize,
+    pub way: Option<usize>, // None: a write-through miss that did not allocate
+    pub hit: bool,
+}
+
+impl CacheAccess {
+    // a line had to be fetched from memory, which is what stalls
+    pub fn filled(&self) -> bool {
+        !self.hit && self.way.is_some()
+    }
+}
+
+// tags, LRU order and counters, what a checkpoint needs to resume with the same hits and misses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheState {
+    lines: Vec<CacheLine>,
+    stamp: u64,
+    hits: u64,
+    misses: u64,
+    writebacks: u64,
+}
+
+#[derive(Debug)]
+pub struct Cache {
+    pub name: &'static str,
+    pub sets: usize,
+    pub ways: usize,
+    pub line_size: usize,
+    pub write_policy: WritePolicy,
+    pub miss_penalty: u64,
+    lines: Vec<CacheLine>,
+    recent: VecDeque<CacheAccess>,
+    stamp: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub writebacks: u64, // dirty evictions, or stores passed through
+}
+
+impl Cache {
+    // `sets` and `line_size` must be powers of two
+    pub fn new(name: &'static str, sets: usize, ways: usize, line_size: usize) -> Self {
+        assert!(sets.is_power_of_two() && line_size.is_power_of_two() && ways > 0);
+        Self {
+            name,
+            sets,
+            ways,
+            line_size,
+            write_policy: WritePolicy::WriteBack,
+            miss_penalty: 0,
+            lines: vec![CacheLine::default(); sets * ways],
+            recent: VecDeque::with_capacity(RECENT_ACCESSES),
+            stamp: 0,
+            hits: 0,
+            misses: 0,
+            writebacks: 0,
+        }
+    }
+
+    pub fn with_geometry(name: &'static str, g: CacheGeometry) -> Self {
+        Self { write_policy: g.write_policy, miss_penalty: g.miss_penalty, ..Self::new(name, g.sets, g.ways, g.line_size) }
+    }
+
+    pub fn reset(&mut self) {
+        self.lines.iter_mut().for_each(|l| *l = CacheLine::default());
+        self.recent.clear();
+        self.stamp = 0;
+        self.hits = 0;
+        self.misses = 0;
+        self.writebacks = 0;
+    }
+
+    pub fn save(&self) -> CacheState {
+        CacheState { lines: self.lines.clone(), stamp: self.stamp, hits: self.hits, misses: self.misses, writebacks: self.writebacks }
+    }
+
+    pub fn restore(&mut self, state: CacheState) -> Result<(), String> {
+        if state.lines.len() != self.lines.len() {
+            return Err(format!("{} geometry differs from the checkpoint", self.name));
+        }
+        self.reset();
+        self.lines = state.lines;
+        self.stamp = state.stamp;
+        self.hits = state.hits;
+        self.misses = state.misses;
+        self.writebacks = state.writebacks;
+        Ok(())
+    }
+
+    fn split(&self, addr: u64) -> (usize, u64) {
+        let block = addr / self.line_size as u64;
+        ((block as usize) & (self.sets - 1), block / self.sets as u64)
+    }
+
+    pub fn line(&self, set: usize, way: usize) -> &CacheLine {
+        &self.lines[set * self.ways + way]
+    }
+
+    // most recent accesses, oldest first
+    pub fn recent(&self) -> impl Iterator<Item = &CacheAccess> {
+        self.recent.iter()
+    }
+
+    pub fn access(&mut self, addr: u64, write: bool) -> CacheAccess {
+        let (set, tag) = self.split(addr);
+        self.stamp += 1;
+        let through = self.write_policy == WritePolicy::WriteThrough;
+        let ways = &mut self.lines[set * self.ways..(set + 1) * self.ways];
+
+        let (way, hit) = match ways.iter().position(|l| l.valid && l.tag == tag) {
+            Some(way) => (Some(way), true),
+            None if write && through => (None, false),
+            None => {
+                // prefer an invalid way, otherwise evict the least recently used one
+                let way = ways.iter().position(|l| !l.valid).unwrap_or_else(|| {
+                    ways.iter().enumerate().min_by_key(|(_, l)| l.lru).map(|(i, _)| i).unwrap()
+                });
+                if ways[way].valid && ways[way].dirty {
+                    self.writebacks += 1;
+                }
+                ways[way] = CacheLine { valid: true, dirty: false, tag, lru: 0 };
+                (Some(way), false)
+            }
+        };
+        if let Some(way) = way {
+            ways[way].lru = self.stamp;
+            ways[way].dirty |= write && !through;
+        }
+        if write && through {
+            self.writebacks += 1;
+        }
+
+        if hit { self.hits += 1; } else { self.misses += 1; }
+        let access = CacheAccess { set, way, hit };
+        if self.recent.len() == RECENT_ACCESSES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(access);
+        access
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 { 0.0 } else { self.hits as f64 / total as f64 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_policy() {
+        // one set, one way: every new line evicts the previous one
+        let geometry = |write_policy| CacheGeometry { sets: 1, ways: 1, line_size: 16, write_policy, miss_penalty: 5 };
+        let mut back = Cache::with_geometry("D-Cache", geometry(WritePolicy::WriteBack));
+        assert!(back.access(0x100, true).filled()); // write miss allocates
+        assert!(back.access(0x104, false).hit);
+        assert!(back.access(0x200, false).filled()); // evicts the dirty line
+        assert_eq!((back.hits, back.misses, back.writebacks), (1, 2, 1));
+
+        let mut through = Cache::with_geometry("D-Cache", geometry(WritePolicy::WriteThrough));
+        let miss = through.access(0x100, true);
+        assert!(!miss.hit && !miss.filled() && miss.way.is_none());
+        assert!(through.access(0x100, false).filled());
+        assert!(through.access(0x108, true).hit);
+        assert!(!through.line(0, 0).dirty);
+        assert_eq!((through.hits, through.misses, through.writebacks, through.miss_penalty), (1, 2, 2, 5));
+    }
+
+    #[test]
+    fn test_stalled_fetch() {
+        // the same fetch stream with and without a load-use stall: the stall costs a cycle,
+        // but re-fetching the held instruction is not another I-cache access
+        let run = |use_reg: &str| {
+            let asm = format!("main:
+  addi sp, sp, -16
+  sw zero, 0(sp)
+  lw t0, 0(sp)
+  addi t1, {}, 1
+  addi sp, sp, 16
+  ret
+", use_reg);
+            let mut sim = super::super::embed::Simulator::load(&crate::runner::test_image(&asm)).unwrap();
+            sim.pipeline.enable_caches(true);
+            assert_eq!(sim.run(Some(1000)), Some(0));
+            let icache = sim.pipeline.icache.as_ref().unwrap();
+            (sim.pipeline.cpu.cycle_count, icache.hits + icache.misses)
+        };
+        let (stalled, stalled_fetches) = run("t0");
+        let (free, fetches) = run("t2");
+        assert!(stalled > free);
+        assert_eq!(stalled_fetches, fetches);
+    }
+}
@@ -0,0 +1,220 @@
+// 命令行调试器（sim> REPL），不需要图形界面，可用于 SSH 和脚本
+// 与 GUI 共用 command.rs 中的命令解析
+
+use std::fs;
+
+use rustyline::config::Config;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use super::command::*;
+use super::config::SimConfig;
+use super::history::{History, HISTORY_FILE, MAX_ENTRIES};
+use super::mem::Memory;
+use super::pipe::Pipeline;
+use super::snapshot::load_checkpoint;
+
+// 运行到程序结束、断点或满足停止条件
+fn run(pipeline: &mut Pipeline, mem: &mut Memory, mut goal: Option<RunGoal>) {
+    while pipeline.cpu.running {
+        pipeline.step(mem);
+        if let Some(hit) = &pipeline.breakpoint_hit {
+            println!("{}", hit);
+            break;
+        }
+        if goal.as_mut().is_some_and(|g| g.reached(pipeline)) {
+            println!("Stopped at 0x{:08x}", pipeline.W_reg.pc);
+            break;
+        }
+    }
+}
+
+// 命令来源：终端输入，或 --script 文件中的各行
+enum Input {
+    Editor(Box<DefaultEditor>, History),
+    Script(std::vec::IntoIter<String>),
+}
+
+// 返回进程退出码，有 assert 失败时为 1（例如从管道读入命令时）
+pub fn run_cli(output: String, config: SimConfig) -> rustyline::Result<i32> {
+    let history = History::load(HISTORY_FILE);
+    // 上下键浏览历史，Ctrl-R 反向搜索，历史在 .sim_history 中跨会话保存
+    let mut rl = DefaultEditor::with_config(Config::builder().max_history_size(MAX_ENTRIES)?.auto_add_history(false).build())?;
+    for entry in history.entries() {
+        let _ = rl.add_history_entry(entry.as_str());
+    }
+    session(output, config, Input::Editor(Box::new(rl), history))
+}
+
+// 依次执行 FILE 中的命令（跳过空行和 # 注释）后退出，返回进程退出码：
+// 有 assert 失败时为 1，命令无法解析时立即停止并返回 1
+pub fn run_script(output: String, config: SimConfig, path: &str) -> Result<i32, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let lines: Vec<String> = text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect();
+    session(output, config, Input::Script(lines.into_iter())).map_err(|e| e.to_string())
+}
+
+fn session(output: String, config: SimConfig, mut input: Input) -> rustyline::Result<i32> {
+    // 默认不逐周期打印流水线状态，用 info r 查看
+    let quiet = !config.trace.state.unwrap_or(false);
+    let (mut pipeline, mut mem) = config.build(&output).unwrap();
+    pipeline.quiet = quiet;
+
+    let mut srcmap = config.symbols(&output);
+    let scripted = matches!(input, Input::Script(_));
+    let mut last = String::new(); // 空行重复的命令
+    let (mut asserts, mut failed) = (0, 0);
+
+    loop {
+        let line = match &mut input {
+            Input::Editor(rl, history) => {
+                let line = match rl.readline("sim> ") {
+                    Ok(line) => line,
+                    Err(ReadlineError::Interrupted) => continue,
+                    Err(ReadlineError::Eof) => break,
+                    Err(e) => return Err(e),
+                };
+                // 与 GDB 相同，空行重复上一条单步、运行或查看类命令
+                if line.trim().is_empty() {
+                    last.clone()
+                } else {
+                    let _ = rl.add_history_entry(line.as_str());
+                    history.push(&line);
+                    line
+                }
+            }
+            Input::Script(lines) => match lines.next() {
+                Some(line) => {
+                    println!("sim> {}", line);
+                    line
+                }
+                None => break,
+            },
+        };
+
+        let command = parse_command_with(&line, &|s| resolve_location(s, &pipeline, &srcmap));
+        last = match &command {
+            Ok(Some(cmd)) if cmd.repeats() => line,
+            _ => String::new(),
+        };
+        match command {
+            Ok(Some(Command::Continue)) => {
+                println!("Continuing execution");
+                run(&mut pipeline, &mut mem, None);
+            }
+            Ok(Some(Command::Start)) => match srcmap.as_ref().ok().and_then(|m| m.symbol("main")) {
+                Some(main) => {
+                    println!("Running to main (0x{:08x})", main);
+                    pipeline.breakpoints.add(main, true, None);
+                    run(&mut pipeline, &mut mem, None);
+                }
+                None => println!("No symbol 'main' in this image"),
+            },
+            Ok(Some(Command::Reload)) => {
+                match config.build(&output) {
+                    Ok((mut new_pipeline, new_mem)) => {
+                        new_pipeline.breakpoints = std::mem::take(&mut pipeline.breakpoints);
+                        new_pipeline.tracer.flags = pipeline.tracer.flags;
+                        pipeline = new_pipeline;
+                        pipeline.quiet = quiet;
+                        mem = new_mem;
+                        srcmap = config.symbols(&output);
+                        println!("Reloaded {}", output);
+                    }
+                    Err(e) => println!("Failed to reload {}", e),
+                }
+            }
+            Ok(Some(Command::StepInst)) => run(&mut pipeline, &mut mem, Some(RunGoal::Step)),
+            Ok(Some(Command::Next)) => run(&mut pipeline, &mut mem, Some(RunGoal::Next)),
+            Ok(Some(Command::Finish)) => match RunGoal::finish(&pipeline) {
+                Some(goal) => run(&mut pipeline, &mut mem, Some(goal)),
+                None => println!("\"finish\" not meaningful in the outermost frame"),
+            },
+            Ok(Some(Command::Quit)) => {
+                println!("Exiting simulator");
+                break;
+            }
+            Ok(Some(Command::Step(n))) => print!("{}", step_cycles(&mut pipeline, &mut mem, n).1),
+            Ok(Some(Command::ReverseStep(n))) => print!("{}", reverse_step(&mut pipeline, &mut mem, n)),
+            Ok(Some(Command::Info(topic))) => print!("{}", format_info(topic, &pipeline, &mem)),
+            Ok(Some(Command::Break { addr, temporary, condition })) => {
+                print!("{}", set_breakpoint(&mut pipeline.breakpoints, addr, temporary, condition));
+            }
+            Ok(Some(Command::Watch { addr, len, kind })) => {
+                print!("{}", set_watchpoint(&mut pipeline.breakpoints, addr, len, kind));
+            }
+            Ok(Some(Command::Disas { addr, count })) => print!("{}", format_disas(&pipeline, &mem, &srcmap, addr, count)),
+            Ok(Some(Command::Print(expr))) => print!("{}", print_expression(&expr, &pipeline, &mem, &srcmap)),
+            Ok(Some(Command::Assert { lhs, op, rhs })) => {
+                asserts += 1;
+                match check_assertion(&lhs, op, &rhs, &pipeline, &mem, &srcmap) {
+                    Ok(passed) => print!("{}", passed),
+                    Err(e) => {
+                        failed += 1;
+                        println!("{}", e);
+                    }
+                }
+            }
+            Ok(Some(Command::Save(path))) => print!("{}", save_checkpoint(&pipeline, &mem, &path)),
+            Ok(Some(Command::Load(path))) => match load_checkpoint(&path, &config, &output) {
+                Ok((mut new_pipeline, new_mem)) => {
+                    new_pipeline.breakpoints = std::mem::take(&mut pipeline.breakpoints);
+                    new_pipeline.tracer.flags = pipeline.tracer.flags;
+                    pipeline = new_pipeline;
+                    pipeline.quiet = quiet;
+                    mem = new_mem;
+                    println!("Loaded checkpoint {} at cycle {}, pc = 0x{:08x}", path, pipeline.cpu.cycle_count, pipeline.cpu.pc);
+                }
+                Err(e) => println!("Failed to load checkpoint {}", e),
+            },
+            Ok(Some(Command::Trace(setting))) => print!("{}", trace_command(&mut pipeline.tracer, setting)),
+            Ok(Some(Command::Delete(id))) => print!("{}", delete_breakpoints(&mut pipeline.breakpoints, id)),
+            Ok(Some(Command::Examine { spec, addr })) => print!("{}", examine(&mem, &spec, addr)),
+            Ok(Some(Command::Export { html, path })) => print!("{}", export_timeline(&pipeline.timeline, html, &path)),
+            Ok(Some(Command::Profile(top))) => print!("{}", pipeline.profile.report(top, srcmap.as_ref().ok())),
+            Ok(Some(Command::Dump { addr, len, path })) => print!("{}", dump_memory(&mem, addr, len, &path)),
+            Ok(Some(Command::Restore { addr, path })) => print!("{}", restore_memory(&mut mem, addr, &path)),
+            Ok(Some(Command::Fill { addr, len, unit, values })) => print!("{}", fill_memory(&mut mem, addr, len, unit, &values)),
+            Ok(Some(Command::Help)) => print!("{}", HELP),
+            Ok(None) => {}
+            Err(e) if scripted => {
+                println!("{}", e);
+                return Ok(1);
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+    if failed > 0 {
+        println!("{} of {} assertions failed", failed, asserts);
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        let asm = "main:
+  li a0, 5
+  li a1, 7
+  ret
+";
+        let image = crate::runner::test_image(asm);
+        let script = crate::runner::test_dir().join("script.txt");
+        let run = |text: &str| {
+            fs::write(&script, text).unwrap();
+            run_script(image.clone(), SimConfig::default(), &script.to_string_lossy()).unwrap()
+        };
+        assert_eq!(run("# run to the end\nc\nassert $a0 == 5\nassert $a1 > $a0 + 1\n"), 0);
+        assert_eq!(run("c\nassert $a0 * 2 == 11\nassert $a0 == 5\n"), 1);
+        assert_eq!(run("c\nassert *0x80000000 != 0\nassert $a1 <= 6\nq\n"), 1);
+        assert_eq!(run("bogus\n"), 1);
+    }
+}
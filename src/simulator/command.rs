@@ -0,0 +1,971 @@
+// Debugger commands shared by the GUI command box and the sim> REPL.
+// Parsing and the text output of each command live here; every frontend
+// only decides how to run the program (free-run, breakpoints, ...).
+
+use std::fs;
+
+use super::breakpoint::{BreakpointManager, CmpOp, Condition, WatchKind};
+use super::cpu::{CPUState, REG_NAMES};
+use super::decode::is_call;
+use super::disasm::{disassemble, disassemble_range, jump_target, DisasmLine};
+use super::expr::evaluate;
+use super::mem::Memory;
+use super::perf::InstClass;
+use super::pipe::Pipeline;
+use super::snapshot::Checkpoint;
+use super::srcmap::SourceMap;
+use super::timeline::{Timeline, STAGE_NAMES};
+use super::trace::{TraceKind, Tracer};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Continue,
+    Start,
+    Reload,
+    Quit,
+    Step(u32),   // cycles
+    ReverseStep(u32), // cycles back
+    StepInst,    // one instruction
+    Next,        // one instruction, running over calls
+    Finish,      // until the current function returns
+    Info(InfoTopic),
+    Break { addr: u64, temporary: bool, condition: Option<Condition> },
+    Watch { addr: u64, len: u64, kind: WatchKind },
+    Delete(Option<usize>), // None deletes all
+    Print(String), // expression
+    Assert { lhs: String, op: CmpOp, rhs: String }, // two expressions, compared as signed integers
+    Disas { addr: Option<u64>, count: usize }, // None: from the oldest instruction in flight
+    Trace(Option<(TraceKind, bool)>), // None shows the settings and the instruction ring buffer
+    Examine { spec: ExamineSpec, addr: u64 },
+    Export { html: bool, path: String },
+    Profile(usize), // top N blocks
+    Dump { addr: u64, len: usize, path: String },
+    Restore { addr: u64, path: String },
+    Fill { addr: u64, len: usize, unit: usize, values: Vec<u64> }, // values repeat, `unit` bytes each
+    Save(String),  // checkpoint file
+    Load(String),
+    Help,
+}
+
+impl Command {
+    // an empty line at the sim> prompt runs it again, as in GDB
+    pub fn repeats(&self) -> bool {
+        matches!(self, Command::Continue | Command::Step(_) | Command::ReverseStep(_) | Command::StepInst
+            | Command::Next | Command::Finish | Command::Examine { .. } | Command::Info(_) | Command::Disas { .. })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InfoTopic {
+    Registers,
+    Pipeline,
+    Stats,
+    Perf,
+    Cache,
+    Mem,
+    Breakpoints,
+}
+
+// GDB-style x/FMT: [count][unit][format], e.g. x/4wx, x/8bd, x/s
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExamineSpec {
+    pub count: usize,
+    pub unit: usize,   // bytes per item: b=1 h=2 w=4 g=8
+    pub format: char,  // x d u o t c s i
+}
+
+impl Default for ExamineSpec {
+    fn default() -> Self {
+        Self { count: 1, unit: 4, format: 'x' }
+    }
+}
+
+impl ExamineSpec {
+    pub fn parse(fmt: &str) -> Result<Self, String> {
+        let mut spec = Self::default();
+        let digits = fmt.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0 {
+            spec.count = fmt[..digits].parse().map_err(|_| String::from("Invalid number"))?;
+        }
+        for c in fmt[digits..].chars() {
+            match c {
+                'b' => spec.unit = 1,
+                'h' => spec.unit = 2,
+                'w' => spec.unit = 4,
+                'g' => spec.unit = 8,
+                'x' | 'd' | 'u' | 'o' | 't' | 'c' | 's' | 'i' => spec.format = c,
+                _ => return Err(format!("Invalid format letter '{}'", c)),
+            }
+        }
+        if spec.format == 'c' {
+            spec.unit = 1;
+        }
+        if spec.format == 'i' {
+            spec.unit = 4;
+        }
+        Ok(spec)
+    }
+
+    fn format_item(&self, value: u64) -> String {
+        let bits = self.unit * 8;
+        let signed = (value << (64 - bits)) as i64 >> (64 - bits);
+        match self.format {
+            'd' => signed.to_string(),
+            'u' => value.to_string(),
+            'o' => format!("0{:o}", value),
+            't' => format!("{:0width$b}", value, width = bits),
+            'c' => format!("{:?}", value as u8 as char),
+            _ => format!("0x{:0width$x}", value, width = self.unit * 2),
+        }
+    }
+
+    // bytes covered, used to highlight the range in the memory panel (0 for strings)
+    pub fn byte_len(&self) -> u64 {
+        if self.format == 's' { 0 } else { (self.count * self.unit) as u64 }
+    }
+}
+
+const MAX_STRING_LEN: usize = 256; // x/s stops here if no NUL is found
+pub const MAX_EXAMINE_COUNT: usize = 4096; // units one x prints, so a typo cannot print gigabytes
+const MAX_DUMP_LEN: usize = 64 << 20; // bytes one dump reads into host memory
+pub const DEFAULT_PROFILE_TOP: usize = 10;
+pub const DEFAULT_DISAS_LINES: usize = 10;
+
+// Where an instruction-level run stops; checked after every cycle.
+// An instruction counts as committed once it reaches the MEM/WB latch,
+// the same point at which breakpoints fire.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunGoal {
+    Step,
+    Next,
+    Return { pc: u64, sp: u64 }, // `pc` commits with the caller's sp restored
+}
+
+impl RunGoal {
+    // None in the outermost frame
+    pub fn finish(pipeline: &Pipeline) -> Option<Self> {
+        pipeline.calls.stack.last().map(|f| RunGoal::Return { pc: f.ret_addr, sp: f.sp })
+    }
+
+    pub fn reached(&mut self, pipeline: &Pipeline) -> bool {
+        let w = &pipeline.W_reg;
+        if w.seq == 0 {
+            return false;
+        }
+        match *self {
+            RunGoal::Step => true,
+            RunGoal::Next if is_call(w.inst) => {
+                // 调用指令：继续运行到返回地址（即写回的链接值），sp 相同以区分递归
+                *self = RunGoal::Return { pc: w.alu_out, sp: pipeline.cpu.reg[2] };
+                false
+            }
+            RunGoal::Next => true,
+            RunGoal::Return { pc, sp } => w.pc == pc && pipeline.cpu.reg[2] == sp,
+        }
+    }
+}
+
+pub const HELP: &str = "\
+Available commands:
+  c          - Continue execution
+  start      - Run to main (temporary breakpoint), needs symbols
+  q          - Quit the simulator
+  reload     - Re-read the image from disk and restart, keeping breakpoints
+  si [N]     - Single step execution (N times, default 1)
+  rsi [N]    - Step N cycles backwards (default 1), undoing register and memory changes
+  s, step    - Run until the next instruction commits
+  n, next    - Like step, but runs over function calls
+  finish     - Run until the current function returns
+  info r     - Print register state to output
+  info pipeline - Print pipeline stage contents and stall flags
+  info stats - Print IPC, stalls, flushes, memory operations and the instruction mix
+  info perf  - Print CPI, misprediction rate, load-use stalls and memory accesses
+  info cache - Print cache configuration, hit/miss counts and miss stall cycles
+  info mem   - Print the memory map and access counts per region
+  info b     - List breakpoints and watchpoints with their numbers, conditions and hit counts
+  b ADDR [if REG OP VALUE] - Set a breakpoint at ADDR, e.g. b main, b 80000010 if $a0 == 5
+              (OP: == != < <= > >=, compared as signed integers)
+  tbreak ADDR [if ...] - Set a temporary breakpoint, deleted after it is hit once
+  watch ADDR [LEN] [r|w|rw] - Stop when LEN bytes at ADDR (default 4) are written (default),
+              read, or either; shows the pc and the old and new value
+  delete [N] - Delete breakpoint or watchpoint N, or all of them
+  p EXPR     - Evaluate an expression, e.g. p $a0 + 4, p *0x80001000, p $pc
+              (registers, symbols, *ADDR reads a word, + - * / and parentheses, 0x for hex)
+  assert EXPR OP EXPR - Compare registers or memory, e.g. assert $a0 == 55, assert *0x80001000 != 0;
+              --script and --cli exit with status 1 if any assertion failed
+  disas [ADDR [N]] - Disassemble N instructions (default 10) from ADDR, or from the oldest
+              instruction in the pipeline, marking the stage each one is in
+  trace [itrace|mtrace|ftrace on|off] - Turn an execution trace on or off, or show which are on
+              and the last instructions committed
+  x N ADDR   - Examine memory at address ADDR, N words (at most 4096)
+              (ADDR format: 0x1234, 1234, a symbol such as main or &count, or a register such as $sp)
+  x/FMT ADDR - Examine memory with a GDB-style format, FMT = [N][b|h|w|g][x|d|u|o|t|c|s|i]
+              e.g. x/4wx 0x80000000, x/8bd ADDR, x/s ADDR
+  export csv|html FILE - Export the pipeline diagram
+  profile [N] - Show the N hottest basic blocks so far (default 10)
+  dump ADDR LEN FILE - Write LEN bytes (at most 64 MiB) of memory at ADDR to FILE
+  restore ADDR FILE  - Load FILE into memory at ADDR
+  fill[/b|h|w|g] ADDR LEN V... - Fill LEN bytes at ADDR with the values V... repeated, each a byte,
+              halfword, word (default) or giant, e.g. fill/w 0x80001000 40 1 2 3 4 5 6 7 8 9 10
+  save FILE  - Save a checkpoint of the CPU, pipeline, statistics and written memory to FILE
+  load FILE  - Restart from a checkpoint saved from the same image, keeping breakpoints
+  help       - Print this help information
+";
+
+// Ok(None) for an empty line; addresses are hex only
+pub fn parse_command(line: &str) -> Result<Option<Command>, String> {
+    parse_command_with(line, &parse_hex_address)
+}
+
+// 地址参数交给 resolve 解析；前端传入 resolve_location，就可以写符号、寄存器或 pc，如 b main、x 4 &count
+pub fn parse_command_with(line: &str, resolve: &dyn Fn(&str) -> Result<u64, String>) -> Result<Option<Command>, String> {
+    let mut parts = line.split_whitespace();
+    let cmd = match parts.next() {
+        Some(cmd) => cmd.to_lowercase(),
+        None => return Ok(None),
+    };
+    if cmd == "fill" || cmd.starts_with("fill/") {
+        return parse_fill(&cmd[4..], parts, resolve).map(Some);
+    }
+    if let Some(fmt) = cmd.strip_prefix("x/") {
+        let spec = ExamineSpec::parse(fmt)?;
+        check_examine_count(spec.count)?;
+        let addr = resolve(parts.next().ok_or("Missing address for x command")?)?;
+        return Ok(Some(Command::Examine { spec, addr }));
+    }
+
+    let command = match cmd.as_str() {
+        "c" => Command::Continue,
+        "start" => Command::Start,
+        "reload" => Command::Reload,
+        "q" => Command::Quit,
+        "si" => Command::Step(match parts.next() {
+            Some(n) => n.parse::<u32>().map_err(|_| String::from("Invalid number"))?,
+            None => 1,
+        }),
+        "rsi" => Command::ReverseStep(match parts.next() {
+            Some(n) => n.parse::<u32>().map_err(|_| String::from("Invalid number"))?,
+            None => 1,
+        }),
+        "info" => match parts.next().map(str::to_lowercase).as_deref() {
+            Some("r") => Command::Info(InfoTopic::Registers),
+            Some("pipeline" | "p") => Command::Info(InfoTopic::Pipeline),
+            Some("stats" | "stat") => Command::Info(InfoTopic::Stats),
+            Some("perf") => Command::Info(InfoTopic::Perf),
+            Some("cache") => Command::Info(InfoTopic::Cache),
+            Some("mem") => Command::Info(InfoTopic::Mem),
+            Some("bp" | "b" | "breakpoints") => Command::Info(InfoTopic::Breakpoints),
+            Some(_) => return Err(String::from("Invalid info subcommand")),
+            None => return Err(String::from("Missing subcommand for info")),
+        },
+        "x" => {
+            let count = parts.next()
+                .ok_or("Missing count for x command")?
+                .parse::<usize>()
+                .map_err(|_| String::from("Invalid number"))?;
+            check_examine_count(count)?;
+            let addr = resolve(parts.next().ok_or("Missing address for x command")?)?;
+            Command::Examine { spec: ExamineSpec { count, ..ExamineSpec::default() }, addr }
+        }
+        "export" => match (parts.next().map(str::to_lowercase).as_deref(), parts.next()) {
+            // 路径保留原始大小写
+            (Some(fmt @ ("csv" | "html")), Some(path)) => Command::Export { html: fmt == "html", path: path.to_string() },
+            (Some(_), Some(_)) => return Err(String::from("Invalid export format, expected csv or html")),
+            _ => return Err(String::from("Usage: export csv|html FILE")),
+        },
+        "b" | "break" | "tb" | "tbreak" => {
+            let addr = resolve(parts.next().ok_or("Missing address for b command")?)?;
+            let condition = match parts.next() {
+                Some("if") => Some(Condition::parse(&parts.collect::<Vec<_>>().join(" "))?),
+                Some(_) => return Err(String::from("Usage: b ADDR [if REG OP VALUE]")),
+                None => None,
+            };
+            Command::Break { addr, temporary: cmd.starts_with('t'), condition }
+        }
+        "watch" => {
+            let addr = resolve(parts.next().ok_or("Missing address for watch command")?)?;
+            let (mut len, mut kind) = (4, WatchKind::Write);
+            for arg in parts {
+                match WatchKind::parse(arg) {
+                    Some(k) => kind = k,
+                    None => len = parse_length(arg)? as u64,
+                }
+            }
+            if len == 0 {
+                return Err(String::from("Watch length must not be zero"));
+            }
+            Command::Watch { addr, len, kind }
+        }
+        "disas" | "disassemble" => {
+            let addr = parts.next().map(resolve).transpose()?;
+            let count = match parts.next() {
+                Some(n) => n.parse::<usize>().map_err(|_| String::from("Invalid number"))?,
+                None => DEFAULT_DISAS_LINES,
+            };
+            Command::Disas { addr, count }
+        }
+        "p" | "print" => {
+            let expr = parts.collect::<Vec<_>>().join(" ");
+            if expr.is_empty() {
+                return Err(String::from("Usage: p EXPR"));
+            }
+            Command::Print(expr)
+        }
+        "assert" => {
+            let text = parts.collect::<Vec<_>>().join(" ");
+            match CmpOp::split(&text) {
+                Some((lhs, op, rhs)) if !lhs.is_empty() && !rhs.is_empty() => {
+                    Command::Assert { lhs: lhs.to_string(), op, rhs: rhs.to_string() }
+                }
+                _ => return Err(String::from("Usage: assert EXPR OP EXPR")),
+            }
+        }
+        "trace" => match (parts.next(), parts.next()) {
+            (None, _) => Command::Trace(None),
+            (Some(kind), Some(state @ ("on" | "off"))) => match TraceKind::parse(kind) {
+                Some(kind) => Command::Trace(Some((kind, state == "on"))),
+                None => return Err(format!("Unknown trace '{}', expected itrace, mtrace or ftrace", kind)),
+            },
+            _ => return Err(String::from("Usage: trace [itrace|mtrace|ftrace on|off]")),
+        },
+        "d" | "delete" => Command::Delete(match parts.next() {
+            Some(n) => Some(n.parse::<usize>().map_err(|_| String::from("Invalid breakpoint number"))?),
+            None => None,
+        }),
+        "profile" => Command::Profile(match parts.next() {
+            Some(n) => n.parse::<usize>().map_err(|_| String::from("Invalid number"))?,
+            None => DEFAULT_PROFILE_TOP,
+        }),
+        "dump" => match (parts.next(), parts.next(), parts.next()) {
+            (Some(addr), Some(len), Some(path)) => {
+                let len = parse_length(len)?;
+                if len > MAX_DUMP_LEN {
+                    return Err(format!("dump is limited to {} bytes", MAX_DUMP_LEN));
+                }
+                Command::Dump { addr: resolve(addr)?, len, path: path.to_string() }
+            }
+            _ => return Err(String::from("Usage: dump ADDR LEN FILE")),
+        },
+        "restore" => match (parts.next(), parts.next()) {
+            (Some(addr), Some(path)) => Command::Restore { addr: resolve(addr)?, path: path.to_string() },
+            _ => return Err(String::from("Usage: restore ADDR FILE")),
+        },
+        "save" | "load" => {
+            let path = parts.next().ok_or(format!("Usage: {} FILE", cmd))?.to_string();
+            if cmd == "save" { Command::Save(path) } else { Command::Load(path) }
+        }
+        "s" | "step" => Command::StepInst,
+        "n" | "next" => Command::Next,
+        "finish" => Command::Finish,
+        "help" => Command::Help,
+        cmd => return Err(format!("Unknown command '{}'. Type 'help' for a list of commands.", cmd)),
+    };
+    Ok(Some(command))
+}
+
+// fill 的单位和参数，unit 为 "" 或 "/b" 等
+fn parse_fill<'a>(unit: &str, mut parts: impl Iterator<Item = &'a str>, resolve: &dyn Fn(&str) -> Result<u64, String>) -> Result<Command, String> {
+    let usage = || String::from("Usage: fill[/b|h|w|g] ADDR LEN VALUE...");
+    let unit = match unit {
+        "" | "/w" => 4,
+        "/b" => 1,
+        "/h" => 2,
+        "/g" => 8,
+        _ => return Err(usage()),
+    };
+    let (Some(addr), Some(len)) = (parts.next(), parts.next()) else {
+        return Err(usage());
+    };
+    let values = parts.map(parse_value).collect::<Result<Vec<_>, _>>()?;
+    if values.is_empty() {
+        return Err(usage());
+    }
+    Ok(Command::Fill { addr: resolve(addr)?, len: parse_length(len)?, unit, values })
+}
+
+pub fn format_registers(cpu: &CPUState) -> String {
+    let mut out = String::from("Register state:\n");
+    for i in 0..32 {
+        out.push_str(&format!("x{:02}: 0x{:016x}\n", i, cpu.reg[i]));
+    }
+    // F 寄存器只列出非零的，附带单精度值
+    for i in (0..32).filter(|&i| cpu.freg[i] != 0) {
+        out.push_str(&format!("f{:02}: 0x{:016x} ({})\n", i, cpu.freg[i], f32::from_bits(cpu.freg[i] as u32)));
+    }
+    out
+}
+
+pub fn format_info(topic: InfoTopic, pipeline: &Pipeline, mem: &Memory) -> String {
+    match topic {
+        InfoTopic::Registers => format_registers(&pipeline.cpu),
+        InfoTopic::Pipeline => format_pipeline(pipeline),
+        InfoTopic::Stats => format_stats(pipeline),
+        InfoTopic::Perf => format_perf(pipeline),
+        InfoTopic::Cache => format_caches(pipeline),
+        InfoTopic::Mem => format_memory_map(pipeline, mem),
+        InfoTopic::Breakpoints => format_breakpoints(&pipeline.breakpoints),
+    }
+}
+
+fn format_pipeline(p: &Pipeline) -> String {
+    let latches = [
+        (p.D_reg.seq, p.D_reg.pc, p.D_reg.inst),
+        (p.E_reg.seq, p.E_reg.pc, p.E_reg.inst),
+        (p.M_reg.seq, p.M_reg.pc, p.M_reg.inst),
+        (p.W_reg.seq, p.W_reg.pc, p.W_reg.inst),
+    ];
+    let mut out = format!("Cycle {}, fetch pc 0x{:08x}\n", p.cpu.cycle_count, p.cpu.pc);
+    // 流水线寄存器保存的是下一拍进入 ID/EX/MEM/WB 的指令
+    for (stage, (seq, pc, inst)) in latches.iter().enumerate() {
+        let name = STAGE_NAMES[stage + 1];
+        if *seq == 0 {
+            out.push_str(&format!("  {:<4} bubble\n", name));
+        } else {
+            out.push_str(&format!("  {:<4} #{:<6} 0x{:08x}  {}\n", name, seq, pc, disassemble(*pc, *inst)));
+        }
+    }
+    let (stall, flush) = p.timeline.records().last().map_or((false, false), |r| (r.stall, r.flush));
+    out.push_str(&format!("Last cycle: stall={} flush={}\n", stall, flush));
+    out
+}
+
+fn format_stats(p: &Pipeline) -> String {
+    let c = p.perf_counters();
+    let mut out = p.statistics().to_string();
+    out.push_str(&format!("Branches/jumps executed: {}, mispredictions: {}\n", c.branches, c.mispredicts));
+    for cache in [&p.icache, &p.dcache].into_iter().flatten() {
+        out.push_str(&format!("{}: {} hits, {} misses ({:.1}% hit rate)\n",
+            cache.name, cache.hits, cache.misses, cache.hit_rate() * 100.0));
+    }
+    out.push_str(&format!("Calls: {}, call depth: {}\n", p.calls.calls.values().sum::<u64>(), p.calls.stack.len()));
+    out.push_str("Instruction mix:\n");
+    for class in InstClass::ALL {
+        out.push_str(&format!("  {:<8} {:>10}  {:5.1}%\n", class.name(), p.inst_mix.count(class), p.inst_mix.fraction(class) * 100.0));
+    }
+    out
+}
+
+// 由计数器推出的几个比率；程序里可以用 rdcycle / rdinstret / hpmcounterN 读到同样的原始计数
+fn format_perf(p: &Pipeline) -> String {
+    let c = p.perf_counters();
+    let cpi = if c.insts == 0 { 0.0 } else { c.cycles as f64 / c.insts as f64 };
+    let rate = if c.branches == 0 { 0.0 } else { c.mispredicts as f64 / c.branches as f64 * 100.0 };
+    let (loads, stores) = (p.inst_mix.count(InstClass::Load), p.inst_mix.count(InstClass::Store));
+    format!(
+        "CPI: {:.3}\nBranch mispredictions: {} of {} ({:.1}%)\nLoad-use stalls: {}\nMemory accesses: {} ({} loads, {} stores)\n",
+        cpi, c.mispredicts, c.branches, rate, c.load_use_stalls, loads + stores, loads, stores,
+    )
+}
+
+// 缓存配置与命中统计；未启用缓存模型时给出提示
+fn format_caches(p: &Pipeline) -> String {
+    let mut out = String::new();
+    for cache in [&p.icache, &p.dcache].into_iter().flatten() {
+        out.push_str(&format!(
+            "{}: {} sets x {} ways, {}B lines, {}, miss penalty {} cycles\n  {} hits, {} misses ({:.1}% hit rate), {} writebacks\n",
+            cache.name, cache.sets, cache.ways, cache.line_size, cache.write_policy.name(), cache.miss_penalty,
+            cache.hits, cache.misses, cache.hit_rate() * 100.0, cache.writebacks,
+        ));
+    }
+    if out.is_empty() {
+        return String::from("Cache models are disabled (cache.enabled = true in the config enables them)\n");
+    }
+    out.push_str(&format!("Miss penalty stall cycles: {}\n", p.cache_stall_cycles));
+    out
+}
+
+fn format_memory_map(p: &Pipeline, mem: &Memory) -> String {
+    let (ram_lo, ram_hi) = mem.ram_range();
+    let image_hi = ram_lo + mem.image_size;
+    let sp = p.cpu.reg[2];
+    let stack_lo = if (image_hi..ram_hi).contains(&sp) { sp } else { ram_hi };
+    let mut regions = vec![
+        ("image", "ram", ram_lo, image_hi),
+        ("heap/free", "ram", image_hi, stack_lo),
+        ("stack", "ram", stack_lo, ram_hi),
+    ];
+    // the other RAM/ROM regions and the devices
+    let map = mem.map();
+    for entry in map.entries().iter().filter(|e| e.base != ram_lo) {
+        regions.push((entry.name.as_str(), entry.kind.name(), entry.base, entry.end()));
+    }
+
+    let mut out = String::from("Region       Kind    Start       End         Reads     Writes\n");
+    for (name, kind, lo, hi) in regions {
+        let count = p.heatmap.sum(lo, hi);
+        out.push_str(&format!("{:<12} {:<7} 0x{:08x}  0x{:08x}  {:<9} {}\n", name, kind, lo, hi, count.reads, count.writes));
+    }
+    if let Some((lo, hi)) = p.heatmap.bounds() {
+        out.push_str(&format!("Touched data range: [0x{:08x}, 0x{:08x})\n", lo, hi));
+    }
+    out
+}
+
+fn format_breakpoints(breakpoints: &BreakpointManager) -> String {
+    if breakpoints.is_empty() {
+        return String::from("No breakpoints\n");
+    }
+    let mut out = format!("{:<4}{:<12}{:<6}{:<12}{:<6}{}\n", "Num", "Type", "Disp", "Address", "Hits", "What");
+    for b in breakpoints.iter() {
+        let disp = if b.temporary { "del" } else { "keep" };
+        let cond = b.condition.map(|c| format!("if {}", c)).unwrap_or_default();
+        out.push_str(&format!("{:<4}{:<12}{:<6}0x{:08x}  {:<6}{}\n", b.id, "breakpoint", disp, b.addr, b.hits, cond));
+    }
+    for w in breakpoints.watchpoints() {
+        let what = format!("{} {} bytes", w.kind.name(), w.len);
+        out.push_str(&format!("{:<4}{:<12}{:<6}0x{:08x}  {:<6}{}\n", w.id, "watchpoint", "keep", w.addr, w.hits, what));
+    }
+    out
+}
+
+// the message for `b` / `tbreak`
+pub fn set_breakpoint(breakpoints: &mut BreakpointManager, addr: u64, temporary: bool, condition: Option<Condition>) -> String {
+    let id = breakpoints.add(addr, temporary, condition);
+    match breakpoints.iter().find(|b| b.id == id) {
+        Some(b) => format!("{}\n", b),
+        None => String::new(),
+    }
+}
+
+// `disas`: address, symbol, raw bits and assembly, plus the pipeline stages holding each instruction
+pub fn format_disas(pipeline: &Pipeline, mem: &Memory, srcmap: &Result<SourceMap, String>, addr: Option<u64>, count: usize) -> String {
+    let stages = pipeline.stage_pcs();
+    let start = addr.or_else(|| stages.iter().rev().flatten().next().copied()).unwrap_or(pipeline.cpu.pc);
+    let lines = disassemble_range(mem, start, count);
+    if lines.is_empty() {
+        return format!("Cannot access memory at 0x{:08x}\n", start);
+    }
+    let mut out = String::new();
+    for line in lines {
+        let label = match srcmap.as_ref().ok().and_then(|m| m.symbol_at(line.addr)) {
+            Some((name, 0)) => format!("<{}>", name),
+            Some((name, off)) => format!("<{}+{}>", name, off),
+            None => String::new(),
+        };
+        let marker = if pipeline.breakpoints.contains(line.addr) { "b" } else { " " };
+        let held: Vec<_> = (0..5).filter(|&i| stages[i] == Some(line.addr)).map(|i| STAGE_NAMES[i]).collect();
+        let text = format!("{} 0x{:08x} {:<14} {}  {}", marker, line.addr, label, line.raw_hex(), symbolic_disasm(&line, srcmap));
+        if held.is_empty() {
+            out.push_str(&format!("{}\n", text));
+        } else {
+            out.push_str(&format!("{:<60} <- {}\n", text, held.join(", ")));
+        }
+    }
+    out
+}
+
+// 跳转目标换成符号：调用函数入口写成 call NAME，其他分支和跳转在目标后标出 <NAME+OFF>
+pub fn symbolic_disasm(line: &DisasmLine, srcmap: &Result<SourceMap, String>) -> String {
+    let (Some(target), Ok(map)) = (jump_target(line.addr, line.inst), srcmap) else {
+        return line.text.clone();
+    };
+    match map.symbol_at(target) {
+        Some((name, 0)) if is_call(line.inst) => format!("call {}", name),
+        Some((name, 0)) => format!("{} <{}>", line.text, name),
+        Some((name, off)) => format!("{} <{}+{}>", line.text, name, off),
+        None => line.text.clone(),
+    }
+}
+
+// the result of `p EXPR` in hex and signed decimal
+pub fn print_expression(expr: &str, pipeline: &Pipeline, mem: &Memory, srcmap: &Result<SourceMap, String>) -> String {
+    match evaluate(expr, pipeline, mem, srcmap.as_ref().ok()) {
+        Ok(value) => format!("{} = 0x{:x} ({})\n", expr, value, value as i64),
+        Err(e) => format!("{}\n", e),
+    }
+}
+
+// `assert`：Ok 为通过时的输出，Err 为失败说明
+pub fn check_assertion(lhs: &str, op: CmpOp, rhs: &str, pipeline: &Pipeline, mem: &Memory, srcmap: &Result<SourceMap, String>) -> Result<String, String> {
+    let srcmap = srcmap.as_ref().ok();
+    let a = evaluate(lhs, pipeline, mem, srcmap)?;
+    let b = evaluate(rhs, pipeline, mem, srcmap)?;
+    let text = format!("{} {} {}", lhs, op.symbol(), rhs);
+    if op.compare(a as i64, b as i64) {
+        Ok(format!("Assertion passed: {}\n", text))
+    } else {
+        Err(format!("Assertion failed: {} ({} = {}, {} = {}), pc = 0x{:08x}", text, lhs, a as i64, rhs, b as i64, pipeline.W_reg.pc))
+    }
+}
+
+// `si N`: the number of cycles actually run, which is fewer if the program stops first
+pub fn step_cycles(pipeline: &mut Pipeline, mem: &mut Memory, n: u32) -> (u32, String) {
+    let mut done = 0;
+    while done < n && pipeline.cpu.running {
+        pipeline.step(mem);
+        done += 1;
+    }
+    let message = if pipeline.cpu.running {
+        format!("Executed {} steps, pc = 0x{:08x}\n", done, pipeline.cpu.pc)
+    } else {
+        format!("Executed {} steps, program halted, pc = 0x{:08x}\n", done, pipeline.cpu.pc)
+    };
+    (done, message)
+}
+
+// `rsi N`
+pub fn reverse_step(pipeline: &mut Pipeline, mem: &mut Memory, n: u32) -> String {
+    let mut done = 0;
+    while done < n && pipeline.step_back(mem) {
+        done += 1;
+    }
+    if done < n {
+        format!("Stepped back {} cycles, no more history, pc = 0x{:08x}\n", done, pipeline.cpu.pc)
+    } else {
+        format!("Stepped back {} cycles, pc = 0x{:08x}\n", done, pipeline.cpu.pc)
+    }
+}
+
+// `save FILE`
+pub fn save_checkpoint(pipeline: &Pipeline, mem: &Memory, path: &str) -> String {
+    let checkpoint = Checkpoint::capture(pipeline, mem);
+    match checkpoint.save(path) {
+        Ok(size) => format!("Saved checkpoint at cycle {} to {} ({} bytes)\n", checkpoint.cycle(), path, size),
+        Err(e) => format!("Failed to save checkpoint {}\n", e),
+    }
+}
+
+// `trace`: switch one trace, or list them with the ring buffer
+pub fn trace_command(tracer: &mut Tracer, setting: Option<(TraceKind, bool)>) -> String {
+    match setting {
+        Some((kind, on)) => {
+            tracer.flags.set(kind, on);
+            format!("{} {}\n", kind.name(), if on { "on" } else { "off" })
+        }
+        None => {
+            let states: Vec<String> = TraceKind::ALL.iter()
+                .map(|&k| format!("{} {}", k.name(), if tracer.flags.get(k) { "on" } else { "off" }))
+                .collect();
+            format!("{}\n{}", states.join(", "), tracer.format_ring())
+        }
+    }
+}
+
+// the message for `watch`
+pub fn set_watchpoint(breakpoints: &mut BreakpointManager, addr: u64, len: u64, kind: WatchKind) -> String {
+    let id = breakpoints.watch(addr, len, kind);
+    match breakpoints.watchpoints().find(|w| w.id == id) {
+        Some(w) => format!("{}\n", w),
+        None => String::new(),
+    }
+}
+
+// the message for `delete [N]`
+pub fn delete_breakpoints(breakpoints: &mut BreakpointManager, id: Option<usize>) -> String {
+    match id {
+        Some(id) if breakpoints.delete(id) => format!("Deleted breakpoint {}\n", id),
+        Some(id) => format!("No breakpoint number {}\n", id),
+        None => {
+            breakpoints.clear();
+            String::from("Deleted all breakpoints\n")
+        }
+    }
+}
+
+pub fn examine(mem: &Memory, spec: &ExamineSpec, addr: u64) -> String {
+    let mut out = String::new();
+    let mut addr = addr;
+    match spec.format {
+        's' => {
+            for _ in 0..spec.count {
+                let start = addr;
+                let mut text = String::new();
+                while text.len() < MAX_STRING_LEN {
+                    match mem.mem_read(addr, 1) {
+                        Ok(0) => { addr = addr.wrapping_add(1); break; }
+                        Ok(b) => text.push(b as u8 as char),
+                        Err(e) => {
+                            out.push_str(&format!("0x{:08x}: <{}>\n", addr, e));
+                            return out;
+                        }
+                    }
+                    addr = addr.wrapping_add(1);
+                }
+                out.push_str(&format!("0x{:08x}: {:?}\n", start, text));
+            }
+        }
+        'i' => {
+            for _ in 0..spec.count {
+                match mem.mem_read(addr, 4) {
+                    Ok(inst) => out.push_str(&format!("0x{:08x}: {}\n", addr, disassemble(addr, inst as u32))),
+                    Err(e) => {
+                        out.push_str(&format!("0x{:08x}: <{}>\n", addr, e));
+                        return out;
+                    }
+                }
+                addr = addr.wrapping_add(4);
+            }
+        }
+        _ => {
+            // 每行 16 字节，与 GDB 相同
+            let per_line = (16 / spec.unit).max(1);
+            for i in 0..spec.count {
+                if i % per_line == 0 {
+                    if i > 0 { out.push('\n'); }
+                    out.push_str(&format!("0x{:08x}:", addr));
+                }
+                match mem.mem_read(addr, spec.unit) {
+                    Ok(v) => out.push_str(&format!(" {}", spec.format_item(v))),
+                    Err(e) => {
+                        out.push_str(&format!(" <{}>\n", e));
+                        return out;
+                    }
+                }
+                addr = addr.wrapping_add(spec.unit as u64);
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+pub fn export_timeline(timeline: &Timeline, html: bool, path: &str) -> String {
+    let content = if html { timeline.to_html() } else { timeline.to_csv() };
+    match fs::write(path, content) {
+        Ok(_) => format!("Pipeline diagram exported to {}\n", path),
+        Err(e) => format!("Failed to export {}: {}\n", path, e),
+    }
+}
+
+// 把 [addr, addr + len) 写入主机文件
+pub fn dump_memory(mem: &Memory, addr: u64, len: usize, path: &str) -> String {
+    let bytes = match mem.read_bytes(addr, len) {
+        Ok(bytes) => bytes,
+        Err(e) => return format!("Failed to dump 0x{:08x}+{}: {}\n", addr, len, e),
+    };
+    match fs::write(path, bytes) {
+        Ok(_) => format!("Dumped {} bytes at 0x{:08x} to {}\n", len, addr, path),
+        Err(e) => format!("Failed to write {}: {}\n", path, e),
+    }
+}
+
+// 把主机文件的内容写回 addr 起的内存
+pub fn restore_memory(mem: &mut Memory, addr: u64, path: &str) -> String {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => return format!("Failed to read {}: {}\n", path, e),
+    };
+    match mem.write_bytes(addr, &data) {
+        Ok(_) => format!("Restored {} bytes from {} at 0x{:08x}\n", data.len(), path, addr),
+        Err(e) => format!("Failed to restore {} at 0x{:08x}: {}\n", path, addr, e),
+    }
+}
+
+// 用 values 循环填满 [addr, addr + len)，每个值取低 unit 字节（小端）
+pub fn fill_memory(mem: &mut Memory, addr: u64, len: usize, unit: usize, values: &[u64]) -> String {
+    let pattern: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()[..unit].to_vec()).collect();
+    let data: Vec<u8> = pattern.iter().copied().cycle().take(len).collect();
+    match mem.write_bytes(addr, &data) {
+        Ok(_) => format!("Filled {} bytes at 0x{:08x}\n", len, addr),
+        Err(e) => format!("Failed to fill 0x{:08x}+{}: {}\n", addr, len, e),
+    }
+}
+
+// 解析地址表达式：十六进制地址、寄存器名（sp / $sp / x2）或符号表中的符号（可写成 &count）
+pub fn resolve_location(expr: &str, pipeline: &Pipeline, srcmap: &Result<SourceMap, String>) -> Result<u64, String> {
+    let expr = expr.trim();
+    let expr = expr.strip_prefix('&').unwrap_or(expr);
+    let name = expr.trim_start_matches('$');
+    if let Some(i) = REG_NAMES.iter().position(|r| *r == name) {
+        return Ok(pipeline.cpu.reg[i]);
+    }
+    if let Some(i) = name.strip_prefix('x').and_then(|n| n.parse::<usize>().ok()).filter(|i| *i < 32) {
+        return Ok(pipeline.cpu.reg[i]);
+    }
+    if name == "pc" {
+        return Ok(pipeline.cpu.pc);
+    }
+    if let Some(addr) = srcmap.as_ref().ok().and_then(|m| m.symbol(name)) {
+        return Ok(addr);
+    }
+    parse_hex_address(expr).map_err(|_| format!("Cannot resolve '{}'", expr))
+}
+
+// byte count: decimal, or hex with 0x
+fn check_examine_count(count: usize) -> Result<(), String> {
+    if count > MAX_EXAMINE_COUNT {
+        return Err(format!("x prints at most {} units at a time", MAX_EXAMINE_COUNT));
+    }
+    Ok(())
+}
+
+fn parse_length(s: &str) -> Result<usize, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse::<usize>(),
+    }.map_err(|_| format!("Invalid length: {}", s))
+}
+
+// 十进制（可为负）或 0x 开头的十六进制
+fn parse_value(s: &str) -> Result<u64, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse::<i64>().ok().map(|v| v as u64),
+    }.ok_or(format!("Invalid value: {}", s))
+}
+
+// 同 parse_value，并检查能放进 width 字节（负数取补码的低 width 字节）
+pub fn parse_sized_value(s: &str, width: usize) -> Result<u64, String> {
+    let value = parse_value(s.trim())?;
+    if width >= 8 {
+        return Ok(value);
+    }
+    let bits = width * 8;
+    let signed = value as i64;
+    if value >> bits == 0 || (signed < 0 && signed >= -(1i64 << (bits - 1))) {
+        Ok(value & ((1u64 << bits) - 1))
+    } else {
+        Err(format!("Value {} does not fit in {} byte(s)", s.trim(), width))
+    }
+}
+
+pub fn parse_hex_address(s: &str) -> Result<u64, String> {
+    let s = s.trim_start_matches("0x").trim_start_matches("0X");
+    u64::from_str_radix(s, 16).map_err(|_| format!("Invalid hex address: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_examine_spec() {
+        assert_eq!(ExamineSpec::parse("4wx").unwrap(), ExamineSpec { count: 4, unit: 4, format: 'x' });
+        assert_eq!(ExamineSpec::parse("8bd").unwrap(), ExamineSpec { count: 8, unit: 1, format: 'd' });
+        assert_eq!(ExamineSpec::parse("s").unwrap(), ExamineSpec { count: 1, unit: 4, format: 's' });
+        assert!(ExamineSpec::parse("4q").is_err());
+        assert!(parse_command("x/100000wx 0x80000000").is_err());
+        assert!(parse_command("x 0xffffffff 0x80000000").is_err());
+    }
+
+    #[test]
+    fn test_parse_x_command() {
+        let cmd = parse_command("x/2gx 0x80000000").unwrap();
+        let spec = ExamineSpec { count: 2, unit: 8, format: 'x' };
+        assert_eq!(cmd, Some(Command::Examine { spec, addr: 0x8000_0000 }));
+
+        let cmd = parse_command("x 3 80000000").unwrap();
+        let spec = ExamineSpec { count: 3, ..ExamineSpec::default() };
+        assert_eq!(cmd, Some(Command::Examine { spec, addr: 0x8000_0000 }));
+    }
+
+    #[test]
+    fn test_parse_break() {
+        let cmd = parse_command("b 80000010 if $a0 >= 0x10").unwrap();
+        let condition = Some(Condition::parse("a0 >= 16").unwrap());
+        assert_eq!(cmd, Some(Command::Break { addr: 0x8000_0010, temporary: false, condition }));
+        let cmd = parse_command("tbreak 0x80000020").unwrap();
+        assert_eq!(cmd, Some(Command::Break { addr: 0x8000_0020, temporary: true, condition: None }));
+        assert_eq!(parse_command("delete 2").unwrap(), Some(Command::Delete(Some(2))));
+        assert!(parse_command("si 5").unwrap().unwrap().repeats());
+        assert!(!parse_command("delete").unwrap().unwrap().repeats());
+        assert_eq!(parse_command("d").unwrap(), Some(Command::Delete(None)));
+        assert!(parse_command("b 80000010 when a0").is_err());
+        let cmd = parse_command("watch 80001000 16 rw").unwrap();
+        assert_eq!(cmd, Some(Command::Watch { addr: 0x8000_1000, len: 16, kind: WatchKind::Access }));
+        assert_eq!(parse_command("p $a0 + 4").unwrap(), Some(Command::Print(String::from("$a0 + 4"))));
+        assert_eq!(parse_command("disas 80000000 4").unwrap(), Some(Command::Disas { addr: Some(0x8000_0000), count: 4 }));
+        assert_eq!(parse_command("trace mtrace on").unwrap(), Some(Command::Trace(Some((TraceKind::Mem, true)))));
+        assert_eq!(parse_command("save run.ckpt").unwrap(), Some(Command::Save(String::from("run.ckpt"))));
+        assert!(parse_command("load").is_err());
+        assert_eq!(parse_command("rsi 3").unwrap(), Some(Command::ReverseStep(3)));
+        assert_eq!(parse_command("trace").unwrap(), Some(Command::Trace(None)));
+        assert!(parse_command("trace xtrace on").is_err());
+        assert_eq!(parse_command("disas").unwrap(), Some(Command::Disas { addr: None, count: DEFAULT_DISAS_LINES }));
+        let cmd = parse_command("watch 0x80001000").unwrap();
+        assert_eq!(cmd, Some(Command::Watch { addr: 0x8000_1000, len: 4, kind: WatchKind::Write }));
+    }
+
+    #[test]
+    fn test_dump_restore() {
+        let cmd = parse_command("dump 0x80001000 0x40 Arr.bin").unwrap();
+        assert_eq!(cmd, Some(Command::Dump { addr: 0x8000_1000, len: 0x40, path: String::from("Arr.bin") }));
+        assert!(parse_command("dump 0x80001000 ten a.bin").is_err());
+
+        let path = crate::runner::test_dir().join("dump.bin");
+        let path = path.to_str().unwrap();
+        let mut mem = Memory::new();
+        mem.mem_write(0x8000_0010, 8, 0x1122_3344_5566_7788).unwrap();
+        dump_memory(&mem, 0x8000_0010, 8, path);
+        restore_memory(&mut mem, 0x8000_0100, path);
+        assert_eq!(mem.mem_read(0x8000_0100, 8).unwrap(), 0x1122_3344_5566_7788);
+        let _ = fs::remove_file(path);
+
+        let cmd = parse_command("fill/h 0x80000200 6 -1 0x2").unwrap();
+        assert_eq!(cmd, Some(Command::Fill { addr: 0x8000_0200, len: 6, unit: 2, values: vec![u64::MAX, 2] }));
+        assert!(parse_command("fill 0x80000200 8").is_err());
+        assert!(parse_command("fill/q 0x80000200 8 1").is_err());
+        fill_memory(&mut mem, 0x8000_0200, 6, 2, &[u64::MAX, 2]);
+        assert_eq!(mem.mem_read(0x8000_0200, 8).unwrap(), 0x0000_ffff_0002_ffff);
+
+        assert_eq!(parse_sized_value("0xff", 1), Ok(0xff));
+        assert_eq!(parse_sized_value("-1", 2), Ok(0xffff));
+        assert_eq!(parse_sized_value("-129", 1), Err(String::from("Value -129 does not fit in 1 byte(s)")));
+        assert_eq!(parse_sized_value("-2", 8), Ok(u64::MAX - 1));
+    }
+
+    #[test]
+    fn test_symbols() {
+        let asm = "main:
+  call twice
+  j done
+done:
+  ret
+twice:
+  add a0, a0, a0
+  ret
+.data
+count:
+  .word 5
+";
+        let path = crate::runner::test_image(asm);
+        let image = crate::codegen::encode::assemble(asm, 0x8000_0000).unwrap();
+        let sim = super::super::embed::Simulator::load(&path).unwrap();
+        let srcmap = SourceMap::parse(&fs::read_to_string(std::path::Path::new(&path).with_extension("map")).unwrap());
+        let resolve = |s: &str| resolve_location(s, &sim.pipeline, &srcmap);
+
+        let cmd = parse_command_with("b twice", &resolve).unwrap();
+        assert_eq!(cmd, Some(Command::Break { addr: image.symbols["twice"], temporary: false, condition: None }));
+        let spec = ExamineSpec { count: 1, ..ExamineSpec::default() };
+        assert_eq!(parse_command_with("x 1 &count", &resolve).unwrap(), Some(Command::Examine { spec, addr: image.symbols["count"] }));
+        assert!(parse_command_with("b nowhere", &resolve).is_err());
+
+        let disas = format_disas(&sim.pipeline, &sim.mem, &srcmap, Some(image.symbols["main"]), 2);
+        assert!(disas.contains("call twice"), "{}", disas);
+        assert!(disas.contains(&format!("jal zero, 0x{:x} <done>", image.symbols["done"])), "{}", disas);
+    }
+
+    #[test]
+    fn test_info_perf() {
+        // hpmcounter4 counts retired loads; the nops let the last lw retire before csrr reads it
+        let asm = "main:
+  addi sp, sp, -16
+  sw zero, 0(sp)
+  lw t0, 0(sp)
+  lw t0, 0(sp)
+  lw t0, 0(sp)
+  nop
+  nop
+  nop
+  csrr a0, hpmcounter4
+  addi sp, sp, 16
+  ret
+";
+        let path = crate::runner::test_image(asm);
+        let mut sim = super::super::embed::Simulator::load(&path).unwrap();
+        assert_eq!(sim.run(Some(1000)), Some(3));
+        assert_eq!(parse_command("info perf").unwrap(), Some(Command::Info(InfoTopic::Perf)));
+        let info = format_info(InfoTopic::Perf, &sim.pipeline, &sim.mem);
+        assert!(info.contains("Memory accesses: 4 (3 loads, 1 stores)"), "{}", info);
+
+        assert_eq!(parse_command("info stat").unwrap(), Some(Command::Info(InfoTopic::Stats)));
+        let stats = sim.pipeline.statistics();
+        assert_eq!((stats.loads, stats.stores), (3, 1));
+        assert!(stats.control_flushes >= 1); // the return from main
+
+        // si N stops with the program and says how far it got
+        let mut sim = super::super::embed::Simulator::load(&path).unwrap();
+        let (done, message) = step_cycles(&mut sim.pipeline, &mut sim.mem, 1000);
+        assert!(done < 1000 && message.starts_with(&format!("Executed {} steps, program halted", done)), "{}", message);
+    }
+}
@@ -0,0 +1,177 @@
+// RV64C: 16-bit instructions are expanded to their 32-bit equivalents at fetch, so decode and the
+// later stages only ever see standard encodings; IFIDReg/IDEXReg remember the original length for
+// the fall-through pc and the link address. C.FLD/C.FSD and their sp forms need the D extension
+// and are left illegal.
+
+use super::mem::{Memory, MemoryError};
+
+// the instruction at `pc` and whether it was compressed. An invalid 16-bit encoding comes back
+// as its raw bits, which no 32-bit pattern matches, so it traps as illegal further down
+pub fn fetch(mem: &Memory, pc: u64) -> Result<(u32, bool), MemoryError> {
+    let word = mem.inst_fetch(pc)?;
+    if word & 3 == 3 {
+        return Ok((word, false));
+    }
+    let half = word as u16;
+    Ok((expand(half).unwrap_or(half as u32), true))
+}
+
+// bits hi..=lo of `inst`, moved to bit `to`
+fn field(inst: u16, hi: u32, lo: u32, to: u32) -> u32 {
+    ((inst as u32 >> lo) & ((1 << (hi - lo + 1)) - 1)) << to
+}
+
+fn sext(value: u32, bits: u32) -> u32 {
+    (((value << (32 - bits)) as i32) >> (32 - bits)) as u32
+}
+
+fn i_type(imm: u32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    (imm & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+fn s_type(imm: u32, rs2: u32, rs1: u32, funct3: u32) -> u32 {
+    (imm >> 5 & 0x7f) << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | (imm & 0x1f) << 7 | 0x23
+}
+
+fn r_type(funct7: u32, rs2: u32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+fn b_type(imm: u32, rs1: u32, funct3: u32) -> u32 {
+    (imm >> 12 & 1) << 31 | (imm >> 5 & 0x3f) << 25 | rs1 << 15 | funct3 << 12 | (imm >> 1 & 0xf) << 8 | (imm >> 11 & 1) << 7 | 0x63
+}
+
+fn j_type(imm: u32, rd: u32) -> u32 {
+    (imm >> 20 & 1) << 31 | (imm >> 1 & 0x3ff) << 21 | (imm >> 11 & 1) << 20 | (imm >> 12 & 0xff) << 12 | rd << 7 | 0x6f
+}
+
+// the 32-bit instruction a compressed one stands for, None if reserved or unsupported
+pub fn expand(inst: u16) -> Option<u32> {
+    let funct3 = field(inst, 15, 13, 0);
+    let rd = field(inst, 11, 7, 0); // also rs1
+    let rs2 = field(inst, 6, 2, 0);
+    let rd_ = 8 + field(inst, 4, 2, 0); // rd' / rs2'
+    let rs1_ = 8 + field(inst, 9, 7, 0); // rs1' / rd'
+    let imm6 = sext(field(inst, 12, 12, 5) | field(inst, 6, 2, 0), 6);
+    let shamt = field(inst, 12, 12, 5) | field(inst, 6, 2, 0);
+
+    Some(match (inst & 3, funct3) {
+        _ if inst == 0 => return None,
+        // C.ADDI4SPN
+        (0, 0) => {
+            let imm = field(inst, 12, 11, 4) | field(inst, 10, 7, 6) | field(inst, 6, 6, 2) | field(inst, 5, 5, 3);
+            if imm == 0 {
+                return None;
+            }
+            i_type(imm, 2, 0, rd_, 0x13)
+        }
+        // C.LW / C.LD
+        (0, 2) => i_type(field(inst, 12, 10, 3) | field(inst, 6, 6, 2) | field(inst, 5, 5, 6), rs1_, 2, rd_, 0x03),
+        (0, 3) => i_type(field(inst, 12, 10, 3) | field(inst, 6, 5, 6), rs1_, 3, rd_, 0x03),
+        // C.SW / C.SD
+        (0, 6) => s_type(field(inst, 12, 10, 3) | field(inst, 6, 6, 2) | field(inst, 5, 5, 6), rd_, rs1_, 2),
+        (0, 7) => s_type(field(inst, 12, 10, 3) | field(inst, 6, 5, 6), rd_, rs1_, 3),
+        // C.ADDI (C.NOP) / C.ADDIW / C.LI
+        (1, 0) => i_type(imm6, rd, 0, rd, 0x13),
+        (1, 1) if rd != 0 => i_type(imm6, rd, 0, rd, 0x1b),
+        (1, 2) => i_type(imm6, 0, 0, rd, 0x13),
+        // C.ADDI16SP
+        (1, 3) if rd == 2 => {
+            let imm = field(inst, 12, 12, 9) | field(inst, 6, 6, 4) | field(inst, 5, 5, 6) | field(inst, 4, 3, 7) | field(inst, 2, 2, 5);
+            if imm == 0 {
+                return None;
+            }
+            i_type(sext(imm, 10), 2, 0, 2, 0x13)
+        }
+        // C.LUI
+        (1, 3) => {
+            if imm6 == 0 || rd == 0 {
+                return None;
+            }
+            (imm6 & 0xfffff) << 12 | rd << 7 | 0x37
+        }
+        (1, 4) => match (field(inst, 11, 10, 0), field(inst, 12, 12, 0), field(inst, 6, 5, 0)) {
+            (0, _, _) => i_type(shamt, rs1_, 5, rs1_, 0x13),                 // C.SRLI
+            (1, _, _) => i_type(0x400 | shamt, rs1_, 5, rs1_, 0x13),         // C.SRAI
+            (2, _, _) => i_type(imm6, rs1_, 7, rs1_, 0x13),                  // C.ANDI
+            (_, 0, 0) => r_type(0x20, rd_, rs1_, 0, rs1_, 0x33),             // C.SUB
+            (_, 0, 1) => r_type(0, rd_, rs1_, 4, rs1_, 0x33),                // C.XOR
+            (_, 0, 2) => r_type(0, rd_, rs1_, 6, rs1_, 0x33),                // C.OR
+            (_, 0, 3) => r_type(0, rd_, rs1_, 7, rs1_, 0x33),                // C.AND
+            (_, 1, 0) => r_type(0x20, rd_, rs1_, 0, rs1_, 0x3b),             // C.SUBW
+            (_, 1, 1) => r_type(0, rd_, rs1_, 0, rs1_, 0x3b),                // C.ADDW
+            _ => return None,
+        },
+        // C.J
+        (1, 5) => {
+            let imm = field(inst, 12, 12, 11) | field(inst, 11, 11, 4) | field(inst, 10, 9, 8) | field(inst, 8, 8, 10)
+                | field(inst, 7, 7, 6) | field(inst, 6, 6, 7) | field(inst, 5, 3, 1) | field(inst, 2, 2, 5);
+            j_type(sext(imm, 12), 0)
+        }
+        // C.BEQZ / C.BNEZ
+        (1, 6 | 7) => {
+            let imm = field(inst, 12, 12, 8) | field(inst, 11, 10, 3) | field(inst, 6, 5, 6) | field(inst, 4, 3, 1) | field(inst, 2, 2, 5);
+            b_type(sext(imm, 9), rs1_, funct3 - 6)
+        }
+        // C.SLLI
+        (2, 0) => i_type(shamt, rd, 1, rd, 0x13),
+        // C.LWSP / C.LDSP
+        (2, 2) if rd != 0 => i_type(field(inst, 12, 12, 5) | field(inst, 6, 4, 2) | field(inst, 3, 2, 6), 2, 2, rd, 0x03),
+        (2, 3) if rd != 0 => i_type(field(inst, 12, 12, 5) | field(inst, 6, 5, 3) | field(inst, 4, 2, 6), 2, 3, rd, 0x03),
+        (2, 4) => match (field(inst, 12, 12, 0), rd, rs2) {
+            (0, 0, 0) => return None,
+            (0, _, 0) => i_type(0, rd, 0, 0, 0x67),                          // C.JR
+            (0, _, _) => r_type(0, rs2, 0, 0, rd, 0x33),                     // C.MV
+            (_, 0, 0) => 0x0010_0073,                                        // C.EBREAK
+            (_, _, 0) => i_type(0, rd, 0, 1, 0x67),                          // C.JALR
+            _ => r_type(0, rs2, rd, 0, rd, 0x33),                            // C.ADD
+        },
+        // C.SWSP / C.SDSP
+        (2, 6) => s_type(field(inst, 12, 9, 2) | field(inst, 8, 7, 6), rs2, 2, 2),
+        (2, 7) => s_type(field(inst, 12, 10, 3) | field(inst, 9, 7, 6), rs2, 2, 3),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::bench::{run_model, CpuModel};
+    use super::super::embed::Simulator;
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand(0x0505), Some(0x0015_0513)); // c.addi a0, 1
+        assert_eq!(expand(0x4501), Some(0x0000_0513)); // c.li a0, 0
+        assert_eq!(expand(0x8082), Some(0x0000_8067)); // c.ret
+        assert_eq!(expand(0x852e), Some(0x00b0_0533)); // c.mv a0, a1
+        assert_eq!(expand(0xe406), Some(0x0011_3423)); // c.sdsp ra, 8(sp)
+        assert_eq!(expand(0x60a2), Some(0x0081_3083)); // c.ldsp ra, 8(sp)
+        assert_eq!(expand(0x1141), Some(0xff01_0113)); // c.addi sp, -16
+        assert_eq!(expand(0x717d), Some(0xff01_0113)); // c.addi16sp sp, -16
+        assert_eq!(expand(0x4188), Some(0x0005_a503)); // c.lw a0, 0(a1)
+        assert_eq!(expand(0xc101), Some(0x0005_0063)); // c.beqz a0, 0
+        assert_eq!(expand(0xbffd), Some(0xffff_f06f)); // c.j -2
+        assert_eq!(expand(0x0000), None);
+        assert_eq!(expand(0x2000), None); // c.fld
+    }
+
+    #[test]
+    fn test_compressed_program() {
+        // a0 = 0; a1 = 3; do { a0 += 2; a1 -= 1; } while (a1); return a0;
+        let asm = "main:
+  .half 0x4501
+  .half 0x458d
+  .half 0x0509
+  .half 0x15fd
+  .half 0xfdf5
+  .half 0x8082
+";
+        let path = crate::runner::test_image(asm);
+        let mut sim = Simulator::load(&path).unwrap();
+        assert_eq!(sim.run(Some(1000)), Some(6));
+        let run = run_model(&path, &Default::default(), CpuModel::SingleCycle, None).unwrap();
+        assert_eq!(run.exit_code, 6);
+    }
+}
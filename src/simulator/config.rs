@@ -0,0 +1,322 @@
+// Simulator configuration, read from `--config sim.toml`. Every field is optional:
+//
+//   [memory]
+//   base = 0x80000000
+//   size = 0x8000000
+//   elf = false             # load the image as ELF even when it is named .bin, same as --elf
+//   regions = [             # more RAM / ROM, same as --region rom:0x1000:0x10000
+//     { kind = "rom", base = 0x1000, size = 0x10000, name = "bootrom" },
+//   ]
+//   misaligned = "emulate"  # or "trap": misaligned loads/stores raise an exception (mcause 4 / 6)
+//   misaligned_penalty = 0  # extra stall cycles of an emulated misaligned access
+//   stack_top = 0x88000000  # sp before the first instruction, defaults to the end of RAM
+//
+//   [cache]
+//   enabled = true
+//   icache = { sets = 16, ways = 2, line_size = 16 }
+//   dcache = { sets = 64, ways = 4, line_size = 32, write_policy = "write-through", miss_penalty = 20 }
+//
+//   [predictor]
+//   kind = "not-taken"      # the only predictor implemented so far
+//
+//   [pipeline]
+//   hz = 1000.0             # free-run speed in the GUI, same as --hz
+//   undo_depth = 10000      # cycles kept for rsi; the interactive frontends default to 10000, the rest to 0
+//
+//   [trace]
+//   state = false           # per-cycle pipeline state dump (GUI, --shell, --cli)
+//   perf_interval = 100     # cycles per performance sample
+//   itrace = false          # log every committed instruction, same as --itrace
+//   mtrace = false          # log every load and store, same as --mtrace
+//   ftrace = false          # log calls and returns by symbol, same as --ftrace
+//   log = "trace.log"       # where the traces go instead of stderr, same as --trace-log
+//
+//   [console]
+//   stdin = "input.txt"     # queued on the UART before the program starts, same as --stdin
+//
+//   [[devices]]
+//   kind = "uart"
+//   base = 0xa00003f8
+//
+//   [[devices]]
+//   kind = "clint"          # mtime / mtimecmp, machine timer interrupts
+//   base = 0x2000000
+//
+// `--set section.field=value` overrides a single field after the file is read.
+
+use std::fs;
+
+use serde::Deserialize;
+
+use super::cache::CacheGeometry;
+use super::device::{CLINT_BASE, CLINT_SIZE, SERIAL_PORT, SERIAL_SIZE};
+use super::mem::{Memory, MEM_BASE, MEM_SIZE};
+use super::memmap::{MemoryMap, RegionConfig, RegionKind};
+use super::pipe::Pipeline;
+use super::srcmap::SourceMap;
+use super::trace::TraceFlags;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryConfig {
+    pub base: u64,
+    pub size: usize,
+    pub elf: bool,
+    pub regions: Vec<RegionConfig>,
+    pub misaligned: MisalignedPolicy,
+    pub misaligned_penalty: u64,
+    pub stack_top: Option<u64>,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self { base: MEM_BASE, size: MEM_SIZE, elf: false, regions: Vec::new(), misaligned: MisalignedPolicy::Emulate, misaligned_penalty: 0, stack_top: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MisalignedPolicy {
+    #[default]
+    Emulate,
+    Trap,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub icache: CacheGeometry,
+    pub dcache: CacheGeometry,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PredictorKind {
+    #[default]
+    NotTaken,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PredictorConfig {
+    pub kind: PredictorKind,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub hz: Option<f64>,
+    pub undo_depth: Option<usize>, // None: the frontend decides
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraceConfig {
+    pub state: Option<bool>, // None: the frontend decides
+    pub perf_interval: Option<u64>,
+    pub itrace: bool,
+    pub mtrace: bool,
+    pub ftrace: bool,
+    pub log: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsoleConfig {
+    pub stdin: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum DeviceConfig {
+    Uart { base: u64 },
+    Clint { base: u64 },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimConfig {
+    pub memory: MemoryConfig,
+    pub cache: CacheConfig,
+    pub predictor: PredictorConfig,
+    pub pipeline: PipelineConfig,
+    pub trace: TraceConfig,
+    pub console: ConsoleConfig,
+    pub devices: Vec<DeviceConfig>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            memory: MemoryConfig::default(),
+            cache: CacheConfig::default(),
+            predictor: PredictorConfig::default(),
+            pipeline: PipelineConfig::default(),
+            trace: TraceConfig::default(),
+            console: ConsoleConfig::default(),
+            devices: vec![DeviceConfig::Uart { base: SERIAL_PORT }, DeviceConfig::Clint { base: CLINT_BASE }],
+        }
+    }
+}
+
+// "a.b=value": the value is parsed as TOML, falling back to a bare string
+fn apply_override(table: &mut toml::Table, assignment: &str) -> Result<(), String> {
+    let (key, value) = assignment.split_once('=').ok_or(format!("--set expects KEY=VALUE, got '{}'", assignment))?;
+    let value = match format!("v = {}", value.trim()).parse::<toml::Table>() {
+        Ok(mut doc) => doc.remove("v").unwrap(),
+        Err(_) => toml::Value::String(value.trim().to_string()),
+    };
+
+    let mut path: Vec<&str> = key.trim().split('.').collect();
+    let last = path.pop().unwrap();
+    let mut table = table;
+    for part in path {
+        table = table
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or(format!("'{}' in '{}' is not a table", part, key))?;
+    }
+    table.insert(last.to_string(), value);
+    Ok(())
+}
+
+impl SimConfig {
+    // read `path` (if any), then apply `--set` overrides in order
+    pub fn load(path: Option<&str>, overrides: &[String]) -> Result<Self, String> {
+        let mut table = match path {
+            Some(path) => {
+                let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+                text.parse::<toml::Table>().map_err(|e| format!("{}: {}", path, e))?
+            }
+            None => toml::Table::new(),
+        };
+        for assignment in overrides {
+            apply_override(&mut table, assignment)?;
+        }
+        let config: SimConfig = toml::Value::Table(table).try_into().map_err(|e| format!("config: {}", e))?;
+        config.cache.icache.validate()?;
+        config.cache.dcache.validate()?;
+        if config.memory.size == 0 {
+            return Err(String::from("config: memory.size must be non-zero"));
+        }
+        if config.pipeline.hz.is_some_and(|hz| !(hz > 0.0 && hz.is_finite())) {
+            return Err(String::from("config: pipeline.hz must be positive"));
+        }
+        config.memory_map()?;
+        Ok(config)
+    }
+
+    // main RAM, extra regions and devices, checked for overlaps
+    pub fn memory_map(&self) -> Result<MemoryMap, String> {
+        let mut map = MemoryMap::default();
+        map.add("ram", RegionKind::Ram, self.memory.base, self.memory.size as u64)?;
+        for region in &self.memory.regions {
+            if region.kind == RegionKind::Device {
+                return Err(String::from("config: devices go in [[devices]], not memory.regions"));
+            }
+            map.add(region.name(), region.kind, region.base, region.size as u64)?;
+        }
+        for device in &self.devices {
+            match device {
+                DeviceConfig::Uart { base } => map.add("uart", RegionKind::Device, *base, SERIAL_SIZE)?,
+                DeviceConfig::Clint { base } => map.add("clint", RegionKind::Device, *base, CLINT_SIZE)?,
+            }
+        }
+        Ok(map)
+    }
+
+    // initial sp, for images that start without setting it up themselves
+    pub fn stack_top(&self) -> u64 {
+        self.memory.stack_top.unwrap_or(self.memory.base + self.memory.size as u64)
+    }
+
+    // empty RAM and devices laid out as configured
+    pub fn new_memory(&self) -> Memory {
+        let mut mem = Memory::with_map(self.memory.base, self.memory.size);
+        for region in &self.memory.regions {
+            mem.add_region(region.name(), region.base, region.size, region.kind == RegionKind::Rom);
+        }
+        mem.elf = self.memory.elf;
+        mem.trap_misaligned = self.memory.misaligned == MisalignedPolicy::Trap;
+        mem.misaligned_penalty = self.memory.misaligned_penalty;
+        for device in &self.devices {
+            match device {
+                DeviceConfig::Uart { base } => mem.uart.base = *base,
+                DeviceConfig::Clint { base } => mem.clint.base = *base,
+            }
+        }
+        mem
+    }
+
+    // the console.stdin file, for the guest's getint / getch
+    pub fn queue_input(&self, mem: &mut Memory) -> Result<(), String> {
+        if let Some(path) = &self.console.stdin {
+            let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            mem.uart.push_input(&text);
+        }
+        Ok(())
+    }
+
+    // call after Pipeline::new(), before init()
+    pub fn apply(&self, pipeline: &mut Pipeline) {
+        pipeline.entry = self.memory.base;
+        pipeline.stack_top = self.stack_top();
+        pipeline.undo.depth = self.pipeline.undo_depth.unwrap_or(0);
+        pipeline.icache_geometry = self.cache.icache;
+        pipeline.dcache_geometry = self.cache.dcache;
+        pipeline.enable_caches(self.cache.enabled);
+        if let Some(state) = self.trace.state {
+            pipeline.quiet = !state;
+        }
+        if let Some(interval) = self.trace.perf_interval {
+            pipeline.perf.interval = interval;
+        }
+        pipeline.tracer.flags = TraceFlags { itrace: self.trace.itrace, mtrace: self.trace.mtrace, ftrace: self.trace.ftrace };
+    }
+
+    // line map or ELF symbols for `image`, loaded the way build() loads the image
+    pub fn symbols(&self, image: &str) -> Result<SourceMap, String> {
+        SourceMap::load_for_image(image).or_else(|e| if self.memory.elf { SourceMap::from_elf(image) } else { Err(e) })
+    }
+
+    // memory, image and pipeline ready to run
+    pub fn build(&self, image: &str) -> Result<(Pipeline, Memory), String> {
+        let mut mem = self.new_memory();
+        mem.load_image(image).map_err(|e| format!("{}: {}", image, e))?;
+        self.queue_input(&mut mem)?;
+        let mut pipeline = Pipeline::new();
+        self.apply(&mut pipeline);
+        pipeline.entry = mem.entry();
+        if let Some(log) = &self.trace.log {
+            pipeline.tracer.open_log(log)?;
+        }
+        pipeline.tracer.symbols = self.symbols(image).ok();
+        pipeline.init();
+        Ok((pipeline, mem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides() {
+        let overrides = ["memory.size=0x1000", "cache.icache.ways=4", "predictor.kind=not-taken", "devices=[{kind = \"uart\", base = 0x10000000}]"]
+            .map(String::from);
+        let config = SimConfig::load(None, &overrides).unwrap();
+        assert_eq!(config.memory.size, 0x1000);
+        assert!(SimConfig::load(None, &[String::from("pipeline.hz=0")]).is_err());
+        assert_eq!(config.memory.base, 0x8000_0000);
+        assert_eq!(config.cache.icache, CacheGeometry { ways: 4, ..CacheGeometry::default() });
+        assert_eq!(config.predictor.kind, PredictorKind::NotTaken);
+        assert_eq!(config.new_memory().uart.base, 0x1000_0000);
+
+        assert!(SimConfig::load(None, &[String::from("memory.sise=1")]).is_err());
+        assert!(SimConfig::load(None, &[String::from("cache.dcache.sets=3")]).is_err());
+        assert!(SimConfig::load(None, &[String::from("predictor.kind=gshare")]).is_err());
+        assert!(SimConfig::load(None, &[String::from("memory.regions=[{kind = \"rom\", base = 0x2000000, size = 0x1000}]")]).is_err());
+    }
+}
@@ -1,14 +1,86 @@
-use colored::Colorize;
+use serde::{Deserialize, Serialize};
 
-const MEM_BASE: u64 = 0x8000_0000; 
-const MEM_SIZE: usize = 0x80_00000; 
+use super::utils::Colorize;
+use super::perf::HPM_COUNTERS;
+use super::mem::MEM_BASE;
 
+pub const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+    "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+    "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
 
-#[derive(Debug)]
+pub const FREG_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7",
+    "fs0", "fs1", "fa0", "fa1", "fa2", "fa3", "fa4", "fa5",
+    "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7",
+    "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+// F registers share the pipeline's register numbers: rd/rs1/rs2 of 32..63 name f0..f31,
+// so hazard detection and forwarding need no special case
+pub const FREG_BASE: i32 = 32;
+
+// fflags and frm are views of fcsr
+pub const CSR_FFLAGS: u64 = 0x001;
+pub const CSR_FRM: u64 = 0x002;
+pub const CSR_FCSR: u64 = 0x003;
+
+// machine-mode CSRs; cycle/instret come from the performance counters, time from the CLINT
+pub const CSR_MSTATUS: u64 = 0x300;
+pub const CSR_MIE: u64 = 0x304;
+pub const CSR_MTVEC: u64 = 0x305;
+pub const CSR_MSCRATCH: u64 = 0x340;
+pub const CSR_MEPC: u64 = 0x341;
+pub const CSR_MCAUSE: u64 = 0x342;
+pub const CSR_MTVAL: u64 = 0x343;
+pub const CSR_MIP: u64 = 0x344;
+pub const CSR_MCYCLE: u64 = 0xb00;
+pub const CSR_MINSTRET: u64 = 0xb02;
+pub const CSR_CYCLE: u64 = 0xc00;
+pub const CSR_TIME: u64 = 0xc01;
+pub const CSR_INSTRET: u64 = 0xc02;
+pub const CSR_MHPMCOUNTER3: u64 = 0xb03;
+pub const CSR_HPMCOUNTER3: u64 = 0xc03;
+
+pub const MSTATUS_MIE: u64 = 1 << 3;
+pub const MSTATUS_MPIE: u64 = 1 << 7;
+pub const MSTATUS_MPP: u64 = 3 << 11;
+pub const MIP_MTIP: u64 = 1 << 7;
+pub const IRQ_M_TIMER: u64 = 1 << 63 | 7; // mcause of the machine timer interrupt
+pub const CAUSE_ILLEGAL_INST: u64 = 2;
+pub const CAUSE_BREAKPOINT: u64 = 3;
+pub const CAUSE_MISALIGNED_LOAD: u64 = 4;
+pub const CAUSE_LOAD_ACCESS: u64 = 5;
+pub const CAUSE_MISALIGNED_STORE: u64 = 6; // also AMOs
+pub const CAUSE_STORE_ACCESS: u64 = 7;
+pub const CAUSE_ECALL_M: u64 = 11;
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Csrs {
+    pub mstatus: u64,
+    pub mie: u64,
+    pub mip: u64, // MTIP follows the CLINT, see set_timer_pending
+    pub mtvec: u64,
+    pub mscratch: u64,
+    pub mepc: u64,
+    pub mcause: u64,
+    pub mtval: u64,
+    pub fcsr: u64, // frm in bits 7:5, the accrued exception flags in 4:0
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CPUState {
     pub reg: [u64; 32],
+    pub freg: [u64; 32], // doubles as they are, single precision values NaN-boxed
     pub pc: u64,
     pub running: bool,
+    pub quiet: bool,
+    pub csr: Csrs,
+    pub trap_pc: Option<u64>, // handler address after an instruction trapped in EX
+    pub hpm: [u64; HPM_COUNTERS], // hpmcounter3.., kept up to date by the pipeline
 
     /* Sequential execution state */
     pub next_pc: u64,
@@ -19,39 +91,51 @@ pub struct CPUState {
     /* Performance counters */
     pub cycle_count: i32,
     pub inst_count: i32,
-    pub branch_count: i32,
-    pub data_hazard_count: i32,
 }
 
 /* Pipeline registers */
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct IFIDReg {
     pub pc: u64,
     pub inst: u32,
+    pub seq: u64, // fetch sequence number, 0 for bubbles
+    pub compressed: bool, // `inst` was expanded from a 16-bit RV64C instruction
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct IDEXReg {
     pub pc: u64,
     pub inst: u32,
+    pub seq: u64,
     
     pub rd: i32,
     pub rs1: i32,
     pub rs2: i32,
+    pub rs3: i32, // fmadd and friends
     
     pub src1: u64,
     pub src2: u64,
+    pub src3: u64,
     pub imm: u64,
 
     pub jump: bool,
     pub load: bool,
     pub store: bool,
+    pub compressed: bool,
+}
+
+impl IDEXReg {
+    // the next instruction in memory, also the link address of jal/jalr
+    pub fn fall_through(&self) -> u64 {
+        self.pc.wrapping_add(if self.compressed { 2 } else { 4 })
+    }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct EXMEMReg {
     pub pc: u64,
     pub inst: u32,
+    pub seq: u64,
     pub rd: i32,
     pub src2: u64,
     pub alu_out: u64,
@@ -59,15 +143,36 @@ pub struct EXMEMReg {
     
     pub load: bool,
     pub store: bool,
+    pub fault: Option<AccessFault>, // no trap handler: stop the program when this reaches writeback
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct MEMWBReg {
     pub pc: u64,
     pub inst: u32,
+    pub seq: u64,
     pub rd: i32,
     pub alu_out: u64,
     pub mem_data: u64,
+    pub fault: Option<AccessFault>,
+}
+
+// a load or store that raised an exception in EX
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AccessFault {
+    pub cause: u64, // one of the CAUSE_MISALIGNED_* / CAUSE_*_ACCESS codes
+    pub addr: u64, // goes to mtval
+}
+
+impl AccessFault {
+    pub fn name(&self) -> &'static str {
+        match self.cause {
+            CAUSE_MISALIGNED_LOAD => "Misaligned load",
+            CAUSE_LOAD_ACCESS => "Load access fault",
+            CAUSE_MISALIGNED_STORE => "Misaligned store",
+            _ => "Store access fault",
+        }
+    }
 }
 
 
@@ -75,30 +180,105 @@ impl CPUState {
     pub fn new() -> Self {
         Self {
             reg: [0; 32],
+            freg: [0; 32],
             pc: MEM_BASE,
             running: false,
+            quiet: false,
+            csr: Csrs::default(),
+            trap_pc: None,
+            hpm: [0; HPM_COUNTERS],
             next_pc: 0,
             pred_pc: 0,
             cycle_count: 0,
             inst_count: 0,
-            branch_count: 0,
-            data_hazard_count: 0,
         }
     }
 
-    
+    // register `r` in the pipeline numbering (see FREG_BASE)
+    pub fn read_reg(&self, r: i32) -> u64 {
+        if r >= FREG_BASE { self.freg[(r - FREG_BASE) as usize] } else { self.reg[r as usize] }
+    }
+
+    pub fn write_reg(&mut self, r: i32, value: u64) {
+        if r >= FREG_BASE { self.freg[(r - FREG_BASE) as usize] = value } else { self.reg[r as usize] = value }
+    }
+
+    // `time` is the CLINT's mtime; CSRs that do not exist read as 0
+    pub fn csr_read(&self, csr: u64, time: u64) -> u64 {
+        match csr {
+            CSR_MSTATUS => self.csr.mstatus,
+            CSR_MIE => self.csr.mie,
+            CSR_MIP => self.csr.mip,
+            CSR_MTVEC => self.csr.mtvec,
+            CSR_MSCRATCH => self.csr.mscratch,
+            CSR_MEPC => self.csr.mepc,
+            CSR_MCAUSE => self.csr.mcause,
+            CSR_MTVAL => self.csr.mtval,
+            CSR_FFLAGS => self.csr.fcsr & 0x1f,
+            CSR_FRM => self.csr.fcsr >> 5 & 7,
+            CSR_FCSR => self.csr.fcsr,
+            CSR_MCYCLE | CSR_CYCLE => self.cycle_count as u64,
+            CSR_MINSTRET | CSR_INSTRET => self.inst_count as u64,
+            CSR_TIME => time,
+            CSR_MHPMCOUNTER3..=0xb1f => self.hpm.get((csr - CSR_MHPMCOUNTER3) as usize).copied().unwrap_or(0),
+            CSR_HPMCOUNTER3..=0xc1f => self.hpm.get((csr - CSR_HPMCOUNTER3) as usize).copied().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    // only the implemented bits are writable; the counters and mip are read-only here
+    pub fn csr_write(&mut self, csr: u64, value: u64) {
+        match csr {
+            CSR_MSTATUS => self.csr.mstatus = value & (MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP),
+            CSR_MIE => self.csr.mie = value & MIP_MTIP,
+            CSR_MTVEC => self.csr.mtvec = value & !3, // direct mode only
+            CSR_MSCRATCH => self.csr.mscratch = value,
+            CSR_MEPC => self.csr.mepc = value & !3,
+            CSR_MCAUSE => self.csr.mcause = value,
+            CSR_MTVAL => self.csr.mtval = value,
+            CSR_FFLAGS => self.csr.fcsr = self.csr.fcsr & !0x1f | value & 0x1f,
+            CSR_FRM => self.csr.fcsr = self.csr.fcsr & 0x1f | (value & 7) << 5,
+            CSR_FCSR => self.csr.fcsr = value & 0xff,
+            _ => {}
+        }
+    }
+
+    pub fn set_timer_pending(&mut self, pending: bool) {
+        if pending { self.csr.mip |= MIP_MTIP } else { self.csr.mip &= !MIP_MTIP }
+    }
+
+    // an interrupt is pending and enabled
+    pub fn interrupt_pending(&self) -> bool {
+        self.csr.mstatus & MSTATUS_MIE != 0 && self.csr.mie & self.csr.mip != 0
+    }
+
+    // trap entry: mepc = pc, interrupts off (the old MIE goes to MPIE), returns the handler address
+    pub fn trap(&mut self, pc: u64, cause: u64) -> u64 {
+        let mie = self.csr.mstatus & MSTATUS_MIE != 0;
+        self.csr.mepc = pc;
+        self.csr.mcause = cause;
+        self.csr.mstatus = (self.csr.mstatus & !(MSTATUS_MIE | MSTATUS_MPIE)) | MSTATUS_MPP | if mie { MSTATUS_MPIE } else { 0 };
+        self.csr.mtvec
+    }
+
+    // mret: MIE comes back from MPIE, returns mepc
+    pub fn mret(&mut self) -> u64 {
+        let mpie = self.csr.mstatus & MSTATUS_MPIE != 0;
+        self.csr.mstatus = (self.csr.mstatus & !MSTATUS_MIE) | MSTATUS_MPIE | if mpie { MSTATUS_MIE } else { 0 };
+        self.csr.mepc
+    }
+
     pub fn halt_trap(&mut self, pc: u64 , code: u64){
+        self.running = false;
+        if self.quiet {
+            return;
+        }
         if code != 0 {
             println!("{}", "HIT BAD TRAP!".red());
         }else{
             println!("{}", "HIT GOOD TRAP!".green());
-            println!("Total instructions executed: {}", self.inst_count);
-            println!("Total cycles: {}\n", self.cycle_count);
-            println!("Total Data Hazard: {}\n", self.data_hazard_count);
-            println!("Total Branch Misprediction: {}", self.branch_count);   
         }
         println!("Program ended at pc 0x{:08x}, with exit code {}", pc, code);
-        self.running = false;
     }
 }
 
@@ -1,14 +1,17 @@
-use colored::Colorize;
 
 use super::cpu::*;
 use super::mem::*;
 use super::instruction::*;
+use super::extension::{self, CustomArgs};
+use super::float::{self, fp_operands};
+use super::syscall;
 
 // use bit_field::BitField;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InstType {
-    I, U, S, R, J, B, N
+    I, U, S, R, J, B, N,
+    R4, // fmadd and friends: rs3 in bits 31:27
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -18,6 +21,26 @@ pub struct Instruction {
     pub inst_type: InstType,
 }
 
+// an encoding check_inst does not recognise, or a load/store that faulted without a trap
+// handler, reached commit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeError {
+    pub pc: u64,
+    pub inst: u32,
+    pub access: Option<AccessFault>,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.access {
+            Some(fault) => write!(f, "{} at address 0x{:08x}, pc 0x{:08x}", fault.name(), fault.addr, self.pc),
+            None => write!(f, "Illegal instruction 0x{:08x} at pc 0x{:08x}", self.inst, self.pc),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
 #[macro_export]
 macro_rules! bits {
     ($val:expr, $high:expr, $low:expr) => {
@@ -88,35 +111,11 @@ pub fn check_inst(inst: u32) -> Option<&'static Instruction> {
             return Some(pattern);
         }
     }
-    None
+    extension::lookup(inst)
 }
 
-pub fn decode_stage(cpu: &CPUState, s: &IFIDReg) -> IDEXReg {
-
-    let inst = s.inst;
-    let match_res = check_inst(inst);
-
-    let (name, ty) = match match_res {
-        None => {
-            println!("{}", "Error".red());
-            panic!("Invalid instruction: 0x{:x}", inst);
-        },
-        Some(_inst) => (_inst.name, _inst.inst_type),
-    };
-
-    let rd = bits!(inst, 11, 7) as i32;
-    let rs1 = bits!(inst, 19, 15) as i32;
-    let rs2 = bits!(inst, 24, 20) as i32;
-                
-    let src1 = if matches!(ty, InstType::I | InstType::S | InstType::B | InstType::R) {
-        cpu.reg[rs1 as usize]
-    } else { 0 };
-    
-    let src2 = if matches!(ty, InstType::S | InstType::B | InstType::R) {
-        cpu.reg[rs2 as usize]
-    } else { 0 };
-
-    let imm = match ty {
+pub fn decode_imm(inst: u32, ty: InstType) -> u64 {
+    match ty {
         InstType::I => sext(bits!(inst, 31, 20) as u64, 12),
         InstType::U => sext(bits!(inst, 31, 12) as u64, 20) << 12,
         InstType::J => {
@@ -138,62 +137,116 @@ pub fn decode_stage(cpu: &CPUState, s: &IFIDReg) -> IDEXReg {
             sext(imm_raw as u64, 13)
         }
         _ => 0,
-    };
+    }
+}
 
-    let jump = matches!(name, "jal" | "jalr" | "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu");
-    let load = matches!(name, "lb" | "lh" | "lw" | "ld" | "lbu" | "lhu");
-    let store = matches!(name, "sb" | "sh" | "sw" | "sd");
+// lr / sc / amo*: read-modify-write in MEM, rd gets what memory_stage returns
+pub fn is_atomic(name: &str) -> bool {
+    name.starts_with("lr.") || name.starts_with("sc.") || name.starts_with("amo")
+}
 
-    IDEXReg {
-        pc: s.pc,
-        inst: s.inst,
-        rd, rs1, rs2,
-        src1, src2, imm,
-        jump, load, store,
-    }
+// jal / jalr that writes ra
+pub fn is_call(inst: u32) -> bool {
+    matches!(check_inst(inst).map(|i| i.name), Some("jal" | "jalr")) && bits!(inst, 11, 7) == 1
 }
 
+// Unknown encodings are an error here; the caller decides whether the slot is on the wrong path
+pub fn decode_stage(cpu: &CPUState, s: &IFIDReg) -> Result<IDEXReg, DecodeError> {
 
-pub fn execute_stage(cpu: &mut CPUState, s: &IDEXReg) -> EXMEMReg {
     let inst = s.inst;
     let match_res = check_inst(inst);
 
-    let (name, ..) = match match_res {
-        None => {
-            println!("{}", "Error".red());
-            panic!("Invalid instruction: 0x{:x}", inst);
-        },
+    let (name, ty) = match match_res {
+        None => return Err(DecodeError { pc: s.pc, inst, access: None }),
         Some(_inst) => (_inst.name, _inst.inst_type),
     };
 
+    // F registers are numbered from FREG_BASE
+    let (fd, f1, f2) = fp_operands(name);
+    let reg = |r: u32, fp: bool| r as i32 + if fp { FREG_BASE } else { 0 };
+    // ecall returns its result in a0, so later instructions get it forwarded like any other
+    let rd = if name == "ecall" { 10 } else { reg(bits!(inst, 11, 7), fd) };
+    let rs1 = reg(bits!(inst, 19, 15), f1);
+    let rs2 = if float::is_unary(name) { 0 } else { reg(bits!(inst, 24, 20), f2) };
+    let rs3 = if ty == InstType::R4 { reg(bits!(inst, 31, 27), true) } else { 0 };
+                
+    let src1 = if matches!(ty, InstType::I | InstType::S | InstType::B | InstType::R | InstType::R4) {
+        cpu.read_reg(rs1)
+    } else { 0 };
+    
+    let src2 = if matches!(ty, InstType::S | InstType::B | InstType::R | InstType::R4) {
+        cpu.read_reg(rs2)
+    } else { 0 };
+
+    let src3 = cpu.read_reg(rs3);
+
+    let imm = decode_imm(inst, ty);
+
+    let jump = matches!(name, "jal" | "jalr" | "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" | "mret");
+    // atomics count as loads: rd is only known after MEM
+    let load = matches!(name, "lb" | "lh" | "lw" | "ld" | "lbu" | "lhu" | "lwu" | "flw" | "fld") || is_atomic(name);
+    let store = matches!(name, "sb" | "sh" | "sw" | "sd" | "fsw" | "fsd");
+
+    Ok(IDEXReg {
+        pc: s.pc,
+        inst: s.inst,
+        seq: s.seq,
+        rd, rs1, rs2, rs3,
+        src1, src2, src3, imm,
+        jump, load, store,
+        compressed: s.compressed,
+    })
+}
+
+
+pub fn execute_stage(cpu: &mut CPUState, s: &IDEXReg, mem: &mut Memory) -> EXMEMReg {
+    let inst = s.inst;
+    // illegal encodings travel down as no-ops and trap at writeback
+    let name = check_inst(inst).map_or("", |i| i.name);
+
     let src1 = s.src1;
     let src2 = s.src2;
     let imm = s.imm;
     let mut alu_out = 0;
 
+    // exceptions go to mtvec once a handler is installed; without one ecall is a system call
+    // (syscall.rs), ebreak stops the simulation and an illegal instruction faults at writeback.
+    // The trapping instruction leaves EX as a bubble, so it never retires.
+    let cause = match name {
+        "ecall" => Some(CAUSE_ECALL_M),
+        "ebreak" => Some(CAUSE_BREAKPOINT),
+        "" if s.seq != 0 => Some(CAUSE_ILLEGAL_INST),
+        _ => None,
+    };
+    if let Some(cause) = cause.filter(|_| cpu.csr.mtvec != 0) {
+        cpu.csr.mtval = if cause == CAUSE_ILLEGAL_INST { inst as u64 } else { 0 };
+        cpu.trap_pc = Some(cpu.trap(s.pc, cause));
+        return EXMEMReg { pc: s.pc, inst: 0x13, ..EXMEMReg::default() };
+    }
+
     // println!("exec stage: inst 0x{:x}", inst);
     // println!("name = {}, src1 = 0x{:x}, src2 = 0x{:x}, imm = 0x{:x}", name, src1, src2, imm);
     match name {
         "lui"    => alu_out = imm,
         "auipc"  => alu_out = s.pc.wrapping_add(imm),
-        "jal"    => { cpu.next_pc = s.pc.wrapping_add(imm); alu_out = s.pc + 4; },
-        "jalr"   => { cpu.next_pc = (src1.wrapping_add(imm)) & !1; alu_out = s.pc + 4; },
-        "beq"    => cpu.next_pc = if src1 == src2 { s.pc.wrapping_add(imm) } else { s.pc + 4 },
-        "bne"    => cpu.next_pc = if src1 != src2 { s.pc.wrapping_add(imm) } else { s.pc + 4 },
-        "blt"    => cpu.next_pc = if (src1 as i64) < (src2 as i64) { s.pc.wrapping_add(imm) } else { s.pc + 4 },
-        "bge"    => cpu.next_pc = if (src1 as i64) >= (src2 as i64) { s.pc.wrapping_add(imm) } else { s.pc + 4 },
-        "bltu"   => cpu.next_pc = if src1 < src2 { s.pc.wrapping_add(imm) } else { s.pc + 4 },
-        "bgeu"   => cpu.next_pc = if src1 >= src2 { s.pc.wrapping_add(imm) } else { s.pc + 4 },
-        "lb" | "lh" | "lw" | "lbu" | "lhu" | "lwu" | "ld" => alu_out = src1.wrapping_add(imm),
-        "sb" | "sh" | "sw" | "sd" => alu_out = src1.wrapping_add(imm),
+        "jal"    => { cpu.next_pc = s.pc.wrapping_add(imm); alu_out = s.fall_through(); },
+        "jalr"   => { cpu.next_pc = (src1.wrapping_add(imm)) & !1; alu_out = s.fall_through(); },
+        "beq"    => cpu.next_pc = if src1 == src2 { s.pc.wrapping_add(imm) } else { s.fall_through() },
+        "bne"    => cpu.next_pc = if src1 != src2 { s.pc.wrapping_add(imm) } else { s.fall_through() },
+        "blt"    => cpu.next_pc = if (src1 as i64) < (src2 as i64) { s.pc.wrapping_add(imm) } else { s.fall_through() },
+        "bge"    => cpu.next_pc = if (src1 as i64) >= (src2 as i64) { s.pc.wrapping_add(imm) } else { s.fall_through() },
+        "bltu"   => cpu.next_pc = if src1 < src2 { s.pc.wrapping_add(imm) } else { s.fall_through() },
+        "bgeu"   => cpu.next_pc = if src1 >= src2 { s.pc.wrapping_add(imm) } else { s.fall_through() },
+        "lb" | "lh" | "lw" | "lbu" | "lhu" | "lwu" | "ld" | "flw" | "fld" => alu_out = src1.wrapping_add(imm),
+        "sb" | "sh" | "sw" | "sd" | "fsw" | "fsd" => alu_out = src1.wrapping_add(imm),
         "addi"   => alu_out = src1.wrapping_add(imm),
         "slti"   => alu_out = if (src1 as i64) < (imm as i64) { 1 } else { 0 },
         "sltiu"  => alu_out = if src1 < imm { 1 } else { 0 },
         "xori"   => alu_out = src1 ^ imm,
         "ori"    => alu_out = src1 | imm,
         "andi"   => alu_out = src1 & imm,
-        "slli"   => alu_out = src1 << imm,
-        "srli"   => alu_out = src1 >> imm,
+        "slli"   => alu_out = src1 << (imm & 0x3F),
+        "srli"   => alu_out = src1 >> (imm & 0x3F),
         "srai"   => alu_out = ((src1 as i64) >> (imm & 0x3F)) as u64,
         "addiw"  => alu_out = (src1.wrapping_add(imm) as i32) as u64,
         "slliw"  => alu_out = (src1.wrapping_shl(imm as u32) as i32) as u64,
@@ -215,10 +268,13 @@ pub fn execute_stage(cpu: &mut CPUState, s: &IDEXReg) -> EXMEMReg {
         "srlw"   => alu_out = ((src1 as u32) >> (src2 & 0x1F)) as u64,
         "sraw"   => alu_out = ((src1 as i32) >> (src2 & 0x1F)) as u64,
         "ebreak" => cpu.halt_trap(s.pc, cpu.reg[10]), // a0 
+        "ecall"  => alu_out = syscall::handle(cpu, mem, s.pc),
+        "mret"   => cpu.next_pc = cpu.mret(),
+        "csrrw" | "csrrs" | "csrrc" | "csrrwi" | "csrrsi" | "csrrci" => alu_out = csr_access(cpu, mem, name, s),
         "mul"    => alu_out = (src1 as i64).wrapping_mul(src2 as i64) as u64,
-        "mulh"   => alu_out = ((src1 as i128) * (src2 as i128) >> 64) as u64,
-        "mulhsu" => alu_out = (((src1 as i128) * (src2 as u128) as i128) >> 64) as u64,
-        "mulhu"  => alu_out = ((src1 as u128) * (src2 as u128) >> 64) as u64,
+        "mulh"   => alu_out = (((src1 as i64 as i128) * (src2 as i64 as i128)) >> 64) as u64,
+        "mulhsu" => alu_out = (((src1 as i64 as i128) * (src2 as i128)) >> 64) as u64,
+        "mulhu"  => alu_out = (((src1 as u128) * (src2 as u128)) >> 64) as u64,
         "div"    => alu_out = (src1 as i64).wrapping_div(src2 as i64) as u64,
         "divu"   => alu_out = src1.wrapping_div(src2),
         "rem"    => alu_out = (src1 as i64).wrapping_rem(src2 as i64) as u64,
@@ -228,33 +284,127 @@ pub fn execute_stage(cpu: &mut CPUState, s: &IDEXReg) -> EXMEMReg {
         "divuw"  => alu_out = (src1 as u32).wrapping_div(src2 as u32) as u64,
         "remw"   => alu_out = (src1 as i32).wrapping_rem(src2 as i32) as u64,
         "remuw"  => alu_out = (src1 as u32).wrapping_rem(src2 as u32) as u64,
-        _ => {},
+        _ if is_atomic(name) => alu_out = src1, // address, no offset
+        _ => {
+            let args = CustomArgs { pc: s.pc, inst, src1, src2, imm };
+            if let Some(out) = float::execute(name, inst, [src1, src2, s.src3], &mut cpu.csr.fcsr).or_else(|| extension::execute(name, &args)) {
+                alu_out = out;
+            }
+        },
+    }
+
+    // the address is known here, so a faulting load/store traps before anything younger has run.
+    // Without a handler it goes on as a bubble that stops the program at writeback, and the
+    // younger instructions are squashed so none of them gets to EX first
+    if let Some(fault) = access_fault(mem, name, s, alu_out) {
+        if cpu.csr.mtvec != 0 {
+            cpu.csr.mtval = fault.addr;
+            cpu.trap_pc = Some(cpu.trap(s.pc, fault.cause));
+            return EXMEMReg { pc: s.pc, inst: 0x13, ..EXMEMReg::default() };
+        }
+        cpu.trap_pc = Some(s.pc);
+        return EXMEMReg { pc: s.pc, inst: s.inst, seq: s.seq, fault: Some(fault), ..EXMEMReg::default() };
     }
 
-    println!("final alu: {}", alu_out);
     EXMEMReg { 
         pc: s.pc,
         inst: s.inst,
+        seq: s.seq,
         rd: s.rd,
         src2: s.src2,
         alu_out: alu_out,  
         store: s.store,
         load: s.load,
+        fault: None,
+    }
+
+}
+
+// the exception of a load, store or AMO at `addr`, if any. AMOs must be aligned; other
+// misaligned accesses only trap when Memory::trap_misaligned is set, and are split otherwise
+fn access_fault(mem: &Memory, name: &str, s: &IDEXReg, addr: u64) -> Option<AccessFault> {
+    if !s.load && !s.store {
+        return None;
     }
+    let len = 1u64 << (s.inst >> 12 & 3);
+    let write = s.store || (is_atomic(name) && !name.starts_with("lr"));
+    let (misaligned, access) = if write {
+        (CAUSE_MISALIGNED_STORE, CAUSE_STORE_ACCESS)
+    } else {
+        (CAUSE_MISALIGNED_LOAD, CAUSE_LOAD_ACCESS)
+    };
+    if !addr.is_multiple_of(len) && (is_atomic(name) || mem.trap_misaligned) {
+        return Some(AccessFault { cause: misaligned, addr });
+    }
+    mem.probe(addr, len as usize, write).is_err().then_some(AccessFault { cause: access, addr })
+}
+
+// Zicsr: rd gets the old value; csrrs/csrrc with x0 (or a zero immediate) only read
+fn csr_access(cpu: &mut CPUState, mem: &Memory, name: &str, s: &IDEXReg) -> u64 {
+    let csr = s.imm & 0xfff;
+    let old = cpu.csr_read(csr, mem.clint.mtime);
+    let operand = if name.ends_with('i') { s.rs1 as u64 } else { s.src1 };
+    let new = match name.trim_end_matches('i') {
+        "csrrw" => Some(operand),
+        "csrrs" => (s.rs1 != 0).then_some(old | operand),
+        _ => (s.rs1 != 0).then_some(old & !operand),
+    };
+    if let Some(value) = new {
+        cpu.csr_write(csr, value);
+    }
+    old
+}
 
+// A extension, done in one step since there is a single hart. rd gets the old memory value,
+// sign-extended for .w; sc gets 0 on success and 1 on failure. lr reserves the address and sc
+// only stores if the reservation survived: sc itself and any store to the reserved doubleword
+// in between (Memory::store) cancel it
+fn atomic(mem: &mut Memory, name: &str, addr: u64, src2: u64) -> u64 {
+    let (op, width) = name.split_once('.').unwrap();
+    let len = if width == "w" { 4 } else { 8 };
+    let ext = |v: u64| if len == 4 { sext(v & 0xffff_ffff, 32) } else { v };
+    match op {
+        "lr" => {
+            let old = ext(mem.load(addr, len).unwrap());
+            mem.reservation = Some(addr);
+            old
+        }
+        "sc" => {
+            if mem.reservation.take() == Some(addr) {
+                mem.store(addr, len, src2).unwrap();
+                0
+            } else {
+                1
+            }
+        }
+        _ => {
+            let old = ext(mem.load(addr, len).unwrap());
+            let src2 = ext(src2);
+            let new = match op {
+                "amoswap" => src2,
+                "amoadd" => old.wrapping_add(src2),
+                "amoxor" => old ^ src2,
+                "amoand" => old & src2,
+                "amoor" => old | src2,
+                "amomin" => (old as i64).min(src2 as i64) as u64,
+                "amomax" => (old as i64).max(src2 as i64) as u64,
+                "amominu" => old.min(src2),
+                _ => old.max(src2), // amomaxu
+            };
+            mem.store(addr, len, new).unwrap();
+            old
+        }
+    }
 }
 
 pub fn memory_stage(cpu: &mut CPUState, s: &EXMEMReg, mem: &mut Memory) -> MEMWBReg {
     let inst = s.inst;
-    let match_res = check_inst(inst);
+    // illegal encodings travel down as no-ops and trap at writeback
+    let name = check_inst(inst).map_or("", |i| i.name);
 
-    let (name, ..) = match match_res {
-        None => {
-            println!("{}", "Error".red());
-            panic!("Invalid instruction: 0x{:x}", inst);
-        },
-        Some(_inst) => (_inst.name, _inst.inst_type),
-    };
+    if s.fault.is_some() {
+        return MEMWBReg { pc: s.pc, inst, seq: s.seq, fault: s.fault, ..MEMWBReg::default() };
+    }
 
     let alu_out = s.alu_out;
     let src2 = s.src2;
@@ -262,41 +412,47 @@ pub fn memory_stage(cpu: &mut CPUState, s: &EXMEMReg, mem: &mut Memory) -> MEMWB
 
     // println!("0x{:x}", alu_out);
     match name {
-        "lb" => mem_data = sext(mem.mem_read(alu_out, 1).unwrap(), 8),
-        "lh" => mem_data = sext(mem.mem_read(alu_out, 2).unwrap(), 16),
-        "lw" => mem_data = sext(mem.mem_read(alu_out, 4).unwrap(), 32),
-        "lbu" => mem_data = mem.mem_read(alu_out, 1).unwrap(),
-        "lhu" => mem_data = mem.mem_read(alu_out, 2).unwrap(),
-        "lwu" => mem_data = mem.mem_read(alu_out, 4).unwrap(),
-        "ld" => mem_data = mem.mem_read(alu_out, 8).unwrap(),
-        "sb" => mem.mem_write(alu_out, 1, src2).unwrap(),
-        "sh" => mem.mem_write(alu_out, 2, src2).unwrap(),
-        "sw" => mem.mem_write(alu_out, 4, src2).unwrap(),
-        "sd" => mem.mem_write(alu_out, 8, src2).unwrap(),
+        "lb" => mem_data = sext(mem.load(alu_out, 1).unwrap(), 8),
+        "lh" => mem_data = sext(mem.load(alu_out, 2).unwrap(), 16),
+        "lw" => mem_data = sext(mem.load(alu_out, 4).unwrap(), 32),
+        "lbu" => mem_data = mem.load(alu_out, 1).unwrap(),
+        "lhu" => mem_data = mem.load(alu_out, 2).unwrap(),
+        "lwu" => mem_data = mem.load(alu_out, 4).unwrap(),
+        "ld" => mem_data = mem.load(alu_out, 8).unwrap(),
+        "flw" => mem_data = float::nan_box(mem.load(alu_out, 4).unwrap() as u32),
+        "fld" => mem_data = mem.load(alu_out, 8).unwrap(),
+        "sb" => mem.store(alu_out, 1, src2).unwrap(),
+        "sh" => mem.store(alu_out, 2, src2).unwrap(),
+        "sw" => mem.store(alu_out, 4, src2).unwrap(),
+        "sd" => mem.store(alu_out, 8, src2).unwrap(),
+        "fsw" => mem.store(alu_out, 4, src2).unwrap(),
+        "fsd" => mem.store(alu_out, 8, src2).unwrap(),
         "ebreak" => cpu.halt_trap(s.pc, cpu.reg[10]),
+        _ if is_atomic(name) => mem_data = atomic(mem, name, alu_out, src2),
         _ => (),
     }
 
     MEMWBReg {
         pc: s.pc,
         inst: s.inst,
+        seq: s.seq,
         rd: s.rd,
         alu_out: alu_out,
         mem_data: mem_data,
+        fault: None,
     }
 
 }
 
-pub fn writeback_stage(cpu: &mut CPUState, s: &MEMWBReg) {
+pub fn writeback_stage(cpu: &mut CPUState, s: &MEMWBReg) -> Result<(), DecodeError> {
     let inst = s.inst;
-    let match_res = check_inst(inst);
-
-    let (name, ..) = match match_res {
-        None => {
-            println!("{}", "Error".red());
-            panic!("Invalid instruction: 0x{:x}", inst);
-        },
-        Some(_inst) => (_inst.name, _inst.inst_type),
+    if s.fault.is_some() {
+        return Err(DecodeError { pc: s.pc, inst, access: s.fault });
+    }
+    let name = match check_inst(inst) {
+        Some(i) => i.name,
+        None if s.seq == 0 => return Ok(()),
+        None => return Err(DecodeError { pc: s.pc, inst, access: None }),
     };
 
     let alu_out = s.alu_out;
@@ -306,11 +462,170 @@ pub fn writeback_stage(cpu: &mut CPUState, s: &MEMWBReg) {
     // println!("name:= {} rd:= {} \nalu_out = 0x{:x} inst = 0x{:x}", name, rd, alu_out, inst);
     match name {
         "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" => (),
-        "sb" | "sh" | "sw" | "sd" => (),
-        "lb" | "lh" | "lw" | "ld" | "lbu" | "lhu" => cpu.reg[rd as usize] = mem_data,
+        "sb" | "sh" | "sw" | "sd" | "fsw" | "fsd" => (),
+        "lb" | "lh" | "lw" | "ld" | "lbu" | "lhu" | "lwu" | "flw" | "fld" => cpu.write_reg(rd, mem_data),
         "ebreak" => cpu.halt_trap(s.pc, cpu.reg[10]),
-        _ => cpu.reg[rd as usize] = alu_out,
+        _ if is_atomic(name) => cpu.write_reg(rd, mem_data),
+        _ => cpu.write_reg(rd, alu_out),
     }
     cpu.reg[0] = 0;
+    if s.seq != 0 { cpu.inst_count += 1; } // bubbles do not retire
+    Ok(())
+
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_illegal() {
+        let cpu = CPUState::new();
+        let slot = |inst| IFIDReg { pc: 0x8000_0000, inst, seq: 1, compressed: false };
+        assert!(decode_stage(&cpu, &slot(0x0000_0013)).is_ok()); // nop
+        assert_eq!(decode_stage(&cpu, &slot(0)).unwrap_err(), DecodeError { pc: 0x8000_0000, inst: 0, access: None });
+        assert!(decode_stage(&cpu, &slot(0xffff_ffff)).is_err());
+    }
+
+    #[test]
+    fn test_exception_traps() {
+        // ecall, ebreak and an illegal word each enter the handler, which adds up mcause / mtval
+        // and skips the instruction; with mtvec cleared again, the final ebreak stops the program
+        let asm = "main:
+  la t0, handler
+  csrw mtvec, t0
+  li s1, 0
+  li s2, 0
+  ecall
+  ebreak
+  .word 0xffffffff
+  csrw mtvec, zero
+  mv a0, s1
+  ret
+handler:
+  csrr t1, mcause
+  add s1, s1, t1
+  csrr t1, mtval
+  add s2, s2, t1
+  csrr t1, mepc
+  addi t1, t1, 4
+  csrw mepc, t1
+  mret
+";
+        let path = crate::runner::test_image(asm);
+        let mut sim = super::super::embed::Simulator::load(&path).unwrap();
+        assert_eq!(sim.run(Some(10_000)), Some(11 + 3 + 2));
+        assert_eq!(sim.registers()[18], 0xffff_ffff);
+        let run = super::super::bench::run_model(&path, &Default::default(), super::super::bench::CpuModel::SingleCycle, None).unwrap();
+        assert_eq!(run.exit_code, 16);
+    }
+
+    #[test]
+    fn test_access_faults() {
+        // with misaligned = "trap": a misaligned lw and a store to ROM at 0x1000 enter the handler,
+        // which adds up mcause / mtval and skips them; the last load has no handler to go to
+        let asm = "main:
+  la t0, handler
+  csrw mtvec, t0
+  li s1, 0
+  li s2, 0
+  la t2, main
+  lw t3, 2(t2)
+  li t2, 0x1000
+  sw zero, 0(t2)
+  csrw mtvec, zero
+  lw t3, 0(zero)
+  ret
+handler:
+  csrr t1, mcause
+  add s1, s1, t1
+  csrr t1, mtval
+  add s2, s2, t1
+  csrr t1, mepc
+  addi t1, t1, 4
+  csrw mepc, t1
+  mret
+";
+        let path = crate::runner::test_image(asm);
+        let image = crate::codegen::encode::assemble(asm, 0x8000_0000).unwrap();
+        let mut config = super::super::config::SimConfig::default();
+        config.memory.misaligned = super::super::config::MisalignedPolicy::Trap;
+        config.memory.regions.push("rom:0x1000:0x1000".parse().unwrap());
+        let (mut pipeline, mut mem) = config.build(&path).unwrap();
+        pipeline.set_quiet(&mut mem, true);
+        while pipeline.cpu.running {
+            pipeline.step(&mut mem);
+        }
+        assert_eq!(pipeline.cpu.reg[9], CAUSE_MISALIGNED_LOAD + CAUSE_STORE_ACCESS);
+        assert_eq!(pipeline.cpu.reg[18], image.symbols["main"] + 2 + 0x1000);
+        let fault = pipeline.fault.unwrap();
+        assert_eq!(fault.access, Some(AccessFault { cause: CAUSE_LOAD_ACCESS, addr: 0 }));
+        assert_eq!(fault.to_string(), format!("Load access fault at address 0x00000000, pc 0x{:08x}", fault.pc));
+
+        // emulated by default: the misaligned load reads across the two words
+        let mut mem = Memory::new();
+        mem.mem_write(MEM_BASE, 8, 0x1122_3344_5566_7788).unwrap();
+        assert_eq!(mem.load(MEM_BASE + 2, 4).unwrap(), 0x3344_5566);
+        assert!(mem.probe(MEM_BASE + 2, 4, false).is_ok());
+    }
+
+    #[test]
+    fn test_lwu() {
+        // lwu zero-extends, and at an unmapped address faults like any other load
+        let asm = "main:
+  addi sp, sp, -16
+  li t1, -16
+  sw t1, 0(sp)
+  lwu a0, 0(sp)
+  lwu t3, 0(zero)
+  ret
+";
+        let path = crate::runner::test_image(asm);
+        let (mut pipeline, mut mem) = super::super::config::SimConfig::default().build(&path).unwrap();
+        pipeline.set_quiet(&mut mem, true);
+        while pipeline.cpu.running {
+            pipeline.step(&mut mem);
+        }
+        assert_eq!(pipeline.cpu.reg[10], 0xffff_fff0);
+        assert_eq!(pipeline.fault.unwrap().access, Some(AccessFault { cause: CAUSE_LOAD_ACCESS, addr: 0 }));
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_atomics() {
+        // 5 + 0 + 1 + 1 + 9 + 9 + 9 - 1: the second sc has no reservation, the third lost it to
+        // a store into the same doubleword
+        let asm = "main:
+  addi sp, sp, -16
+  li t0, 5
+  sw t0, 0(sp)
+  li t1, 3
+  amoadd.w t2, t1, (sp)
+  lr.w t3, (sp)
+  addi t3, t3, 1
+  sc.w t4, t3, (sp)
+  sc.w t5, t3, (sp)
+  lr.w.aq t3, (sp)
+  sw zero, 4(sp)
+  sc.w.rl t6, t3, (sp)
+  li t1, -1
+  amominu.w a1, t1, (sp)
+  amomax.w a2, t1, (sp)
+  amoswap.w a3, t1, (sp)
+  lw a4, 0(sp)
+  add a0, t2, t4
+  add a0, a0, t5
+  add a0, a0, t6
+  add a0, a0, a1
+  add a0, a0, a2
+  add a0, a0, a3
+  add a0, a0, a4
+  addi sp, sp, 16
+  ret
+";
+        let path = crate::runner::test_image(asm);
+        let mut sim = super::super::embed::Simulator::load(&path).unwrap();
+        assert_eq!(sim.run(Some(10_000)), Some(33));
+        let run = super::super::bench::run_model(&path, &Default::default(), super::super::bench::CpuModel::SingleCycle, None).unwrap();
+        assert_eq!(run.exit_code, 33);
+        assert_eq!(super::super::disasm::disassemble(0, 0x0062_b3af), "amoadd.d t2, t1, (t0)");
+    }
+}
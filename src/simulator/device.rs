@@ -0,0 +1,180 @@
+// Memory-mapped devices. The UART follows the NEMU serial layout:
+// data register at the base (SERIAL_PORT by default), 16550-style line status at base + 5.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+
+pub const SERIAL_PORT: u64 = 0xa000_03f8;
+const SERIAL_DATA: u64 = 0;
+const SERIAL_LSR: u64 = 5;
+const LSR_DATA_READY: u64 = 0x01;
+const LSR_THR_EMPTY: u64 = 0x20;
+
+#[derive(Debug)]
+pub struct Uart {
+    pub base: u64,        // SERIAL_PORT unless configured otherwise
+    pub rx: VecDeque<u8>, // guest stdin, fed by the frontend
+    pub tx: Vec<u8>,      // everything the guest has written
+    pub quiet: bool,      // don't echo tx to the host stdout
+    pub host_stdin: bool, // refill rx from the host stdin a line at a time (--headless)
+}
+
+pub const SERIAL_SIZE: u64 = 8;
+
+impl Default for Uart {
+    fn default() -> Self {
+        Self { base: SERIAL_PORT, rx: VecDeque::new(), tx: Vec::new(), quiet: false, host_stdin: false }
+    }
+}
+
+impl Uart {
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.base..self.base + SERIAL_SIZE).contains(&addr)
+    }
+
+    pub fn push_input(&mut self, text: &str) {
+        self.rx.extend(text.bytes());
+    }
+
+    // with host_stdin, waits for the next line once the queue is empty; stops at the host's EOF
+    pub fn refill(&mut self) {
+        if !self.host_stdin || !self.rx.is_empty() {
+            return;
+        }
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(n) if n > 0 => self.push_input(&line),
+            _ => self.host_stdin = false,
+        }
+    }
+
+    // reading the data register consumes a byte; an empty queue reads as 0xff (EOF)
+    pub fn read(&mut self, addr: u64) -> u64 {
+        self.refill();
+        match addr - self.base {
+            SERIAL_DATA => self.rx.pop_front().map_or(0xff, |b| b as u64),
+            SERIAL_LSR => {
+                let ready = if self.rx.is_empty() { 0 } else { LSR_DATA_READY };
+                ready | LSR_THR_EMPTY
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u64, data: u64) {
+        if addr - self.base == SERIAL_DATA {
+            let byte = data as u8;
+            self.tx.push(byte);
+            if !self.quiet {
+                print!("{}", byte as char);
+                let _ = io::stdout().flush();
+            }
+        }
+    }
+}
+
+// CLINT (core-local interruptor) in the SiFive layout: msip at the base, mtimecmp at base + 0x4000,
+// mtime at base + 0xbff8. mtime counts cycles; the machine timer interrupt is pending while
+// mtime >= mtimecmp. Registers are 64-bit, 32-bit accesses reach either half.
+pub const CLINT_BASE: u64 = 0x0200_0000;
+pub const CLINT_SIZE: u64 = 0x1_0000;
+const CLINT_MSIP: u64 = 0;
+const CLINT_MTIMECMP: u64 = 0x4000;
+const CLINT_MTIME: u64 = 0xbff8;
+
+#[derive(Debug)]
+pub struct Clint {
+    pub base: u64, // CLINT_BASE unless configured otherwise
+    pub msip: u64,
+    pub mtimecmp: u64,
+    pub mtime: u64,
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self { base: CLINT_BASE, msip: 0, mtimecmp: u64::MAX, mtime: 0 }
+    }
+}
+
+impl Clint {
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.base..self.base + CLINT_SIZE).contains(&addr)
+    }
+
+    // one cycle passes
+    pub fn tick(&mut self) {
+        self.mtime = self.mtime.wrapping_add(1);
+    }
+
+    pub fn timer_pending(&self) -> bool {
+        self.mtime >= self.mtimecmp
+    }
+
+    // the register holding `addr` and the bit offset of `addr` in it
+    fn register(&mut self, addr: u64) -> Option<(&mut u64, u64)> {
+        let offset = addr - self.base;
+        let (reg, start) = match offset {
+            o if (CLINT_MSIP..CLINT_MSIP + 4).contains(&o) => (&mut self.msip, CLINT_MSIP),
+            o if (CLINT_MTIMECMP..CLINT_MTIMECMP + 8).contains(&o) => (&mut self.mtimecmp, CLINT_MTIMECMP),
+            o if (CLINT_MTIME..CLINT_MTIME + 8).contains(&o) => (&mut self.mtime, CLINT_MTIME),
+            _ => return None,
+        };
+        Some((reg, (offset - start) * 8))
+    }
+
+    pub fn read(&mut self, addr: u64, len: usize) -> u64 {
+        let mask = if len >= 8 { u64::MAX } else { (1 << (len * 8)) - 1 };
+        self.register(addr).map_or(0, |(reg, shift)| (*reg >> shift) & mask)
+    }
+
+    pub fn write(&mut self, addr: u64, len: usize, data: u64) {
+        let mask = if len >= 8 { u64::MAX } else { (1 << (len * 8)) - 1 };
+        if let Some((reg, shift)) = self.register(addr) {
+            *reg = (*reg & !(mask << shift)) | ((data & mask) << shift);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::bench::{run_model, CpuModel};
+    use super::super::config::SimConfig;
+    use super::super::embed::Simulator;
+
+    #[test]
+    fn test_timer_interrupt() {
+        // spin until the timer handler sets a0; the handler switches the timer off again, and
+        // clearing mtvec lets _start's ebreak stop the program
+        let asm = "main:
+  la t0, handler
+  csrw mtvec, t0
+  li t1, 0x2004000
+  rdtime t2
+  addi t2, t2, 200
+  sd t2, 0(t1)
+  li t0, 128
+  csrs mie, t0
+  csrsi mstatus, 8
+  li a0, 0
+spin:
+  beqz a0, spin
+  csrw mtvec, zero
+  ret
+handler:
+  li t1, 0x2004000
+  li t2, -1
+  sd t2, 0(t1)
+  csrr t3, mcause
+  li a0, 7
+  mret
+";
+        let path = crate::runner::test_image(asm);
+        let mut sim = Simulator::load(&path).unwrap();
+        assert_eq!(sim.run(Some(10_000)), Some(7));
+        assert_eq!(sim.registers()[28], 1 << 63 | 7);
+        assert!(sim.cycles() > 200);
+        let run = run_model(&path, &SimConfig::default(), CpuModel::SingleCycle, None).unwrap();
+        assert_eq!(run.exit_code, 7);
+    }
+}
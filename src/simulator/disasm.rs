@@ -0,0 +1,111 @@
+// Disassembly for the `disas` / `x/i` commands, the profiler and the disassembly views. Words are
+// matched against the same pattern table as decode (check_inst), so custom instructions show up
+// too; 16-bit instructions are expanded first and read as their 32-bit equivalents.
+
+use crate::bits;
+
+use super::compressed;
+use super::cpu::{FREG_NAMES, REG_NAMES};
+use super::decode::{check_inst, decode_imm, is_atomic, InstType};
+use super::float::{self, fp_operands};
+use super::mem::{Memory, MemoryError};
+
+// one instruction word as assembly, the form the built-in assembler accepts
+pub fn disassemble(pc: u64, inst: u32) -> String {
+    let (name, ty) = match check_inst(inst) {
+        Some(i) => (i.name, i.inst_type),
+        None => return format!(".word 0x{:08x}", inst),
+    };
+    let (fd, f1, f2) = fp_operands(name);
+    let reg_name = |r: u32, fp: bool| if fp { FREG_NAMES[r as usize] } else { REG_NAMES[r as usize] };
+    let rd = reg_name(bits!(inst, 11, 7), fd);
+    let rs1 = reg_name(bits!(inst, 19, 15), f1);
+    let rs2 = reg_name(bits!(inst, 24, 20), f2);
+    let rs3 = FREG_NAMES[bits!(inst, 31, 27) as usize];
+    let imm = decode_imm(inst, ty) as i64;
+    let imm = if matches!(name, "slli" | "srli" | "srai" | "slliw" | "srliw" | "sraiw") { imm & 0x3f } else { imm };
+    let target = pc.wrapping_add(imm as u64); // branch / jal target
+    let load = (name.starts_with('l') || name == "flw" || name == "fld") && ty == InstType::I;
+
+    match ty {
+        InstType::R if float::is_unary(name) => format!("{} {}, {}", name, rd, rs1),
+        InstType::R if name.starts_with("lr.") => format!("{} {}, ({})", name, rd, rs1),
+        InstType::R if is_atomic(name) => format!("{} {}, {}, ({})", name, rd, rs2, rs1),
+        InstType::R => format!("{} {}, {}, {}", name, rd, rs1, rs2),
+        InstType::R4 => format!("{} {}, {}, {}, {}", name, rd, rs1, rs2, rs3),
+        InstType::I if load || name == "jalr" => format!("{} {}, {}({})", name, rd, imm, rs1),
+        InstType::I if name.starts_with("csr") && name.ends_with('i') => format!("{} {}, 0x{:x}, {}", name, rd, imm & 0xfff, bits!(inst, 19, 15)),
+        InstType::I if name.starts_with("csr") => format!("{} {}, 0x{:x}, {}", name, rd, imm & 0xfff, rs1),
+        InstType::I => format!("{} {}, {}, {}", name, rd, rs1, imm),
+        InstType::S => format!("{} {}, {}({})", name, rs2, imm, rs1),
+        InstType::B => format!("{} {}, {}, 0x{:x}", name, rs1, rs2, target),
+        InstType::U => format!("{} {}, 0x{:x}", name, rd, (imm as u64 >> 12) & 0xfffff),
+        InstType::J => format!("{} {}, 0x{:x}", name, rd, target),
+        InstType::N => name.to_string(),
+    }
+}
+
+// the target of a branch or jal, so that the views can name it
+pub fn jump_target(pc: u64, inst: u32) -> Option<u64> {
+    let ty = check_inst(inst)?.inst_type;
+    matches!(ty, InstType::B | InstType::J).then(|| pc.wrapping_add(decode_imm(inst, ty) as i64 as u64))
+}
+
+// one instruction in memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisasmLine {
+    pub addr: u64,
+    pub raw: u32, // the 16 bits for a compressed instruction
+    pub inst: u32, // expanded to 32 bits
+    pub len: u64, // 2 or 4
+    pub text: String,
+}
+
+impl DisasmLine {
+    pub fn raw_hex(&self) -> String {
+        if self.len == 2 { format!("{:04x}    ", self.raw) } else { format!("{:08x}", self.raw) }
+    }
+}
+
+pub fn disassemble_at(mem: &Memory, addr: u64) -> Result<DisasmLine, MemoryError> {
+    let word = mem.inst_fetch(addr)?;
+    let (inst, compressed) = compressed::fetch(mem, addr)?;
+    let (raw, len) = if compressed { (word & 0xffff, 2) } else { (word, 4) };
+    Ok(DisasmLine { addr, raw, inst, len, text: disassemble(addr, inst) })
+}
+
+// up to `count` instructions from `addr`, stopping at memory that cannot be read
+pub fn disassemble_range(mem: &Memory, addr: u64, count: usize) -> Vec<DisasmLine> {
+    let mut lines = Vec::with_capacity(count);
+    let mut addr = addr;
+    for _ in 0..count {
+        let Ok(line) = disassemble_at(mem, addr) else { break };
+        addr = addr.wrapping_add(line.len);
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble_range() {
+        let mut mem = Memory::new();
+        mem.mem_write(0x8000_0000, 4, 0x0015_0513).unwrap(); // addi a0, a0, 1
+        mem.mem_write(0x8000_0004, 2, 0x8082).unwrap(); // c.ret
+        mem.mem_write(0x8000_0006, 4, 0x00b5_0533).unwrap(); // add a0, a0, a1
+        let lines = disassemble_range(&mem, 0x8000_0000, 3);
+        let text: Vec<_> = lines.iter().map(|l| (l.addr, l.len, l.text.as_str())).collect();
+        assert_eq!(text, [
+            (0x8000_0000, 4, "addi a0, a0, 1"),
+            (0x8000_0004, 2, "jalr zero, 0(ra)"),
+            (0x8000_0006, 4, "add a0, a0, a1"),
+        ]);
+        assert_eq!(lines[1].raw_hex(), "8082    ");
+        assert_eq!(jump_target(0x8000_0010, 0x0100_00ef), Some(0x8000_0020)); // jal ra, +16
+        assert_eq!(jump_target(0x8000_0010, lines[0].inst), None);
+        assert_eq!(disassemble(0x8000_0000, 0xffff_ffff), ".word 0xffffffff");
+    }
+}
@@ -0,0 +1,92 @@
+// Simulator façade for embedding: load an image, step or run it, inspect registers and memory.
+// Runs quietly (no per-cycle pipeline dump, no UART echo); guest output is kept in `output()`.
+
+use super::config::SimConfig;
+use super::decode::DecodeError;
+use super::mem::{Memory, MemoryError};
+use super::pipe::Pipeline;
+
+pub struct Simulator {
+    pub pipeline: Pipeline,
+    pub mem: Memory,
+}
+
+impl Simulator {
+    // load a raw binary or ELF image
+    pub fn load(path: &str) -> Result<Self, MemoryError> {
+        Self::load_with(path, &SimConfig::default())
+    }
+
+    // same, with the memory map, caches and devices from `config`
+    pub fn load_with(path: &str, config: &SimConfig) -> Result<Self, MemoryError> {
+        let mut mem = config.new_memory();
+        mem.load_image(path)?;
+        let mut pipeline = Pipeline::new();
+        config.apply(&mut pipeline);
+        pipeline.entry = mem.entry();
+        pipeline.init();
+        pipeline.set_quiet(&mut mem, true);
+        Ok(Self { pipeline, mem })
+    }
+
+    pub fn running(&self) -> bool {
+        self.pipeline.cpu.running
+    }
+
+    // advance one cycle, returns whether the program is still running
+    pub fn step(&mut self) -> bool {
+        if self.running() {
+            self.pipeline.step(&mut self.mem);
+        }
+        self.running()
+    }
+
+    // run until the program stops or `max_cycles` elapse, returns the exit code if it exited
+    pub fn run(&mut self, max_cycles: Option<u64>) -> Option<u64> {
+        let mut cycles = 0;
+        while self.running() && max_cycles.is_none_or(|max| cycles < max) {
+            self.pipeline.step(&mut self.mem);
+            cycles += 1;
+        }
+        self.exit_code()
+    }
+
+    // None while running or after an illegal instruction
+    pub fn exit_code(&self) -> Option<u64> {
+        if self.running() || self.fault().is_some() { None } else { Some(self.pipeline.cpu.reg[10]) }
+    }
+
+    pub fn fault(&self) -> Option<&DecodeError> {
+        self.pipeline.fault.as_ref()
+    }
+
+    pub fn pc(&self) -> u64 {
+        self.pipeline.cpu.pc
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.pipeline.cpu.cycle_count as u64
+    }
+
+    // architectural registers x0..x31
+    pub fn registers(&self) -> &[u64; 32] {
+        &self.pipeline.cpu.reg
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.mem
+    }
+
+    pub fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>, MemoryError> {
+        self.mem.read_bytes(addr, len)
+    }
+
+    // bytes the guest has written to the UART
+    pub fn output(&self) -> &[u8] {
+        &self.mem.uart.tx
+    }
+
+    pub fn input(&mut self, text: &str) {
+        self.mem.uart.push_input(text);
+    }
+}
@@ -0,0 +1,171 @@
+// Debugger expressions for `p`: 64-bit integer arithmetic over literals, registers, memory and
+// symbols.
+//
+//   expr  := term (('+' | '-') term)*
+//   term  := unary (('*' | '/') unary)*
+//   unary := '-' unary | '*' unary | '(' expr ')' | NUMBER | $REG | NAME
+//
+// Numbers are decimal, or hex with 0x. $REG is an x or f register by ABI name or number, or $pc.
+// A bare NAME is a register or a symbol from the image's line map. `*addr` reads a 32-bit word and
+// sign-extends it like a SysY int. Arithmetic wraps; dividing by zero is an error.
+
+use super::cpu::{FREG_NAMES, REG_NAMES};
+use super::mem::Memory;
+use super::pipe::Pipeline;
+use super::srcmap::SourceMap;
+
+pub fn evaluate(expr: &str, pipeline: &Pipeline, mem: &Memory, srcmap: Option<&SourceMap>) -> Result<u64, String> {
+    let mut parser = Parser { text: expr, pos: 0, pipeline, mem, srcmap };
+    let value = parser.expr()?;
+    parser.skip_space();
+    if parser.pos < expr.len() {
+        return Err(format!("Unexpected '{}' in expression", &expr[parser.pos..]));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    pipeline: &'a Pipeline,
+    mem: &'a Memory,
+    srcmap: Option<&'a SourceMap>,
+}
+
+impl Parser<'_> {
+    fn skip_space(&mut self) {
+        while self.text[self.pos..].starts_with(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    // consumes `c` if it is the next non-space character
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_space();
+        if self.text[self.pos..].starts_with(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<u64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value = value.wrapping_add(self.term()?);
+            } else if self.eat('-') {
+                value = value.wrapping_sub(self.term()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<u64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value = value.wrapping_mul(self.unary()?);
+            } else if self.eat('/') {
+                let divisor = self.unary()? as i64;
+                if divisor == 0 {
+                    return Err(String::from("Division by zero"));
+                }
+                value = (value as i64).wrapping_div(divisor) as u64;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<u64, String> {
+        if self.eat('-') {
+            return Ok(self.unary()?.wrapping_neg());
+        }
+        if self.eat('*') {
+            let addr = self.unary()?;
+            let word = self.mem.mem_read(addr, 4).map_err(|_| format!("Cannot access memory at 0x{:08x}", addr))?;
+            return Ok(word as u32 as i32 as i64 as u64);
+        }
+        if self.eat('(') {
+            let value = self.expr()?;
+            if !self.eat(')') {
+                return Err(String::from("Missing ')' in expression"));
+            }
+            return Ok(value);
+        }
+
+        let start = self.pos;
+        let len = self.text[start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '.'))
+            .unwrap_or(self.text.len() - start);
+        if len == 0 {
+            return Err(match self.text[start..].chars().next() {
+                Some(c) => format!("Unexpected '{}' in expression", c),
+                None => String::from("Incomplete expression"),
+            });
+        }
+        self.pos += len;
+        self.operand(&self.text[start..start + len])
+    }
+
+    fn operand(&self, token: &str) -> Result<u64, String> {
+        if token.starts_with(|c: char| c.is_ascii_digit()) {
+            return match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => token.parse::<u64>(),
+            }.map_err(|_| format!("Invalid number '{}'", token));
+        }
+        let name = token.strip_prefix('$').unwrap_or(token);
+        if let Some(value) = self.register(name) {
+            return Ok(value);
+        }
+        if token.starts_with('$') {
+            return Err(format!("Unknown register '{}'", token));
+        }
+        self.srcmap.and_then(|m| m.symbol(name)).ok_or_else(|| format!("No symbol '{}'", name))
+    }
+
+    fn register(&self, name: &str) -> Option<u64> {
+        let cpu = &self.pipeline.cpu;
+        if name == "pc" {
+            return Some(cpu.pc);
+        }
+        let index = |names: &[&str], prefix: char| {
+            names.iter().position(|r| *r == name)
+                .or_else(|| name.strip_prefix(prefix).and_then(|n| n.parse::<usize>().ok()).filter(|i| *i < 32))
+        };
+        if let Some(i) = index(&REG_NAMES, 'x') {
+            return Some(cpu.reg[i]);
+        }
+        index(&FREG_NAMES, 'f').map(|i| cpu.freg[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let mut pipeline = Pipeline::new();
+        let mut mem = Memory::new();
+        pipeline.cpu.reg[10] = 16;
+        pipeline.cpu.pc = 0x8000_0010;
+        mem.mem_write(0x8000_1000, 4, 0xffff_fffe).unwrap();
+
+        let eval = |expr: &str| evaluate(expr, &pipeline, &mem, None);
+        assert_eq!(eval("$a0 + 4"), Ok(20));
+        assert_eq!(eval("$x10*2 - 0x10"), Ok(16));
+        assert_eq!(eval("$pc"), Ok(0x8000_0010));
+        assert_eq!(eval("*0x80001000"), Ok(-2i64 as u64));
+        assert_eq!(eval("*(0x80000ff0 + a0) + 3"), Ok(1));
+        assert_eq!(eval("-(1 + 2) * 3"), Ok(-9i64 as u64));
+        assert!(eval("1 / (a0 - 16)").is_err());
+        assert!(eval("$q0").is_err());
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+// Custom instruction hook: register an extra encoding with its own execute function, e.g. a
+// course's accelerator instruction in the custom-0 opcode space:
+//
+//   register_instruction("0000000 ????? ????? 000 ????? 00010 11", "popcnt2", InstType::R,
+//       |a| (a.src1.count_ones() + a.src2.count_ones()) as u64)?;
+//
+// Built-in instructions take precedence. A registered instruction is decoded like any other
+// instruction of its type, shows up by name in disassembly and traces, and writes the value
+// returned by its execute function to rd (no memory access, no control transfer).
+
+use std::sync::RwLock;
+
+use super::decode::{inst_match, InstType, Instruction};
+
+// operands handed to a custom execute function
+#[derive(Debug, Clone, Copy)]
+pub struct CustomArgs {
+    pub pc: u64,
+    pub inst: u32,
+    pub src1: u64,
+    pub src2: u64,
+    pub imm: u64,
+}
+
+type ExecFn = Box<dyn Fn(&CustomArgs) -> u64 + Send + Sync>;
+
+struct CustomInst {
+    inst: &'static Instruction,
+    exec: ExecFn,
+}
+
+static CUSTOM: RwLock<Vec<CustomInst>> = RwLock::new(Vec::new());
+
+pub fn register_instruction(
+    pattern: &'static str,
+    name: &'static str,
+    inst_type: InstType,
+    exec: impl Fn(&CustomArgs) -> u64 + Send + Sync + 'static,
+) -> Result<(), String> {
+    let bits: Vec<char> = pattern.chars().filter(|c| *c != ' ').collect();
+    if bits.len() != 32 || bits.iter().any(|c| !matches!(c, '0' | '1' | '?')) {
+        return Err(format!("pattern for '{}' must have 32 positions of 0, 1 or ?", name));
+    }
+    let mut custom = CUSTOM.write().unwrap();
+    if custom.iter().any(|c| c.inst.name == name) {
+        return Err(format!("instruction '{}' is already registered", name));
+    }
+    // lives for the rest of the program, like the built-in table
+    let inst = Box::leak(Box::new(Instruction { pattern, name, inst_type }));
+    custom.push(CustomInst { inst, exec: Box::new(exec) });
+    Ok(())
+}
+
+// consulted by check_inst after the built-in table
+pub fn lookup(inst: u32) -> Option<&'static Instruction> {
+    CUSTOM.read().unwrap().iter().find(|c| inst_match(inst, c.inst.pattern)).map(|c| c.inst)
+}
+
+// run the execute function of custom instruction `name`, if there is one
+pub fn execute(name: &str, args: &CustomArgs) -> Option<u64> {
+    CUSTOM.read().unwrap().iter().find(|c| c.inst.name == name).map(|c| (c.exec)(args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::cpu::{CPUState, IFIDReg};
+    use super::super::decode::{decode_stage, execute_stage};
+    use super::super::disasm::disassemble;
+    use super::super::mem::Memory;
+
+    #[test]
+    fn test_custom_instruction() {
+        register_instruction("0000000 ????? ????? 000 ????? 00010 11", "popcnt2", InstType::R, |a| {
+            (a.src1.count_ones() + a.src2.count_ones()) as u64
+        }).unwrap();
+        assert!(register_instruction("0000000 ????? ????? 000 ????? 00010 11", "popcnt2", InstType::R, |_| 0).is_err());
+        assert!(register_instruction("0101", "short", InstType::N, |_| 0).is_err());
+
+        let inst = (12 << 20) | (11 << 15) | (10 << 7) | 0x0b; // popcnt2 a0, a1, a2
+        assert_eq!(disassemble(0x8000_0000, inst), "popcnt2 a0, a1, a2");
+
+        let mut cpu = CPUState::new();
+        cpu.quiet = true;
+        cpu.reg[11] = 0xff;
+        cpu.reg[12] = 0b101;
+        let decoded = decode_stage(&cpu, &IFIDReg { pc: 0x8000_0000, inst, seq: 1, compressed: false }).unwrap();
+        assert_eq!(execute_stage(&mut cpu, &decoded, &mut Memory::new()).alu_out, 10);
+    }
+}
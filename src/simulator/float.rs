@@ -0,0 +1,303 @@
+// RV64F / RV64D: doubles fill the 64-bit F registers, single precision values live NaN-boxed in them.
+// Arithmetic rounds to nearest even (Rust's float operations); conversions to integers honour the
+// instruction's rounding mode, or frm for the dynamic one. Of the exception flags in fcsr only
+// NV (invalid) and DZ (divide by zero) are raised; mstatus.FS is not modelled, the F registers are
+// always on.
+
+use std::num::FpCategory;
+
+const CANONICAL_NAN: u32 = 0x7fc0_0000;
+const CANONICAL_NAN_D: u64 = 0x7ff8_0000_0000_0000;
+
+// fflags bits
+const NV: u64 = 0x10;
+const DZ: u64 = 0x08;
+
+pub fn nan_box(bits: u32) -> u64 {
+    0xffff_ffff_0000_0000 | bits as u64
+}
+
+// a value that is not properly boxed reads as the canonical NaN
+pub fn unbox(value: u64) -> f32 {
+    if value >> 32 == 0xffff_ffff { f32::from_bits(value as u32) } else { f32::from_bits(CANONICAL_NAN) }
+}
+
+// arithmetic results: any NaN becomes the canonical one
+fn boxed(x: f32) -> u64 {
+    nan_box(if x.is_nan() { CANONICAL_NAN } else { x.to_bits() })
+}
+
+fn double(x: f64) -> u64 {
+    if x.is_nan() { CANONICAL_NAN_D } else { x.to_bits() }
+}
+
+// which of rd / rs1 / rs2 are F registers (rs3 always is)
+pub fn fp_operands(name: &str) -> (bool, bool, bool) {
+    match name {
+        "flw" | "fld" | "fcvt.s.w" | "fcvt.s.wu" | "fcvt.s.l" | "fcvt.s.lu" | "fmv.w.x"
+        | "fcvt.d.w" | "fcvt.d.wu" | "fcvt.d.l" | "fcvt.d.lu" | "fmv.d.x" => (true, false, false),
+        "fsw" | "fsd" => (false, false, true),
+        "fcvt.w.s" | "fcvt.wu.s" | "fcvt.l.s" | "fcvt.lu.s" | "fmv.x.w" | "fclass.s"
+        | "fcvt.w.d" | "fcvt.wu.d" | "fcvt.l.d" | "fcvt.lu.d" | "fmv.x.d" | "fclass.d" => (false, true, false),
+        "feq.s" | "flt.s" | "fle.s" | "feq.d" | "flt.d" | "fle.d" => (false, true, true),
+        _ if name.starts_with('f') && (name.ends_with(".s") || name.ends_with(".d")) => (true, true, true),
+        _ => (false, false, false),
+    }
+}
+
+// R-type F instructions whose rs2 field selects the operation instead of naming a register
+pub fn is_unary(name: &str) -> bool {
+    matches!(name, "fsqrt.s" | "fcvt.w.s" | "fcvt.wu.s" | "fcvt.l.s" | "fcvt.lu.s" | "fmv.x.w" | "fclass.s"
+        | "fcvt.s.w" | "fcvt.s.wu" | "fcvt.s.l" | "fcvt.s.lu" | "fmv.w.x"
+        | "fsqrt.d" | "fcvt.w.d" | "fcvt.wu.d" | "fcvt.l.d" | "fcvt.lu.d" | "fmv.x.d" | "fclass.d"
+        | "fcvt.d.w" | "fcvt.d.wu" | "fcvt.d.l" | "fcvt.d.lu" | "fmv.d.x" | "fcvt.s.d" | "fcvt.d.s")
+}
+
+// rm field: 1 towards zero, 2 down, 3 up, 4 nearest away from zero, otherwise nearest even.
+// Singles are widened first, which is exact
+fn round(x: f64, rm: u32) -> f64 {
+    match rm {
+        1 => x.trunc(),
+        2 => x.floor(),
+        3 => x.ceil(),
+        4 => x.round(),
+        _ => x.round_ties_even(),
+    }
+}
+
+// -0.0 is smaller than +0.0, and a NaN operand loses to a number; exact for widened singles too
+fn min_max(a: f64, b: f64, max: bool) -> f64 {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => f64::NAN,
+        (true, false) => b,
+        (false, true) => a,
+        _ if a == b => if a.is_sign_negative() != max { a } else { b },
+        _ => if (a < b) != max { a } else { b },
+    }
+}
+
+fn classify(category: FpCategory, negative: bool, quiet: bool) -> u64 {
+    let bit = match category {
+        FpCategory::Infinite => if negative { 0 } else { 7 },
+        FpCategory::Normal => if negative { 1 } else { 6 },
+        FpCategory::Subnormal => if negative { 2 } else { 5 },
+        FpCategory::Zero => if negative { 3 } else { 4 },
+        FpCategory::Nan => if quiet { 9 } else { 8 },
+    };
+    1 << bit
+}
+
+// float to integer: out of range saturates (Rust's `as` does the same) and NaN converts to the
+// largest value, both invalid; `min`/`max` is the target range
+fn to_int(x: f64, rm: u32, min: f64, max: f64, flags: &mut u64) -> f64 {
+    let r = round(x, rm);
+    if x.is_nan() {
+        *flags |= NV;
+        return max;
+    }
+    if r < min || r > max {
+        *flags |= NV;
+    }
+    r
+}
+
+// result of an F or D computational instruction, None if `name` is not one. `src` holds the
+// rs1 / rs2 / rs3 values; the exception flags accrue into `fcsr`, whose frm is the dynamic rounding mode
+pub fn execute(name: &str, inst: u32, src: [u64; 3], fcsr: &mut u64) -> Option<u64> {
+    let rm = match (inst >> 12) & 0x7 {
+        7 => (*fcsr >> 5 & 7) as u32,
+        rm => rm,
+    };
+    let mut flags = 0;
+    let out = match execute_s(name, rm, src, &mut flags) {
+        Some(out) => out,
+        None => execute_d(name, rm, src, &mut flags)?,
+    };
+    *fcsr |= flags;
+    Some(out)
+}
+
+// NaN out of numbers in: invalid; a finite non-zero value divided by zero: DZ
+fn arith_flags(name: &str, inputs: [f64; 3], out: f64, flags: &mut u64) {
+    let operands = match name {
+        _ if name.contains("madd") || name.contains("msub") => 3,
+        "fsqrt.s" | "fsqrt.d" => 1,
+        _ => 2,
+    };
+    if out.is_nan() && !inputs[..operands].iter().any(|x| x.is_nan()) {
+        *flags |= NV;
+    }
+    if name.starts_with("fdiv") && inputs[1] == 0.0 && inputs[0].is_finite() && inputs[0] != 0.0 {
+        *flags |= DZ;
+    }
+}
+
+fn execute_s(name: &str, rm: u32, src: [u64; 3], flags: &mut u64) -> Option<u64> {
+    let (a, b, c) = (unbox(src[0]), unbox(src[1]), unbox(src[2]));
+    let sign = |bits: u32| bits & 0x8000_0000;
+    let arith = |x: f32, flags: &mut u64| {
+        arith_flags(name, [a as f64, b as f64, c as f64], x as f64, flags);
+        boxed(x)
+    };
+    let out = match name {
+        "fadd.s" => arith(a + b, flags),
+        "fsub.s" => arith(a - b, flags),
+        "fmul.s" => arith(a * b, flags),
+        "fdiv.s" => arith(a / b, flags),
+        "fsqrt.s" => arith(a.sqrt(), flags),
+        "fmadd.s" => arith(a.mul_add(b, c), flags),
+        "fmsub.s" => arith(a.mul_add(b, -c), flags),
+        "fnmsub.s" => arith((-a).mul_add(b, c), flags),
+        "fnmadd.s" => arith((-a).mul_add(b, -c), flags),
+        "fsgnj.s" => nan_box(a.to_bits() & !0x8000_0000 | sign(b.to_bits())),
+        "fsgnjn.s" => nan_box(a.to_bits() & !0x8000_0000 | sign(!b.to_bits())),
+        "fsgnjx.s" => nan_box(a.to_bits() ^ sign(b.to_bits())),
+        "fmin.s" => boxed(min_max(a as f64, b as f64, false) as f32),
+        "fmax.s" => boxed(min_max(a as f64, b as f64, true) as f32),
+        "fcvt.w.s" => to_int(a as f64, rm, i32::MIN as f64, i32::MAX as f64, flags) as i32 as u64,
+        "fcvt.wu.s" => to_int(a as f64, rm, 0.0, u32::MAX as f64, flags) as u32 as i32 as u64,
+        "fcvt.l.s" => to_int(a as f64, rm, i64::MIN as f64, i64::MAX as f64, flags) as i64 as u64,
+        "fcvt.lu.s" => to_int(a as f64, rm, 0.0, u64::MAX as f64, flags) as u64,
+        "fcvt.s.w" => boxed(src[0] as i32 as f32),
+        "fcvt.s.wu" => boxed(src[0] as u32 as f32),
+        "fcvt.s.l" => boxed(src[0] as i64 as f32),
+        "fcvt.s.lu" => boxed(src[0] as f32),
+        "fcvt.d.s" => double(a as f64),
+        "fmv.x.w" => src[0] as u32 as i32 as u64,
+        "fmv.w.x" => nan_box(src[0] as u32),
+        "fclass.s" => classify(a.classify(), a.is_sign_negative(), a.to_bits() & 0x0040_0000 != 0),
+        "feq.s" => (a == b) as u64,
+        "flt.s" | "fle.s" if a.is_nan() || b.is_nan() => { *flags |= NV; 0 }
+        "flt.s" => (a < b) as u64,
+        "fle.s" => (a <= b) as u64,
+        _ => return None,
+    };
+    Some(out)
+}
+
+fn execute_d(name: &str, rm: u32, src: [u64; 3], flags: &mut u64) -> Option<u64> {
+    let (a, b, c) = (f64::from_bits(src[0]), f64::from_bits(src[1]), f64::from_bits(src[2]));
+    const SIGN: u64 = 1 << 63;
+    let arith = |x: f64, flags: &mut u64| {
+        arith_flags(name, [a, b, c], x, flags);
+        double(x)
+    };
+    let out = match name {
+        "fadd.d" => arith(a + b, flags),
+        "fsub.d" => arith(a - b, flags),
+        "fmul.d" => arith(a * b, flags),
+        "fdiv.d" => arith(a / b, flags),
+        "fsqrt.d" => arith(a.sqrt(), flags),
+        "fmadd.d" => arith(a.mul_add(b, c), flags),
+        "fmsub.d" => arith(a.mul_add(b, -c), flags),
+        "fnmsub.d" => arith((-a).mul_add(b, c), flags),
+        "fnmadd.d" => arith((-a).mul_add(b, -c), flags),
+        "fsgnj.d" => src[0] & !SIGN | src[1] & SIGN,
+        "fsgnjn.d" => src[0] & !SIGN | !src[1] & SIGN,
+        "fsgnjx.d" => src[0] ^ src[1] & SIGN,
+        "fmin.d" => double(min_max(a, b, false)),
+        "fmax.d" => double(min_max(a, b, true)),
+        "fcvt.w.d" => to_int(a, rm, i32::MIN as f64, i32::MAX as f64, flags) as i32 as u64,
+        "fcvt.wu.d" => to_int(a, rm, 0.0, u32::MAX as f64, flags) as u32 as i32 as u64,
+        "fcvt.l.d" => to_int(a, rm, i64::MIN as f64, i64::MAX as f64, flags) as i64 as u64,
+        "fcvt.lu.d" => to_int(a, rm, 0.0, u64::MAX as f64, flags) as u64,
+        "fcvt.d.w" => double(src[0] as i32 as f64),
+        "fcvt.d.wu" => double(src[0] as u32 as f64),
+        "fcvt.d.l" => double(src[0] as i64 as f64),
+        "fcvt.d.lu" => double(src[0] as f64),
+        "fcvt.s.d" => boxed(a as f32),
+        "fmv.x.d" | "fmv.d.x" => src[0],
+        "fclass.d" => classify(a.classify(), a.is_sign_negative(), src[0] & 1 << 51 != 0),
+        "feq.d" => (a == b) as u64,
+        "flt.d" | "fle.d" if a.is_nan() || b.is_nan() => { *flags |= NV; 0 }
+        "flt.d" => (a < b) as u64,
+        "fle.d" => (a <= b) as u64,
+        _ => return None,
+    };
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_float_ops() {
+        let f = |x: f32| nan_box(x.to_bits());
+        let mut fcsr = 0;
+        let mut exec = |name, inst, src1, src2| execute(name, inst, [src1, src2, 0], &mut fcsr);
+        let mut run = |name, src1, src2| exec(name, 0, src1, src2).unwrap();
+        assert_eq!(unbox(run("fadd.s", f(1.5), f(2.25))), 3.75);
+        assert_eq!(run("fdiv.s", f(0.0), f(0.0)), nan_box(CANONICAL_NAN));
+        assert_eq!(unbox(run("fsgnjn.s", f(2.0), f(2.0))), -2.0);
+        assert_eq!(unbox(run("fmin.s", f(0.0), f(-0.0))).to_bits(), (-0.0f32).to_bits());
+        assert_eq!(unbox(run("fmax.s", f(f32::NAN), f(1.0))), 1.0);
+        // rtz and round-to-nearest-even conversions, saturation and NaN
+        assert_eq!(exec("fcvt.w.s", 1 << 12, f(-2.5), 0), Some(-2i64 as u64));
+        let mut run = |name, src1, src2| exec(name, 0, src1, src2).unwrap();
+        assert_eq!(run("fcvt.w.s", f(2.5), 0), 2);
+        assert_eq!(run("fcvt.w.s", f(1e20), 0), i32::MAX as u64);
+        assert_eq!(run("fcvt.w.s", f(f32::NAN), 0), i32::MAX as u64);
+        assert_eq!(unbox(run("fcvt.s.w", -3i64 as u64, 0)), -3.0);
+        assert_eq!(run("fmv.x.w", f(-1.0), 0), 0xffff_ffff_bf80_0000);
+        assert_eq!(run("flt.s", f(1.0), f(f32::NAN)), 0);
+        assert_eq!(run("fclass.s", f(-0.0), 0), 1 << 3);
+        // a value that is not NaN-boxed reads as NaN
+        assert!(unbox(1.0f32.to_bits() as u64).is_nan());
+    }
+
+    #[test]
+    fn test_double_ops() {
+        let d = |x: f64| x.to_bits();
+        let mut fcsr = 0;
+        let mut run = |name, src: [u64; 3]| execute(name, 0, src, &mut fcsr).unwrap();
+        assert_eq!(f64::from_bits(run("fmul.d", [d(1.5), d(-4.0), 0])), -6.0);
+        assert_eq!(f64::from_bits(run("fmadd.d", [d(2.0), d(3.0), d(0.5)])), 6.5);
+        assert_eq!(f64::from_bits(run("fnmsub.d", [d(2.0), d(3.0), d(0.5)])), -5.5);
+        assert_eq!(unbox(run("fcvt.s.d", [d(0.1), 0, 0])), 0.1f32);
+        assert_eq!(f64::from_bits(run("fcvt.d.s", [nan_box(0.5f32.to_bits()), 0, 0])), 0.5);
+        assert_eq!(run("fsgnjx.d", [d(2.0), d(-1.0), 0]), d(-2.0));
+        assert_eq!(run("fclass.d", [d(f64::INFINITY), 0, 0]), 1 << 7);
+        assert_eq!(fcsr, 0);
+        // out of range conversion: NV; division by zero: DZ
+        assert_eq!(execute("fcvt.l.d", 0, [d(-1e300), 0, 0], &mut fcsr), Some(i64::MIN as u64));
+        execute("fdiv.d", 0, [d(1.0), d(0.0), 0], &mut fcsr);
+        assert_eq!(fcsr, NV | DZ);
+        // the dynamic rounding mode is frm (rdn here)
+        let mut fcsr = 2 << 5;
+        assert_eq!(execute("fcvt.w.d", 7 << 12, [d(-0.5), 0, 0], &mut fcsr), Some(-1i64 as u64));
+    }
+
+    #[test]
+    fn test_double_program() {
+        // (3.0 * 2.0 + 2.0) as float doubled, times 8.0 again: 128; 5.0 / 2.0 rounded up by frm: 3
+        let asm = "main:
+  addi sp, sp, -16
+  li t0, 3
+  fcvt.d.w fa0, t0
+  li t0, 2
+  fcvt.d.w fa1, t0
+  fsd fa1, 8(sp)
+  fld fa2, 8(sp)
+  fmadd.d fa3, fa0, fa1, fa2
+  fcvt.s.d fa4, fa3
+  fadd.s fa4, fa4, fa4
+  fcvt.d.s fa5, fa4
+  fmul.d fa5, fa5, fa3
+  fcvt.w.d a0, fa5, rtz
+  li t0, 5
+  fcvt.d.w ft0, t0
+  fdiv.d ft0, ft0, fa1
+  csrwi frm, 3
+  fcvt.w.d a1, ft0
+  add a0, a0, a1
+  addi sp, sp, 16
+  ret
+";
+        let path = crate::runner::test_image(asm);
+        let mut sim = super::super::embed::Simulator::load(&path).unwrap();
+        assert_eq!(sim.run(Some(10_000)), Some(131));
+        let run = super::super::bench::run_model(&path, &Default::default(), super::super::bench::CpuModel::SingleCycle, None).unwrap();
+        assert_eq!(run.exit_code, 131);
+    }
+}
@@ -0,0 +1,47 @@
+// Function call tracing: the live call stack and an aggregated call graph.
+// Calls are `jal`/`jalr` writing ra, returns are `jalr x0, 0(ra)`.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CallFrame {
+    pub func: u64,     // callee entry address
+    pub call_pc: u64,  // address of the call instruction
+    pub ret_addr: u64,
+    pub sp: u64,       // caller's sp / s0 at the call
+    pub fp: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct CallTracker {
+    pub stack: Vec<CallFrame>,
+    pub edges: BTreeMap<(u64, u64), u64>, // (caller, callee) -> count, caller 0 is the entry code
+    pub calls: BTreeMap<u64, u64>,        // callee -> count
+}
+
+impl CallTracker {
+    pub fn clear(&mut self) {
+        self.stack.clear();
+        self.edges.clear();
+        self.calls.clear();
+    }
+
+    pub fn current_func(&self) -> u64 {
+        self.stack.last().map_or(0, |f| f.func)
+    }
+
+    pub fn on_call(&mut self, call_pc: u64, ret_addr: u64, target: u64, sp: u64, fp: u64) {
+        *self.edges.entry((self.current_func(), target)).or_insert(0) += 1;
+        *self.calls.entry(target).or_insert(0) += 1;
+        self.stack.push(CallFrame { func: target, call_pc, ret_addr, sp, fp });
+    }
+
+    // pop up to the frame returning to `target`; unmatched returns are ignored
+    pub fn on_return(&mut self, target: u64) {
+        if let Some(depth) = self.stack.iter().rposition(|f| f.ret_addr == target) {
+            self.stack.truncate(depth);
+        }
+    }
+}
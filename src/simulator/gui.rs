@@ -1,6 +1,21 @@
 use eframe::{egui};
 use super::pipe::*;
 use super::mem::*;
+use super::cpu::REG_NAMES;
+use super::disasm::{disassemble, disassemble_range};
+use super::command::*;
+use super::srcmap::SourceMap;
+use super::history::{History, HISTORY_FILE};
+use super::timeline::{CycleRecord, STAGE_NAMES};
+use super::cache::Cache;
+use super::heatmap::HEATMAP_GRANULE;
+use super::perf::{InstClass, PerfSample};
+use super::layout::*;
+use super::config::SimConfig;
+use super::snapshot::load_checkpoint;
+use super::breakpoint::Condition;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process;
 pub struct GuiApp {
     pipeline: Pipeline,
@@ -9,19 +24,70 @@ pub struct GuiApp {
     debug_mode: bool,
     command_input: String,
     output: String,
-    register_display: String,
+    reg_edit: Option<(usize, String)>, // 正在编辑的寄存器，32 表示 PC
     last_registers: [u64; 32], 
+    changed_registers: [bool; 32], // 上一次执行中被修改的寄存器
+    reg_format: RegFormat,
+    reg_nonzero_only: bool,
+    output_filter: String,
+    mem_range_input: String, // 形如 "0x80000000-0x80000100"
+    mem_range: Option<(u64, u64)>,
+    mem_hide_zero: bool,
+    timeline_cycles: usize,
+    export_path: String,
+    mem_dump_path: String,
+    mem_fill_values: String,
+    mem_addr_input: String,
+    mem_base: u64,
+    disasm_addr_input: String,
+    disasm_base: u64,
+    disasm_follow: bool, // 跟随 PC 滚动
+    disasm_scrolled_pc: Option<u64>, // 上次滚动到的 PC，PC 变化时才再次滚动
+    mem_unit: usize, // 每个单元的字节数：1、4 或 8
+    mem_seen: HashMap<(u64, usize), u64>, // 本周期显示过的单元值
+    mem_prev: HashMap<(u64, usize), u64>, // 上一次刷新时的值，用于标出变化
+    mem_seen_cycle: i32,
+    mem_edit: Option<(u64, String)>, // 正在编辑的单元：地址与输入
+    mem_highlight: Option<(u64, u64)>, // x 命令查看的区间 [lo, hi)
+    mem_watches: Vec<MemWatch>,
+    watch_expr: String,
+    watch_words: usize,
+    srcmap: Result<SourceMap, String>,
+    bp_addr_input: String,  // 断点面板的输入：地址、条件、是否临时
+    bp_cond_input: String,
+    bp_temporary: bool,
+    run_until_input: String, // 工具栏 Run until 的目标：地址或符号
+    run_goal: Option<RunGoal>,    // step / next / finish 的停止条件
+    last_source_line: Option<usize>,
+    history: History,
+    history_search: Option<HistorySearch>,
+    heatmap_view: Option<(u64, u64)>, // None: fit to touched range
+    perf_baseline: Option<(String, Vec<PerfSample>)>, // 固定下来用于对比的一次运行：说明与采样
+    guest_input: String,
+    guest_output_path: String,
+    open_dialog: Option<OpenDialog>, // Open... 打开的镜像选择窗口
+    run_hz: f64,         // 连续运行时每秒执行的周期数
+    run_unlimited: bool,
+    run_budget: f64,     // 累积的待执行周期（小数部分）
+    settings: GuiSettings,
+    config: SimConfig,   // 重新加载时使用
 }
 
 
 impl GuiApp {
     // 创建自定义构造函数而不是使用 Default
-    pub fn new(output: String) -> Self {
-        let mut mem = Memory::new();
-        mem.load_image(&output).unwrap();
-        let mut pipeline = Pipeline::new();
-        pipeline.init(); 
+    pub fn new(output: String, config: SimConfig, storage: Option<&dyn eframe::Storage>) -> Self {
+        let mut settings: GuiSettings = storage
+            .and_then(|s| eframe::get_value(s, eframe::APP_KEY))
+            .unwrap_or_default();
+        // 未指定镜像时打开上次的镜像
+        let output = if output.is_empty() { settings.last_image.clone() } else { output };
+        settings.last_image = output.clone();
+
+        let (pipeline, mem) = config.build(&output).unwrap();
+        let hz = config.pipeline.hz;
         let last_registers = pipeline.cpu.reg.clone(); // 初始寄存器状态
+        let srcmap = config.symbols(&output);
         
         let mut app = Self {
             pipeline,
@@ -30,22 +96,191 @@ impl GuiApp {
             debug_mode: true,
             command_input: String::new(),
             output: String::new(),
-            register_display: String::new(),
+            reg_edit: None,
             last_registers, 
+            changed_registers: [false; 32],
+            reg_format: RegFormat::Hex,
+            reg_nonzero_only: false,
+            output_filter: String::new(),
+            mem_range_input: String::new(),
+            mem_range: None,
+            mem_hide_zero: false,
+            timeline_cycles: 32,
+            export_path: String::from("pipeline.csv"),
+            mem_dump_path: String::from("memory.bin"),
+            mem_fill_values: String::from("0"),
+            mem_addr_input: String::from("0x80000000"),
+            mem_base: 0x8000_0000,
+            disasm_addr_input: String::from("0x80000000"),
+            disasm_base: 0x8000_0000,
+            disasm_follow: true,
+            disasm_scrolled_pc: None,
+            mem_unit: 1,
+            mem_seen: HashMap::new(),
+            mem_prev: HashMap::new(),
+            mem_seen_cycle: 0,
+            mem_edit: None,
+            mem_highlight: None,
+            mem_watches: Vec::new(),
+            watch_expr: String::new(),
+            watch_words: 4,
+            srcmap,
+            bp_addr_input: String::new(),
+            bp_cond_input: String::new(),
+            bp_temporary: false,
+            run_until_input: String::new(),
+            run_goal: None,
+            last_source_line: None,
+            history: History::load(HISTORY_FILE),
+            history_search: None,
+            heatmap_view: None,
+            perf_baseline: None,
+            guest_input: String::new(),
+            guest_output_path: String::from("output.txt"),
+            open_dialog: None,
+            run_hz: hz.unwrap_or(settings.run_hz),
+            run_unlimited: hz.is_none() && settings.run_unlimited,
+            run_budget: 0.0,
+            settings,
+            config,
         };
-        
-        app.update_register_display();
+        if app.settings.stop_at_main {
+            app.start();
+        }
         app
     }
 
+    // 从磁盘重新读取镜像并重置内存与流水线，保留断点、观察区间和面板设置
+    fn reload(&mut self) {
+        let image = self.settings.last_image.clone();
+        match self.load_image(&image) {
+            Ok(()) => self.output = format!("Reloaded {}\n", image),
+            Err(e) => self.output = format!("Failed to reload {}\n", e),
+        }
+        if self.settings.stop_at_main {
+            self.start();
+        }
+    }
+
+    // 换成另一个镜像，之后 Reload 和下次启动都使用它
+    fn open_image(&mut self, image: &str) {
+        match self.load_image(image) {
+            Ok(()) => self.output = format!("Opened {}\n", image),
+            Err(e) => {
+                self.output = format!("Failed to open {}\n", e);
+                return;
+            }
+        }
+        if self.settings.stop_at_main {
+            self.start();
+        }
+    }
+
+    // 失败时保留当前的流水线和内存
+    fn load_image(&mut self, image: &str) -> Result<(), String> {
+        let (mut pipeline, mem) = self.config.build(image)?;
+        pipeline.enable_caches(self.pipeline.icache.is_some());
+        self.install(pipeline, mem, image);
+        self.settings.last_image = image.to_string();
+        Ok(())
+    }
+
+    // 从检查点恢复，断点和界面设置保持不变
+    fn load_checkpoint(&mut self, path: &str) {
+        let image = self.settings.last_image.clone();
+        match load_checkpoint(path, &self.config, &image) {
+            Ok((pipeline, mem)) => {
+                self.install(pipeline, mem, &image);
+                self.output = format!("Loaded checkpoint {} at cycle {}, pc = 0x{:08x}\n", path, self.pipeline.cpu.cycle_count, self.pipeline.cpu.pc);
+            }
+            Err(e) => self.output = format!("Failed to load checkpoint {}\n", e),
+        }
+    }
+
+    // 换上新的流水线和内存，沿用当前的断点、跟踪开关和采样间隔
+    fn install(&mut self, mut pipeline: Pipeline, mem: Memory, image: &str) {
+        pipeline.perf.interval = self.pipeline.perf.interval;
+        pipeline.breakpoints = std::mem::take(&mut self.pipeline.breakpoints);
+        pipeline.tracer.flags = self.pipeline.tracer.flags;
+
+        self.mem = mem;
+        self.pipeline = pipeline;
+        self.srcmap = self.config.symbols(image);
+        self.step_counter = 0;
+        self.debug_mode = true;
+        self.last_registers = self.pipeline.cpu.reg;
+        self.changed_registers = [false; 32];
+        self.last_source_line = None;
+        self.heatmap_view = None;
+    }
+
+    // 连续运行直到满足停止条件（或断点、程序结束），None 表示一直运行
+    fn run_to(&mut self, goal: Option<RunGoal>) {
+        self.run_goal = goal;
+        self.debug_mode = false;
+        self.run_budget = 0.0;
+    }
+
+    fn finish(&mut self) {
+        match RunGoal::finish(&self.pipeline) {
+            Some(goal) => self.run_to(Some(goal)),
+            None => self.output.push_str("\"finish\" not meaningful in the outermost frame\n"),
+        }
+    }
+
+    // 在 main 处设置临时断点并开始运行，类似 gdb 的 start
+    fn start(&mut self) {
+        match self.srcmap.as_ref().ok().and_then(|m| m.symbol("main")) {
+            Some(addr) => self.run_until(addr),
+            None => self.output.push_str("No symbol 'main' in this image\n"),
+        }
+    }
+
+    // 在 addr 处设置临时断点并继续运行，这条指令提交时停下，断点随即删除
+    fn run_until(&mut self, addr: u64) {
+        if !self.pipeline.cpu.running {
+            self.output.push_str("The program is not running\n");
+            return;
+        }
+        self.pipeline.breakpoints.add(addr, true, None);
+        self.run_to(None);
+        self.output.push_str(&format!("Running to {} (0x{:08x})\n", symbolize(&self.srcmap, addr), addr));
+    }
+
     // ... 其他方法保持不变 ...
 }
 
 
 impl eframe::App for GuiApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.settings.run_hz = self.run_hz;
+        self.settings.run_unlimited = self.run_unlimited;
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings);
+    }
+
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         // 请求持续重绘以确保UI更新
         ctx.request_repaint();
+        self.apply_theme(ctx, frame);
+
+        if self.debug_mode && self.pipeline.cpu.running {
+            let (f10, f11, shift) = ctx.input(|i| (i.key_pressed(egui::Key::F10), i.key_pressed(egui::Key::F11), i.modifiers.shift));
+            if f11 && shift {
+                self.finish();
+            } else if f11 {
+                self.run_to(Some(RunGoal::Step));
+            } else if f10 {
+                self.run_to(Some(RunGoal::Next));
+            }
+        }
+
+        if !self.debug_mode && self.pipeline.cpu.running {
+            let dt = ctx.input(|i| i.stable_dt) as f64;
+            self.free_run(dt);
+        }
+
+        // 停靠面板需在 CentralPanel 之前添加
+        self.show_docked_panels(ctx);
         
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Pipeline Simulator");
@@ -54,17 +289,13 @@ impl eframe::App for GuiApp {
             ui.columns(2, |columns| {
                 // 左列：寄存器状态
                 columns[0].group(|ui| {
-                    ui.label("Register State");
-                    ui.add(
-                        egui::TextEdit::multiline(&mut self.register_display)
-                            .font(egui::TextStyle::Monospace)
-                            .desired_width(f32::INFINITY)
-                    );
+                    ui.label("Register State (double-click to edit)");
+                    self.register_panel(ui);
                     ui.group(|ui| {
                         ui.label("CPU State");
                         ui.horizontal(|ui| {
                             ui.label("PC:");
-                            ui.monospace(format!("0x{:016x}", self.pipeline.cpu.pc));
+                            self.register_value(ui, 32);
                         });
                         ui.horizontal(|ui| {
                             ui.label("Cycle:");
@@ -109,105 +340,384 @@ impl eframe::App for GuiApp {
                     
                     // 命令输出
                     ui.group(|ui| {
-                        ui.label("Output:");
-                        ui.add(
-                            egui::TextEdit::multiline(&mut self.output)
-                                .font(egui::TextStyle::Monospace)
-                                .desired_width(f32::INFINITY)
-                        );
+                        ui.horizontal(|ui| {
+                            ui.label("Output:");
+                            ui.add(egui::TextEdit::singleline(&mut self.output_filter)
+                                .hint_text("filter")
+                                .desired_width(150.0));
+                            if !self.output_filter.is_empty() && ui.small_button("✖").clicked() {
+                                self.output_filter.clear();
+                            }
+                        });
+                        egui::ScrollArea::vertical().id_source("output").max_height(300.0).show(ui, |ui| {
+                            if self.output_filter.is_empty() {
+                                ui.add(
+                                    egui::TextEdit::multiline(&mut self.output)
+                                        .font(egui::TextStyle::Monospace)
+                                        .desired_width(f32::INFINITY)
+                                );
+                            } else {
+                                // 过滤时只读显示匹配的行（不区分大小写）
+                                let needle = self.output_filter.to_lowercase();
+                                let matched: Vec<&str> = self.output.lines()
+                                    .filter(|l| l.to_lowercase().contains(&needle))
+                                    .collect();
+                                ui.label(format!("{} matching line(s)", matched.len()));
+                                ui.monospace(matched.join("\n"));
+                            }
+                        });
                     });
 
                     // 命令输入和执行按钮
                     ui.vertical(|ui| {
-                        ui.label("Enter command:");
-                        let response = ui.text_edit_singleline(&mut self.command_input);
-                        if ui.button("Execute").clicked() || response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        ui.label("Enter command (Up/Down: history, Ctrl-R: search):");
+                        let submitted = command_line(ui, &mut self.command_input, &mut self.history, &mut self.history_search);
+                        if ui.button("Execute").clicked() || submitted {
                             self.execute_command();
                         }
                     });
 
+                    // 客户程序标准输入（经 UART 读取），与调试命令分开
+                    ui.group(|ui| {
+                        ui.label("Guest input (stdin):");
+                        ui.horizontal(|ui| {
+                            let response = ui.text_edit_singleline(&mut self.guest_input);
+                            let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                            if ui.button("Send").clicked() || enter {
+                                self.mem.uart.push_input(&self.guest_input);
+                                self.mem.uart.push_input("\n");
+                                self.guest_input.clear();
+                            }
+                        });
+                        ui.label(format!("{} byte(s) pending", self.mem.uart.rx.len()));
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Speed:");
+                        ui.add_enabled(!self.run_unlimited,
+                            egui::Slider::new(&mut self.run_hz, 1.0..=1_000_000.0).logarithmic(true).suffix(" Hz"));
+                        ui.checkbox(&mut self.run_unlimited, "Unlimited");
+                        if self.debug_mode {
+                            if ui.button("Run").clicked() {
+                                self.run_to(None);
+                            }
+                        } else if ui.button("Pause").clicked() {
+                            self.debug_mode = true;
+                            self.run_goal = None;
+                        }
+                        if ui.button("Reload").on_hover_text("Re-read the image and restart, keeping breakpoints").clicked() {
+                            self.reload();
+                        }
+                        if ui.button("Open...").on_hover_text("Load another image (flat binary or ELF)").clicked() {
+                            self.open_dialog = Some(OpenDialog::new(&self.settings.last_image));
+                        }
+                    });
+
+                    // 指令级单步：F11 step / F10 next / Shift+F11 finish
+                    ui.horizontal(|ui| {
+                        ui.add_enabled_ui(self.debug_mode && self.pipeline.cpu.running, |ui| {
+                            if ui.button("Step").on_hover_text("Run until the next instruction commits (F11)").clicked() {
+                                self.run_to(Some(RunGoal::Step));
+                            }
+                            if ui.button("Next").on_hover_text("Step over calls (F10)").clicked() {
+                                self.run_to(Some(RunGoal::Next));
+                            }
+                            if ui.button("Finish").on_hover_text("Run until the current function returns (Shift+F11)").clicked() {
+                                self.finish();
+                            }
+                        });
+                        // 程序结束后也可以倒退
+                        ui.add_enabled_ui(self.debug_mode && !self.pipeline.undo.is_empty(), |ui| {
+                            if ui.button("Back").on_hover_text("Undo the last cycle (rsi)").clicked() {
+                                self.output = reverse_step(&mut self.pipeline, &mut self.mem, 1);
+                                self.step_counter = self.step_counter.saturating_sub(1);
+                            }
+                        });
+                        // 运行到指定地址：临时断点，到达后删除
+                        ui.separator();
+                        let response = ui.add(egui::TextEdit::singleline(&mut self.run_until_input)
+                            .hint_text("0x… or symbol")
+                            .desired_width(110.0));
+                        let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        let run = ui.add_enabled(self.debug_mode && self.pipeline.cpu.running, egui::Button::new("Run until"))
+                            .on_hover_text("Run until the instruction at this address commits (temporary breakpoint)");
+                        if (run.clicked() || enter && self.debug_mode) && !self.run_until_input.trim().is_empty() {
+                            match self.resolve_location(self.run_until_input.trim()) {
+                                Ok(addr) => self.run_until(addr),
+                                Err(e) => self.output = format!("{}\n", e),
+                            }
+                        }
+                    });
+
                     ui.label(format!("Steps taken: {}", self.step_counter));
+                    ui.checkbox(&mut self.settings.stop_at_main, "Stop at main on load");
+                    self.panel_chooser(ui);
                 });
             });
         });
+
+        self.show_floating_panels(ctx);
+        self.open_dialog_window(ctx);
+    }
+}
+
+impl GuiApp {
+    // 每个面板可隐藏、浮动或停靠在左/右/下侧，布局随设置一起保存
+    fn panel_chooser(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Panels").default_open(true).show(ui, |ui| {
+            egui::Grid::new("panel_docks").show(ui, |ui| {
+                for kind in PanelKind::ALL {
+                    let mut dock = self.settings.dock(kind);
+                    ui.label(kind.title());
+                    egui::ComboBox::from_id_source(("dock", kind))
+                        .selected_text(dock.name())
+                        .show_ui(ui, |ui| {
+                            for d in Dock::ALL {
+                                ui.selectable_value(&mut dock, d, d.name());
+                            }
+                        });
+                    self.settings.set_dock(kind, dock);
+                    ui.end_row();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Theme");
+                egui::ComboBox::from_id_source("theme")
+                    .selected_text(self.settings.theme.name())
+                    .show_ui(ui, |ui| {
+                        for theme in Theme::ALL {
+                            ui.selectable_value(&mut self.settings.theme, theme, theme.name());
+                        }
+                    });
+                // 面板恢复默认停靠位置，浮动窗口回到默认位置和大小
+                if ui.button("Reset layout").clicked() {
+                    self.settings.docks.clear();
+                    ui.ctx().memory_mut(|m| m.reset_areas());
+                }
+            });
+        });
+    }
+
+    // 按设置切换深色/浅色主题；跟随系统时读取 eframe 报告的系统主题，读不到就保持当前主题
+    fn apply_theme(&self, ctx: &egui::Context, frame: &eframe::Frame) {
+        let dark = match self.settings.theme {
+            Theme::Dark => Some(true),
+            Theme::Light => Some(false),
+            Theme::System => frame.info().system_theme.map(|t| t == eframe::Theme::Dark),
+        };
+        if let Some(dark) = dark {
+            if ctx.style().visuals.dark_mode != dark {
+                ctx.set_visuals(if dark { egui::Visuals::dark() } else { egui::Visuals::light() });
+            }
+        }
+    }
+
+    fn panel_contents(&mut self, kind: PanelKind, ui: &mut egui::Ui) {
+        match kind {
+            PanelKind::Timeline => self.timeline_panel(ui),
+            PanelKind::Memory => self.memory_panel(ui),
+            PanelKind::Disasm => self.disasm_panel(ui),
+            PanelKind::Source => self.source_panel(ui),
+            PanelKind::Perf => self.perf_panel(ui),
+            PanelKind::Cache => self.cache_panel(ui),
+            PanelKind::Breakpoints => self.breakpoints_panel(ui),
+            PanelKind::Heatmap => self.heatmap_panel(ui),
+            PanelKind::Calls => self.calls_panel(ui),
+            PanelKind::Output => self.program_output_panel(ui),
+        }
+    }
+
+    fn show_docked_panels(&mut self, ctx: &egui::Context) {
+        for kind in PanelKind::ALL {
+            let id = egui::Id::new(("docked", kind));
+            let contents = |app: &mut Self, ui: &mut egui::Ui| {
+                ui.heading(kind.title());
+                egui::ScrollArea::both().id_source(id).show(ui, |ui| app.panel_contents(kind, ui));
+            };
+            match self.settings.dock(kind) {
+                Dock::Left => { egui::SidePanel::left(id).resizable(true).show(ctx, |ui| contents(self, ui)); }
+                Dock::Right => { egui::SidePanel::right(id).resizable(true).show(ctx, |ui| contents(self, ui)); }
+                Dock::Bottom => { egui::TopBottomPanel::bottom(id).resizable(true).show(ctx, |ui| contents(self, ui)); }
+                Dock::Hidden | Dock::Floating => {}
+            }
+        }
+    }
+
+    fn show_floating_panels(&mut self, ctx: &egui::Context) {
+        for kind in PanelKind::ALL {
+            if self.settings.dock(kind) != Dock::Floating {
+                continue;
+            }
+            let mut open = true;
+            egui::Window::new(kind.title())
+                .open(&mut open)
+                .default_size(kind.default_size())
+                .show(ctx, |ui| self.panel_contents(kind, ui));
+            if !open {
+                self.settings.set_dock(kind, Dock::Hidden);
+            }
+        }
     }
 }
 
 impl GuiApp {
+    // 镜像选择窗口：双击目录进入，单击文件选中，双击文件或 Open 打开
+    fn open_dialog_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.open_dialog else {
+            return;
+        };
+        let mut open = true;
+        let mut chosen = None;
+        egui::Window::new("Open Image")
+            .open(&mut open)
+            .default_size([420.0, 360.0])
+            .show(ctx, |ui| {
+                ui.label(dialog.dir.display().to_string());
+                let mut enter = None;
+                egui::ScrollArea::vertical().max_height(260.0).show(ui, |ui| {
+                    if ui.selectable_label(false, "../").double_clicked() {
+                        enter = Some(dialog.dir.join(".."));
+                    }
+                    for (name, is_dir) in &dialog.entries {
+                        let path = dialog.dir.join(name);
+                        if *is_dir {
+                            if ui.selectable_label(false, format!("{}/", name)).double_clicked() {
+                                enter = Some(path);
+                            }
+                            continue;
+                        }
+                        let path = path.to_string_lossy().into_owned();
+                        let response = ui.selectable_label(dialog.path == path, name);
+                        if response.double_clicked() {
+                            chosen = Some(path.clone());
+                        }
+                        if response.clicked() {
+                            dialog.path = path;
+                        }
+                    }
+                });
+                if let Some(dir) = enter {
+                    dialog.enter(dir);
+                }
+                ui.horizontal(|ui| {
+                    let response = ui.text_edit_singleline(&mut dialog.path);
+                    let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button("Open").clicked() || enter {
+                        chosen = Some(dialog.path.clone());
+                    }
+                });
+            });
+        if let Some(path) = chosen {
+            self.open_dialog = None;
+            self.open_image(&path);
+        } else if !open {
+            self.open_dialog = None;
+        }
+    }
+
     fn execute_command(&mut self,) {
         self.output.clear();
-        let input = self.command_input.trim().to_lowercase();
-        let mut parts = input.split_whitespace();
-        
-        match parts.next() {
-            Some("c") => {
+        self.history.push(&self.command_input);
+
+        let command = parse_command_with(&self.command_input, &|s| resolve_location(s, &self.pipeline, &self.srcmap));
+        match command {
+            Ok(Some(Command::Continue)) => {
+                // 实际执行在 update() 中按设定速度逐帧进行
                 self.debug_mode = false;
+                self.run_budget = 0.0;
                 self.output.push_str("Continuing execution\n");
-                
-                for _ in 0..100000 {
-                    if !self.pipeline.cpu.running { break; }
-                    let prev_pc = self.pipeline.cpu.pc;
-                    self.pipeline.step(&mut self.mem);
-                    self.step_counter += 1;
-                    
-                }
             },
-            Some("q") => {
+            Ok(Some(Command::Quit)) => {
                 self.debug_mode = false;
                 self.pipeline.cpu.running = false;
                 self.output.push_str("Exiting simulator\n");
                 process::exit(0);
             },
-            Some("si") => {
-                let n = match parts.next() {
-                    Some(num_str) => num_str.parse::<u32>().unwrap_or(1),
-                    None => 1,
-                };
-
-                for _ in 0..n {
-                    if !self.pipeline.cpu.running { break; }
-                    
-                    let prev_pc = self.pipeline.cpu.pc;
-                    self.pipeline.step(&mut self.mem);
-                    self.step_counter += 1;
-                    
-                }
-                self.output.push_str(&format!("Executed {} steps\n", n));
+            Ok(Some(Command::Step(n))) => {
+                let (done, message) = step_cycles(&mut self.pipeline, &mut self.mem, n);
+                self.step_counter += done;
+                self.output.push_str(&message);
             },
-            Some("info") => {
-                match parts.next() {
-                    Some("r") => {
-                        self.output.push_str("Register state:\n");
-                        for i in 0..32 {
-                            self.output.push_str(&format!("x{:02}: 0x{:016x}\n", i, self.pipeline.cpu.reg[i]));
-                        }
-                    },
-                    Some(_) => self.output.push_str("Invalid info subcommand\n"),
-                    None => self.output.push_str("Missing subcommand for info\n"),
-                }
+            Ok(Some(Command::ReverseStep(n))) => {
+                self.output.push_str(&reverse_step(&mut self.pipeline, &mut self.mem, n));
+                self.step_counter = self.step_counter.saturating_sub(n);
+            },
+            Ok(Some(Command::Info(topic))) => {
+                self.output.push_str(&format_info(topic, &self.pipeline, &self.mem));
+            },
+            Ok(Some(Command::Break { addr, temporary, condition })) => {
+                self.output.push_str(&set_breakpoint(&mut self.pipeline.breakpoints, addr, temporary, condition));
+            },
+            Ok(Some(Command::Watch { addr, len, kind })) => {
+                self.output.push_str(&set_watchpoint(&mut self.pipeline.breakpoints, addr, len, kind));
+            },
+            Ok(Some(Command::Disas { addr, count })) => {
+                self.output.push_str(&format_disas(&self.pipeline, &self.mem, &self.srcmap, addr, count));
+            },
+            Ok(Some(Command::Print(expr))) => {
+                self.output.push_str(&print_expression(&expr, &self.pipeline, &self.mem, &self.srcmap));
+            },
+            Ok(Some(Command::Assert { lhs, op, rhs })) => {
+                let result = check_assertion(&lhs, op, &rhs, &self.pipeline, &self.mem, &self.srcmap);
+                self.output.push_str(&result.unwrap_or_else(|e| format!("{}\n", e)));
             },
-            Some("x") => {
-                
+            Ok(Some(Command::Trace(setting))) => {
+                self.output.push_str(&trace_command(&mut self.pipeline.tracer, setting));
             },
-            Some("help") => {
-                self.print_help();
+            Ok(Some(Command::Delete(id))) => {
+                self.output.push_str(&delete_breakpoints(&mut self.pipeline.breakpoints, id));
             },
-            Some(cmd) => {
-                self.output.push_str(&format!("Unknown command '{}'. Type 'help' for a list of commands.\n", cmd));
+            Ok(Some(Command::Examine { spec, addr })) => {
+                self.output.push_str(&examine(&self.mem, &spec, addr));
+                // 同时在内存面板中定位并高亮
+                self.mem_base = addr & !(MEM_ROW_BYTES - 1);
+                self.mem_highlight = Some((addr, addr.wrapping_add(spec.byte_len())));
+                self.settings.show(PanelKind::Memory);
             },
-            None => {}
+            Ok(Some(Command::Export { html, path })) => {
+                self.output.push_str(&export_timeline(&self.pipeline.timeline, html, &path));
+            },
+            Ok(Some(Command::Start)) => self.start(),
+            Ok(Some(Command::StepInst)) => self.run_to(Some(RunGoal::Step)),
+            Ok(Some(Command::Next)) => self.run_to(Some(RunGoal::Next)),
+            Ok(Some(Command::Finish)) => self.finish(),
+            Ok(Some(Command::Reload)) => self.reload(),
+            Ok(Some(Command::Profile(top))) => {
+                self.output.push_str(&self.pipeline.profile.report(top, self.srcmap.as_ref().ok()));
+            },
+            Ok(Some(Command::Dump { addr, len, path })) => {
+                self.output.push_str(&dump_memory(&self.mem, addr, len, &path));
+            },
+            Ok(Some(Command::Restore { addr, path })) => {
+                self.output.push_str(&restore_memory(&mut self.mem, addr, &path));
+            },
+            Ok(Some(Command::Fill { addr, len, unit, values })) => {
+                self.output.push_str(&fill_memory(&mut self.mem, addr, len, unit, &values));
+            },
+            Ok(Some(Command::Save(path))) => {
+                self.output.push_str(&save_checkpoint(&self.pipeline, &self.mem, &path));
+            },
+            Ok(Some(Command::Load(path))) => self.load_checkpoint(&path),
+            Ok(Some(Command::Help)) => self.output.push_str(HELP),
+            Ok(None) => {}
+            Err(e) => self.output.push_str(&format!("{}\n", e)),
         }
 
+        self.finish_command();
+    }
+
+    fn finish_command(&mut self) {
         // 检测寄存器变化并更新显示
         self.detect_register_changes();
-        self.update_register_display();
         self.command_input.clear();
     }
 
     // 检测哪些寄存器发生了变化
     fn detect_register_changes(&mut self) {
         for i in 0..32 {
-            if self.pipeline.cpu.reg[i] != self.last_registers[i] {
+            // 先记录变化再更新 last_registers，面板据此高亮
+            self.changed_registers[i] = self.pipeline.cpu.reg[i] != self.last_registers[i];
+            if self.changed_registers[i] {
                 self.output.push_str(&format!("Register x{} changed: 0x{:x} -> 0x{:x}\n", 
                     i, self.last_registers[i], self.pipeline.cpu.reg[i]));
                 self.last_registers[i] = self.pipeline.cpu.reg[i];
@@ -215,40 +725,1263 @@ impl GuiApp {
         }
     }
 
-    // 更新寄存器显示的方法
-    fn update_register_display(&mut self) {
-        self.register_display.clear();
-        
-        // 添加通用寄存器
-        self.register_display.push_str("General Registers:\n");
-        for i in 0..32 {
-            let value = self.pipeline.cpu.reg[i];
-            let changed = if value != self.last_registers[i] { "*" } else { " " };
-            self.register_display.push_str(&format!("x{:02}{}: 0x{:016x}\n", i, changed, value));
+    // 寄存器面板：双击数值进行修改，上一步修改过的寄存器高亮
+    fn register_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.reg_format, RegFormat::Hex, "Hex");
+            ui.radio_value(&mut self.reg_format, RegFormat::Signed, "Signed");
+            ui.radio_value(&mut self.reg_format, RegFormat::Unsigned, "Unsigned");
+            ui.separator();
+            ui.checkbox(&mut self.reg_nonzero_only, "Nonzero only");
+        });
+        // 目前只模拟 RV64IM，没有浮点寄存器和 CSR
+        egui::CollapsingHeader::new("Integer registers").default_open(true).show(ui, |ui| {
+            let visible: Vec<usize> = (0..32)
+                .filter(|&i| !self.reg_nonzero_only || self.pipeline.cpu.reg[i] != 0)
+                .collect();
+            egui::Grid::new("registers").striped(true).show(ui, |ui| {
+                for (n, &i) in visible.iter().enumerate() {
+                    ui.monospace(format!("x{:02} {:>4}", i, REG_NAMES[i]));
+                    self.register_value(ui, i);
+                    if n % 2 == 1 {
+                        ui.end_row();
+                    }
+                }
+            });
+        });
+    }
+
+    fn register_value(&mut self, ui: &mut egui::Ui, idx: usize) {
+        let value = if idx == 32 { self.pipeline.cpu.pc } else { self.pipeline.cpu.reg[idx] };
+
+        if let Some((edit_idx, text)) = &mut self.reg_edit {
+            if *edit_idx == idx {
+                let response = ui.add(
+                    egui::TextEdit::singleline(text)
+                        .font(egui::TextStyle::Monospace)
+                        .desired_width(150.0)
+                );
+                response.request_focus();
+                if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    self.reg_edit = None;
+                } else if response.lost_focus() {
+                    let text = text.clone();
+                    self.reg_edit = None;
+                    self.write_register(idx, &text);
+                }
+                return;
+            }
+        }
+
+        let text = match self.reg_format {
+            _ if idx == 32 => format!("0x{:016x}", value),
+            RegFormat::Hex => format!("0x{:016x}", value),
+            RegFormat::Signed => format!("{:>20}", value as i64),
+            RegFormat::Unsigned => format!("{:>20}", value),
+        };
+        let mut text = egui::RichText::new(text).monospace();
+        if idx < 32 && self.changed_registers[idx] {
+            text = text.color(egui::Color32::YELLOW);
+        }
+        let response = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+        if response.double_clicked() {
+            self.reg_edit = Some((idx, format!("0x{:x}", value)));
+        }
+    }
+
+    fn write_register(&mut self, idx: usize, text: &str) {
+        // 支持十六进制 (0x..) 与有符号十进制
+        let value = match parse_sized_value(text, 8) {
+            Ok(value) => value,
+            Err(e) => {
+                self.output = format!("{}\n", e);
+                return;
+            }
+        };
+
+        match idx {
+            0 => self.output = String::from("x0 is hardwired to zero\n"),
+            32 => {
+                self.pipeline.redirect(value);
+                self.output = format!("PC set to 0x{:x}\n", value);
+            }
+            _ => {
+                self.pipeline.cpu.reg[idx] = value;
+                self.last_registers[idx] = value;
+                self.output = format!("x{} ({}) set to 0x{:x}\n", idx, REG_NAMES[idx], value);
+            }
+        }
+    }
+
+    // 流水线时空图：每行一条指令，每列一个周期
+    fn timeline_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Cycles:");
+            ui.add(egui::DragValue::new(&mut self.timeline_cycles).clamp_range(1..=512));
+            ui.separator();
+            ui.text_edit_singleline(&mut self.export_path);
+            if ui.button("Export CSV").clicked() {
+                self.output = export_timeline(&self.pipeline.timeline, false, &self.export_path);
+            }
+            if ui.button("Export HTML").clicked() {
+                self.output = export_timeline(&self.pipeline.timeline, true, &self.export_path);
+            }
+        });
+        ui.separator();
+
+        let timeline = &self.pipeline.timeline;
+        let Some((first, last)) = timeline.cycle_range(self.timeline_cycles) else {
+            ui.label("No cycles recorded yet");
+            return;
+        };
+        if let Some(rec) = timeline.last() {
+            stage_boxes(ui, rec);
+            ui.separator();
+        }
+        let rows = timeline.gantt(self.timeline_cycles);
+        let bubbles = timeline.bubbles(self.timeline_cycles);
+
+        egui::ScrollArea::both().stick_to_right(true).show(ui, |ui| {
+            egui::Grid::new("pipeline_diagram")
+                .striped(true)
+                .min_col_width(28.0)
+                .show(ui, |ui| {
+                    ui.monospace("PC");
+                    ui.monospace("INST");
+                    for cycle in first..=last {
+                        ui.monospace(format!("{}", cycle));
+                    }
+                    ui.end_row();
+
+                    for row in &rows {
+                        ui.monospace(format!("0x{:08x}", row.pc));
+                        ui.monospace(disassemble(row.pc, row.inst)).on_hover_text(format!("0x{:08x}", row.inst));
+                        for cycle in first..=last {
+                            match row.cells.get(&cycle) {
+                                Some(label) => {
+                                    ui.label(egui::RichText::new(label).monospace().color(stage_color(label)));
+                                }
+                                None => {
+                                    ui.label("");
+                                }
+                            }
+                        }
+                        ui.end_row();
+                    }
+
+                    // 每周期处于气泡的级数，悬停显示具体是哪几级
+                    ui.monospace("");
+                    ui.monospace("bubbles");
+                    for cycle in first..=last {
+                        match bubbles.get(&cycle) {
+                            Some(stages) if !stages.is_empty() => {
+                                ui.label(egui::RichText::new(format!("○{}", stages.len())).monospace().weak())
+                                    .on_hover_text(stages.join(" "));
+                            }
+                            _ => {
+                                ui.label("");
+                            }
+                        }
+                    }
+                    ui.end_row();
+                });
+        });
+    }
+}
+
+// 当前周期的五级流水线：每级一个方框，显示反汇编后的指令；气泡为空心灰框，冲刷的指令加删除线
+fn stage_boxes(ui: &mut egui::Ui, rec: &CycleRecord) {
+    ui.horizontal(|ui| {
+        ui.label(format!("Cycle {}", rec.cycle));
+        for (stage, slot) in rec.stages.iter().enumerate() {
+            let name = STAGE_NAMES[stage];
+            let front = stage <= 1;
+            let label = if slot.seq != 0 && front && rec.flush {
+                format!("{}x", name)
+            } else if slot.seq != 0 && front && rec.stall {
+                format!("{}*", name)
+            } else {
+                name.to_string()
+            };
+            let fill = if slot.seq == 0 { egui::Color32::TRANSPARENT } else { stage_color(&label) };
+            let stroke = if slot.seq == 0 {
+                egui::Stroke::new(1.0, egui::Color32::GRAY)
+            } else {
+                egui::Stroke::new(1.0, fill)
+            };
+            egui::Frame::none().fill(fill).stroke(stroke).rounding(4.0).inner_margin(6.0).show(ui, |ui| {
+                ui.set_min_width(150.0);
+                ui.vertical(|ui| {
+                    let text_color = if front && rec.flush { egui::Color32::WHITE } else { egui::Color32::BLACK };
+                    if slot.seq == 0 {
+                        ui.label(egui::RichText::new(label).monospace().strong());
+                        ui.label(egui::RichText::new("bubble").monospace().italics().weak());
+                        return;
+                    }
+                    ui.label(egui::RichText::new(label).monospace().strong().color(text_color));
+                    let mut text = egui::RichText::new(disassemble(slot.pc, slot.inst)).monospace().color(text_color);
+                    if front && rec.flush {
+                        text = text.strikethrough();
+                    }
+                    ui.label(text).on_hover_text(format!("0x{:08x}: 0x{:08x}", slot.pc, slot.inst));
+                });
+            });
+        }
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RegFormat {
+    Hex,
+    Signed,
+    Unsigned,
+}
+
+// 内存十六进制编辑器：每行 16 字节，可按字节或字显示
+const MEM_ROW_BYTES: u64 = 16;
+const MEM_ROWS: u64 = 32;
+const MEM_MAX_RANGE_ROWS: u64 = 4096;
+const DISASM_ROWS: u64 = 64;
+// 全局数据（代码段之后）和初始栈顶的符号：先找 linker.ld 和内置汇编器的，再找常见工具链的
+const DATA_SYMBOLS: [&str; 3] = ["_etext", "__DATA_BEGIN__", "__data_start"];
+const STACK_TOP_SYMBOLS: [&str; 2] = ["_stack_pointer", "__stack_top"];
+
+impl GuiApp {
+    fn memory_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Address/symbol:");
+            let response = ui.text_edit_singleline(&mut self.mem_addr_input);
+            if ui.button("Go").clicked() || response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                match self.resolve_location(&self.mem_addr_input) {
+                    Ok(addr) => self.mem_base = addr & !(MEM_ROW_BYTES - 1),
+                    Err(e) => self.output = format!("{}\n", e),
+                }
+            }
+            ui.separator();
+            ui.radio_value(&mut self.mem_unit, 1, "Byte");
+            ui.radio_value(&mut self.mem_unit, 4, "Word");
+            ui.radio_value(&mut self.mem_unit, 8, "Dword");
+        });
+        // 快速跳转：PC、SP、全局数据段起点和栈顶，镜像中没有对应符号时按钮不可用
+        ui.horizontal(|ui| {
+            ui.label("Jump to:");
+            let presets = [
+                ("PC", Some(self.pipeline.cpu.pc)),
+                ("SP", Some(self.pipeline.cpu.reg[2])),
+                ("Globals", self.find_symbol(&DATA_SYMBOLS)),
+                ("Stack top", self.find_symbol(&STACK_TOP_SYMBOLS)),
+            ];
+            for (name, addr) in presets {
+                let button = ui.add_enabled(addr.is_some(), egui::Button::new(name));
+                if let Some(addr) = addr {
+                    // 栈顶之上没有内容，显示它下面的一页
+                    let base = if name == "Stack top" { addr.saturating_sub(MEM_ROW_BYTES * MEM_ROWS) } else { addr };
+                    if button.on_hover_text(format!("0x{:08x}", addr)).clicked() {
+                        self.mem_base = base & !(MEM_ROW_BYTES - 1);
+                        self.mem_range = None;
+                    }
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("<< Prev").clicked() {
+                self.mem_base = self.mem_base.wrapping_sub(MEM_ROW_BYTES * MEM_ROWS);
+            }
+            if ui.button("Next >>").clicked() {
+                self.mem_base = self.mem_base.wrapping_add(MEM_ROW_BYTES * MEM_ROWS);
+            }
+            ui.label("Double-click a cell to edit (0x hex or decimal), Enter to write back");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Range:");
+            let response = ui.add(egui::TextEdit::singleline(&mut self.mem_range_input)
+                .hint_text("lo-hi or symbol+len")
+                .desired_width(180.0));
+            if ui.button("Apply").clicked() || response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                match self.parse_mem_range(&self.mem_range_input) {
+                    Ok((lo, hi)) => {
+                        self.mem_range = Some((lo, hi));
+                        self.mem_base = lo & !(MEM_ROW_BYTES - 1);
+                    }
+                    Err(e) => self.output = format!("{}\n", e),
+                }
+            }
+            if self.mem_range.is_some() && ui.button("Clear").clicked() {
+                self.mem_range = None;
+            }
+            ui.checkbox(&mut self.mem_hide_zero, "Hide zero rows");
+        });
+        // 导出选中区间，或把文件写回区间起点（未选区间时为当前页起点）
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.mem_dump_path).desired_width(180.0));
+            let dump = ui.add_enabled(self.mem_range.is_some(), egui::Button::new("Dump range"));
+            if dump.clicked() {
+                if let Some((lo, hi)) = self.mem_range {
+                    self.output = dump_memory(&self.mem, lo, (hi - lo) as usize, &self.mem_dump_path);
+                }
+            }
+            if ui.button("Restore").clicked() {
+                let addr = self.mem_range.map_or(self.mem_base, |(lo, _)| lo);
+                self.output = restore_memory(&mut self.mem, addr, &self.mem_dump_path);
+            }
+        });
+        // 用一组值循环填充选中区间，按当前视图的单位（字节、字或双字）
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.mem_fill_values)
+                .hint_text("values, e.g. 0 or 1 2 3")
+                .desired_width(180.0));
+            let fill = ui.add_enabled(self.mem_range.is_some(), egui::Button::new("Fill range"));
+            if fill.clicked() {
+                if let Some((lo, hi)) = self.mem_range {
+                    let suffix = match self.mem_unit { 8 => 'g', 4 => 'w', _ => 'b' };
+                    let line = format!("fill/{} 0x{:x} {} {}", suffix, lo, hi - lo, self.mem_fill_values);
+                    self.output = match parse_command(&line) {
+                        Ok(Some(Command::Fill { addr, len, unit, values })) => fill_memory(&mut self.mem, addr, len, unit, &values),
+                        Ok(_) => String::new(),
+                        Err(e) => format!("{}\n", e),
+                    };
+                }
+            }
+        });
+        ui.separator();
+
+        self.watch_section(ui);
+        ui.separator();
+
+        // 每次单步后内存面板都会重新读取，周期变化时把上次的值留作对比
+        if self.mem_seen_cycle != self.pipeline.cpu.cycle_count {
+            self.mem_prev = std::mem::take(&mut self.mem_seen);
+            self.mem_seen_cycle = self.pipeline.cpu.cycle_count;
+        }
+        let width = self.mem_unit;
+        // 指定区间时显示整个区间（有上限），否则显示 mem_base 起的一页
+        let rows = match self.mem_range {
+            Some((lo, hi)) => (hi.saturating_sub(lo & !(MEM_ROW_BYTES - 1))).div_ceil(MEM_ROW_BYTES).min(MEM_MAX_RANGE_ROWS),
+            None => MEM_ROWS,
+        };
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("memory_hex").striped(true).show(ui, |ui| {
+                for row in 0..rows {
+                    let row_addr = self.mem_base.wrapping_add(row * MEM_ROW_BYTES);
+                    if self.mem_hide_zero && self.mem.mem_read(row_addr, 8).ok() == Some(0)
+                        && self.mem.mem_read(row_addr.wrapping_add(8), 8).ok() == Some(0) {
+                        continue;
+                    }
+                    ui.monospace(format!("0x{:08x}", row_addr));
+                    let mut ascii = String::new();
+                    for col in (0..MEM_ROW_BYTES).step_by(width) {
+                        self.memory_cell(ui, row_addr.wrapping_add(col), width);
+                    }
+                    for col in 0..MEM_ROW_BYTES {
+                        ascii.push(match self.mem.mem_read(row_addr.wrapping_add(col), 1) {
+                            Ok(b) if (0x20..0x7f).contains(&b) => b as u8 as char,
+                            Ok(_) => '.',
+                            Err(_) => '?',
+                        });
+                    }
+                    ui.monospace(ascii);
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    // 反汇编视图：地址栏接受十六进制地址、符号或寄存器名，点击一行设置/取消断点；
+    // 每条指令旁标出它当前所在的流水级，颜色与流水线图相同，箭头指向取指 PC
+    fn disasm_panel(&mut self, ui: &mut egui::Ui) {
+        let window = DISASM_ROWS * 4;
+        ui.horizontal(|ui| {
+            ui.label("Address/symbol:");
+            let response = ui.text_edit_singleline(&mut self.disasm_addr_input);
+            if ui.button("Go").clicked() || response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                match self.resolve_location(&self.disasm_addr_input) {
+                    Ok(addr) => {
+                        self.disasm_base = addr & !3;
+                        self.disasm_follow = false;
+                    }
+                    Err(e) => self.output = format!("{}\n", e),
+                }
+            }
+            if ui.button("Go to PC").clicked() {
+                self.disasm_base = self.pipeline.cpu.pc & !3;
+                self.disasm_follow = true;
+            }
+            ui.checkbox(&mut self.disasm_follow, "Follow PC");
+            // 前后翻半屏，翻页后不再跟随 PC
+            if ui.button("▲").on_hover_text("Earlier instructions").clicked() {
+                self.disasm_base = self.disasm_base.saturating_sub(window / 2);
+                self.disasm_follow = false;
+            }
+            if ui.button("▼").on_hover_text("Later instructions").clicked() {
+                self.disasm_base = self.disasm_base.wrapping_add(window / 2);
+                self.disasm_follow = false;
+            }
+        });
+        ui.separator();
+
+        let pc = self.pipeline.cpu.pc;
+        let stages = self.pipeline.stage_pcs();
+        if self.disasm_follow && !(self.disasm_base..self.disasm_base.wrapping_add(window)).contains(&pc) {
+            self.disasm_base = pc.saturating_sub(window / 4) & !3;
+        }
+        let scroll_to_pc = self.disasm_follow && self.disasm_scrolled_pc != Some(pc);
+        self.disasm_scrolled_pc = Some(pc);
+
+        let mut toggle = None;
+        let mut run_to_cursor = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("disasm").striped(true).show(ui, |ui| {
+                for line in disassemble_range(&self.mem, self.disasm_base, DISASM_ROWS as usize) {
+                    let addr = line.addr;
+                    let has_bp = self.pipeline.breakpoints.contains(addr);
+                    let marker = format!("{}{}", if has_bp { "●" } else { " " }, if addr == pc { "→" } else { " " });
+                    let gutter = egui::RichText::new(format!("{} 0x{:08x}", marker, addr))
+                        .monospace()
+                        .color(if has_bp { egui::Color32::RED } else { egui::Color32::GRAY });
+                    let gutter = ui.add(egui::Label::new(gutter).sense(egui::Sense::click()));
+                    if gutter.clicked() {
+                        toggle = Some(addr);
+                    }
+                    if scroll_to_pc && addr == pc {
+                        gutter.scroll_to_me(Some(egui::Align::Center));
+                    }
+                    let label = match &self.srcmap {
+                        Ok(map) => map.symbol_at(addr).filter(|(_, off)| *off == 0).map(|(name, _)| format!("<{}>", name)),
+                        Err(_) => None,
+                    };
+                    ui.monospace(label.unwrap_or_default());
+                    ui.monospace(line.raw_hex());
+                    // 最靠后的流水级决定颜色，同一地址可能同时在多个流水级（短循环）
+                    let held: Vec<_> = (0..5).filter(|&i| stages[i] == Some(addr)).map(|i| STAGE_NAMES[i]).collect();
+                    let mut text = egui::RichText::new(symbolic_disasm(&line, &self.srcmap)).monospace();
+                    if let Some(stage) = held.last() {
+                        text = text.color(egui::Color32::BLACK).background_color(stage_color(stage));
+                    }
+                    if addr == pc {
+                        text = text.strong();
+                    }
+                    let response = ui.add(egui::Label::new(text).sense(egui::Sense::click()))
+                        .on_hover_text("Click to toggle a breakpoint, right-click to run to this line");
+                    if response.clicked() {
+                        toggle = Some(addr);
+                    }
+                    response.context_menu(|ui| {
+                        if ui.button("Run to cursor").clicked() {
+                            run_to_cursor = Some(addr);
+                            ui.close_menu();
+                        }
+                    });
+                    ui.monospace(held.join(" "));
+                    ui.end_row();
+                }
+            });
+        });
+
+        if let Some(addr) = toggle {
+            self.pipeline.breakpoints.toggle(addr);
+        }
+        if let Some(addr) = run_to_cursor {
+            self.run_until(addr);
+        }
+    }
+
+    // "lo-hi" 或 "start+len"，两端都可以是地址、符号或寄存器
+    fn parse_mem_range(&self, text: &str) -> Result<(u64, u64), String> {
+        if let Some((lo, hi)) = text.split_once('-') {
+            let (lo, hi) = (self.resolve_location(lo)?, self.resolve_location(hi)?);
+            if hi <= lo {
+                return Err(format!("Empty range: 0x{:x}-0x{:x}", lo, hi));
+            }
+            Ok((lo, hi))
+        } else if let Some((start, len)) = text.split_once('+') {
+            let start = self.resolve_location(start)?;
+            let len = len.trim();
+            let len = match len.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => len.parse::<u64>(),
+            }.map_err(|_| format!("Invalid length: {}", len))?;
+            Ok((start, start.wrapping_add(len.max(1))))
+        } else {
+            Err(String::from("Expected a range like 0x80000000-0x80000100 or sp+64"))
+        }
+    }
+
+    // 固定观察的内存区间，每个周期自动刷新，变化的值高亮
+    fn watch_section(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Pinned ranges").default_open(true).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Addr/symbol/reg:");
+                ui.add(egui::TextEdit::singleline(&mut self.watch_expr).desired_width(120.0));
+                ui.label("Words:");
+                ui.add(egui::DragValue::new(&mut self.watch_words).clamp_range(1..=64));
+                if ui.button("Pin").clicked() {
+                    match self.resolve_location(&self.watch_expr) {
+                        Ok(_) => {
+                            self.mem_watches.push(MemWatch::new(self.watch_expr.trim(), self.watch_words));
+                            self.watch_expr.clear();
+                        }
+                        Err(e) => self.output = format!("{}\n", e),
+                    }
+                }
+            });
+
+            let cycle = self.pipeline.cpu.cycle_count;
+            let mut unpin = None;
+            for (i, watch) in self.mem_watches.iter_mut().enumerate() {
+                // 表达式可能依赖寄存器（如 sp），每次刷新时重新求值
+                let base = resolve_location(&watch.expr, &self.pipeline, &self.srcmap).ok();
+                watch.refresh(cycle, base, &self.mem);
+
+                ui.horizontal(|ui| {
+                    if ui.small_button("x").clicked() {
+                        unpin = Some(i);
+                    }
+                    match base {
+                        Some(base) => ui.monospace(format!("{} @ 0x{:08x}:", watch.expr, base)),
+                        None => ui.monospace(format!("{} @ ?:", watch.expr)),
+                    };
+                    for (j, value) in watch.cur.iter().enumerate() {
+                        let text = match value {
+                            Some(v) => egui::RichText::new(format!("{:08x}", v)).monospace(),
+                            None => egui::RichText::new("????????").monospace().color(egui::Color32::RED),
+                        };
+                        let changed = watch.prev.get(j).is_some_and(|p| p != value);
+                        ui.label(if changed { text.color(egui::Color32::YELLOW) } else { text });
+                    }
+                });
+            }
+            if let Some(i) = unpin {
+                self.mem_watches.remove(i);
+            }
+        });
+    }
+
+    // 第一个在符号表中存在的名字的地址
+    fn find_symbol(&self, names: &[&str]) -> Option<u64> {
+        let map = self.srcmap.as_ref().ok()?;
+        names.iter().find_map(|name| map.symbol(name))
+    }
+
+    fn resolve_location(&self, expr: &str) -> Result<u64, String> {
+        resolve_location(expr, &self.pipeline, &self.srcmap)
+    }
+
+    fn memory_cell(&mut self, ui: &mut egui::Ui, addr: u64, width: usize) {
+        let digits = width * 2;
+        let value = match self.mem.mem_read(addr, width) {
+            Ok(v) => v,
+            Err(_) => {
+                // 越界地址显式标出，不可编辑
+                ui.label(egui::RichText::new("?".repeat(digits)).monospace().color(egui::Color32::RED))
+                    .on_hover_text(format!("Invalid address: 0x{:x}", addr));
+                return;
+            }
+        };
+
+        if let Some((edit_addr, text)) = &mut self.mem_edit {
+            if *edit_addr == addr {
+                let response = ui.add(
+                    egui::TextEdit::singleline(text)
+                        .font(egui::TextStyle::Monospace)
+                        .desired_width(digits as f32 * 8.0)
+                );
+                response.request_focus();
+                if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    self.mem_edit = None;
+                } else if response.lost_focus() {
+                    let text = text.clone();
+                    self.mem_edit = None;
+                    self.write_memory_cell(addr, width, &text);
+                }
+                return;
+            }
+        }
+
+        let mut text = egui::RichText::new(format!("{:0width$x}", value, width = digits)).monospace();
+        if self.mem_highlight.is_some_and(|(lo, hi)| (lo..hi).contains(&addr)) {
+            text = text.background_color(egui::Color32::from_rgb(0x20, 0x40, 0x60));
+        }
+        // 自上次刷新以来变化的单元
+        self.mem_seen.insert((addr, width), value);
+        if self.mem_prev.get(&(addr, width)).is_some_and(|&prev| prev != value) {
+            text = text.color(egui::Color32::YELLOW);
+        }
+        let response = ui.add(egui::Label::new(text).sense(egui::Sense::click()))
+            .on_hover_text(format!("0x{:08x} = {}", addr, value));
+        if response.double_clicked() {
+            self.mem_edit = Some((addr, format!("0x{:0width$x}", value, width = digits)));
+        }
+    }
+
+    // 十六进制 (0x..) 或有符号十进制，负数按补码写入
+    fn write_memory_cell(&mut self, addr: u64, width: usize, text: &str) {
+        match parse_sized_value(text, width) {
+            Ok(v) => match self.mem.mem_write(addr, width, v) {
+                Ok(_) => self.output = format!("Wrote 0x{:x} to 0x{:08x}\n", v, addr),
+                Err(e) => self.output = format!("{}\n", e),
+            },
+            Err(e) => self.output = format!("{}\n", e),
+        }
+    }
+}
+
+// 不限速时每帧最多执行的周期数，保证界面仍能响应
+const UNLIMITED_STEPS_PER_FRAME: u32 = 100000;
+
+impl GuiApp {
+    fn free_run(&mut self, dt: f64) {
+        let steps = if self.run_unlimited {
+            UNLIMITED_STEPS_PER_FRAME
+        } else {
+            self.run_budget += self.run_hz * dt;
+            let steps = self.run_budget.floor();
+            self.run_budget -= steps;
+            steps.min(UNLIMITED_STEPS_PER_FRAME as f64) as u32
+        };
+
+        for _ in 0..steps {
+            if !self.pipeline.cpu.running { break; }
+            self.pipeline.step(&mut self.mem);
+            self.step_counter += 1;
+
+            if self.hit_breakpoint() {
+                self.debug_mode = true;
+                break;
+            }
+            if self.run_goal.as_mut().is_some_and(|g| g.reached(&self.pipeline)) {
+                let pc = self.pipeline.W_reg.pc;
+                self.output.push_str(&format!("Stopped at 0x{:08x} ({})\n", pc, symbolize(&self.srcmap, pc)));
+                self.debug_mode = true;
+                break;
+            }
+        }
+        if !self.pipeline.cpu.running {
+            self.debug_mode = true;
+        }
+        if self.debug_mode {
+            self.run_goal = None;
+        }
+        self.detect_register_changes();
+    }
+
+    // 断点在指令提交（进入 WB）时由 Pipeline::step 检查，这里只负责报告
+    fn hit_breakpoint(&mut self) -> bool {
+        let Some(hit) = self.pipeline.breakpoint_hit.take() else {
+            return false;
+        };
+        let pc = hit.pc();
+        match self.srcmap.as_ref().ok().and_then(|m| m.line_of(pc)) {
+            Some(line) => self.output.push_str(&format!("{} (line {})\n", hit, line)),
+            None => self.output.push_str(&format!("{} ({})\n", hit, symbolize(&self.srcmap, pc))),
+        }
+        true
+    }
+
+    // 源码视图：高亮当前提交指令对应的行，点击行号设置断点
+    fn source_panel(&mut self, ui: &mut egui::Ui) {
+        let map = match &self.srcmap {
+            Ok(map) => map,
+            Err(e) => {
+                ui.label("No line map available for this image");
+                ui.label(e);
+                return;
+            }
+        };
+
+        if map.lines.is_empty() {
+            ui.label("This image only has symbols, no source line map");
+            return;
+        }
+
+        let w = &self.pipeline.W_reg;
+        let current = if w.seq != 0 { map.line_of(w.pc) } else { None };
+        ui.label(&map.source_path);
+        ui.separator();
+
+        // 仅在当前行变化时滚动，避免妨碍手动浏览
+        let follow = current.is_some() && current != self.last_source_line;
+        self.last_source_line = current;
+
+        let mut toggle = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (i, text) in map.lines.iter().enumerate() {
+                let line = i + 1;
+                let addr = map.addr_of(line);
+                let has_bp = addr.is_some_and(|a| self.pipeline.breakpoints.contains(a));
+                ui.horizontal(|ui| {
+                    let marker = if has_bp { "●" } else { " " };
+                    let gutter = egui::RichText::new(format!("{} {:4}", marker, line))
+                        .monospace()
+                        .color(egui::Color32::RED);
+                    let response = ui.add(egui::Label::new(gutter).sense(egui::Sense::click()));
+                    if response.clicked() {
+                        toggle = addr;
+                    }
+
+                    let mut code = egui::RichText::new(text).monospace();
+                    if Some(line) == current {
+                        code = code.background_color(egui::Color32::from_rgb(0x50, 0x50, 0x00));
+                    }
+                    let response = ui.label(code);
+                    if follow && Some(line) == current {
+                        response.scroll_to_me(Some(egui::Align::Center));
+                    }
+                });
+            }
+        });
+
+        if let Some(addr) = toggle {
+            self.pipeline.breakpoints.toggle(addr);
+        }
+    }
+}
+
+impl GuiApp {
+    // 性能曲线：每 N 个周期采样一次，事件数换算成每千周期的次数；
+    // 可以把当前曲线固定为基线，改变 Cache 等配置后重新运行，以灰色叠加对比
+    fn perf_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Sample every");
+            ui.add(egui::DragValue::new(&mut self.pipeline.perf.interval).clamp_range(1..=100000));
+            ui.label("cycles");
+            ui.separator();
+            let samples: Vec<_> = self.pipeline.perf.samples().copied().collect();
+            if ui.add_enabled(!samples.is_empty(), egui::Button::new("Pin as baseline")).clicked() {
+                let caches = if self.pipeline.dcache.is_some() { "caches on" } else { "caches off" };
+                let note = format!("{} cycles, {}, {:?} predictor", self.pipeline.cpu.cycle_count, caches, self.config.predictor.kind);
+                self.perf_baseline = Some((note, samples));
+            }
+            if self.perf_baseline.is_some() && ui.button("Clear baseline").clicked() {
+                self.perf_baseline = None;
+            }
+        });
+        if let Some((note, _)) = &self.perf_baseline {
+            ui.label(egui::RichText::new(format!("Baseline (gray): {}", note)).weak());
+        }
+        let samples: Vec<_> = self.pipeline.perf.samples().copied().collect();
+        if samples.is_empty() {
+            ui.label("No samples yet");
+            return;
+        }
+        let last = samples[samples.len() - 1];
+        ui.label(format!("cycle {}  IPC {:.3}  mispredict rate {:.1}%", last.cycle, last.ipc, last.mispredict_rate * 100.0));
+        ui.separator();
+
+        let baseline = self.perf_baseline.as_ref().map(|(_, s)| s.as_slice()).unwrap_or_default();
+        let series = |samples: &[PerfSample], f: &dyn Fn(&PerfSample) -> f32| samples.iter().map(f).collect::<Vec<f32>>();
+        let base_color = egui::Color32::from_gray(0x70);
+
+        let ipc = series(&samples, &|s| s.ipc);
+        let base_ipc = series(baseline, &|s| s.ipc);
+        line_chart(ui, "IPC", &[(&base_ipc, base_color), (&ipc, egui::Color32::LIGHT_GREEN)], 1.0);
+
+        let mispredicts = series(&samples, &|s| s.per_kilo_cycles(s.mispredicts));
+        let base_mispredicts = series(baseline, &|s| s.per_kilo_cycles(s.mispredicts));
+        let max = mispredicts.iter().chain(&base_mispredicts).cloned().fold(1.0, f32::max);
+        line_chart(ui, "Branch mispredicts per 1k cycles",
+            &[(&base_mispredicts, base_color), (&mispredicts, egui::Color32::LIGHT_BLUE)], max);
+
+        let load_use = series(&samples, &|s| s.per_kilo_cycles(s.load_use_stalls));
+        let flush = series(&samples, &|s| s.per_kilo_cycles(s.flush_cycles));
+        let memory = series(&samples, &|s| s.per_kilo_cycles(s.cache_stall_cycles));
+        let base_stalls = series(baseline, &|s| s.per_kilo_cycles(s.load_use_stalls + s.flush_cycles + s.cache_stall_cycles));
+        let max = load_use.iter().chain(&flush).chain(&memory).chain(&base_stalls).cloned().fold(1.0, f32::max);
+        line_chart(ui, "Stall cycles per 1k cycles: load-use (yellow) / branch flush (red) / memory (blue)",
+            &[(&base_stalls, base_color), (&load_use, egui::Color32::YELLOW), (&flush, egui::Color32::LIGHT_RED),
+                (&memory, egui::Color32::LIGHT_BLUE)], max);
+
+        let rate = series(&samples, &|s| s.mispredict_rate);
+        line_chart(ui, "Branch misprediction rate", &[(&rate, egui::Color32::LIGHT_BLUE)], 1.0);
+
+        ui.separator();
+        let mix = &self.pipeline.inst_mix;
+        ui.label(format!("Instruction mix ({} retired)", mix.total()));
+        egui::Grid::new("inst_mix").num_columns(2).show(ui, |ui| {
+            for class in InstClass::ALL {
+                ui.label(class.name());
+                ui.add(egui::ProgressBar::new(mix.fraction(class) as f32)
+                    .desired_width(200.0)
+                    .text(format!("{} ({:.1}%)", mix.count(class), mix.fraction(class) * 100.0)));
+                ui.end_row();
+            }
+        });
+    }
+}
+
+impl GuiApp {
+    // 缓存状态：每组每路的有效位、脏位与标签，最近的命中/缺失着色
+    fn cache_panel(&mut self, ui: &mut egui::Ui) {
+        let mut enabled = self.pipeline.dcache.is_some();
+        if ui.checkbox(&mut enabled, "Enable cache models").changed() {
+            self.pipeline.enable_caches(enabled);
+        }
+        ui.label("green = recent hit, red = recent miss, V/D = valid/dirty");
+        ui.label(format!("Miss penalty stall cycles: {}", self.pipeline.cache_stall_cycles));
+        ui.separator();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for cache in [&self.pipeline.icache, &self.pipeline.dcache].into_iter().flatten() {
+                cache_grid(ui, cache);
+                ui.separator();
+            }
+        });
+    }
+}
+
+impl GuiApp {
+    // 断点与观察点列表：添加断点（地址可以是符号或寄存器，条件如 a0 == 5）、逐个删除或全部删除；
+    // 观察点用 watch 命令添加
+    fn breakpoints_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Address:");
+            ui.add(egui::TextEdit::singleline(&mut self.bp_addr_input).desired_width(120.0));
+            ui.label("if");
+            ui.add(egui::TextEdit::singleline(&mut self.bp_cond_input).desired_width(120.0).hint_text("a0 == 5"));
+            ui.checkbox(&mut self.bp_temporary, "Temporary");
+            if ui.button("Add").clicked() {
+                let addr = resolve_location(&self.bp_addr_input, &self.pipeline, &self.srcmap);
+                let condition = match self.bp_cond_input.trim() {
+                    "" => Ok(None),
+                    text => Condition::parse(text).map(Some),
+                };
+                match (addr, condition) {
+                    (Ok(addr), Ok(condition)) => {
+                        self.output = set_breakpoint(&mut self.pipeline.breakpoints, addr, self.bp_temporary, condition);
+                    }
+                    (Err(e), _) | (_, Err(e)) => self.output = format!("{}\n", e),
+                }
+            }
+            if ui.button("Delete all").clicked() {
+                self.output = delete_breakpoints(&mut self.pipeline.breakpoints, None);
+            }
+        });
+        ui.separator();
+
+        let mut delete = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("breakpoints").striped(true).show(ui, |ui| {
+                for title in ["#", "address", "where", "hits", "condition", ""] {
+                    ui.monospace(title);
+                }
+                ui.end_row();
+                for b in self.pipeline.breakpoints.iter() {
+                    ui.monospace(format!("{}{}", b.id, if b.temporary { " (temp)" } else { "" }));
+                    ui.monospace(format!("0x{:08x}", b.addr));
+                    ui.monospace(symbolize(&self.srcmap, b.addr));
+                    ui.monospace(format!("{}", b.hits));
+                    ui.monospace(b.condition.map(|c| c.to_string()).unwrap_or_default());
+                    if ui.button("Delete").clicked() {
+                        delete = Some(b.id);
+                    }
+                    ui.end_row();
+                }
+                for w in self.pipeline.breakpoints.watchpoints() {
+                    ui.monospace(format!("{} (watch)", w.id));
+                    ui.monospace(format!("0x{:08x}", w.addr));
+                    ui.monospace(symbolize(&self.srcmap, w.addr));
+                    ui.monospace(format!("{}", w.hits));
+                    ui.monospace(format!("{} {} bytes", w.kind.name(), w.len));
+                    if ui.button("Delete").clicked() {
+                        delete = Some(w.id);
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+        if self.pipeline.breakpoints.is_empty() {
+            ui.label("(no breakpoints)");
+        }
+
+        if let Some(id) = delete {
+            self.output = delete_breakpoints(&mut self.pipeline.breakpoints, Some(id));
         }
-    
     }
+}
+
+impl GuiApp {
+    // 访存热力图：上条为读，下条为写，颜色按访问次数的对数缩放
+    fn heatmap_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(bounds) = self.pipeline.heatmap.bounds() else {
+            ui.label("No memory accesses yet");
+            return;
+        };
+        let (lo, hi) = self.heatmap_view.unwrap_or(bounds);
+
+        ui.horizontal(|ui| {
+            ui.monospace(format!("[0x{:08x}, 0x{:08x})", lo, hi));
+            let span = hi - lo;
+            if ui.button("Zoom in").clicked() && span > 2 * HEATMAP_GRANULE {
+                self.heatmap_view = Some((lo + span / 4, hi - span / 4));
+            }
+            if ui.button("Zoom out").clicked() {
+                let (min, max) = bounds;
+                let lo = lo.saturating_sub(span / 2).max(min.min(lo));
+                let hi = hi.saturating_add(span / 2).min(max.max(hi));
+                self.heatmap_view = Some((lo, hi));
+            }
+            if ui.button("Fit").clicked() {
+                self.heatmap_view = None;
+            }
+        });
+        ui.label("Hover for counts, click to zoom on a region, right-click to open it in the memory panel");
 
-    fn print_help(&mut self) {
-        self.output.push_str("Available commands:\n");
-        self.output.push_str("  c          - Continue execution\n");
-        self.output.push_str("  q          - Quit the simulator\n");
-        self.output.push_str("  si [N]     - Single step execution (N times, default 1)\n");
-        self.output.push_str("  info r     - Print register state to output\n");
-        self.output.push_str("  x N ADDR   - Examine memory at address ADDR, N words\n");
-        self.output.push_str("              (ADDR format: 0x1234 or 1234)\n");
-        self.output.push_str("  help       - Print this help information\n");
+        let (rect, response) = ui.allocate_exact_size(
+            egui::vec2(ui.available_width(), 60.0), egui::Sense::click());
+        let painter = ui.painter_at(rect);
+        let cols = (rect.width() as usize).clamp(1, 512);
+        let col_bytes = ((hi - lo) / cols as u64).max(1);
+        let col_width = rect.width() / cols as f32;
+
+        let counts: Vec<_> = (0..cols as u64)
+            .map(|i| self.pipeline.heatmap.sum(lo + i * col_bytes, lo + (i + 1) * col_bytes))
+            .collect();
+        let max = counts.iter().map(|c| c.reads.max(c.writes)).max().unwrap_or(1).max(1);
+        let heat = |n: u32| {
+            let t = ((n as f32 + 1.0).ln() / (max as f32 + 1.0).ln() * 255.0) as u8;
+            egui::Color32::from_rgb(t, t / 3, 255 - t)
+        };
+
+        let half = rect.height() / 2.0;
+        for (i, c) in counts.iter().enumerate() {
+            let x = rect.left() + i as f32 * col_width;
+            let read = egui::Rect::from_min_size(egui::pos2(x, rect.top()), egui::vec2(col_width.max(1.0), half));
+            let write = read.translate(egui::vec2(0.0, half));
+            painter.rect_filled(read, 0.0, heat(c.reads));
+            painter.rect_filled(write, 0.0, heat(c.writes));
+        }
+
+        if let Some(pos) = response.hover_pos() {
+            let i = (((pos.x - rect.left()) / col_width) as usize).min(cols - 1);
+            let addr = lo + i as u64 * col_bytes;
+            let c = counts[i];
+            let response = response.clone().on_hover_text(format!(
+                "0x{:08x}..0x{:08x}\nreads {}  writes {}", addr, addr + col_bytes, c.reads, c.writes));
+            let span = (hi - lo) / 8;
+            if response.clicked() && span >= HEATMAP_GRANULE {
+                self.heatmap_view = Some((addr.saturating_sub(span / 2).max(lo), (addr + span / 2).min(hi)));
+            }
+            if response.secondary_clicked() {
+                self.mem_base = addr & !(MEM_ROW_BYTES - 1);
+                self.settings.show(PanelKind::Memory);
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("top: reads  bottom: writes");
+            ui.label(format!("max {} per column", max));
+        });
     }
 }
 
+impl GuiApp {
+    // 调用栈（最内层在上）与聚合调用图
+    fn calls_panel(&mut self, ui: &mut egui::Ui) {
+        let calls = &self.pipeline.calls;
+        let name = |addr: u64| symbolize(&self.srcmap, addr);
 
-fn parse_hex_address(s: &str) -> Result<u64, String> {
-    let s = s.trim_start_matches("0x").trim_start_matches("0X");
-    u64::from_str_radix(s, 16).map_err(|_| format!("Invalid hex address: {}", s))
+        ui.heading("Call stack");
+        egui::Grid::new("call_stack").striped(true).show(ui, |ui| {
+            for title in ["#", "function", "called from", "return", "sp", "fp"] {
+                ui.monospace(title);
+            }
+            ui.end_row();
+            for (depth, f) in calls.stack.iter().rev().enumerate() {
+                ui.monospace(format!("{}", depth));
+                ui.monospace(name(f.func));
+                ui.monospace(name(f.call_pc));
+                ui.monospace(format!("0x{:08x}", f.ret_addr));
+                ui.monospace(format!("0x{:08x}", f.sp));
+                ui.monospace(format!("0x{:08x}", f.fp));
+                ui.end_row();
+            }
+        });
+        if calls.stack.is_empty() {
+            ui.label("(no active calls)");
+        }
+
+        ui.separator();
+        ui.heading("Call graph");
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("call_graph").striped(true).show(ui, |ui| {
+                for title in ["caller", "callee", "count"] {
+                    ui.monospace(title);
+                }
+                ui.end_row();
+                for ((caller, callee), count) in &calls.edges {
+                    ui.monospace(if *caller == 0 { String::from("<entry>") } else { name(*caller) });
+                    ui.monospace(name(*callee));
+                    ui.monospace(format!("{}", count));
+                    ui.end_row();
+                }
+            });
+        });
+    }
 }
 
+// 地址转为 "符号+偏移"，没有符号表时直接显示地址
+impl GuiApp {
+    // 客户程序通过串口输出的内容，与调试命令的输出分开显示
+    fn program_output_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Clear").clicked() {
+                self.mem.uart.tx.clear();
+            }
+            ui.separator();
+            ui.add(egui::TextEdit::singleline(&mut self.guest_output_path).desired_width(150.0));
+            if ui.button("Save").clicked() {
+                self.output = match std::fs::write(&self.guest_output_path, &self.mem.uart.tx) {
+                    Ok(_) => format!("Program output saved to {}\n", self.guest_output_path),
+                    Err(e) => format!("Failed to save {}: {}\n", self.guest_output_path, e),
+                };
+            }
+            ui.label(format!("{} byte(s)", self.mem.uart.tx.len()));
+        });
+        ui.separator();
+
+        let text = String::from_utf8_lossy(&self.mem.uart.tx);
+        egui::ScrollArea::both().stick_to_bottom(true).show(ui, |ui| {
+            ui.monospace(text);
+        });
+    }
+}
+
+fn symbolize(srcmap: &Result<SourceMap, String>, addr: u64) -> String {
+    match srcmap.as_ref().ok().and_then(|m| m.symbol_at(addr)) {
+        Some((name, 0)) => name.to_string(),
+        Some((name, off)) => format!("{}+0x{:x}", name, off),
+        None => format!("0x{:08x}", addr),
+    }
+}
+
+fn cache_grid(ui: &mut egui::Ui, cache: &Cache) {
+    ui.label(format!("{}: {} sets x {} ways, {}B lines, {}, hits {} misses {} ({:.1}% hit), {} writebacks",
+        cache.name, cache.sets, cache.ways, cache.line_size, cache.write_policy.name(),
+        cache.hits, cache.misses, cache.hit_rate() * 100.0, cache.writebacks));
+
+    // 越新的访问颜色越亮
+    let recent: Vec<_> = cache.recent().collect();
+    let highlight = |set: usize, way: usize| {
+        recent.iter().rposition(|a| a.set == set && a.way == Some(way)).map(|i| {
+            let a = recent[i];
+            let fade = 80 + (175 * (i + 1) / recent.len()) as u8;
+            if a.hit { egui::Color32::from_rgb(0, fade, 0) } else { egui::Color32::from_rgb(fade, 0, 0) }
+        })
+    };
+
+    egui::Grid::new(cache.name).striped(true).show(ui, |ui| {
+        ui.monospace("set");
+        for way in 0..cache.ways {
+            ui.monospace(format!("way {}", way));
+        }
+        ui.end_row();
+
+        for set in 0..cache.sets {
+            ui.monospace(format!("{:3}", set));
+            for way in 0..cache.ways {
+                let line = cache.line(set, way);
+                let text = if line.valid {
+                    format!("V{} {:08x}", if line.dirty { "D" } else { " " }, line.tag)
+                } else {
+                    String::from("-- --------")
+                };
+                let mut text = egui::RichText::new(text).monospace();
+                if let Some(color) = highlight(set, way) {
+                    text = text.background_color(color).color(egui::Color32::WHITE);
+                } else if !line.valid {
+                    text = text.color(egui::Color32::DARK_GRAY);
+                }
+                ui.label(text);
+            }
+            ui.end_row();
+        }
+    });
+}
+
+// 用 Painter 画简单折线图，纵轴范围 [0, max]
+fn line_chart(ui: &mut egui::Ui, title: &str, series: &[(&Vec<f32>, egui::Color32)], max: f32) {
+    ui.label(format!("{} (max {:.2})", title, max));
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 110.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, egui::Color32::DARK_GRAY));
+
+    // 悬停时显示光标处各条曲线的值
+    if let Some(pos) = response.hover_pos() {
+        let at = (pos.x - rect.left()) / rect.width();
+        let values: Vec<String> = series.iter()
+            .filter(|(values, _)| !values.is_empty())
+            .map(|(values, _)| format!("{:.3}", values[((at * (values.len() - 1) as f32).round() as usize).min(values.len() - 1)]))
+            .collect();
+        painter.vline(pos.x, rect.y_range(), egui::Stroke::new(1.0, egui::Color32::GRAY));
+        response.on_hover_text(values.join("  "));
+    }
+
+    for (values, color) in series {
+        if values.len() < 2 {
+            continue;
+        }
+        let step = rect.width() / (values.len() - 1) as f32;
+        let points: Vec<egui::Pos2> = values.iter().enumerate()
+            .map(|(i, v)| egui::pos2(
+                rect.left() + i as f32 * step,
+                rect.bottom() - (v / max).clamp(0.0, 1.0) * rect.height(),
+            ))
+            .collect();
+        painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, *color)));
+    }
+}
+
+// Ctrl-R 反向搜索状态
+#[derive(Debug, Default)]
+pub struct HistorySearch {
+    query: String,
+    matched: Option<usize>,
+}
+
+// 带历史记录的命令输入框，回车提交时返回 true
+pub fn command_line(ui: &mut egui::Ui, input: &mut String, history: &mut History, search: &mut Option<HistorySearch>) -> bool {
+    if let Some(state) = search {
+        let mut submitted = false;
+        let mut done = false;
+        ui.horizontal(|ui| {
+            ui.monospace("(reverse-i-search)");
+            let response = ui.add(egui::TextEdit::singleline(&mut state.query).desired_width(120.0));
+            response.request_focus();
+
+            if response.changed() {
+                state.matched = history.search(&state.query, None).map(|(i, _)| i);
+            }
+            if ui.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::R)) {
+                // 继续向更早的记录搜索
+                if let Some((i, _)) = history.search(&state.query, state.matched) {
+                    state.matched = Some(i);
+                }
+            }
+
+            let found = state.matched.and_then(|i| history.entries().get(i)).cloned();
+            ui.monospace(format!(": {}", found.as_deref().unwrap_or("")));
+
+            if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                done = true;
+            } else if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                if let Some(found) = found {
+                    *input = found;
+                    submitted = true;
+                }
+                done = true;
+            }
+        });
+        if done {
+            *search = None;
+        }
+        return submitted;
+    }
+
+    let response = ui.text_edit_singleline(input);
+    if response.has_focus() {
+        let recalled = if ui.input(|i| i.key_pressed(egui::Key::ArrowUp)) {
+            history.prev(input).map(String::from)
+        } else if ui.input(|i| i.key_pressed(egui::Key::ArrowDown)) {
+            history.next().map(String::from)
+        } else {
+            None
+        };
+        if let Some(cmd) = recalled {
+            *input = cmd;
+            move_cursor_to_end(ui.ctx(), response.id, input.chars().count());
+        }
+        if ui.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::R)) {
+            *search = Some(HistorySearch::default());
+        }
+    }
+    let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    if submitted {
+        // 回车后焦点留在输入框，可以接着输入下一条命令
+        response.request_focus();
+    }
+    submitted
+}
+
+fn move_cursor_to_end(ctx: &egui::Context, id: egui::Id, len: usize) {
+    if let Some(mut state) = egui::TextEdit::load_state(ctx, id) {
+        let cursor = egui::text::CCursor::new(len);
+        state.cursor.set_char_range(Some(egui::text::CCursorRange::one(cursor)));
+        state.store(ctx, id);
+    }
+}
+
+#[derive(Debug)]
+struct MemWatch {
+    expr: String,
+    words: usize,
+    cycle: i32,
+    cur: Vec<Option<u64>>,
+    prev: Vec<Option<u64>>,
+}
+
+impl MemWatch {
+    fn new(expr: &str, words: usize) -> Self {
+        Self { expr: expr.to_string(), words, cycle: -1, cur: Vec::new(), prev: Vec::new() }
+    }
+
+    fn refresh(&mut self, cycle: i32, base: Option<u64>, mem: &Memory) {
+        if cycle == self.cycle {
+            return;
+        }
+        self.cycle = cycle;
+        self.prev = std::mem::take(&mut self.cur);
+        self.cur = (0..self.words)
+            .map(|i| base.and_then(|b| mem.mem_read(b.wrapping_add(i as u64 * 4), 4).ok()))
+            .collect();
+    }
+}
+
+struct OpenDialog {
+    dir: PathBuf,
+    path: String,               // 输入框中的路径
+    entries: Vec<(String, bool)>, // (名字, 是否目录)，目录在前
+}
+
+impl OpenDialog {
+    // 从当前镜像所在的目录开始
+    fn new(image: &str) -> Self {
+        let dir = Path::new(image).parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let mut dialog = Self { dir: PathBuf::new(), path: image.to_string(), entries: Vec::new() };
+        dialog.enter(dir.to_path_buf());
+        dialog
+    }
+
+    fn enter(&mut self, dir: PathBuf) {
+        let dir = dir.canonicalize().unwrap_or(dir);
+        self.entries = std::fs::read_dir(&dir)
+            .map(|entries| entries
+                .filter_map(|e| e.ok())
+                .map(|e| (e.file_name().to_string_lossy().into_owned(), e.path().is_dir()))
+                .filter(|(name, _)| !name.starts_with('.'))
+                .collect())
+            .unwrap_or_default();
+        self.entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        self.dir = dir;
+    }
+}
+
+fn stage_color(label: &str) -> egui::Color32 {
+    if label.ends_with('x') {
+        return egui::Color32::DARK_GRAY;
+    }
+    if label.ends_with('*') {
+        return egui::Color32::GRAY;
+    }
+    match label {
+        "IF" => egui::Color32::LIGHT_BLUE,
+        "ID" => egui::Color32::LIGHT_GREEN,
+        "EX" => egui::Color32::YELLOW,
+        "MEM" => egui::Color32::LIGHT_RED,
+        _ => egui::Color32::from_rgb(0xc0, 0xa0, 0xe0),
+    }
+}
+
+
 // 运行 GUI
-pub fn run_gui(output: String) -> Result<(), eframe::Error> {
+pub fn run_gui(output: String, config: SimConfig) -> Result<(), eframe::Error> {
     eframe::run_native(
         "Pipeline Simulator",
         eframe::NativeOptions {
@@ -256,6 +1989,6 @@ pub fn run_gui(output: String) -> Result<(), eframe::Error> {
                 .with_inner_size([1000.0, 800.0]),  
             ..Default::default()
         },
-        Box::new(|_cc| Box::new(GuiApp::new(output))), 
+        Box::new(move |cc| Box::new(GuiApp::new(output, config, cc.storage))), 
     )
 }
\ No newline at end of file
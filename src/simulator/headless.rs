@@ -0,0 +1,131 @@
+// Batch runs for `-sim IMAGE --headless`: no window and no debugger, the guest's UART output goes
+// to stdout and the statistics to stderr once the program stops. Its input comes from stdin, or
+// from the --stdin file. The process exits with the
+// guest's exit code (a0 & 0xff), so CI scripts can check it directly; EXIT_CYCLE_LIMIT and
+// EXIT_FAULT tell a run that hit --max-cycles or an illegal instruction apart from a normal exit.
+
+use super::config::SimConfig;
+use super::mem::Memory;
+use super::pipe::Pipeline;
+
+pub const EXIT_CYCLE_LIMIT: i32 = 124; // same as timeout(1)
+pub const EXIT_FAULT: i32 = 125;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Exited(u64), // a0
+    CycleLimit,
+    Fault,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Exited(a0) => (a0 & 0xff) as i32,
+            Outcome::CycleLimit => EXIT_CYCLE_LIMIT,
+            Outcome::Fault => EXIT_FAULT,
+        }
+    }
+}
+
+// step until the program stops or `max_cycles` elapse
+pub fn run(pipeline: &mut Pipeline, mem: &mut Memory, max_cycles: Option<u64>) -> Outcome {
+    while pipeline.cpu.running {
+        if max_cycles.is_some_and(|max| pipeline.cpu.cycle_count as u64 >= max) {
+            return Outcome::CycleLimit;
+        }
+        pipeline.step(mem);
+    }
+    match pipeline.fault {
+        Some(_) => Outcome::Fault,
+        None => Outcome::Exited(pipeline.cpu.reg[10]),
+    }
+}
+
+fn print_stats(pipeline: &Pipeline, outcome: Outcome) {
+    match outcome {
+        Outcome::Exited(a0) => eprintln!("Exit code: {}", a0 & 0xff),
+        Outcome::CycleLimit => eprintln!("Stopped at the cycle limit, pc = 0x{:08x}", pipeline.cpu.pc),
+        Outcome::Fault => {
+            if let Some(fault) = &pipeline.fault {
+                eprintln!("{}", fault);
+            }
+            eprint!("{}", pipeline.tracer.format_ring());
+        }
+    }
+    let counters = pipeline.perf_counters();
+    eprint!("{}", pipeline.statistics());
+    eprintln!("Branches/jumps executed: {}, mispredictions: {}", counters.branches, counters.mispredicts);
+    for cache in pipeline.icache.iter().chain(pipeline.dcache.iter()) {
+        eprintln!("{}: {:.1}% hit", cache.name, cache.hit_rate() * 100.0);
+    }
+}
+
+// returns the host exit code
+pub fn pipe_exc_once(image: &str, config: &SimConfig, max_cycles: Option<u64>) -> Result<i32, String> {
+    let (mut pipeline, mut mem) = config.build(image)?;
+    pipeline.set_quiet(&mut mem, true);
+    mem.uart.quiet = false; // guest output still goes to stdout
+    mem.uart.host_stdin = config.console.stdin.is_none(); // and reads from stdin
+    let outcome = run(&mut pipeline, &mut mem, max_cycles);
+    print_stats(&pipeline, outcome);
+    Ok(outcome.exit_code())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_headless() {
+        let asm = "main:
+  li a0, 300
+loop:
+  addi a0, a0, -1
+  bnez a0, loop
+  li a0, 258
+  ret
+";
+        let path = crate::runner::test_image(asm);
+        let config = SimConfig::default();
+        let build = || {
+            let (mut pipeline, mut mem) = config.build(&path).unwrap();
+            pipeline.set_quiet(&mut mem, true);
+            (pipeline, mem)
+        };
+
+        let (mut pipeline, mut mem) = build();
+        let outcome = run(&mut pipeline, &mut mem, None);
+        assert_eq!(outcome, Outcome::Exited(258));
+        assert_eq!(outcome.exit_code(), 2);
+
+        let (mut pipeline, mut mem) = build();
+        assert_eq!(run(&mut pipeline, &mut mem, Some(100)), Outcome::CycleLimit);
+        assert_eq!(pipeline.cpu.cycle_count, 100);
+    }
+
+    #[test]
+    fn test_stdin_file() {
+        // exits with the second byte read from the UART
+        let asm = "main:
+  lui t0, 0x50000
+  slli t0, t0, 1
+  addi t0, t0, 0x3f8
+  lbu a0, 0(t0)
+  lbu a0, 0(t0)
+  ret
+";
+        let path = crate::runner::test_image(asm);
+        let input = crate::runner::test_dir().join("stdin.in");
+        std::fs::write(&input, "7A").unwrap();
+        let mut config = SimConfig::default();
+        config.console.stdin = Some(input.to_string_lossy().to_string());
+        let (mut pipeline, mut mem) = config.build(&path).unwrap();
+        pipeline.set_quiet(&mut mem, true);
+        assert_eq!(run(&mut pipeline, &mut mem, None), Outcome::Exited(b'A' as u64));
+
+        config.console.stdin = Some(String::from("no-such-input.txt"));
+        assert!(config.build(&path).is_err());
+    }
+}
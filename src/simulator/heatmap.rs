@@ -0,0 +1,44 @@
+// Per-region read/write counters for the memory access heatmap.
+
+use std::collections::BTreeMap;
+
+pub const HEATMAP_GRANULE: u64 = 16; // bytes per bucket
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AccessCount {
+    pub reads: u32,
+    pub writes: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct AccessHeatmap {
+    buckets: BTreeMap<u64, AccessCount>, // keyed by address / HEATMAP_GRANULE
+}
+
+impl AccessHeatmap {
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+
+    pub fn record(&mut self, addr: u64, write: bool) {
+        let count = self.buckets.entry(addr / HEATMAP_GRANULE).or_default();
+        if write { count.writes += 1; } else { count.reads += 1; }
+    }
+
+    // lowest and highest touched address
+    pub fn bounds(&self) -> Option<(u64, u64)> {
+        let lo = *self.buckets.keys().next()?;
+        let hi = *self.buckets.keys().next_back()?;
+        Some((lo * HEATMAP_GRANULE, (hi + 1) * HEATMAP_GRANULE))
+    }
+
+    // total accesses in [lo, hi)
+    pub fn sum(&self, lo: u64, hi: u64) -> AccessCount {
+        let first = lo / HEATMAP_GRANULE;
+        let last = hi.div_ceil(HEATMAP_GRANULE);
+        self.buckets.range(first..last).fold(AccessCount::default(), |acc, (_, c)| AccessCount {
+            reads: acc.reads + c.reads,
+            writes: acc.writes + c.writes,
+        })
+    }
+}
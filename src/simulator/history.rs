@@ -0,0 +1,99 @@
+// Command history shared by the console frontends, persisted across sessions.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+pub const HISTORY_FILE: &str = ".sim_history";
+pub const MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    cursor: Option<usize>, // entry being shown while browsing with Up/Down
+    draft: String,         // the line typed before browsing started
+    path: Option<PathBuf>,
+}
+
+impl History {
+    // load history from `path`; a missing file just means an empty history
+    pub fn load(path: &str) -> Self {
+        let mut entries: Vec<String> = fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(String::from)
+            .collect();
+        if entries.len() > MAX_ENTRIES {
+            entries.drain(..entries.len() - MAX_ENTRIES);
+        }
+        Self {
+            entries,
+            path: Some(PathBuf::from(path)),
+            ..Self::default()
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    // record an executed command and append it to the history file
+    pub fn push(&mut self, cmd: &str) {
+        self.cursor = None;
+        self.draft.clear();
+
+        let cmd = cmd.trim();
+        if cmd.is_empty() || self.entries.last().map(String::as_str) == Some(cmd) {
+            return;
+        }
+        self.entries.push(cmd.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+
+        if let Some(path) = &self.path {
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", cmd);
+            }
+        }
+    }
+
+    // Up: older entry; `current` is remembered so Down can restore it
+    pub fn prev(&mut self, current: &str) -> Option<&str> {
+        let idx = match self.cursor {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            }
+            Some(0) => 0,
+            Some(i) => i - 1,
+        };
+        self.cursor = Some(idx);
+        Some(&self.entries[idx])
+    }
+
+    // Down: newer entry, or the original draft once past the newest one
+    pub fn next(&mut self) -> Option<&str> {
+        let idx = self.cursor?;
+        if idx + 1 < self.entries.len() {
+            self.cursor = Some(idx + 1);
+            Some(&self.entries[idx + 1])
+        } else {
+            self.cursor = None;
+            Some(&self.draft)
+        }
+    }
+
+    // reverse incremental search: newest entry containing `query` strictly before `before`
+    pub fn search(&self, query: &str, before: Option<usize>) -> Option<(usize, &str)> {
+        let end = before.unwrap_or(self.entries.len()).min(self.entries.len());
+        self.entries[..end]
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, e)| e.contains(query))
+            .map(|(i, e)| (i, e.as_str()))
+    }
+}
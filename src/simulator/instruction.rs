@@ -295,12 +295,60 @@ pub const INSTRUCTIONS: &[Instruction] = &[
         name: "sraw",
         inst_type: InstType::R,
     },
+    // ECALL
+    Instruction { 
+        pattern: "0000000 00000 00000 000 00000 11100 11",
+        name: "ecall",
+        inst_type: InstType::N,
+    },
     // EBREAK
     Instruction { 
         pattern: "0000000 00001 00000 000 00000 11100 11",
         name: "ebreak",
         inst_type: InstType::N,
     },
+    // CSRRW
+    Instruction { 
+        pattern: "??????? ????? ????? 001 ????? 11100 11",
+        name: "csrrw",
+        inst_type: InstType::I,
+    },
+    // CSRRS
+    Instruction { 
+        pattern: "??????? ????? ????? 010 ????? 11100 11",
+        name: "csrrs",
+        inst_type: InstType::I,
+    },
+    // CSRRC
+    Instruction { 
+        pattern: "??????? ????? ????? 011 ????? 11100 11",
+        name: "csrrc",
+        inst_type: InstType::I,
+    },
+    // CSRRWI
+    Instruction { 
+        pattern: "??????? ????? ????? 101 ????? 11100 11",
+        name: "csrrwi",
+        inst_type: InstType::I,
+    },
+    // CSRRSI
+    Instruction { 
+        pattern: "??????? ????? ????? 110 ????? 11100 11",
+        name: "csrrsi",
+        inst_type: InstType::I,
+    },
+    // CSRRCI
+    Instruction { 
+        pattern: "??????? ????? ????? 111 ????? 11100 11",
+        name: "csrrci",
+        inst_type: InstType::I,
+    },
+    // MRET
+    Instruction { 
+        pattern: "0011000 00010 00000 000 00000 11100 11",
+        name: "mret",
+        inst_type: InstType::N,
+    },
     // MUL
     Instruction { 
         pattern: "0000001 ????? ????? 000 ????? 01100 11",
@@ -379,4 +427,508 @@ pub const INSTRUCTIONS: &[Instruction] = &[
         name: "remuw",
         inst_type: InstType::R,
     },
+    // LR.W
+    Instruction { 
+        pattern: "00010?? 00000 ????? 010 ????? 01011 11",
+        name: "lr.w",
+        inst_type: InstType::R,
+    },
+    // SC.W
+    Instruction { 
+        pattern: "00011?? ????? ????? 010 ????? 01011 11",
+        name: "sc.w",
+        inst_type: InstType::R,
+    },
+    // AMOSWAP.W
+    Instruction { 
+        pattern: "00001?? ????? ????? 010 ????? 01011 11",
+        name: "amoswap.w",
+        inst_type: InstType::R,
+    },
+    // AMOADD.W
+    Instruction { 
+        pattern: "00000?? ????? ????? 010 ????? 01011 11",
+        name: "amoadd.w",
+        inst_type: InstType::R,
+    },
+    // AMOXOR.W
+    Instruction { 
+        pattern: "00100?? ????? ????? 010 ????? 01011 11",
+        name: "amoxor.w",
+        inst_type: InstType::R,
+    },
+    // AMOAND.W
+    Instruction { 
+        pattern: "01100?? ????? ????? 010 ????? 01011 11",
+        name: "amoand.w",
+        inst_type: InstType::R,
+    },
+    // AMOOR.W
+    Instruction { 
+        pattern: "01000?? ????? ????? 010 ????? 01011 11",
+        name: "amoor.w",
+        inst_type: InstType::R,
+    },
+    // AMOMIN.W
+    Instruction { 
+        pattern: "10000?? ????? ????? 010 ????? 01011 11",
+        name: "amomin.w",
+        inst_type: InstType::R,
+    },
+    // AMOMAX.W
+    Instruction { 
+        pattern: "10100?? ????? ????? 010 ????? 01011 11",
+        name: "amomax.w",
+        inst_type: InstType::R,
+    },
+    // AMOMINU.W
+    Instruction { 
+        pattern: "11000?? ????? ????? 010 ????? 01011 11",
+        name: "amominu.w",
+        inst_type: InstType::R,
+    },
+    // AMOMAXU.W
+    Instruction { 
+        pattern: "11100?? ????? ????? 010 ????? 01011 11",
+        name: "amomaxu.w",
+        inst_type: InstType::R,
+    },
+    // LR.D
+    Instruction { 
+        pattern: "00010?? 00000 ????? 011 ????? 01011 11",
+        name: "lr.d",
+        inst_type: InstType::R,
+    },
+    // SC.D
+    Instruction { 
+        pattern: "00011?? ????? ????? 011 ????? 01011 11",
+        name: "sc.d",
+        inst_type: InstType::R,
+    },
+    // AMOSWAP.D
+    Instruction { 
+        pattern: "00001?? ????? ????? 011 ????? 01011 11",
+        name: "amoswap.d",
+        inst_type: InstType::R,
+    },
+    // AMOADD.D
+    Instruction { 
+        pattern: "00000?? ????? ????? 011 ????? 01011 11",
+        name: "amoadd.d",
+        inst_type: InstType::R,
+    },
+    // AMOXOR.D
+    Instruction { 
+        pattern: "00100?? ????? ????? 011 ????? 01011 11",
+        name: "amoxor.d",
+        inst_type: InstType::R,
+    },
+    // AMOAND.D
+    Instruction { 
+        pattern: "01100?? ????? ????? 011 ????? 01011 11",
+        name: "amoand.d",
+        inst_type: InstType::R,
+    },
+    // AMOOR.D
+    Instruction { 
+        pattern: "01000?? ????? ????? 011 ????? 01011 11",
+        name: "amoor.d",
+        inst_type: InstType::R,
+    },
+    // AMOMIN.D
+    Instruction { 
+        pattern: "10000?? ????? ????? 011 ????? 01011 11",
+        name: "amomin.d",
+        inst_type: InstType::R,
+    },
+    // AMOMAX.D
+    Instruction { 
+        pattern: "10100?? ????? ????? 011 ????? 01011 11",
+        name: "amomax.d",
+        inst_type: InstType::R,
+    },
+    // AMOMINU.D
+    Instruction { 
+        pattern: "11000?? ????? ????? 011 ????? 01011 11",
+        name: "amominu.d",
+        inst_type: InstType::R,
+    },
+    // AMOMAXU.D
+    Instruction { 
+        pattern: "11100?? ????? ????? 011 ????? 01011 11",
+        name: "amomaxu.d",
+        inst_type: InstType::R,
+    },
+    // FLW
+    Instruction { 
+        pattern: "??????? ????? ????? 010 ????? 00001 11",
+        name: "flw",
+        inst_type: InstType::I,
+    },
+    // FSW
+    Instruction { 
+        pattern: "??????? ????? ????? 010 ????? 01001 11",
+        name: "fsw",
+        inst_type: InstType::S,
+    },
+    // FADD.S
+    Instruction { 
+        pattern: "0000000 ????? ????? ??? ????? 10100 11",
+        name: "fadd.s",
+        inst_type: InstType::R,
+    },
+    // FSUB.S
+    Instruction { 
+        pattern: "0000100 ????? ????? ??? ????? 10100 11",
+        name: "fsub.s",
+        inst_type: InstType::R,
+    },
+    // FMUL.S
+    Instruction { 
+        pattern: "0001000 ????? ????? ??? ????? 10100 11",
+        name: "fmul.s",
+        inst_type: InstType::R,
+    },
+    // FDIV.S
+    Instruction { 
+        pattern: "0001100 ????? ????? ??? ????? 10100 11",
+        name: "fdiv.s",
+        inst_type: InstType::R,
+    },
+    // FSQRT.S
+    Instruction { 
+        pattern: "0101100 00000 ????? ??? ????? 10100 11",
+        name: "fsqrt.s",
+        inst_type: InstType::R,
+    },
+    // FSGNJ.S
+    Instruction { 
+        pattern: "0010000 ????? ????? 000 ????? 10100 11",
+        name: "fsgnj.s",
+        inst_type: InstType::R,
+    },
+    // FSGNJN.S
+    Instruction { 
+        pattern: "0010000 ????? ????? 001 ????? 10100 11",
+        name: "fsgnjn.s",
+        inst_type: InstType::R,
+    },
+    // FSGNJX.S
+    Instruction { 
+        pattern: "0010000 ????? ????? 010 ????? 10100 11",
+        name: "fsgnjx.s",
+        inst_type: InstType::R,
+    },
+    // FMIN.S
+    Instruction { 
+        pattern: "0010100 ????? ????? 000 ????? 10100 11",
+        name: "fmin.s",
+        inst_type: InstType::R,
+    },
+    // FMAX.S
+    Instruction { 
+        pattern: "0010100 ????? ????? 001 ????? 10100 11",
+        name: "fmax.s",
+        inst_type: InstType::R,
+    },
+    // FCVT.W.S
+    Instruction { 
+        pattern: "1100000 00000 ????? ??? ????? 10100 11",
+        name: "fcvt.w.s",
+        inst_type: InstType::R,
+    },
+    // FCVT.WU.S
+    Instruction { 
+        pattern: "1100000 00001 ????? ??? ????? 10100 11",
+        name: "fcvt.wu.s",
+        inst_type: InstType::R,
+    },
+    // FCVT.L.S
+    Instruction { 
+        pattern: "1100000 00010 ????? ??? ????? 10100 11",
+        name: "fcvt.l.s",
+        inst_type: InstType::R,
+    },
+    // FCVT.LU.S
+    Instruction { 
+        pattern: "1100000 00011 ????? ??? ????? 10100 11",
+        name: "fcvt.lu.s",
+        inst_type: InstType::R,
+    },
+    // FMV.X.W
+    Instruction { 
+        pattern: "1110000 00000 ????? 000 ????? 10100 11",
+        name: "fmv.x.w",
+        inst_type: InstType::R,
+    },
+    // FCLASS.S
+    Instruction { 
+        pattern: "1110000 00000 ????? 001 ????? 10100 11",
+        name: "fclass.s",
+        inst_type: InstType::R,
+    },
+    // FEQ.S
+    Instruction { 
+        pattern: "1010000 ????? ????? 010 ????? 10100 11",
+        name: "feq.s",
+        inst_type: InstType::R,
+    },
+    // FLT.S
+    Instruction { 
+        pattern: "1010000 ????? ????? 001 ????? 10100 11",
+        name: "flt.s",
+        inst_type: InstType::R,
+    },
+    // FLE.S
+    Instruction { 
+        pattern: "1010000 ????? ????? 000 ????? 10100 11",
+        name: "fle.s",
+        inst_type: InstType::R,
+    },
+    // FCVT.S.W
+    Instruction { 
+        pattern: "1101000 00000 ????? ??? ????? 10100 11",
+        name: "fcvt.s.w",
+        inst_type: InstType::R,
+    },
+    // FCVT.S.WU
+    Instruction { 
+        pattern: "1101000 00001 ????? ??? ????? 10100 11",
+        name: "fcvt.s.wu",
+        inst_type: InstType::R,
+    },
+    // FCVT.S.L
+    Instruction { 
+        pattern: "1101000 00010 ????? ??? ????? 10100 11",
+        name: "fcvt.s.l",
+        inst_type: InstType::R,
+    },
+    // FCVT.S.LU
+    Instruction { 
+        pattern: "1101000 00011 ????? ??? ????? 10100 11",
+        name: "fcvt.s.lu",
+        inst_type: InstType::R,
+    },
+    // FMV.W.X
+    Instruction { 
+        pattern: "1111000 00000 ????? 000 ????? 10100 11",
+        name: "fmv.w.x",
+        inst_type: InstType::R,
+    },
+    // FLD
+    Instruction { 
+        pattern: "??????? ????? ????? 011 ????? 00001 11",
+        name: "fld",
+        inst_type: InstType::I,
+    },
+    // FSD
+    Instruction { 
+        pattern: "??????? ????? ????? 011 ????? 01001 11",
+        name: "fsd",
+        inst_type: InstType::S,
+    },
+    // FADD.D
+    Instruction { 
+        pattern: "0000001 ????? ????? ??? ????? 10100 11",
+        name: "fadd.d",
+        inst_type: InstType::R,
+    },
+    // FSUB.D
+    Instruction { 
+        pattern: "0000101 ????? ????? ??? ????? 10100 11",
+        name: "fsub.d",
+        inst_type: InstType::R,
+    },
+    // FMUL.D
+    Instruction { 
+        pattern: "0001001 ????? ????? ??? ????? 10100 11",
+        name: "fmul.d",
+        inst_type: InstType::R,
+    },
+    // FDIV.D
+    Instruction { 
+        pattern: "0001101 ????? ????? ??? ????? 10100 11",
+        name: "fdiv.d",
+        inst_type: InstType::R,
+    },
+    // FSQRT.D
+    Instruction { 
+        pattern: "0101101 00000 ????? ??? ????? 10100 11",
+        name: "fsqrt.d",
+        inst_type: InstType::R,
+    },
+    // FSGNJ.D
+    Instruction { 
+        pattern: "0010001 ????? ????? 000 ????? 10100 11",
+        name: "fsgnj.d",
+        inst_type: InstType::R,
+    },
+    // FSGNJN.D
+    Instruction { 
+        pattern: "0010001 ????? ????? 001 ????? 10100 11",
+        name: "fsgnjn.d",
+        inst_type: InstType::R,
+    },
+    // FSGNJX.D
+    Instruction { 
+        pattern: "0010001 ????? ????? 010 ????? 10100 11",
+        name: "fsgnjx.d",
+        inst_type: InstType::R,
+    },
+    // FMIN.D
+    Instruction { 
+        pattern: "0010101 ????? ????? 000 ????? 10100 11",
+        name: "fmin.d",
+        inst_type: InstType::R,
+    },
+    // FMAX.D
+    Instruction { 
+        pattern: "0010101 ????? ????? 001 ????? 10100 11",
+        name: "fmax.d",
+        inst_type: InstType::R,
+    },
+    // FCVT.W.D
+    Instruction { 
+        pattern: "1100001 00000 ????? ??? ????? 10100 11",
+        name: "fcvt.w.d",
+        inst_type: InstType::R,
+    },
+    // FCVT.WU.D
+    Instruction { 
+        pattern: "1100001 00001 ????? ??? ????? 10100 11",
+        name: "fcvt.wu.d",
+        inst_type: InstType::R,
+    },
+    // FCVT.L.D
+    Instruction { 
+        pattern: "1100001 00010 ????? ??? ????? 10100 11",
+        name: "fcvt.l.d",
+        inst_type: InstType::R,
+    },
+    // FCVT.LU.D
+    Instruction { 
+        pattern: "1100001 00011 ????? ??? ????? 10100 11",
+        name: "fcvt.lu.d",
+        inst_type: InstType::R,
+    },
+    // FMV.X.D
+    Instruction { 
+        pattern: "1110001 00000 ????? 000 ????? 10100 11",
+        name: "fmv.x.d",
+        inst_type: InstType::R,
+    },
+    // FCLASS.D
+    Instruction { 
+        pattern: "1110001 00000 ????? 001 ????? 10100 11",
+        name: "fclass.d",
+        inst_type: InstType::R,
+    },
+    // FEQ.D
+    Instruction { 
+        pattern: "1010001 ????? ????? 010 ????? 10100 11",
+        name: "feq.d",
+        inst_type: InstType::R,
+    },
+    // FLT.D
+    Instruction { 
+        pattern: "1010001 ????? ????? 001 ????? 10100 11",
+        name: "flt.d",
+        inst_type: InstType::R,
+    },
+    // FLE.D
+    Instruction { 
+        pattern: "1010001 ????? ????? 000 ????? 10100 11",
+        name: "fle.d",
+        inst_type: InstType::R,
+    },
+    // FCVT.D.W
+    Instruction { 
+        pattern: "1101001 00000 ????? ??? ????? 10100 11",
+        name: "fcvt.d.w",
+        inst_type: InstType::R,
+    },
+    // FCVT.D.WU
+    Instruction { 
+        pattern: "1101001 00001 ????? ??? ????? 10100 11",
+        name: "fcvt.d.wu",
+        inst_type: InstType::R,
+    },
+    // FCVT.D.L
+    Instruction { 
+        pattern: "1101001 00010 ????? ??? ????? 10100 11",
+        name: "fcvt.d.l",
+        inst_type: InstType::R,
+    },
+    // FCVT.D.LU
+    Instruction { 
+        pattern: "1101001 00011 ????? ??? ????? 10100 11",
+        name: "fcvt.d.lu",
+        inst_type: InstType::R,
+    },
+    // FMV.D.X
+    Instruction { 
+        pattern: "1111001 00000 ????? 000 ????? 10100 11",
+        name: "fmv.d.x",
+        inst_type: InstType::R,
+    },
+    // FCVT.S.D
+    Instruction { 
+        pattern: "0100000 00001 ????? ??? ????? 10100 11",
+        name: "fcvt.s.d",
+        inst_type: InstType::R,
+    },
+    // FCVT.D.S
+    Instruction { 
+        pattern: "0100001 00000 ????? ??? ????? 10100 11",
+        name: "fcvt.d.s",
+        inst_type: InstType::R,
+    },
+    // FMADD.S
+    Instruction { 
+        pattern: "?????00 ????? ????? ??? ????? 10000 11",
+        name: "fmadd.s",
+        inst_type: InstType::R4,
+    },
+    // FMSUB.S
+    Instruction { 
+        pattern: "?????00 ????? ????? ??? ????? 10001 11",
+        name: "fmsub.s",
+        inst_type: InstType::R4,
+    },
+    // FNMSUB.S
+    Instruction { 
+        pattern: "?????00 ????? ????? ??? ????? 10010 11",
+        name: "fnmsub.s",
+        inst_type: InstType::R4,
+    },
+    // FNMADD.S
+    Instruction { 
+        pattern: "?????00 ????? ????? ??? ????? 10011 11",
+        name: "fnmadd.s",
+        inst_type: InstType::R4,
+    },
+    // FMADD.D
+    Instruction { 
+        pattern: "?????01 ????? ????? ??? ????? 10000 11",
+        name: "fmadd.d",
+        inst_type: InstType::R4,
+    },
+    // FMSUB.D
+    Instruction { 
+        pattern: "?????01 ????? ????? ??? ????? 10001 11",
+        name: "fmsub.d",
+        inst_type: InstType::R4,
+    },
+    // FNMSUB.D
+    Instruction { 
+        pattern: "?????01 ????? ????? ??? ????? 10010 11",
+        name: "fnmsub.d",
+        inst_type: InstType::R4,
+    },
+    // FNMADD.D
+    Instruction { 
+        pattern: "?????01 ????? ????? ??? ????? 10011 11",
+        name: "fnmadd.d",
+        inst_type: InstType::R4,
+    },
 ];
\ No newline at end of file
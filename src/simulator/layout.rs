@@ -0,0 +1,169 @@
+// GUI panel layout and settings persisted between sessions (via eframe storage).
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum PanelKind {
+    Timeline,
+    Memory,
+    Disasm,
+    Source,
+    Perf,
+    Cache,
+    Breakpoints,
+    Heatmap,
+    Calls,
+    Output,
+}
+
+impl PanelKind {
+    pub const ALL: [PanelKind; 10] = [
+        PanelKind::Timeline, PanelKind::Memory, PanelKind::Disasm, PanelKind::Source, PanelKind::Perf,
+        PanelKind::Cache, PanelKind::Breakpoints, PanelKind::Heatmap, PanelKind::Calls, PanelKind::Output,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            PanelKind::Timeline => "Pipeline Diagram",
+            PanelKind::Memory => "Memory",
+            PanelKind::Disasm => "Disassembly",
+            PanelKind::Source => "Source",
+            PanelKind::Perf => "Performance",
+            PanelKind::Cache => "Caches",
+            PanelKind::Breakpoints => "Breakpoints",
+            PanelKind::Heatmap => "Memory Heatmap",
+            PanelKind::Calls => "Calls",
+            PanelKind::Output => "Program Output",
+        }
+    }
+
+    // program output is visible by default, everything else starts hidden
+    pub fn default_dock(self) -> Dock {
+        match self {
+            PanelKind::Output => Dock::Bottom,
+            _ => Dock::Hidden,
+        }
+    }
+
+    pub fn default_size(self) -> [f32; 2] {
+        match self {
+            PanelKind::Timeline => [800.0, 400.0],
+            PanelKind::Memory => [700.0, 500.0],
+            PanelKind::Disasm => [600.0, 500.0],
+            PanelKind::Source => [600.0, 500.0],
+            PanelKind::Perf => [600.0, 520.0],
+            PanelKind::Cache => [600.0, 500.0],
+            PanelKind::Breakpoints => [600.0, 300.0],
+            PanelKind::Heatmap => [700.0, 200.0],
+            PanelKind::Calls => [600.0, 450.0],
+            PanelKind::Output => [500.0, 300.0],
+        }
+    }
+}
+
+// where a panel is shown: a floating window or docked to an edge of the main window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Dock {
+    Hidden,
+    Floating,
+    Left,
+    Right,
+    Bottom,
+}
+
+impl Dock {
+    pub const ALL: [Dock; 5] = [Dock::Hidden, Dock::Floating, Dock::Left, Dock::Right, Dock::Bottom];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dock::Hidden => "hidden",
+            Dock::Floating => "floating",
+            Dock::Left => "left",
+            Dock::Right => "right",
+            Dock::Bottom => "bottom",
+        }
+    }
+}
+
+// colour scheme; System follows the desktop setting where eframe can read it, dark otherwise
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::System, Theme::Dark, Theme::Light];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::System => "system",
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+        }
+    }
+}
+
+// window size and position, and the positions of floating panels, are saved by eframe itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuiSettings {
+    pub docks: BTreeMap<PanelKind, Dock>,
+    pub last_image: String,
+    pub run_hz: f64,
+    pub run_unlimited: bool,
+    pub stop_at_main: bool,
+    pub theme: Theme,
+}
+
+impl Default for GuiSettings {
+    fn default() -> Self {
+        Self {
+            docks: BTreeMap::new(),
+            last_image: String::new(),
+            run_hz: 1000.0,
+            run_unlimited: true,
+            stop_at_main: false,
+            theme: Theme::System,
+        }
+    }
+}
+
+impl GuiSettings {
+    pub fn dock(&self, kind: PanelKind) -> Dock {
+        self.docks.get(&kind).copied().unwrap_or(kind.default_dock())
+    }
+
+    pub fn set_dock(&mut self, kind: PanelKind, dock: Dock) {
+        self.docks.insert(kind, dock);
+    }
+
+    // make a panel visible, keeping its dock position if it already has one
+    pub fn show(&mut self, kind: PanelKind) {
+        if self.dock(kind) == Dock::Hidden {
+            self.set_dock(kind, Dock::Floating);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings() {
+        // settings saved before a field existed still load, with the default for it
+        let old: GuiSettings = serde_json::from_str(r#"{"docks": {"Memory": "Right"}, "last_image": "a.bin"}"#).unwrap();
+        assert_eq!(old.dock(PanelKind::Memory), Dock::Right);
+        assert_eq!(old.dock(PanelKind::Output), Dock::Bottom);
+        assert_eq!(old.theme, Theme::System);
+
+        let settings = GuiSettings { theme: Theme::Light, ..old };
+        let saved: GuiSettings = serde_json::from_str(&serde_json::to_string(&settings).unwrap()).unwrap();
+        assert_eq!(saved.theme, Theme::Light);
+        assert_eq!(saved.last_image, "a.bin");
+    }
+}
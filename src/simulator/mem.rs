@@ -1,11 +1,18 @@
+use std::collections::{BTreeSet, HashMap};
 use std::fmt;
 use std::fs::File;
 use std::io::Read;
 use object::{Object, ObjectSegment};
 use std::fs;
+use std::path::Path;
 
-const MEM_BASE: u64 = 0x8000_0000; 
-const MEM_SIZE: usize = 0x80_00000; 
+use super::device::{Clint, Uart, CLINT_SIZE, SERIAL_SIZE};
+use super::memmap::{MemoryMap, RegionKind};
+
+// the default main RAM, see MemoryConfig
+pub const MEM_BASE: u64 = 0x8000_0000;
+pub const MEM_SIZE: usize = 0x80_00000;
+pub const PAGE_SIZE: usize = 4096; // allocation unit, and granularity of dirty tracking for checkpoints
 
 // MemoryError
 #[derive(Debug)]
@@ -14,6 +21,7 @@ pub enum MemoryError {
     InvalidReadLength { len: usize },
     InvalidWriteLength { len: usize },
     ZeroPc,
+    ReadOnly { addr: u64 },
     FileError(std::io::Error),
     EmptyFilePath,
     ImageLoadFailed,
@@ -31,6 +39,8 @@ impl fmt::Display for MemoryError {
                 write!(f, "Invalid write length: {}", len),
             MemoryError::ZeroPc => 
                 write!(f, "PC is zero"),
+            MemoryError::ReadOnly { addr } => 
+                write!(f, "Store to read-only memory at 0x{:x}", addr),
             MemoryError::FileError(e) => 
                 write!(f, "File operation error: {}", e),
             MemoryError::EmptyFilePath => 
@@ -59,87 +69,290 @@ impl From<std::io::Error> for MemoryError {
 }
 
 
+// whether load_image treats `path` as ELF: a .bin file is always a flat binary
+pub fn is_elf(path: &str) -> bool {
+    if Path::new(path).extension().is_some_and(|ext| ext == "bin") {
+        return false;
+    }
+    let mut magic = [0u8; 4];
+    File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && magic == *b"\x7fELF"
+}
+
+// sparse backing store: pages are allocated on the first write, untouched ones read as zero,
+// so a region can span gigabytes while only the pages in use cost host memory
+#[derive(Default)]
+struct Pages {
+    pages: HashMap<usize, Box<[u8; PAGE_SIZE]>>, // by page number within the region
+}
+
+impl Pages {
+    // split [offset, offset + len) at page boundaries: (page, offset in page, offset in buffer, length)
+    fn chunks(offset: usize, len: usize) -> impl Iterator<Item = (usize, usize, usize, usize)> {
+        let mut done = 0;
+        std::iter::from_fn(move || {
+            if done == len {
+                return None;
+            }
+            let at = offset + done;
+            let n = (PAGE_SIZE - at % PAGE_SIZE).min(len - done);
+            let chunk = (at / PAGE_SIZE, at % PAGE_SIZE, done, n);
+            done += n;
+            Some(chunk)
+        })
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) {
+        for (page, at, from, n) in Self::chunks(offset, buf.len()) {
+            match self.pages.get(&page) {
+                Some(data) => buf[from..from + n].copy_from_slice(&data[at..at + n]),
+                None => buf[from..from + n].fill(0),
+            }
+        }
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        for (page, at, from, n) in Self::chunks(offset, data.len()) {
+            let bytes = self.pages.entry(page).or_insert_with(|| Box::new([0; PAGE_SIZE]));
+            bytes[at..at + n].copy_from_slice(&data[from..from + n]);
+        }
+    }
+
+    // zero [offset, offset + len) without allocating pages that are still untouched
+    fn zero(&mut self, offset: usize, len: usize) {
+        for (page, at, _, n) in Self::chunks(offset, len) {
+            if let Some(bytes) = self.pages.get_mut(&page) {
+                bytes[at..at + n].fill(0);
+            }
+        }
+    }
+}
+
+// one RAM or ROM block of the memory map
+pub struct Region {
+    pub name: String,
+    pub base: u64,
+    pub size: usize,
+    pub readonly: bool, // ROM: guest stores fail, loading the image and the debugger still write
+    data: Pages,
+    dirty: BTreeSet<usize>, // pages written since the image was loaded
+}
+
+impl Region {
+    fn new(name: &str, base: u64, size: usize, readonly: bool) -> Self {
+        Self { name: name.to_string(), base, size, readonly, data: Pages::default(), dirty: BTreeSet::new() }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.base + self.size as u64
+    }
+
+    // host memory in use, in bytes
+    pub fn allocated(&self) -> usize {
+        self.data.pages.len() * PAGE_SIZE
+    }
+}
+
 //////////////
 /// Memory ///
 //////////////
 pub struct Memory {
-    mem: Box<[u8; MEM_SIZE]>, // 使用 Box 避免栈溢出
+    regions: Vec<Region>, // the main RAM first
+    pub uart: Uart,
+    pub clint: Clint,
+    pub image_size: u64, // bytes loaded by load_image
+    entry: Option<u64>, // ELF entry point, None for a flat binary
+    pub elf: bool, // load_image parses every image as ELF, whatever its name (--elf)
+    pub trap_misaligned: bool, // misaligned loads and stores raise an exception instead of being split
+    pub misaligned_penalty: u64, // extra stall cycles of a split access
+    pub reservation: Option<u64>, // address reserved by the last lr, see decode::atomic
+    journal: Option<Vec<(u64, Vec<u8>)>>, // old bytes of each write, kept for reverse stepping
 }
 
 impl Memory {
     pub fn new() -> Self {
-        let mem = vec![0u8; MEM_SIZE].into_boxed_slice();
-        let mem = mem.try_into().expect("Failed to create memory array");
-        
-        Self { mem }
+        Self::with_map(MEM_BASE, MEM_SIZE)
     }
 
-    /// translate guest address to host address
-    pub fn guest_to_host(&self, addr: u64) -> Result<*const u8, MemoryError> {
-        if addr < MEM_BASE || addr >= MEM_BASE + MEM_SIZE as u64 {
-            return Err(MemoryError::InvalidAddress { addr });
+    // main RAM of `size` bytes starting at `base`
+    pub fn with_map(base: u64, size: usize) -> Self {
+        Self {
+            regions: vec![Region::new("ram", base, size, false)],
+            uart: Uart::default(),
+            clint: Clint::default(),
+            image_size: 0,
+            entry: None,
+            elf: false,
+            trap_misaligned: false,
+            misaligned_penalty: 0,
+            reservation: None,
+            journal: None,
         }
-        
-        let offset = (addr - MEM_BASE) as usize;
-        Ok(unsafe { self.mem.as_ptr().add(offset) })
     }
 
-    /// translate guest address to host mutable address
-    pub fn guest_to_host_mut(&mut self, addr: u64) -> Result<*mut u8, MemoryError> {
-        if addr < MEM_BASE || addr >= MEM_BASE + MEM_SIZE as u64 {
-            return Err(MemoryError::InvalidAddress { addr });
+    // another RAM or ROM region; SimConfig::memory_map has checked that it overlaps nothing
+    pub fn add_region(&mut self, name: &str, base: u64, size: usize, readonly: bool) {
+        self.regions.push(Region::new(name, base, size, readonly));
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    // the regions and device ranges, as `info mem` shows them
+    pub fn map(&self) -> MemoryMap {
+        let mut map = MemoryMap::default();
+        for r in &self.regions {
+            let kind = if r.readonly { RegionKind::Rom } else { RegionKind::Ram };
+            let _ = map.add(&r.name, kind, r.base, r.size as u64);
         }
-        
-        let offset = (addr - MEM_BASE) as usize;
-        Ok(unsafe { self.mem.as_mut_ptr().add(offset) })
+        let _ = map.add("uart", RegionKind::Device, self.uart.base, SERIAL_SIZE);
+        let _ = map.add("clint", RegionKind::Device, self.clint.base, CLINT_SIZE);
+        map
+    }
+
+    // region index and offset of [addr, addr + len), which must lie inside one region
+    fn locate(&self, addr: u64, len: usize) -> Result<(usize, usize), MemoryError> {
+        let end = addr.checked_add(len as u64).ok_or(MemoryError::InvalidAddress { addr })?;
+        self.regions.iter()
+            .position(|r| addr >= r.base && end <= r.end())
+            .map(|i| (i, (addr - self.regions[i].base) as usize))
+            .ok_or(MemoryError::InvalidAddress { addr })
+    }
+
+    // bytes the regions take on the host, see Pages
+    pub fn allocated(&self) -> usize {
+        self.regions.iter().map(Region::allocated).sum()
     }
 
-    /// read data from host address
-    fn host_read<T: Copy>(addr: *const u8) -> T {
-        unsafe { (addr as *const T).read_unaligned() }
+    // called before every write, the range is already checked
+    fn mark_dirty(&mut self, addr: u64, len: usize) {
+        let Ok((i, offset)) = self.locate(addr, len) else {
+            return;
+        };
+        let region = &mut self.regions[i];
+        let last = offset + len.max(1) - 1;
+        region.dirty.extend(offset / PAGE_SIZE..=last / PAGE_SIZE);
+        if let Some(journal) = &mut self.journal {
+            let mut old = vec![0; len];
+            region.data.read(offset, &mut old);
+            journal.push((addr, old));
+        }
     }
 
-    /// write data to host address
-    fn host_write<T>(addr: *mut u8, value: T) {
-        unsafe { (addr as *mut T).write_unaligned(value) };
+    /// the writes journaled since the last call, oldest first; journaling starts with the first call
+    pub fn record_writes(&mut self) -> Vec<(u64, Vec<u8>)> {
+        self.journal.replace(Vec::new()).unwrap_or_default()
     }
 
-    /// read data from memory
+    /// put back the old bytes of journaled writes, newest first
+    pub fn undo_writes(&mut self, writes: Vec<(u64, Vec<u8>)>) {
+        for (addr, old) in writes.into_iter().rev() {
+            if let Ok((i, offset)) = self.locate(addr, old.len()) {
+                self.regions[i].data.write(offset, &old);
+            }
+        }
+    }
+
+    /// (start, length) of the pages written since the image was loaded, in every region
+    pub fn dirty_pages(&self) -> impl Iterator<Item = (u64, usize)> + '_ {
+        self.regions.iter().flat_map(|r| {
+            r.dirty.iter().map(move |page| {
+                let offset = page * PAGE_SIZE;
+                (r.base + offset as u64, PAGE_SIZE.min(r.size - offset))
+            })
+        })
+    }
+
+    // the main RAM as [base, end)
+    pub fn ram_range(&self) -> (u64, u64) {
+        (self.regions[0].base, self.regions[0].end())
+    }
+
+    /// check that [addr, addr + len) lies inside guest memory
+    pub fn check_range(&self, addr: u64, len: usize) -> Result<(), MemoryError> {
+        self.locate(addr, len).map(|_| ())
+    }
+
+    /// read data from memory (little endian)
     pub fn mem_read(&self, addr: u64, len: usize) -> Result<u64, MemoryError> {
-        let host_addr = self.guest_to_host(addr)? as *const u8;
-        
-        match len {
-            1 => Ok(Self::host_read::<u8>(host_addr) as u64),
-            2 => Ok(Self::host_read::<u16>(host_addr) as u64),
-            4 => Ok(Self::host_read::<u32>(host_addr) as u64),
-            8 => Ok(Self::host_read::<u64>(host_addr)),
-            _ => Err(MemoryError::InvalidReadLength { len }),
+        if !matches!(len, 1 | 2 | 4 | 8) {
+            return Err(MemoryError::InvalidReadLength { len });
         }
+        let (i, offset) = self.locate(addr, len)?;
+        let mut bytes = [0u8; 8];
+        self.regions[i].data.read(offset, &mut bytes[..len]);
+        Ok(u64::from_le_bytes(bytes))
     }
 
-    /// write data to memory
+    /// write data to memory (little endian)
     pub fn mem_write(&mut self, addr: u64, len: usize, data: u64) -> Result<(), MemoryError> {
-        let host_addr = self.guest_to_host_mut(addr)? as *mut u8;
-        
-        match len {
-            1 => {
-                Self::host_write(host_addr, data as u8);
-                Ok(())
-            }
-            2 => {
-                Self::host_write(host_addr, data as u16);
-                Ok(())
-            }
-            4 => {
-                Self::host_write(host_addr, data as u32);
-                Ok(())
-            }
-            8 => {
-                Self::host_write(host_addr, data);
-                Ok(())
-            }
-            _ => Err(MemoryError::InvalidWriteLength { len }),
+        if !matches!(len, 1 | 2 | 4 | 8) {
+            return Err(MemoryError::InvalidWriteLength { len });
+        }
+        self.write_bytes(addr, &data.to_le_bytes()[..len])
+    }
+
+    /// the bytes of [addr, addr + len) in RAM or ROM
+    pub fn read_bytes(&self, addr: u64, len: usize) -> Result<Vec<u8>, MemoryError> {
+        let (i, offset) = self.locate(addr, len)?;
+        let mut bytes = vec![0; len];
+        self.regions[i].data.read(offset, &mut bytes);
+        Ok(bytes)
+    }
+
+    /// copy `data` into RAM or ROM starting at `addr`
+    pub fn write_bytes(&mut self, addr: u64, data: &[u8]) -> Result<(), MemoryError> {
+        let (i, offset) = self.locate(addr, data.len())?;
+        self.mark_dirty(addr, data.len());
+        self.regions[i].data.write(offset, data);
+        Ok(())
+    }
+
+    /// guest load: MMIO devices first, then RAM
+    pub fn load(&mut self, addr: u64, len: usize) -> Result<u64, MemoryError> {
+        if self.uart.contains(addr) {
+            return Ok(self.uart.read(addr));
         }
+        if self.clint.contains(addr) {
+            return Ok(self.clint.read(addr, len));
+        }
+        self.mem_read(addr, len)
+    }
+
+    /// check that a guest load or store of [addr, addr + len) reaches a device, RAM or (for loads) ROM
+    pub fn probe(&self, addr: u64, len: usize, write: bool) -> Result<(), MemoryError> {
+        if self.uart.contains(addr) || self.clint.contains(addr) {
+            return Ok(());
+        }
+        let (i, _) = self.locate(addr, len)?;
+        if write && self.regions[i].readonly {
+            return Err(MemoryError::ReadOnly { addr });
+        }
+        Ok(())
+    }
+
+    /// guest store: MMIO devices first, then RAM
+    pub fn store(&mut self, addr: u64, len: usize, data: u64) -> Result<(), MemoryError> {
+        if self.reservation.is_some_and(|r| addr < (r & !7) + 8 && (r & !7) < addr + len as u64) {
+            self.reservation = None;
+        }
+        if self.uart.contains(addr) {
+            self.uart.write(addr, data);
+            return Ok(());
+        }
+        if self.clint.contains(addr) {
+            self.clint.write(addr, len, data);
+            return Ok(());
+        }
+        if self.locate(addr, len).is_ok_and(|(i, _)| self.regions[i].readonly) {
+            return Err(MemoryError::ReadOnly { addr });
+        }
+        self.mem_write(addr, len, data)
+    }
+
+    // where the loaded image starts: the ELF entry point, or the RAM base for a flat binary
+    pub fn entry(&self) -> u64 {
+        self.entry.unwrap_or(self.regions[0].base)
     }
 
     /// fetch instruction from memory (4 bytes)
@@ -148,35 +361,42 @@ impl Memory {
             return Err(MemoryError::ZeroPc);
         }
         
-        let host_addr = self.guest_to_host(pc)?;
-        Ok(Self::host_read::<u32>(host_addr))
+        Ok(self.mem_read(pc, 4)? as u32)
     }
 
     /// load image file to memory (bin or elf)
     pub fn load_image(&mut self, filepath: &str) -> Result<(), MemoryError> {
-        println!("Physical Memory Range: [0x{:016x}, 0x{:016x}]", 
-                 MEM_BASE, MEM_BASE + MEM_SIZE as u64 - 1);
+        let (base, end) = self.ram_range();
+        println!("Physical Memory Range: [0x{:016x}, 0x{:016x}]", base, end - 1);
         
         if filepath.is_empty() {
             return Err(MemoryError::EmptyFilePath);
         }
+
+        self.entry = None;
+        if self.elf || is_elf(filepath) {
+            println!("The image is {} (ELF)", filepath);
+            return self.load_elf(filepath).map_err(|e| {
+                println!("Failed to load ELF: {}", e);
+                MemoryError::ImageLoadFailed
+            });
+        }
         
-        let mut file = File::open(filepath)?;
-        let mut size = file.metadata()?.len() as usize;
+        let mut image = Vec::new();
+        File::open(filepath)?.read_to_end(&mut image)?;
         
-        println!("The image is {}, size = {}", filepath, size);
+        println!("The image is {}, size = {}", filepath, image.len());
         
         // make sure the image size is not too large
-        if size > MEM_SIZE {
-            size = MEM_SIZE;
+        let ram = &mut self.regions[0];
+        if image.len() > ram.size {
+            image.truncate(ram.size);
             println!("Warning: Image truncated to fit in memory");
         }
         
-        // load the file to the start of the memory
-        let host_ptr = self.guest_to_host_mut(MEM_BASE)? as *mut u8;
-        let slice = unsafe { std::slice::from_raw_parts_mut(host_ptr, size) };
-        
-        file.read_exact(slice)?;
+        // load the file to the start of the main RAM
+        ram.data.write(0, &image);
+        self.image_size = image.len() as u64;
 
         Ok(())
     }
@@ -185,22 +405,32 @@ impl Memory {
         let data = fs::read(path)?;
         let obj = object::File::parse(&*data)?;
         
-        // load each segments
+        let entry = obj.entry();
+        if self.check_range(entry, 2).is_err() {
+            return Err(format!("Entry point 0x{:x} outside memory", entry).into());
+        }
+
+        // load each segment (object yields PT_LOAD segments only) into the region holding it
         for segment in obj.segments() {
-            if segment.address() == 0 { continue; }
+            if segment.size() == 0 { continue; }
             
             let data = segment.data()?;
-            let addr = (segment.address() - MEM_BASE) as usize;
             let size = segment.size() as usize;
-            
-            // make sure the segment is not too large
-            if size > MEM_SIZE {
+            let (i, addr) = self.locate(segment.address(), size)
+                .map_err(|_| format!("Segment [0x{:x}, 0x{:x}) out of memory bounds", segment.address(), segment.address() + size as u64))?;
+            if data.len() > size {
                 return Err("Segment out of memory bounds".into());
             }
             
-            // copy the segment data to memory
-            self.mem[addr..addr+size].copy_from_slice(data);
+            // copy the segment data to memory, the rest (.bss) is zeroed
+            let region = &mut self.regions[i].data;
+            region.write(addr, data);
+            region.zero(addr + data.len(), size - data.len());
+            if i == 0 {
+                self.image_size = self.image_size.max((addr + size) as u64);
+            }
         }
+        self.entry = Some(entry);
         
         // 初始化栈指针 (根据 ELF 中的 .bss 或自定义链接脚本)
         // if let Some(stack_section) = obj.section_by_name(".stack") {
@@ -246,6 +476,8 @@ mod tests {
         
         assert!(mem.mem_read(MEM_BASE - 1, 4).is_err());
         assert!(mem.mem_read(MEM_BASE + MEM_SIZE as u64, 4).is_err());
+        assert!(mem.mem_read(MEM_BASE + MEM_SIZE as u64 - 2, 4).is_err());
+        assert!(mem.mem_write(MEM_BASE + MEM_SIZE as u64 - 1, 2, 0).is_err());
         
         mem.mem_write(MEM_BASE, 1, 0x12).unwrap();
         assert_eq!(mem.mem_read(MEM_BASE, 1).unwrap(), 0x12);
@@ -263,6 +495,61 @@ mod tests {
         assert_eq!(mem.inst_fetch(MEM_BASE).unwrap(), 0xDEADBEEF);
     }
 
+    #[test]
+    fn test_regions() {
+        let mut mem = Memory::new();
+        mem.add_region("rom", 0x1000, 0x100, true);
+        mem.write_bytes(0x1000, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(mem.load(0x1000, 4).unwrap(), 0x0403_0201);
+        assert!(matches!(mem.store(0x1000, 4, 0), Err(MemoryError::ReadOnly { addr: 0x1000 })));
+        assert!(mem.mem_read(0x10fe, 4).is_err());
+        mem.store(MEM_BASE, 4, 5).unwrap();
+        assert_eq!(mem.dirty_pages().collect::<Vec<_>>(), vec![(MEM_BASE, PAGE_SIZE), (0x1000, 0x100)]);
+    }
+
+    #[test]
+    fn test_sparse_pages() {
+        // a 4 GiB region far above the main RAM only allocates the pages that are written
+        let mut mem = Memory::new();
+        let base = 0x40_0000_0000;
+        mem.add_region("far", base, 1 << 32, false);
+        assert_eq!(mem.mem_read(base + 0x1234_5678, 8).unwrap(), 0);
+        assert_eq!(mem.allocated(), 0);
+        let addr = base + (1 << 32) - PAGE_SIZE as u64 - 2; // straddles two pages
+        mem.mem_write(addr, 4, 0x1122_3344).unwrap();
+        assert_eq!(mem.mem_read(addr, 4).unwrap(), 0x1122_3344);
+        assert_eq!(mem.allocated(), 2 * PAGE_SIZE);
+        assert!(mem.mem_read(base + (1 << 32) - 2, 4).is_err());
+    }
+
+    #[test]
+    fn test_elf_entry() {
+        // ELF64 header and one PT_LOAD: 4 bytes of code at base + 0x1000, 4 more of .bss
+        let entry = MEM_BASE + 0x1000;
+        let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+        elf.resize(16, 0);
+        for (value, size) in [(2, 2), (0xf3, 2), (1, 4), (entry, 8), (64, 8), (0, 8), (0, 4), (64, 2), (56, 2), (1, 2), (64, 2), (0, 2), (0, 2),
+                              (1, 4), (5, 4), (120, 8), (entry, 8), (entry, 8), (4, 8), (8, 8), (4, 8)] {
+            elf.extend_from_slice(&u64::to_le_bytes(value)[..size]);
+        }
+        elf.extend_from_slice(&0x0015_0513u32.to_le_bytes()); // addi a0, a0, 1
+
+        let dir = crate::runner::test_dir();
+        let mut mem = Memory::new();
+        mem.mem_write(entry + 4, 4, 0xffff_ffff).unwrap();
+        std::fs::write(dir.join("entry.elf"), &elf).unwrap();
+        mem.load_image(&dir.join("entry.elf").to_string_lossy()).unwrap();
+        assert_eq!(mem.entry(), entry);
+        assert_eq!(mem.inst_fetch(entry).unwrap(), 0x0015_0513);
+        assert_eq!(mem.mem_read(entry + 4, 4).unwrap(), 0);
+
+        // the same bytes named .bin are loaded flat
+        std::fs::write(dir.join("entry.bin"), &elf).unwrap();
+        mem.load_image(&dir.join("entry.bin").to_string_lossy()).unwrap();
+        assert_eq!(mem.entry(), MEM_BASE);
+        assert_eq!(mem.mem_read(MEM_BASE, 4).unwrap(), 0x464c_457f);
+    }
+
     #[test]
     fn test_image_loading() {
         let mut mem = Memory::new();
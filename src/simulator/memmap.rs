@@ -0,0 +1,131 @@
+// The guest physical address space. `[memory]` gives the main RAM that flat images are loaded
+// into; more RAM or ROM regions come from `[[memory.regions]]` or `--region KIND:BASE:SIZE`, e.g. a
+// boot ROM at the reset vector for an ELF image linked there. The MemoryMap checks that the
+// regions and the device ranges from `[[devices]]` do not overlap.
+//
+// ROM is written only while the image is loaded (and by the debugger); guest stores to it fail.
+
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegionKind {
+    Ram,
+    Rom,
+    Device,
+}
+
+impl RegionKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            RegionKind::Ram => "ram",
+            RegionKind::Rom => "rom",
+            RegionKind::Device => "device",
+        }
+    }
+}
+
+// one `[[memory.regions]]` entry
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegionConfig {
+    pub kind: RegionKind,
+    pub base: u64,
+    pub size: usize,
+    pub name: Option<String>, // defaults to the kind
+}
+
+impl RegionConfig {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.kind.name())
+    }
+}
+
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16).ok(),
+        None => s.replace('_', "").parse().ok(),
+    }
+}
+
+// "rom:0x1000:0x10000", as given to --region
+impl FromStr for RegionConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let usage = || format!("--region expects KIND:BASE:SIZE with KIND ram or rom, got '{}'", s);
+        let mut parts = s.split(':');
+        let kind = match parts.next() {
+            Some("ram") => RegionKind::Ram,
+            Some("rom") => RegionKind::Rom,
+            _ => return Err(usage()),
+        };
+        let (Some(base), Some(size), None) = (parts.next().and_then(parse_number), parts.next().and_then(parse_number), parts.next()) else {
+            return Err(usage());
+        };
+        Ok(Self { kind, base, size: size as usize, name: None })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapEntry {
+    pub name: String,
+    pub kind: RegionKind,
+    pub base: u64,
+    pub size: u64,
+}
+
+impl MapEntry {
+    pub fn end(&self) -> u64 {
+        self.base + self.size
+    }
+}
+
+// entries sorted by base address, without overlaps
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMap {
+    entries: Vec<MapEntry>,
+}
+
+impl MemoryMap {
+    pub fn add(&mut self, name: &str, kind: RegionKind, base: u64, size: u64) -> Result<(), String> {
+        if size == 0 {
+            return Err(format!("memory map: {} at 0x{:x} is empty", name, base));
+        }
+        let end = base.checked_add(size).ok_or(format!("memory map: {} at 0x{:x} wraps around", name, base))?;
+        if let Some(other) = self.entries.iter().find(|e| base < e.end() && e.base < end) {
+            return Err(format!("memory map: {} [0x{:x}, 0x{:x}) overlaps {} [0x{:x}, 0x{:x})",
+                name, base, end, other.name, other.base, other.end()));
+        }
+        let at = self.entries.partition_point(|e| e.base < base);
+        self.entries.insert(at, MapEntry { name: name.to_string(), kind, base, size });
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[MapEntry] {
+        &self.entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_map() {
+        let rom: RegionConfig = "rom:0x1000:0x1000".parse().unwrap();
+        assert_eq!(rom, RegionConfig { kind: RegionKind::Rom, base: 0x1000, size: 0x1000, name: None });
+        assert!("flash:0x1000:0x1000".parse::<RegionConfig>().is_err());
+        assert!("ram:0x1000".parse::<RegionConfig>().is_err());
+
+        let mut map = MemoryMap::default();
+        map.add("ram", RegionKind::Ram, 0x8000_0000, 0x1000).unwrap();
+        map.add(rom.name(), rom.kind, rom.base, rom.size as u64).unwrap();
+        assert!(map.add("uart", RegionKind::Device, 0x1800, 8).is_err());
+        assert!(map.add("empty", RegionKind::Ram, 0x4000, 0).is_err());
+        assert_eq!(map.entries()[0].name, "rom");
+        assert_eq!(map.entries()[1].end(), 0x8000_1000);
+    }
+}
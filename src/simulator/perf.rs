@@ -0,0 +1,229 @@
+// Periodic performance samples (IPC, stall causes, misprediction rate) for live charts.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+const MAX_SAMPLES: usize = 512;
+
+// what the guest reads from mhpmcounter3.. / hpmcounter3..: retired instructions of each class in
+// InstClass::ALL order, then load-use stalls and branch mispredictions; the rest read as 0
+pub const HPM_COUNTERS: usize = InstClass::ALL.len() + 2;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PerfCounters {
+    pub cycles: u64,
+    pub insts: u64,
+    pub load_use_stalls: u64,
+    pub mispredicts: u64,
+    pub branches: u64,
+    pub cache_stall_cycles: u64,
+}
+
+// run totals for `info stats` and the summary printed when the program halts
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Statistics {
+    pub cycles: u64,
+    pub insts: u64,
+    pub load_use_stalls: u64,
+    pub control_flushes: u64, // mispredicted branches and jumps, traps and interrupts
+    pub loads: u64,
+    pub stores: u64,
+    pub cache_stall_cycles: u64,
+}
+
+impl Statistics {
+    pub fn ipc(&self) -> f64 {
+        if self.cycles == 0 { 0.0 } else { self.insts as f64 / self.cycles as f64 }
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Cycles: {}, instructions: {}, IPC: {:.3}", self.cycles, self.insts, self.ipc())?;
+        writeln!(f, "Load-use stalls: {}, control flushes: {}", self.load_use_stalls, self.control_flushes)?;
+        writeln!(f, "Memory operations: {} ({} loads, {} stores)", self.loads + self.stores, self.loads, self.stores)?;
+        if self.cache_stall_cycles > 0 {
+            writeln!(f, "Memory stall cycles (cache misses, misaligned accesses): {}", self.cache_stall_cycles)?;
+        }
+        Ok(())
+    }
+}
+
+// one data point covering the last `cycles` cycles (normally `interval`)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PerfSample {
+    pub cycle: u64,
+    pub cycles: u32,
+    pub ipc: f32,
+    pub load_use_stalls: u32,
+    pub flush_cycles: u32,
+    pub mispredicts: u32,
+    pub cache_stall_cycles: u32,
+    pub mispredict_rate: f32,
+}
+
+impl PerfSample {
+    // `count` events in this sample scaled to a rate per 1000 cycles, so samples taken with
+    // different intervals can be compared
+    pub fn per_kilo_cycles(&self, count: u32) -> f32 {
+        if self.cycles == 0 { 0.0 } else { count as f32 * 1000.0 / self.cycles as f32 }
+    }
+}
+
+#[derive(Debug)]
+pub struct PerfMonitor {
+    pub interval: u64,
+    last: PerfCounters,
+    samples: VecDeque<PerfSample>,
+}
+
+impl Default for PerfMonitor {
+    fn default() -> Self {
+        Self {
+            interval: 100,
+            last: PerfCounters::default(),
+            samples: VecDeque::with_capacity(MAX_SAMPLES),
+        }
+    }
+}
+
+impl PerfMonitor {
+    pub fn clear(&mut self) {
+        self.last = PerfCounters::default();
+        self.samples.clear();
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &PerfSample> {
+        self.samples.iter()
+    }
+
+    // called once per cycle; records a sample every `interval` cycles
+    pub fn observe(&mut self, now: PerfCounters) {
+        if now.cycles < self.last.cycles + self.interval.max(1) {
+            return;
+        }
+        let cycles = (now.cycles - self.last.cycles) as f32;
+        let branches = now.branches - self.last.branches;
+        let mispredicts = now.mispredicts - self.last.mispredicts;
+
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(PerfSample {
+            cycle: now.cycles,
+            cycles: cycles as u32,
+            ipc: (now.insts - self.last.insts) as f32 / cycles,
+            load_use_stalls: (now.load_use_stalls - self.last.load_use_stalls) as u32,
+            flush_cycles: (mispredicts * 2) as u32, // a misprediction squashes IF and ID
+            mispredicts: mispredicts as u32,
+            // caches are not rewound by rsi, so a replayed stretch can stall less than the first time
+            cache_stall_cycles: now.cache_stall_cycles.saturating_sub(self.last.cache_stall_cycles) as u32,
+            mispredict_rate: if branches == 0 { 0.0 } else { mispredicts as f32 / branches as f32 },
+        });
+        self.last = now;
+    }
+}
+
+// instruction classes for the dynamic instruction mix
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstClass {
+    Alu,
+    Load,
+    Store,
+    Branch,
+    Jump,
+    MulDiv,
+    Float,
+    System,
+}
+
+impl InstClass {
+    pub const ALL: [InstClass; 8] = [
+        InstClass::Alu, InstClass::Load, InstClass::Store, InstClass::Branch,
+        InstClass::Jump, InstClass::MulDiv, InstClass::Float, InstClass::System,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InstClass::Alu => "ALU",
+            InstClass::Load => "Load",
+            InstClass::Store => "Store",
+            InstClass::Branch => "Branch",
+            InstClass::Jump => "Jump",
+            InstClass::MulDiv => "Mul/Div",
+            InstClass::Float => "Float",
+            InstClass::System => "System",
+        }
+    }
+
+    // classify by opcode (and funct7 for the M extension); F loads and stores count as memory,
+    // atomics as loads like in the hazard logic
+    pub fn of(inst: u32) -> InstClass {
+        match inst & 0x7f {
+            0x03 | 0x07 | 0x2f => InstClass::Load,
+            0x23 | 0x27 => InstClass::Store,
+            0x63 => InstClass::Branch,
+            0x6f | 0x67 => InstClass::Jump,
+            0x73 => InstClass::System,
+            0x33 | 0x3b if inst >> 25 == 1 => InstClass::MulDiv,
+            0x53 | 0x43 | 0x47 | 0x4b | 0x4f => InstClass::Float,
+            _ => InstClass::Alu,
+        }
+    }
+}
+
+// retired instruction counts per class
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct InstMix {
+    counts: [u64; 8],
+}
+
+impl InstMix {
+    pub fn clear(&mut self) {
+        self.counts = [0; 8];
+    }
+
+    pub fn record(&mut self, inst: u32) {
+        self.counts[InstClass::of(inst) as usize] += 1;
+    }
+
+    pub fn counts(&self) -> [u64; 8] {
+        self.counts
+    }
+
+    pub fn count(&self, class: InstClass) -> u64 {
+        self.counts[class as usize]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn fraction(&self, class: InstClass) -> f64 {
+        let total = self.total();
+        if total == 0 { 0.0 } else { self.count(class) as f64 / total as f64 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perf_samples() {
+        let mut perf = PerfMonitor { interval: 500, ..PerfMonitor::default() };
+        let counters = |cycles, insts, mispredicts| PerfCounters { cycles, insts, mispredicts, branches: 10, ..PerfCounters::default() };
+        perf.observe(counters(499, 400, 1));
+        assert_eq!(perf.samples().count(), 0);
+        perf.observe(counters(500, 400, 2));
+        perf.observe(counters(1000, 900, 5));
+
+        let samples: Vec<_> = perf.samples().copied().collect();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1].ipc, 1.0);
+        assert_eq!(samples[1].mispredicts, 3);
+        assert_eq!(samples[1].per_kilo_cycles(samples[1].mispredicts), 6.0);
+    }
+}
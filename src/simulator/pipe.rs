@@ -2,19 +2,27 @@
 // use crate::common::*;
 // use crate::memory::*;
 
-use colored::Colorize;
+use super::utils::Colorize;
 
 use super::decode::*;
 use super::cpu::*;
 use super::mem::*;
 use super::decode::*;
-
-const MEM_BASE: u64 = 0x8000_0000; 
-const MEM_SIZE: usize = 0x80_00000; 
+use super::timeline::*;
+use super::perf::*;
+use super::cache::*;
+use super::heatmap::*;
+use super::ftrace::*;
+use super::profile::*;
+use super::compressed;
+use super::breakpoint::*;
+use super::trace::Tracer;
+use super::undo::{CycleUndo, UndoLog};
 
 pub struct Pipeline {
     pub cpu: CPUState,
     pub debug_mode: bool,
+    pub quiet: bool, // no per-cycle state dump on stdout
     
     pub D_reg: IFIDReg,
     pub E_reg: IDEXReg,
@@ -31,6 +39,33 @@ pub struct Pipeline {
     pub branch_count: u32,
     pub data_hazard_count: u32,
 
+    pub branch_exec_count: u32,
+    pub flush_count: u32, // mispredictions, traps and interrupts
+
+    pub next_seq: u64,
+    pub timeline: Timeline,
+    pub perf: PerfMonitor,
+
+    // optional cache models, None when disabled
+    pub icache: Option<Cache>,
+    pub dcache: Option<Cache>,
+    pub cache_stall: u64, // cycles left until the last miss is served
+    pub cache_stall_cycles: u64,
+
+    pub heatmap: AccessHeatmap,
+    pub calls: CallTracker,
+    pub inst_mix: InstMix,
+    pub profile: Profiler,
+    pub fault: Option<DecodeError>, // illegal instruction that stopped the program
+    pub breakpoints: BreakpointManager,
+    pub breakpoint_hit: Option<Hit>, // set by the cycle whose commit hit a breakpoint or watchpoint
+    pub tracer: Tracer,
+    pub undo: UndoLog,
+
+    pub entry: u64, // pc after init
+    pub stack_top: u64, // sp after init
+    pub icache_geometry: CacheGeometry,
+    pub dcache_geometry: CacheGeometry,
 }
 
 const NOP_INST: u32 = 0x13; // NOP instruction
@@ -40,6 +75,7 @@ impl Pipeline {
         Self {
             cpu: CPUState::new(),
             debug_mode: false,
+            quiet: false,
             D_reg: IFIDReg::default(),
             E_reg: IDEXReg::default(),
             M_reg: EXMEMReg::default(),
@@ -52,15 +88,41 @@ impl Pipeline {
             d_stall: false,
             branch_count: 0,
             data_hazard_count: 0,
+            branch_exec_count: 0,
+            flush_count: 0,
+            next_seq: 1,
+            timeline: Timeline::default(),
+            perf: PerfMonitor::default(),
+            icache: None,
+            dcache: None,
+            cache_stall: 0,
+            cache_stall_cycles: 0,
+            heatmap: AccessHeatmap::default(),
+            calls: CallTracker::default(),
+            inst_mix: InstMix::default(),
+            profile: Profiler::default(),
+            fault: None,
+            breakpoints: BreakpointManager::default(),
+            breakpoint_hit: None,
+            tracer: Tracer::default(),
+            undo: UndoLog::default(),
+            entry: MEM_BASE,
+            stack_top: MEM_BASE + MEM_SIZE as u64,
+            icache_geometry: CacheGeometry::default(),
+            dcache_geometry: CacheGeometry::default(),
         }
     }
 
     pub fn init(&mut self) {
-        self.cpu.pc = MEM_BASE;
+        self.cpu.pc = self.entry;
         self.cpu.reg[0] = 0;
+        self.cpu.reg[2] = self.stack_top;
         self.cpu.running = true;
         self.cpu.cycle_count = 0;
         self.cpu.inst_count = 0;
+        self.cpu.csr = Csrs::default();
+        self.cpu.trap_pc = None;
+        self.cpu.hpm = [0; HPM_COUNTERS];
         
         self.D_reg.inst = NOP_INST;
         self.E_reg.inst = NOP_INST;
@@ -69,34 +131,159 @@ impl Pipeline {
         
         self.f_stall = false;
         self.d_stall = false;
+
+        self.next_seq = 1;
+        self.timeline.clear();
+        self.perf.clear();
+        if let Some(c) = &mut self.icache { c.reset(); }
+        if let Some(c) = &mut self.dcache { c.reset(); }
+        self.cache_stall = 0;
+        self.cache_stall_cycles = 0;
+        self.heatmap.clear();
+        self.calls.clear();
+        self.inst_mix.clear();
+        self.profile.clear();
+        self.fault = None;
+        self.breakpoint_hit = None;
+        self.tracer.clear();
+        self.undo.clear();
+    }
+
+    pub fn enable_caches(&mut self, enable: bool) {
+        if enable {
+            self.icache = Some(Cache::with_geometry("I-Cache", self.icache_geometry));
+            self.dcache = Some(Cache::with_geometry("D-Cache", self.dcache_geometry));
+        } else {
+            self.icache = None;
+            self.dcache = None;
+        }
     }
 
     pub fn step(&mut self, mem: &mut Memory) {
+        if self.undo.depth > 0 {
+            self.undo.attach_writes(mem.record_writes());
+            self.undo.push(CycleUndo::capture(self, mem));
+        }
+        self.breakpoint_hit = None;
         self.cpu.cycle_count += 1;
+        mem.clint.tick();
+        self.cpu.set_timer_pending(mem.clint.timer_pending());
+
+        // a cache miss freezes every stage until the line arrives
+        if self.cache_stall > 0 {
+            self.cache_stall -= 1;
+            self.cache_stall_cycles += 1;
+            self.perf.observe(self.perf_counters());
+            return;
+        }
 
-
-        self.print_state(mem);
+        if !self.quiet {
+            self.print_state(mem);
+        }
 
         // Write Back Stage
-        writeback_stage(&mut self.cpu, &self.W_reg);
+        if self.W_reg.seq != 0 {
+            self.tracer.commit(self.W_reg.pc, self.W_reg.inst);
+        }
+        if let Err(e) = writeback_stage(&mut self.cpu, &self.W_reg) {
+            if !self.quiet {
+                println!("{}", e.to_string().red());
+            }
+            if !self.quiet || self.tracer.flags.itrace {
+                self.tracer.dump_ring();
+            }
+            self.cpu.running = false;
+            self.fault = Some(e);
+            return;
+        }
+        if self.W_reg.seq != 0 {
+            self.inst_mix.record(self.W_reg.inst);
+            self.profile.record(Some((self.W_reg.pc, self.W_reg.inst)));
+        } else {
+            self.profile.record(None);
+        }
 
         // Memory Stage
+        let watched = self.watched_access(mem);
         self.w_reg = memory_stage(&mut self.cpu, &self.M_reg, mem);
+        if let Some((size, old)) = watched {
+            let m = &self.M_reg;
+            let name = check_inst(m.inst).map_or("", |i| i.name);
+            let access = WatchHit {
+                id: 0,
+                pc: m.pc,
+                addr: m.alu_out,
+                size,
+                read: m.load,
+                write: m.store || (is_atomic(name) && !name.starts_with("lr")),
+                old,
+                new: mem.mem_read(m.alu_out, size).unwrap_or(0),
+            };
+            self.breakpoint_hit = self.breakpoints.check_access(access).map(Hit::Watchpoint);
+        }
+        if self.M_reg.seq != 0 && (self.M_reg.load || self.M_reg.store) && self.tracer.flags.mtrace {
+            let m = &self.M_reg;
+            let size = 1 << (m.inst >> 12 & 3);
+            let value = if m.load { self.w_reg.mem_data } else { mem.mem_read(m.alu_out, size).unwrap_or(0) };
+            self.tracer.mem_access(m.pc, m.alu_out, size, m.store, value);
+        }
+        if self.M_reg.load || self.M_reg.store {
+            self.heatmap.record(self.M_reg.alu_out, self.M_reg.store);
+            if !self.M_reg.alu_out.is_multiple_of(1 << (self.M_reg.inst >> 12 & 3)) {
+                self.cache_stall += mem.misaligned_penalty; // split into two accesses
+            }
+            if let Some(dcache) = &mut self.dcache {
+                if dcache.access(self.M_reg.alu_out, self.M_reg.store).filled() {
+                    self.cache_stall += dcache.miss_penalty;
+                }
+            }
+        }
 
         // Execute Stage
-        self.m_reg = execute_stage(&mut self.cpu, &self.E_reg);
+        self.cpu.hpm = self.hpm_counters();
+        let running = self.cpu.running;
+        self.m_reg = execute_stage(&mut self.cpu, &self.E_reg, mem);
+        if running && !self.cpu.running && !self.cpu.quiet {
+            print!("{}", self.statistics()); // after the exit message of ebreak / exit
+        }
+        self.trace_call();
 
         // Decode Stage
-        self.e_reg = decode_stage(&self.cpu, &self.D_reg);
+        // an illegal word may be on the wrong path, so let it flow down as a no-op
+        // and only trap once it reaches EX (or fault at writeback without a trap handler)
+        self.e_reg = decode_stage(&self.cpu, &self.D_reg).unwrap_or(IDEXReg {
+            pc: self.D_reg.pc,
+            inst: self.D_reg.inst,
+            seq: self.D_reg.seq,
+            compressed: self.D_reg.compressed,
+            ..IDEXReg::default()
+        });
 
         // Fetch Stage
+        // after a stall IF fetches the same instruction again (same pc, seq not handed out yet),
+        // which is no new cache access
+        let refetch = self.d_reg.seq == self.next_seq && self.d_reg.pc == self.cpu.pc;
         self.d_reg.pc = self.cpu.pc;
-        self.d_reg.inst = mem.inst_fetch(self.cpu.pc).expect("Invalid instruction fetch");
-        self.cpu.pred_pc = self.cpu.pc.wrapping_add(4);
+        let (inst, compressed) = compressed::fetch(mem, self.cpu.pc).unwrap_or((0, false)); // 0 is not a valid encoding
+        self.d_reg.inst = inst;
+        self.d_reg.compressed = compressed;
+        self.d_reg.seq = self.next_seq;
+        if let Some(icache) = self.icache.as_mut().filter(|_| !refetch) {
+            if icache.access(self.cpu.pc, false).filled() {
+                self.cache_stall += icache.miss_penalty;
+            }
+        }
+        self.cpu.pred_pc = self.cpu.pc.wrapping_add(if compressed { 2 } else { 4 });
         
         // // Data hazard detection
+        let fetched = self.d_reg;
         self.data_hazard();
-        self.branch_pred_miss();
+        let flush = self.branch_pred_miss() || self.take_trap() || self.take_interrupt();
+        self.record_cycle(fetched, flush);
+        if flush { self.flush_count += 1; }
+        if !self.f_stall { self.next_seq += 1; }
+        if self.E_reg.jump && self.E_reg.seq != 0 { self.branch_exec_count += 1; }
+        self.perf.observe(self.perf_counters());
 
         // // Update all state 
         self.W_reg = self.w_reg;
@@ -108,6 +295,9 @@ impl Pipeline {
         if self.d_stall { self.d_stall = false; }
         if self.f_stall { self.f_stall = false; }
 
+        if self.breakpoint_hit.is_none() && self.W_reg.seq != 0 {
+            self.breakpoint_hit = self.breakpoints.check(self.W_reg.pc, &self.cpu).map(Hit::Breakpoint);
+        }
 
     }
 
@@ -124,6 +314,13 @@ impl Pipeline {
     }
 
     fn data_hazard(&mut self) {
+        // ecall reads a0-a7 straight from the register file in EX: wait in ID until the
+        // instruction ahead has left EX, it then writes back before ecall executes
+        if check_inst(self.e_reg.inst).map(|i| i.name) == Some("ecall") && self.E_reg.seq != 0 && self.E_reg.rd != 0 && !self.E_reg.store {
+            self.exec_stall();
+            return;
+        }
+
         let alu_a = self.e_reg.rs1; 
         let alu_b = self.e_reg.rs2;
 
@@ -154,9 +351,114 @@ impl Pipeline {
             else {self.e_reg.src2 = self.w_reg.alu_out;}
         }
     }
+
+        // the third source of fmadd and friends, 0 for everything else (and after a stall)
+        let alu_c = self.e_reg.rs3;
+        if alu_c == dst_e && dst_e != 0 && !self.E_reg.store {
+            if self.E_reg.load { self.exec_stall(); }  // load-use hazard
+            else { self.e_reg.src3 = self.m_reg.alu_out; }
+        }
+        else if alu_c == dst_m && dst_m != 0 && !self.M_reg.store {
+            if self.M_reg.load { self.e_reg.src3 = self.w_reg.mem_data; }
+            else { self.e_reg.src3 = self.w_reg.alu_out; }
+        }
+    }
+
+    // what hpmcounter3.. read, see HPM_COUNTERS
+    fn hpm_counters(&self) -> [u64; HPM_COUNTERS] {
+        let mut hpm = [0; HPM_COUNTERS];
+        hpm[..InstClass::ALL.len()].copy_from_slice(&self.inst_mix.counts());
+        hpm[InstClass::ALL.len()] = self.data_hazard_count as u64;
+        hpm[InstClass::ALL.len() + 1] = self.branch_count as u64;
+        hpm
+    }
+
+    // size and current value of the memory the instruction in MEM is about to access, when there
+    // are watchpoints to check it against
+    fn watched_access(&self, mem: &Memory) -> Option<(usize, u64)> {
+        let m = &self.M_reg;
+        if m.seq == 0 || !(m.load || m.store) || !self.breakpoints.has_watchpoints() {
+            return None;
+        }
+        let size = 1 << (m.inst >> 12 & 3); // funct3 of every load, store and AMO
+        Some((size, mem.mem_read(m.alu_out, size).unwrap_or(0)))
+    }
+
+    // pc of the instruction in each stage from IF to WB, None for a bubble; the latches hold what
+    // enters ID..WB next cycle, like `info pipeline` shows them
+    pub fn stage_pcs(&self) -> [Option<u64>; 5] {
+        let pc = |seq: u64, pc: u64| (seq != 0).then_some(pc);
+        [
+            self.cpu.running.then_some(self.cpu.pc),
+            pc(self.D_reg.seq, self.D_reg.pc),
+            pc(self.E_reg.seq, self.E_reg.pc),
+            pc(self.M_reg.seq, self.M_reg.pc),
+            pc(self.W_reg.seq, self.W_reg.pc),
+        ]
     }
 
-    fn branch_pred_miss(&mut self) {    
+    pub fn statistics(&self) -> Statistics {
+        Statistics {
+            cycles: self.cpu.cycle_count as u64,
+            insts: self.cpu.inst_count as u64,
+            load_use_stalls: self.data_hazard_count as u64,
+            control_flushes: self.flush_count as u64,
+            loads: self.inst_mix.count(InstClass::Load),
+            stores: self.inst_mix.count(InstClass::Store),
+            cache_stall_cycles: self.cache_stall_cycles,
+        }
+    }
+
+    pub fn perf_counters(&self) -> PerfCounters {
+        PerfCounters {
+            cycles: self.cpu.cycle_count as u64,
+            insts: self.cpu.inst_count as u64,
+            load_use_stalls: self.data_hazard_count as u64,
+            mispredicts: self.branch_count as u64,
+            branches: self.branch_exec_count as u64,
+            cache_stall_cycles: self.cache_stall_cycles,
+        }
+    }
+
+    // jumps resolve in EX and are never squashed afterwards, so track calls here
+    fn trace_call(&mut self) {
+        let e = &self.E_reg;
+        if !e.jump || e.seq == 0 {
+            return;
+        }
+        match check_inst(e.inst).map(|i| i.name) {
+            Some("jal") | Some("jalr") if e.rd == 1 => {
+                self.tracer.call(self.calls.stack.len(), e.pc, self.cpu.next_pc);
+                self.calls.on_call(e.pc, e.fall_through(), self.cpu.next_pc, self.cpu.reg[2], self.cpu.reg[8]);
+            }
+            Some("jalr") if e.rd == 0 && e.rs1 == 1 => {
+                let target = self.cpu.next_pc;
+                if let Some(depth) = self.calls.stack.iter().rposition(|f| f.ret_addr == target) {
+                    self.tracer.ret(depth, e.pc, self.calls.stack[depth].func);
+                }
+                self.calls.on_return(target);
+            }
+            _ => {}
+        }
+    }
+
+    fn record_cycle(&mut self, fetched: IFIDReg, flush: bool) {
+        let slot = |seq: u64, pc: u64, inst: u32| StageSlot { seq, pc, inst };
+        self.timeline.record(CycleRecord {
+            cycle: self.cpu.cycle_count,
+            stages: [
+                slot(fetched.seq, fetched.pc, fetched.inst),
+                slot(self.D_reg.seq, self.D_reg.pc, self.D_reg.inst),
+                slot(self.E_reg.seq, self.E_reg.pc, self.E_reg.inst),
+                slot(self.M_reg.seq, self.M_reg.pc, self.M_reg.inst),
+                slot(self.W_reg.seq, self.W_reg.pc, self.W_reg.inst),
+            ],
+            stall: self.d_stall,
+            flush,
+        });
+    }
+
+    fn branch_pred_miss(&mut self) -> bool {    
         if self.E_reg.jump && self.cpu.next_pc != self.D_reg.pc { /* branch prediction miss */
             self.e_reg = IDEXReg {
                 inst: NOP_INST,
@@ -171,9 +473,84 @@ impl Pipeline {
             self.cpu.pred_pc = self.cpu.next_pc;
 
             self.branch_count += 1; 
+            return true;
+        }
+        false
+    }
+
+    // an instruction trapped in EX: squash the younger ones and fetch the handler
+    fn take_trap(&mut self) -> bool {
+        match self.cpu.trap_pc.take() {
+            Some(handler) => {
+                self.flush_to(handler);
+                true
+            }
+            None => false,
         }
     }
 
+    // a pending, enabled interrupt is taken in place of the instruction about to enter EX: it
+    // becomes mepc and fetch restarts at mtvec. Bubbles are skipped, so mepc is a real instruction.
+    fn take_interrupt(&mut self) -> bool {
+        if self.e_reg.seq == 0 || !self.cpu.interrupt_pending() {
+            return false;
+        }
+        let handler = self.cpu.trap(self.e_reg.pc, IRQ_M_TIMER);
+        self.flush_to(handler);
+        true
+    }
+
+    fn flush_to(&mut self, pc: u64) {
+        self.cpu.pred_pc = pc;
+        self.e_reg = IDEXReg {
+            inst: NOP_INST,
+            ..IDEXReg::default()
+        };
+        self.d_reg = IFIDReg {
+            inst: NOP_INST,
+            ..IFIDReg::default()
+        };
+        self.d_stall = false;
+        self.f_stall = false;
+    }
+
+    // undo the last cycle, false when there is no history left
+    pub fn step_back(&mut self, mem: &mut Memory) -> bool {
+        if self.undo.depth == 0 {
+            return false; // recording is off, and so is the memory journal
+        }
+        self.undo.attach_writes(mem.record_writes());
+        match self.undo.pop() {
+            Some(cycle) => {
+                cycle.restore(self, mem);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // silence all stdout output (state dump, exit message, UART echo),
+    // used by frontends that own the terminal
+    pub fn set_quiet(&mut self, mem: &mut Memory, quiet: bool) {
+        self.quiet = quiet;
+        self.cpu.quiet = quiet;
+        mem.uart.quiet = quiet;
+    }
+
+    // redirect fetch to `pc`, squashing the instruction in IF/ID and the one latched to enter it
+    pub fn redirect(&mut self, pc: u64) {
+        self.cpu.pc = pc;
+        self.cpu.pred_pc = pc;
+        let bubble = IFIDReg {
+            inst: NOP_INST,
+            ..IFIDReg::default()
+        };
+        self.D_reg = bubble;
+        self.d_reg = bubble;
+        self.d_stall = false;
+        self.f_stall = false;
+    }
+
     fn pipe_check_rv64m(&mut self) {
 
     }
@@ -181,7 +558,7 @@ impl Pipeline {
     pub fn print_state(&self, mem: &mut Memory) {
         println!("{}", "CPU State:".green());
         println!("  PC: 0x{:016x}", self.cpu.pc);
-        println!("  Cycle: {}, Inst: 0x{:08x}", self.cpu.cycle_count, mem.inst_fetch(self.cpu.pc).expect("Invalid instruction fetch"));
+        println!("  Cycle: {}, Inst: 0x{:08x}", self.cpu.cycle_count, mem.inst_fetch(self.cpu.pc).unwrap_or(0));
         
         println!("{}", "\nPipeline Registers:".blue());
         println!("  IF/ID: PC=0x{:08x}, INST=0x{:08x}", 
@@ -194,24 +571,43 @@ impl Pipeline {
             self.W_reg.pc, self.W_reg.inst, self.W_reg.rd, self.W_reg.alu_out);
         
         println!("\nRegisters:");
-        for i in 0..32 {
-            if self.cpu.reg[i] != 0 {
-                let name = match i {
-                    0 => "zero",
-                    1 => "ra", 2 => "sp", 3 => "gp", 4 => "tp",
-                    5 => "t0", 6 => "t1", 7 => "t2",
-                    8 => "s0", 9 => "s1",
-                    10 => "a0", 11 => "a1", 12 => "a2", 13 => "a3",
-                    14 => "a4", 15 => "a5", 16 => "a6", 17 => "a7",
-                    18 => "s2", 19 => "s3", 20 => "s4", 21 => "s5",
-                    22 => "s6", 23 => "s7", 24 => "s8", 25 => "s9",
-                    26 => "s10", 27 => "s11",
-                    28 => "t3", 29 => "t4", 30 => "t5", 31 => "t6",
-                    _ => continue,
-                };
-                println!("  {} (x{}): 0x{:016x}", name, i, self.cpu.reg[i]);
+        for (i, (name, value)) in REG_NAMES.iter().zip(self.cpu.reg).enumerate() {
+            if value != 0 {
+                println!("  {} (x{}): 0x{:016x}", name, i, value);
+            }
+        }
+        for (i, (name, value)) in FREG_NAMES.iter().zip(self.cpu.freg).enumerate() {
+            if value != 0 {
+                println!("  {} (f{}): 0x{:016x}", name, i, value);
             }
         }
         println!();
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::super::embed::Simulator;
+
+    #[test]
+    fn test_redirect() {
+        // set pc while `li a0, 3` sits in IF/ID: neither it nor `li a0, 4` may retire
+        let asm = "main:
+  li a0, 1
+  li a0, 2
+three:
+  li a0, 3
+  li a0, 4
+skip:
+  addi a0, a0, 10
+  ret
+";
+        let symbols = crate::codegen::encode::assemble(asm, 0x8000_0000).unwrap().symbols;
+        let mut sim = Simulator::load(&crate::runner::test_image(asm)).unwrap();
+        while sim.pipeline.D_reg.pc != symbols["three"] {
+            sim.pipeline.step(&mut sim.mem);
+        }
+        sim.pipeline.redirect(symbols["skip"]);
+        assert_eq!((sim.pipeline.D_reg.seq, sim.pipeline.d_reg.seq), (0, 0));
+        assert_eq!(sim.run(Some(1000)), Some(12));
+    }
+}
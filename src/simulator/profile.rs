@@ -0,0 +1,171 @@
+// Exact execution profile: commits and cycles per pc, aggregated into basic blocks.
+// Every cycle is charged to an instruction: bubbles (stalls, flushes, cache misses) go to the
+// next instruction that commits, since that is the one the pipeline was waiting for.
+// Blocks are found from the run itself: a block ends after a branch or jump and a new one
+// starts wherever control arrived from somewhere other than pc - 4.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::config::SimConfig;
+use super::disasm::disassemble;
+use super::perf::InstClass;
+use super::srcmap::SourceMap;
+
+const MAX_CYCLES: u64 = 500_000_000;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PcProfile {
+    pub inst: u32,
+    pub commits: u64,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Block {
+    pub start: u64,
+    pub end: u64, // last instruction
+    pub entries: u64,
+    pub insts: u64,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Profiler {
+    pcs: BTreeMap<u64, PcProfile>,
+    leaders: BTreeSet<u64>,
+    last: Option<(u64, u32)>, // last committed pc and inst
+    pending: u64,             // bubble cycles not charged yet
+}
+
+fn ends_block(inst: u32) -> bool {
+    matches!(InstClass::of(inst), InstClass::Branch | InstClass::Jump)
+}
+
+impl Profiler {
+    pub fn clear(&mut self) {
+        self.pcs.clear();
+        self.leaders.clear();
+        self.last = None;
+        self.pending = 0;
+    }
+
+    // called once per cycle with the instruction committing this cycle, if any
+    pub fn record(&mut self, commit: Option<(u64, u32)>) {
+        self.pending += 1;
+        let Some((pc, inst)) = commit else { return };
+        let leader = match self.last {
+            Some((last_pc, last_inst)) => ends_block(last_inst) || pc != last_pc.wrapping_add(4),
+            None => true,
+        };
+        if leader {
+            self.leaders.insert(pc);
+        }
+        let entry = self.pcs.entry(pc).or_default();
+        entry.inst = inst;
+        entry.commits += 1;
+        entry.cycles += self.pending;
+        self.pending = 0;
+        self.last = Some((pc, inst));
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.pcs.values().map(|p| p.cycles).sum::<u64>() + self.pending
+    }
+
+    // executed code split into basic blocks, in address order
+    pub fn blocks(&self) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut prev: Option<(u64, u32)> = None;
+        for (&pc, p) in &self.pcs {
+            let split = match prev {
+                Some((prev_pc, prev_inst)) => self.leaders.contains(&pc) || ends_block(prev_inst) || pc != prev_pc.wrapping_add(4),
+                None => true,
+            };
+            match blocks.last_mut() {
+                Some(block) if !split => {
+                    block.end = pc;
+                    block.insts += p.commits;
+                    block.cycles += p.cycles;
+                }
+                _ => blocks.push(Block { start: pc, end: pc, entries: p.commits, insts: p.commits, cycles: p.cycles }),
+            }
+            prev = Some((pc, p.inst));
+        }
+        blocks
+    }
+
+    // the `top` blocks with the most cycles, with per-instruction disassembly
+    pub fn report(&self, top: usize, srcmap: Option<&SourceMap>) -> String {
+        let total = self.total_cycles().max(1) as f64;
+        let mut blocks = self.blocks();
+        if blocks.is_empty() {
+            return String::from("No instructions committed yet\n");
+        }
+        blocks.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.start.cmp(&b.start)));
+
+        let mut out = format!("{} blocks executed, {} cycles; top {}:\n", blocks.len(), self.total_cycles(), top.min(blocks.len()));
+        for (rank, block) in blocks.iter().take(top).enumerate() {
+            let location = match srcmap.and_then(|m| m.symbol_at(block.start)) {
+                Some((name, 0)) => format!(" <{}>", name),
+                Some((name, off)) => format!(" <{}+{}>", name, off),
+                None => String::new(),
+            };
+            let line = srcmap.and_then(|m| m.line_of(block.start)).map_or(String::new(), |l| format!(" line {}", l));
+            out.push_str(&format!(
+                "\n#{} 0x{:08x}-0x{:08x}{}{}: {:.1}% cycles, {} cycles, {} entries, {} insts, CPI {:.2}\n",
+                rank + 1, block.start, block.end, location, line,
+                block.cycles as f64 / total * 100.0, block.cycles, block.entries, block.insts,
+                block.cycles as f64 / block.insts.max(1) as f64,
+            ));
+            for (&pc, p) in self.pcs.range(block.start..=block.end) {
+                out.push_str(&format!("  0x{:08x} {:>10} {:>10}  {}\n", pc, p.commits, p.cycles, disassemble(pc, p.inst)));
+            }
+        }
+        out
+    }
+}
+
+// `profile IMAGE [N]`: run the image to completion and print the N hottest blocks
+pub fn run_profile(image: &str, config: &SimConfig, top: usize) -> Result<(), String> {
+    let (mut pipeline, mut mem) = config.build(image)?;
+    pipeline.set_quiet(&mut mem, true);
+    while pipeline.cpu.running {
+        if pipeline.cpu.cycle_count as u64 == MAX_CYCLES {
+            return Err(format!("no exit after {} cycles", MAX_CYCLES));
+        }
+        pipeline.step(&mut mem);
+    }
+    if let Some(fault) = &pipeline.fault {
+        println!("{}", fault);
+    }
+    let srcmap = config.symbols(image).ok();
+    print!("{}", pipeline.profile.report(top, srcmap.as_ref()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks() {
+        let mut profiler = Profiler::default();
+        let addi = 0x0010_0093; // addi ra, zero, 1
+        let bne = 0xfe00_9ee3; // bne ra, zero, -4
+        // straight-line entry, then a two-instruction loop taken twice
+        profiler.record(Some((0x1000, addi)));
+        for _ in 0..2 {
+            profiler.record(Some((0x1004, addi)));
+            profiler.record(None);
+            profiler.record(Some((0x1008, bne)));
+        }
+        profiler.record(Some((0x100c, addi)));
+
+        let blocks = profiler.blocks();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!((blocks[1].start, blocks[1].end, blocks[1].entries, blocks[1].insts), (0x1004, 0x1008, 2, 4));
+        assert_eq!(blocks[1].cycles, 6);
+        assert_eq!(profiler.total_cycles(), 8);
+        assert!(profiler.report(1, None).contains("#1 0x00001004-0x00001008"));
+    }
+}
@@ -0,0 +1,334 @@
+// Difftest: a reference interpreter run in lockstep with the pipeline, `difftest IMAGE`.
+//
+// RefCpu is a plain fetch-decode-execute loop for RV64IM with its own copy of memory, written
+// independently of decode.rs so the two implementations check each other. Each time the pipeline
+// retires an instruction the reference executes the same one, then the pc and all x registers are
+// compared; the first mismatch stops the run and both states are printed.
+//
+// Instructions the reference does not model (CSRs, ecall/ebreak/mret, F and A extensions) and
+// loads from MMIO devices are skipped NEMU-style: the reference copies the registers from the
+// pipeline after it retires them, plus any memory they changed. A timer interrupt taken between two
+// instructions, or a load/store fault, is accepted when mepc is where the reference expected to
+// continue.
+
+use super::compressed;
+use super::config::SimConfig;
+use super::cpu::{CPUState, CAUSE_MISALIGNED_LOAD, CAUSE_STORE_ACCESS, IRQ_M_TIMER, REG_NAMES};
+use super::disasm::disassemble;
+use super::mem::Memory;
+
+const MAX_CYCLES: u64 = 500_000_000;
+
+// what happened to the instruction the reference was asked to run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefStep {
+    Executed,
+    Skipped,
+}
+
+pub struct RefCpu {
+    pub reg: [u64; 32],
+    pub pc: Option<u64>, // None after a skip: the next retired pc is taken from the pipeline
+    mem: Memory,
+}
+
+fn sext(value: u64, bits: u32) -> u64 {
+    (((value << (64 - bits)) as i64) >> (64 - bits)) as u64
+}
+
+impl RefCpu {
+    pub fn new(config: &SimConfig, image: &str) -> Result<Self, String> {
+        let mut mem = config.new_memory();
+        mem.load_image(image).map_err(|e| format!("{}: {}", image, e))?;
+        let mut reg = CPUState::new().reg;
+        reg[2] = config.stack_top(); // same as Pipeline::init
+        Ok(Self { reg, pc: Some(mem.entry()), mem })
+    }
+
+    fn load(&self, addr: u64, len: usize) -> Option<u64> {
+        self.mem.mem_read(addr, len).ok()
+    }
+
+    // run the instruction at `pc`
+    pub fn step(&mut self, pc: u64) -> RefStep {
+        let Ok((inst, compressed)) = compressed::fetch(&self.mem, pc) else {
+            return RefStep::Skipped;
+        };
+        let inst = inst as u64;
+        let rd = (inst >> 7 & 0x1f) as usize;
+        let rs1 = self.reg[(inst >> 15 & 0x1f) as usize];
+        let rs2 = self.reg[(inst >> 20 & 0x1f) as usize];
+        let funct3 = inst >> 12 & 7;
+        let funct7 = inst >> 25;
+        let imm_i = sext(inst >> 20, 12);
+        let imm_s = sext((inst >> 25) << 5 | (inst >> 7 & 0x1f), 12);
+        let imm_b = sext((inst >> 31) << 12 | (inst >> 7 & 1) << 11 | (inst >> 25 & 0x3f) << 5 | (inst >> 8 & 0xf) << 1, 13);
+        let imm_j = sext((inst >> 31) << 20 | (inst >> 12 & 0xff) << 12 | (inst >> 20 & 1) << 11 | (inst >> 21 & 0x3ff) << 1, 21);
+        let next = pc.wrapping_add(if compressed { 2 } else { 4 });
+        let mut next_pc = next;
+
+        let result = match inst & 0x7f {
+            0x37 => Some(sext(inst & 0xffff_f000, 32)),
+            0x17 => Some(pc.wrapping_add(sext(inst & 0xffff_f000, 32))),
+            0x6f => {
+                next_pc = pc.wrapping_add(imm_j);
+                Some(next)
+            }
+            0x67 if funct3 == 0 => {
+                next_pc = rs1.wrapping_add(imm_i) & !1;
+                Some(next)
+            }
+            0x63 => {
+                let taken = match funct3 {
+                    0 => rs1 == rs2,
+                    1 => rs1 != rs2,
+                    4 => (rs1 as i64) < (rs2 as i64),
+                    5 => (rs1 as i64) >= (rs2 as i64),
+                    6 => rs1 < rs2,
+                    7 => rs1 >= rs2,
+                    _ => return RefStep::Skipped,
+                };
+                if taken {
+                    next_pc = pc.wrapping_add(imm_b);
+                }
+                None
+            }
+            0x03 => {
+                let len = 1 << (funct3 & 3);
+                // MMIO and anything outside RAM is skipped
+                let Some(value) = self.load(rs1.wrapping_add(imm_i), len) else {
+                    return RefStep::Skipped;
+                };
+                match funct3 {
+                    0..=2 => Some(sext(value, 8 * len as u32)),
+                    3..=6 => Some(value),
+                    _ => return RefStep::Skipped,
+                }
+            }
+            0x23 if funct3 < 4 => {
+                let addr = rs1.wrapping_add(imm_s);
+                // device writes have no effect on RAM
+                let _ = self.mem.mem_write(addr, 1 << funct3, rs2);
+                None
+            }
+            0x13 => {
+                let shamt = (imm_i & 0x3f) as u32;
+                Some(match funct3 {
+                    0 => rs1.wrapping_add(imm_i),
+                    1 => rs1 << shamt,
+                    2 => ((rs1 as i64) < (imm_i as i64)) as u64,
+                    3 => (rs1 < imm_i) as u64,
+                    4 => rs1 ^ imm_i,
+                    5 if inst >> 30 & 1 == 1 => ((rs1 as i64) >> shamt) as u64,
+                    5 => rs1 >> shamt,
+                    6 => rs1 | imm_i,
+                    _ => rs1 & imm_i,
+                })
+            }
+            0x1b => {
+                let shamt = (imm_i & 0x1f) as u32;
+                Some(match funct3 {
+                    0 => sext(rs1.wrapping_add(imm_i), 32),
+                    1 => sext(rs1 << shamt, 32),
+                    5 if inst >> 30 & 1 == 1 => ((rs1 as i32) >> shamt) as i64 as u64,
+                    5 => sext((rs1 as u32 >> shamt) as u64, 32),
+                    _ => return RefStep::Skipped,
+                })
+            }
+            0x33 => match Self::alu(funct7, funct3, rs1, rs2) {
+                Some(value) => Some(value),
+                None => return RefStep::Skipped,
+            },
+            0x3b => match Self::alu32(funct7, funct3, rs1, rs2) {
+                Some(value) => Some(value),
+                None => return RefStep::Skipped,
+            },
+            0x0f => None, // fence
+            _ => return RefStep::Skipped,
+        };
+
+        if let Some(value) = result {
+            if rd != 0 {
+                self.reg[rd] = value;
+            }
+        }
+        self.pc = Some(next_pc);
+        RefStep::Executed
+    }
+
+    fn alu(funct7: u64, funct3: u64, a: u64, b: u64) -> Option<u64> {
+        let shamt = (b & 0x3f) as u32;
+        Some(match (funct7, funct3) {
+            (0x00, 0) => a.wrapping_add(b),
+            (0x20, 0) => a.wrapping_sub(b),
+            (0x00, 1) => a << shamt,
+            (0x00, 2) => ((a as i64) < (b as i64)) as u64,
+            (0x00, 3) => (a < b) as u64,
+            (0x00, 4) => a ^ b,
+            (0x00, 5) => a >> shamt,
+            (0x20, 5) => ((a as i64) >> shamt) as u64,
+            (0x00, 6) => a | b,
+            (0x00, 7) => a & b,
+            (0x01, 0) => a.wrapping_mul(b),
+            (0x01, 1) => ((a as i64 as i128 * b as i64 as i128) >> 64) as u64,
+            (0x01, 2) => ((a as i64 as i128 * b as u128 as i128) >> 64) as u64,
+            (0x01, 3) => ((a as u128 * b as u128) >> 64) as u64,
+            (0x01, 4) if b == 0 => u64::MAX,
+            (0x01, 4) => (a as i64).wrapping_div(b as i64) as u64,
+            (0x01, 5) if b == 0 => u64::MAX,
+            (0x01, 5) => a / b,
+            (0x01, 6) if b == 0 => a,
+            (0x01, 6) => (a as i64).wrapping_rem(b as i64) as u64,
+            (0x01, 7) if b == 0 => a,
+            (0x01, 7) => a % b,
+            _ => return None,
+        })
+    }
+
+    fn alu32(funct7: u64, funct3: u64, a: u64, b: u64) -> Option<u64> {
+        let (a, b) = (a as u32, b as u32);
+        let shamt = b & 0x1f;
+        let value = match (funct7, funct3) {
+            (0x00, 0) => a.wrapping_add(b),
+            (0x20, 0) => a.wrapping_sub(b),
+            (0x00, 1) => a << shamt,
+            (0x00, 5) => a >> shamt,
+            (0x20, 5) => ((a as i32) >> shamt) as u32,
+            (0x01, 0) => a.wrapping_mul(b),
+            (0x01, 4) if b == 0 => u32::MAX,
+            (0x01, 4) => (a as i32).wrapping_div(b as i32) as u32,
+            (0x01, 5) if b == 0 => u32::MAX,
+            (0x01, 5) => a / b,
+            (0x01, 6) if b == 0 => a,
+            (0x01, 6) => (a as i32).wrapping_rem(b as i32) as u32,
+            (0x01, 7) if b == 0 => a,
+            (0x01, 7) => a % b,
+            _ => return None,
+        };
+        Some(value as i32 as i64 as u64)
+    }
+
+    // take over the pipeline's state after an instruction the reference skipped
+    pub fn sync(&mut self, cpu: &CPUState, mem: &Memory, inst: u32) {
+        self.reg = cpu.reg;
+        self.pc = None;
+        // SYSTEM (syscalls), AMO and FP stores may have written memory
+        if matches!(inst & 0x7f, 0x73 | 0x2f | 0x27) {
+            for (page, len) in mem.dirty_pages() {
+                if let (Ok(theirs), Ok(ours)) = (mem.read_bytes(page, len), self.mem.read_bytes(page, len)) {
+                    if theirs != ours {
+                        let _ = self.mem.write_bytes(page, &theirs);
+                    }
+                }
+            }
+        }
+    }
+
+    // run the instruction the pipeline just retired and compare, Err with a report on a mismatch
+    pub fn check(&mut self, pc: u64, inst: u32, cpu: &CPUState, mem: &Memory) -> Result<RefStep, String> {
+        let expected = match self.pc {
+            Some(expected) if expected != pc && trapped(cpu.csr.mcause) && cpu.csr.mepc == expected => pc,
+            Some(expected) => expected,
+            None => pc,
+        };
+        if expected != pc {
+            return Err(self.report(pc, inst, cpu, &format!("pipeline retired 0x{:08x}, reference expected 0x{:08x}", pc, expected)));
+        }
+        let step = self.step(pc);
+        if step == RefStep::Skipped {
+            self.sync(cpu, mem, inst);
+        } else if self.reg[1..] != cpu.reg[1..] {
+            return Err(self.report(pc, inst, cpu, "registers differ"));
+        }
+        Ok(step)
+    }
+
+    fn report(&self, pc: u64, inst: u32, cpu: &CPUState, what: &str) -> String {
+        let mut out = format!("difftest: {} at 0x{:08x}: {}\n", what, pc, disassemble(pc, inst));
+        out.push_str(&format!("{:<6} {:>18} {:>18}\n", "", "reference", "pipeline"));
+        for (name, (ours, theirs)) in REG_NAMES.iter().zip(self.reg.iter().zip(cpu.reg.iter())) {
+            let marker = if ours != theirs { "  <-" } else { "" };
+            out.push_str(&format!("{:<6} 0x{:016x} 0x{:016x}{}\n", name, ours, theirs, marker));
+        }
+        out
+    }
+}
+
+// the pipeline went to the handler instead of retiring the expected instruction
+fn trapped(mcause: u64) -> bool {
+    mcause == IRQ_M_TIMER || (CAUSE_MISALIGNED_LOAD..=CAUSE_STORE_ACCESS).contains(&mcause)
+}
+
+// run `image` on the pipeline with the reference in lockstep until it exits or they diverge
+pub fn run_difftest(image: &str, config: &SimConfig) -> Result<(), String> {
+    let (mut pipeline, mut mem) = config.build(image)?;
+    pipeline.set_quiet(&mut mem, true);
+    let mut reference = RefCpu::new(config, image)?;
+    let (mut checked, mut skipped) = (0u64, 0u64);
+
+    while pipeline.cpu.running {
+        if pipeline.cpu.cycle_count as u64 == MAX_CYCLES {
+            return Err(format!("difftest: no exit after {} cycles", MAX_CYCLES));
+        }
+        let retiring = pipeline.W_reg;
+        let retired = pipeline.cpu.inst_count;
+        pipeline.step(&mut mem);
+        // a cache stall or a fault leaves the instruction in WB
+        if retiring.seq == 0 || pipeline.cpu.inst_count == retired || pipeline.fault.is_some() {
+            continue;
+        }
+        match reference.check(retiring.pc, retiring.inst, &pipeline.cpu, &mem)? {
+            RefStep::Executed => checked += 1,
+            RefStep::Skipped => skipped += 1,
+        }
+    }
+    if let Some(fault) = &pipeline.fault {
+        return Err(format!("difftest: {}", fault));
+    }
+    println!("difftest: no divergence, {} instructions checked, {} skipped, exit code {}", checked, skipped, pipeline.cpu.reg[10] as i64);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_reference() {
+        // sum of i * i for i in 1..=10, with a loop, a call, loads and stores
+        let asm = "main:
+  addi sp, sp, -16
+  sd ra, 8(sp)
+  li a0, 0
+  li a1, 10
+loop:
+  mv a2, a1
+  call square
+  add a0, a0, a2
+  sw a0, 0(sp)
+  lw a0, 0(sp)
+  addi a1, a1, -1
+  bnez a1, loop
+  ld ra, 8(sp)
+  addi sp, sp, 16
+  ret
+square:
+  mulw a2, a2, a2
+  ret
+";
+        let image = crate::runner::test_image(asm);
+        let config = SimConfig::default();
+        assert_eq!(run_difftest(&image, &config), Ok(()));
+
+        // a wrong register value is caught at the next retired instruction
+        let mut reference = RefCpu::new(&config, &image).unwrap();
+        let mut cpu = CPUState::new();
+        cpu.reg = reference.reg;
+        cpu.reg[10] = 1;
+        let inst = 0xff01_0113; // addi sp, sp, -16
+        cpu.reg[2] = cpu.reg[2].wrapping_sub(16);
+        let report = reference.check(0x8000_0000, inst, &cpu, &config.new_memory()).unwrap_err();
+        assert!(report.contains("registers differ") && report.contains("a0"));
+    }
+}
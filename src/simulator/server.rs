@@ -0,0 +1,178 @@
+// Remote control over TCP: one JSON request per line, one JSON response per line.
+//
+//   -> {"id": 1, "method": "step", "params": {"n": 10}}
+//   <- {"id": 1, "result": {"pc": 2147483660, "cycle": 10, "running": true}}
+//   <- {"id": 2, "error": "unknown method 'foo'"}
+//
+// Methods:
+//   status                        pc / cycle / running / exit code
+//   step {n}                      run n cycles (default 1)
+//   continue {max_cycles}         run until exit or breakpoint (default limit 10^8)
+//   read_regs                     pc and x0..x31
+//   read_mem {addr, len}          len bytes starting at addr, at most 1 MiB
+//   set_breakpoint {addr, temporary, condition}
+//                                 stop `continue` when addr commits, e.g. condition "a0 == 5"
+//   clear_breakpoint {addr}       delete every breakpoint at addr
+//   read_output                   guest UART output written so far
+//   input {text}                  queue bytes for the guest UART
+//   reset                         reload the image and restart
+// Clients are served one at a time; the simulator state persists across connections.
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+use serde_json::{json, Value};
+
+use super::breakpoint::Condition;
+use super::config::SimConfig;
+use super::mem::Memory;
+use super::pipe::Pipeline;
+
+const DEFAULT_MAX_CYCLES: u64 = 100_000_000;
+const MAX_READ_LEN: u64 = 1 << 20;
+
+struct Server {
+    image: String,
+    config: SimConfig,
+    pipeline: Pipeline,
+    mem: Memory,
+}
+
+impl Server {
+    fn new(image: String, config: SimConfig) -> Self {
+        let (mut pipeline, mut mem) = config.build(&image).unwrap();
+        pipeline.set_quiet(&mut mem, true);
+        Self { image, config, pipeline, mem }
+    }
+
+    fn status(&self) -> Value {
+        let cpu = &self.pipeline.cpu;
+        json!({
+            "pc": cpu.pc,
+            "cycle": cpu.cycle_count,
+            "instructions": cpu.inst_count,
+            "running": cpu.running,
+            "exit_code": if cpu.running { Value::Null } else { json!(cpu.reg[10]) },
+        })
+    }
+
+    // returns true if a breakpoint was hit
+    fn step(&mut self) -> bool {
+        self.pipeline.step(&mut self.mem);
+        self.pipeline.breakpoint_hit.is_some()
+    }
+
+    fn breakpoint_addrs(&self) -> BTreeSet<u64> {
+        self.pipeline.breakpoints.iter().map(|b| b.addr).collect()
+    }
+
+    fn handle(&mut self, method: &str, params: &Value) -> Result<Value, String> {
+        let u64_param = |name: &str| params.get(name).and_then(Value::as_u64);
+
+        match method {
+            "status" => Ok(self.status()),
+            "step" => {
+                let n = u64_param("n").unwrap_or(1);
+                for _ in 0..n {
+                    if !self.pipeline.cpu.running || self.step() { break; }
+                }
+                Ok(self.status())
+            }
+            "continue" => {
+                let max = u64_param("max_cycles").unwrap_or(DEFAULT_MAX_CYCLES);
+                let mut breakpoint = false;
+                for _ in 0..max {
+                    if !self.pipeline.cpu.running { break; }
+                    if self.step() {
+                        breakpoint = true;
+                        break;
+                    }
+                }
+                let mut status = self.status();
+                status["breakpoint"] = json!(breakpoint);
+                Ok(status)
+            }
+            "read_regs" => Ok(json!({ "pc": self.pipeline.cpu.pc, "regs": self.pipeline.cpu.reg.to_vec() })),
+            "read_mem" => {
+                let addr = u64_param("addr").ok_or("missing 'addr'")?;
+                let len = u64_param("len").unwrap_or(4);
+                if len > MAX_READ_LEN {
+                    return Err(format!("'len' is limited to {} bytes", MAX_READ_LEN));
+                }
+                let bytes = (0..len)
+                    .map(|i| self.mem.mem_read(addr.wrapping_add(i), 1).map(|b| b as u8))
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map_err(|e| e.to_string())?;
+                Ok(json!({ "addr": addr, "data": bytes }))
+            }
+            "set_breakpoint" => {
+                let addr = u64_param("addr").ok_or("missing 'addr'")?;
+                let temporary = params.get("temporary").and_then(Value::as_bool).unwrap_or(false);
+                let condition = match params.get("condition").and_then(Value::as_str) {
+                    Some(text) => Some(Condition::parse(text)?),
+                    None => None,
+                };
+                self.pipeline.breakpoints.add(addr, temporary, condition);
+                Ok(json!({ "breakpoints": self.breakpoint_addrs() }))
+            }
+            "clear_breakpoint" => {
+                let addr = u64_param("addr").ok_or("missing 'addr'")?;
+                self.pipeline.breakpoints.remove_at(addr);
+                Ok(json!({ "breakpoints": self.breakpoint_addrs() }))
+            }
+            "read_output" => Ok(json!({ "output": String::from_utf8_lossy(&self.mem.uart.tx) })),
+            "input" => {
+                let text = params.get("text").and_then(Value::as_str).ok_or("missing 'text'")?;
+                self.mem.uart.push_input(text);
+                Ok(json!({ "queued": self.mem.uart.rx.len() }))
+            }
+            "reset" => {
+                let breakpoints = std::mem::take(&mut self.pipeline.breakpoints);
+                *self = Self::new(std::mem::take(&mut self.image), self.config.clone());
+                self.pipeline.breakpoints = breakpoints;
+                Ok(self.status())
+            }
+            _ => Err(format!("unknown method '{}'", method)),
+        }
+    }
+
+    fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<Value>(&line) {
+                Ok(req) => {
+                    let id = req.get("id").cloned().unwrap_or(Value::Null);
+                    let method = req.get("method").and_then(Value::as_str).unwrap_or("");
+                    match self.handle(method, req.get("params").unwrap_or(&Value::Null)) {
+                        Ok(result) => json!({ "id": id, "result": result }),
+                        Err(e) => json!({ "id": id, "error": e }),
+                    }
+                }
+                Err(e) => json!({ "id": null, "error": format!("invalid request: {}", e) }),
+            };
+            writeln!(writer, "{}", response)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn run_server(output: String, port: u16, config: SimConfig) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Simulator server listening on 127.0.0.1:{}", port);
+    let mut server = Server::new(output, config);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream.peer_addr()?;
+        println!("Client {} connected", peer);
+        if let Err(e) = server.serve(stream) {
+            eprintln!("Client {}: {}", peer, e);
+        }
+        println!("Client {} disconnected", peer);
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+// Checkpoints for `save FILE` / `load FILE`: CPUState, the pipeline latches, counters and cache
+// tags, plus the memory pages written since the image was loaded, as gzip-compressed JSON. Loading
+// starts from a fresh copy of the same image and puts the saved pages on top, so a checkpoint
+// stays small however large the memory is, and the run resumes cycle for cycle where it was saved.
+//
+// What only feeds the views (pipeline diagram, perf samples, profile, heatmap, call graph) is not
+// saved and starts over after a load; breakpoints belong to the debugger session and are kept.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use super::cache::CacheState;
+use super::config::SimConfig;
+use super::cpu::{CPUState, EXMEMReg, IDEXReg, IFIDReg, MEMWBReg};
+use super::ftrace::CallFrame;
+use super::mem::Memory;
+use super::perf::InstMix;
+use super::pipe::Pipeline;
+
+const CHECKPOINT_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    version: u32,
+    regions: Vec<(u64, u64)>, // [base, end) of every RAM and ROM region
+    image_size: u64, // a cheap check that the same image is loaded again
+
+    cpu: CPUState,
+    latches: (IFIDReg, IDEXReg, EXMEMReg, MEMWBReg),
+    f_stall: bool,
+    d_stall: bool,
+    next_seq: u64,
+    branch_count: u32,
+    data_hazard_count: u32,
+    branch_exec_count: u32,
+    flush_count: u32,
+    cache_stall: u64,
+    cache_stall_cycles: u64,
+    icache: Option<CacheState>,
+    dcache: Option<CacheState>,
+    inst_mix: InstMix,
+    call_stack: Vec<CallFrame>,
+
+    uart_rx: Vec<u8>,
+    uart_tx: Vec<u8>,
+    clint: (u64, u64, u64), // msip, mtimecmp, mtime
+    reservation: Option<u64>,
+    pages: Vec<(u64, Vec<u8>)>, // dirty pages by start address
+}
+
+impl Checkpoint {
+    pub fn capture(pipeline: &Pipeline, mem: &Memory) -> Self {
+        let pages = mem.dirty_pages()
+            .map(|(addr, len)| (addr, mem.read_bytes(addr, len).unwrap_or_default()))
+            .collect();
+        Self {
+            version: CHECKPOINT_VERSION,
+            regions: region_ranges(mem),
+            image_size: mem.image_size,
+            cpu: pipeline.cpu.clone(),
+            latches: (pipeline.D_reg, pipeline.E_reg, pipeline.M_reg, pipeline.W_reg),
+            f_stall: pipeline.f_stall,
+            d_stall: pipeline.d_stall,
+            next_seq: pipeline.next_seq,
+            branch_count: pipeline.branch_count,
+            data_hazard_count: pipeline.data_hazard_count,
+            branch_exec_count: pipeline.branch_exec_count,
+            flush_count: pipeline.flush_count,
+            cache_stall: pipeline.cache_stall,
+            cache_stall_cycles: pipeline.cache_stall_cycles,
+            icache: pipeline.icache.as_ref().map(|c| c.save()),
+            dcache: pipeline.dcache.as_ref().map(|c| c.save()),
+            inst_mix: pipeline.inst_mix.clone(),
+            call_stack: pipeline.calls.stack.clone(),
+            uart_rx: mem.uart.rx.iter().copied().collect(),
+            uart_tx: mem.uart.tx.clone(),
+            clint: (mem.clint.msip, mem.clint.mtimecmp, mem.clint.mtime),
+            reservation: mem.reservation,
+            pages,
+        }
+    }
+
+    // `pipeline` and `mem` must be freshly built from the image the checkpoint was taken from
+    pub fn restore(self, pipeline: &mut Pipeline, mem: &mut Memory) -> Result<(), String> {
+        if self.regions != region_ranges(mem) {
+            return Err(String::from("Checkpoint memory map differs from the configured one"));
+        }
+        if self.image_size != mem.image_size {
+            return Err(String::from("Checkpoint was taken from a different image"));
+        }
+        for (addr, data) in &self.pages {
+            mem.write_bytes(*addr, data).map_err(|e| format!("Bad checkpoint page 0x{:x}: {}", addr, e))?;
+        }
+        mem.uart.rx = self.uart_rx.into();
+        mem.uart.tx = self.uart_tx;
+        (mem.clint.msip, mem.clint.mtimecmp, mem.clint.mtime) = self.clint;
+        mem.reservation = self.reservation;
+
+        pipeline.enable_caches(self.icache.is_some());
+        if let (Some(cache), Some(state)) = (&mut pipeline.icache, self.icache) {
+            cache.restore(state)?;
+        }
+        if let (Some(cache), Some(state)) = (&mut pipeline.dcache, self.dcache) {
+            cache.restore(state)?;
+        }
+        pipeline.cpu = CPUState { quiet: pipeline.cpu.quiet, ..self.cpu };
+        (pipeline.D_reg, pipeline.E_reg, pipeline.M_reg, pipeline.W_reg) = self.latches;
+        pipeline.f_stall = self.f_stall;
+        pipeline.d_stall = self.d_stall;
+        pipeline.next_seq = self.next_seq;
+        pipeline.branch_count = self.branch_count;
+        pipeline.data_hazard_count = self.data_hazard_count;
+        pipeline.branch_exec_count = self.branch_exec_count;
+        pipeline.flush_count = self.flush_count;
+        pipeline.cache_stall = self.cache_stall;
+        pipeline.cache_stall_cycles = self.cache_stall_cycles;
+        pipeline.inst_mix = self.inst_mix;
+        pipeline.calls.stack = self.call_stack;
+        Ok(())
+    }
+
+    // returns the compressed size in bytes
+    pub fn save(&self, path: &str) -> Result<u64, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+        serde_json::to_writer(&mut encoder, self).map_err(|e| format!("{}: {}", path, e))?;
+        let file = encoder.finish().and_then(|w| w.into_inner().map_err(|e| e.into_error()))
+            .map_err(|e| format!("{}: {}", path, e))?;
+        Ok(file.metadata().map(|m| m.len()).unwrap_or(0))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let checkpoint: Checkpoint = serde_json::from_reader(GzDecoder::new(BufReader::new(file)))
+            .map_err(|e| format!("{}: not a checkpoint ({})", path, e))?;
+        if checkpoint.version != CHECKPOINT_VERSION {
+            return Err(format!("{}: checkpoint version {} is not supported", path, checkpoint.version));
+        }
+        Ok(checkpoint)
+    }
+
+    pub fn cycle(&self) -> i32 {
+        self.cpu.cycle_count
+    }
+}
+
+fn region_ranges(mem: &Memory) -> Vec<(u64, u64)> {
+    mem.regions().iter().map(|r| (r.base, r.end())).collect()
+}
+
+// a new pipeline and memory for `image`, resumed from the checkpoint at `path`
+pub fn load_checkpoint(path: &str, config: &SimConfig, image: &str) -> Result<(Pipeline, Memory), String> {
+    let checkpoint = Checkpoint::load(path)?;
+    let (mut pipeline, mut mem) = config.build(image)?;
+    checkpoint.restore(&mut pipeline, &mut mem)?;
+    Ok((pipeline, mem))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_checkpoint() {
+        // a0 = sum of 1..=20, stored to a global after every iteration
+        let asm = "main:
+  li a0, 0
+  li a1, 20
+  la a2, sum
+loop:
+  add a0, a0, a1
+  sw a0, 0(a2)
+  addi a1, a1, -1
+  bnez a1, loop
+  ret
+sum:
+  .word 0
+";
+        let image = crate::runner::test_image(asm);
+        let config = SimConfig::default();
+        let run = |pipeline: &mut Pipeline, mem: &mut Memory| {
+            while pipeline.cpu.running {
+                pipeline.step(mem);
+            }
+        };
+
+        let (mut pipeline, mut mem) = config.build(&image).unwrap();
+        pipeline.set_quiet(&mut mem, true);
+        for _ in 0..40 {
+            pipeline.step(&mut mem);
+        }
+        let path = crate::runner::test_dir().join("checkpoint.ckpt").to_string_lossy().to_string();
+        Checkpoint::capture(&pipeline, &mem).save(&path).unwrap();
+        run(&mut pipeline, &mut mem);
+
+        let (mut resumed, mut resumed_mem) = load_checkpoint(&path, &config, &image).unwrap();
+        resumed.set_quiet(&mut resumed_mem, true);
+        assert_eq!(resumed.cpu.cycle_count, 40);
+        run(&mut resumed, &mut resumed_mem);
+        assert_eq!(resumed.cpu.reg[10], 210);
+        assert_eq!(resumed.cpu.cycle_count, pipeline.cpu.cycle_count);
+        assert_eq!(resumed.cpu.inst_count, pipeline.cpu.inst_count);
+        assert!(load_checkpoint(&image, &config, &image).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+// Source line map produced by the compiler, used to show SysY source in the GUI.
+//
+// File format (one entry per line, '#' starts a comment):
+//   source <path to .c file>
+//   sym <name> <hex address>
+//   <hex address> <source line>
+// Addresses are sorted by the loader; an address maps to the line of the
+// closest entry at or below it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use object::{Object, ObjectSymbol, SymbolKind};
+
+use super::mem::is_elf;
+
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    pub source_path: String,
+    pub lines: Vec<String>,
+    addr_to_line: BTreeMap<u64, usize>,
+    symbols: BTreeMap<String, u64>,
+}
+
+impl SourceMap {
+    pub fn load(map_path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(map_path)
+            .map_err(|e| format!("Failed to read line map {}: {}", map_path, e))?;
+        let mut map = Self::parse(&text)?;
+
+        if map.source_path.is_empty() {
+            return Err(format!("Line map {} has no source entry", map_path));
+        }
+        let source = fs::read_to_string(&map.source_path)
+            .map_err(|e| format!("Failed to read source {}: {}", map.source_path, e))?;
+        map.lines = source.lines().map(String::from).collect();
+        Ok(map)
+    }
+
+    // line map next to the image: foo.bin -> foo.map;
+    // without one, an ELF image still provides its symbol table
+    pub fn load_for_image(image_path: &str) -> Result<Self, String> {
+        let map_path = Path::new(image_path).with_extension("map");
+        Self::load(&map_path.to_string_lossy())
+            .or_else(|e| if is_elf(image_path) { Self::from_elf(image_path) } else { Err(e) })
+    }
+
+    // symbols only (functions and data objects), no source lines
+    pub fn from_elf(path: &str) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let obj = object::File::parse(&*data).map_err(|e| format!("Failed to parse ELF {}: {}", path, e))?;
+        let mut map = Self::default();
+        for sym in obj.symbols() {
+            if let (Ok(name), SymbolKind::Text | SymbolKind::Data) = (sym.name(), sym.kind()) {
+                if !name.is_empty() {
+                    map.symbols.insert(name.to_string(), sym.address());
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut map = Self::default();
+        for (no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("source"), Some(path)) => map.source_path = path.to_string(),
+                (Some("sym"), Some(name)) => {
+                    let addr = parts.next()
+                        .and_then(|a| u64::from_str_radix(a.trim_start_matches("0x"), 16).ok())
+                        .ok_or_else(|| format!("line {}: invalid symbol address", no + 1))?;
+                    map.symbols.insert(name.to_string(), addr);
+                }
+                (Some(addr), Some(src_line)) => {
+                    let addr = u64::from_str_radix(addr.trim_start_matches("0x"), 16)
+                        .map_err(|_| format!("line {}: invalid address {}", no + 1, addr))?;
+                    let src_line = src_line.parse::<usize>()
+                        .map_err(|_| format!("line {}: invalid source line {}", no + 1, src_line))?;
+                    map.addr_to_line.insert(addr, src_line);
+                }
+                _ => return Err(format!("line {}: malformed entry", no + 1)),
+            }
+        }
+        Ok(map)
+    }
+
+    // 1-based source line for the instruction at `pc`
+    pub fn line_of(&self, pc: u64) -> Option<usize> {
+        self.addr_to_line.range(..=pc).next_back().map(|(_, line)| *line)
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<u64> {
+        self.symbols.get(name).copied()
+    }
+
+    // symbol containing `addr`, i.e. the closest one at or below it
+    pub fn symbol_at(&self, addr: u64) -> Option<(&str, u64)> {
+        self.symbols.iter()
+            .filter(|(_, a)| **a <= addr)
+            .max_by_key(|(_, a)| **a)
+            .map(|(name, a)| (name.as_str(), addr - a))
+    }
+
+    // lowest address generated for a source line, used to place breakpoints
+    pub fn addr_of(&self, line: usize) -> Option<u64> {
+        self.addr_to_line.iter().find(|(_, l)| **l == line).map(|(addr, _)| *addr)
+    }
+}
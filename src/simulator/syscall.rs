@@ -0,0 +1,105 @@
+// Linux-style system calls for `ecall`, so a program can do I/O and exit without knowing where
+// the UART is: a7 holds the call number, a0-a2 the arguments, and the result goes back to a0
+// (a negative errno on failure). fd 0 reads the UART input queue (what the frontend or a .in file
+// fed it), fd 1 and 2 write to the UART like any other guest output.
+//
+// execute_stage runs the call when ecall reaches EX and no trap handler is installed (mtvec = 0);
+// with one, ecall traps like on real hardware. The pipeline holds ecall in ID until the
+// instruction ahead of it has written back, so a0-a7 are up to date by then.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::cpu::CPUState;
+use super::mem::Memory;
+
+pub const SYS_READ: u64 = 63;
+pub const SYS_WRITE: u64 = 64;
+pub const SYS_EXIT: u64 = 93;
+pub const SYS_GETTIMEOFDAY: u64 = 169;
+
+const EBADF: i64 = 9;
+const EFAULT: i64 = 14;
+const ENOSYS: i64 = 38;
+
+// run the call for the ecall at `pc`, returns the new a0
+pub fn handle(cpu: &mut CPUState, mem: &mut Memory, pc: u64) -> u64 {
+    let (a0, a1, a2) = (cpu.reg[10], cpu.reg[11], cpu.reg[12]);
+    let result = match cpu.reg[17] {
+        SYS_READ => read(mem, a0, a1, a2),
+        SYS_WRITE => write(mem, a0, a1, a2),
+        SYS_EXIT => {
+            cpu.halt_trap(pc, a0);
+            Ok(a0 as i64)
+        }
+        SYS_GETTIMEOFDAY => gettimeofday(mem, a0),
+        _ => Err(ENOSYS),
+    };
+    result.unwrap_or_else(|errno| -errno) as u64
+}
+
+// returns as many bytes as are queued, up to `len`; 0 means end of input
+fn read(mem: &mut Memory, fd: u64, buf: u64, len: u64) -> Result<i64, i64> {
+    if fd != 0 {
+        return Err(EBADF);
+    }
+    mem.uart.refill();
+    let count = mem.uart.rx.len().min(len as usize);
+    let bytes: Vec<u8> = mem.uart.rx.iter().take(count).copied().collect();
+    mem.write_bytes(buf, &bytes).map_err(|_| EFAULT)?;
+    mem.uart.rx.drain(..count);
+    Ok(count as i64)
+}
+
+fn write(mem: &mut Memory, fd: u64, buf: u64, len: u64) -> Result<i64, i64> {
+    if fd != 1 && fd != 2 {
+        return Err(EBADF);
+    }
+    let bytes = mem.read_bytes(buf, len as usize).map_err(|_| EFAULT)?;
+    let port = mem.uart.base;
+    for byte in bytes {
+        mem.uart.write(port, byte as u64);
+    }
+    Ok(len as i64)
+}
+
+// struct timeval { long tv_sec; long tv_usec; } from the host clock; tz is ignored
+fn gettimeofday(mem: &mut Memory, tv: u64) -> Result<i64, i64> {
+    if tv != 0 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        mem.store(tv, 8, now.as_secs()).map_err(|_| EFAULT)?;
+        mem.store(tv + 8, 8, now.subsec_micros() as u64).map_err(|_| EFAULT)?;
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::embed::Simulator;
+
+    #[test]
+    fn test_syscalls() {
+        // read(0, buf, 8), write(1, buf, n), exit(n + 40)
+        let asm = "main:
+  addi sp, sp, -16
+  li a0, 0
+  mv a1, sp
+  li a2, 8
+  li a7, 63
+  ecall
+  mv a2, a0
+  li a0, 1
+  mv a1, sp
+  li a7, 64
+  ecall
+  addi a0, a0, 40
+  li a7, 93
+  ecall
+";
+        let path = crate::runner::test_image(asm);
+        let mut sim = Simulator::load(&path).unwrap();
+        sim.input("hi!");
+        assert_eq!(sim.run(Some(1000)), Some(43));
+        assert_eq!(sim.output(), b"hi!");
+    }
+}
@@ -0,0 +1,218 @@
+// Cycle-by-cycle record of which instruction occupied which pipeline stage.
+// Cell labels: "ID*" = held by a stall, "IDx" = squashed by a branch miss.
+
+use std::collections::{BTreeMap, VecDeque};
+
+pub const STAGE_NAMES: [&str; 5] = ["IF", "ID", "EX", "MEM", "WB"];
+
+const DEFAULT_CAPACITY: usize = 4096;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StageSlot {
+    pub seq: u64, // 0 means bubble
+    pub pc: u64,
+    pub inst: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CycleRecord {
+    pub cycle: i32,
+    pub stages: [StageSlot; 5],
+    pub stall: bool, // IF and ID held this cycle (load-use hazard)
+    pub flush: bool, // IF and ID squashed this cycle (branch misprediction)
+}
+
+#[derive(Debug, Clone)]
+pub struct GanttRow {
+    pub seq: u64,
+    pub pc: u64,
+    pub inst: u32,
+    pub cells: BTreeMap<i32, String>,
+}
+
+// A bounded ring buffer of cycle records
+#[derive(Debug)]
+pub struct Timeline {
+    records: VecDeque<CycleRecord>,
+    capacity: usize,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Timeline {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn record(&mut self, rec: CycleRecord) {
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(rec);
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &CycleRecord> {
+        self.records.iter()
+    }
+
+    pub fn last(&self) -> Option<&CycleRecord> {
+        self.records.back()
+    }
+
+    // cycle range [first, last] covered by the last `n` records
+    pub fn cycle_range(&self, n: usize) -> Option<(i32, i32)> {
+        let last = self.records.back()?.cycle;
+        let skip = self.records.len().saturating_sub(n);
+        let first = self.records.get(skip)?.cycle;
+        Some((first, last))
+    }
+
+    // build the instruction x cycle diagram for the last `n` cycles
+    pub fn gantt(&self, n: usize) -> Vec<GanttRow> {
+        let skip = self.records.len().saturating_sub(n);
+        let mut rows: BTreeMap<u64, GanttRow> = BTreeMap::new();
+
+        for rec in self.records.iter().skip(skip) {
+            for (stage, slot) in rec.stages.iter().enumerate() {
+                if slot.seq == 0 {
+                    continue;
+                }
+                let mut label = STAGE_NAMES[stage].to_string();
+                if stage <= 1 {
+                    if rec.flush {
+                        label.push('x');
+                    } else if rec.stall {
+                        label.push('*');
+                    }
+                }
+                rows.entry(slot.seq)
+                    .or_insert_with(|| GanttRow {
+                        seq: slot.seq,
+                        pc: slot.pc,
+                        inst: slot.inst,
+                        cells: BTreeMap::new(),
+                    })
+                    .cells
+                    .insert(rec.cycle, label);
+            }
+        }
+        rows.into_values().collect()
+    }
+
+    // the stages that held a bubble, per cycle, over the last `n` cycles
+    pub fn bubbles(&self, n: usize) -> BTreeMap<i32, Vec<&'static str>> {
+        let skip = self.records.len().saturating_sub(n);
+        self.records.iter().skip(skip)
+            .map(|rec| (rec.cycle, (0..5).filter(|&i| rec.stages[i].seq == 0).map(|i| STAGE_NAMES[i]).collect()))
+            .collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::new();
+        let Some((first, last)) = self.cycle_range(self.records.len()) else {
+            return out;
+        };
+
+        out.push_str("seq,pc,inst");
+        for cycle in first..=last {
+            out.push_str(&format!(",{}", cycle));
+        }
+        out.push('\n');
+
+        for row in self.gantt(self.records.len()) {
+            out.push_str(&format!("{},0x{:08x},0x{:08x}", row.seq, row.pc, row.inst));
+            for cycle in first..=last {
+                out.push(',');
+                if let Some(label) = row.cells.get(&cycle) {
+                    out.push_str(label);
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str("<title>Pipeline Diagram</title>\n<style>\n");
+        out.push_str("table { border-collapse: collapse; font-family: monospace; }\n");
+        out.push_str("th, td { border: 1px solid #999; padding: 2px 6px; text-align: center; }\n");
+        out.push_str("td.IF { background: #cfe2ff; } td.ID { background: #d1e7dd; }\n");
+        out.push_str("td.EX { background: #fff3cd; } td.MEM { background: #f8d7da; }\n");
+        out.push_str("td.WB { background: #e2d9f3; } td.stall { background: #dddddd; }\n");
+        out.push_str("td.squash { background: #555555; color: #ffffff; }\n");
+        out.push_str("</style>\n</head>\n<body>\n<table>\n");
+
+        if let Some((first, last)) = self.cycle_range(self.records.len()) {
+            out.push_str("<tr><th>pc</th><th>inst</th>");
+            for cycle in first..=last {
+                out.push_str(&format!("<th>{}</th>", cycle));
+            }
+            out.push_str("</tr>\n");
+
+            for row in self.gantt(self.records.len()) {
+                out.push_str(&format!("<tr><td>0x{:08x}</td><td>0x{:08x}</td>", row.pc, row.inst));
+                for cycle in first..=last {
+                    match row.cells.get(&cycle) {
+                        Some(label) => {
+                            let class = if label.ends_with('x') {
+                                "squash"
+                            } else if label.ends_with('*') {
+                                "stall"
+                            } else {
+                                label.as_str()
+                            };
+                            out.push_str(&format!("<td class=\"{}\">{}</td>", class, label));
+                        }
+                        None => out.push_str("<td></td>"),
+                    }
+                }
+                out.push_str("</tr>\n");
+            }
+        }
+
+        out.push_str("</table>\n</body>\n</html>\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gantt() {
+        let slot = |seq: u64| StageSlot { seq, pc: 0x8000_0000 + 4 * seq, inst: 0x13 };
+        let mut timeline = Timeline::new(2);
+        timeline.record(CycleRecord { cycle: 1, stages: [slot(1), slot(0), slot(0), slot(0), slot(0)], stall: false, flush: false });
+        timeline.record(CycleRecord { cycle: 2, stages: [slot(2), slot(1), slot(0), slot(0), slot(0)], stall: false, flush: true });
+        timeline.record(CycleRecord { cycle: 3, stages: [slot(3), slot(0), slot(0), slot(0), slot(0)], stall: false, flush: false });
+
+        assert_eq!(timeline.cycle_range(8), Some((2, 3)));
+        assert_eq!(timeline.last().unwrap().cycle, 3);
+        let rows = timeline.gantt(8);
+        assert_eq!(rows[0].cells[&2], "IDx");
+        assert_eq!(rows[1].cells[&2], "IFx");
+        assert_eq!(timeline.bubbles(1)[&3], ["ID", "EX", "MEM", "WB"]);
+    }
+}
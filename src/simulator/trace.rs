@@ -0,0 +1,184 @@
+// NEMU-style execution traces, each off by default:
+//
+//   itrace  every committed instruction with its disassembly
+//   mtrace  every load and store: pc, address, size and value
+//   ftrace  calls and returns with symbol names, indented by call depth
+//
+// They are turned on with --itrace/--mtrace/--ftrace, the [trace] config section or the `trace`
+// command, and go to stderr or to the file named by --trace-log. Independently of itrace, the
+// last ITRACE_RING_SIZE committed instructions are kept in a ring buffer that is dumped when the
+// program faults, so a crash always shows how execution got there.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+
+use super::disasm::disassemble;
+use super::srcmap::SourceMap;
+
+pub const ITRACE_RING_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceKind {
+    Inst,
+    Mem,
+    Func,
+}
+
+impl TraceKind {
+    pub const ALL: [TraceKind; 3] = [TraceKind::Inst, TraceKind::Mem, TraceKind::Func];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "i" | "itrace" => Some(TraceKind::Inst),
+            "m" | "mtrace" => Some(TraceKind::Mem),
+            "f" | "ftrace" => Some(TraceKind::Func),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TraceKind::Inst => "itrace",
+            TraceKind::Mem => "mtrace",
+            TraceKind::Func => "ftrace",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TraceFlags {
+    pub itrace: bool,
+    pub mtrace: bool,
+    pub ftrace: bool,
+}
+
+impl TraceFlags {
+    pub fn get(&self, kind: TraceKind) -> bool {
+        match kind {
+            TraceKind::Inst => self.itrace,
+            TraceKind::Mem => self.mtrace,
+            TraceKind::Func => self.ftrace,
+        }
+    }
+
+    pub fn set(&mut self, kind: TraceKind, on: bool) {
+        match kind {
+            TraceKind::Inst => self.itrace = on,
+            TraceKind::Mem => self.mtrace = on,
+            TraceKind::Func => self.ftrace = on,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Tracer {
+    pub flags: TraceFlags,
+    pub symbols: Option<SourceMap>, // names for ftrace, from the image's line map
+    ring: VecDeque<(u64, u32)>,     // (pc, inst), oldest first
+    log: Option<LineWriter<File>>,  // None: stderr
+}
+
+impl Tracer {
+    pub fn clear(&mut self) {
+        self.ring.clear();
+    }
+
+    pub fn open_log(&mut self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.log = Some(LineWriter::new(file));
+        Ok(())
+    }
+
+    fn emit(&mut self, line: &str) {
+        match &mut self.log {
+            Some(log) => {
+                let _ = writeln!(log, "{}", line);
+            }
+            None => eprintln!("{}", line),
+        }
+    }
+
+    // `name` or `name+off` for an address, the bare address without symbols
+    fn label(&self, addr: u64) -> String {
+        match self.symbols.as_ref().and_then(|m| m.symbol_at(addr)) {
+            Some((name, 0)) => format!("{}@0x{:08x}", name, addr),
+            Some((name, off)) => format!("{}+{}@0x{:08x}", name, off, addr),
+            None => format!("0x{:08x}", addr),
+        }
+    }
+
+    // an instruction reached writeback
+    pub fn commit(&mut self, pc: u64, inst: u32) {
+        if self.ring.len() == ITRACE_RING_SIZE {
+            self.ring.pop_front();
+        }
+        self.ring.push_back((pc, inst));
+        if self.flags.itrace {
+            self.emit(&format!("[itrace] 0x{:08x}: {:08x}  {}", pc, inst, disassemble(pc, inst)));
+        }
+    }
+
+    pub fn mem_access(&mut self, pc: u64, addr: u64, size: usize, write: bool, value: u64) {
+        if self.flags.mtrace {
+            let dir = if write { "write" } else { "read " };
+            self.emit(&format!("[mtrace] 0x{:08x}: {} {} bytes at 0x{:08x} = 0x{:x}", pc, dir, size, addr, value));
+        }
+    }
+
+    // `depth` is the number of frames below the callee
+    pub fn call(&mut self, depth: usize, pc: u64, target: u64) {
+        if self.flags.ftrace {
+            let line = format!("[ftrace] 0x{:08x}: {}call [{}]", pc, "  ".repeat(depth), self.label(target));
+            self.emit(&line);
+        }
+    }
+
+    // `depth` is the number of frames below the returning function
+    pub fn ret(&mut self, depth: usize, pc: u64, func: u64) {
+        if self.flags.ftrace {
+            let line = format!("[ftrace] 0x{:08x}: {}ret  [{}]", pc, "  ".repeat(depth), self.label(func));
+            self.emit(&line);
+        }
+    }
+
+    // the ring buffer, the last instruction marked with -->
+    pub fn format_ring(&self) -> String {
+        if self.ring.is_empty() {
+            return String::from("No instructions committed yet\n");
+        }
+        let mut out = format!("Last {} instructions:\n", self.ring.len());
+        for (i, &(pc, inst)) in self.ring.iter().enumerate() {
+            let marker = if i + 1 == self.ring.len() { "-->" } else { "   " };
+            out.push_str(&format!("{} 0x{:08x}: {:08x}  {}\n", marker, pc, inst, disassemble(pc, inst)));
+        }
+        out
+    }
+
+    pub fn dump_ring(&mut self) {
+        let text = self.format_ring();
+        self.emit(text.trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring() {
+        let mut tracer = Tracer::default();
+        for i in 0..ITRACE_RING_SIZE as u64 + 4 {
+            tracer.commit(0x8000_0000 + 4 * i, 0x0015_0513); // addi a0, a0, 1
+        }
+        let ring = tracer.format_ring();
+        let lines: Vec<&str> = ring.lines().collect();
+        assert_eq!(lines.len(), ITRACE_RING_SIZE + 1);
+        assert!(lines[1].contains("0x80000010"));
+        assert!(lines[ITRACE_RING_SIZE].starts_with("--> 0x8000004c"));
+
+        assert_eq!(TraceKind::parse("mtrace"), Some(TraceKind::Mem));
+        tracer.flags.set(TraceKind::Func, true);
+        assert!(tracer.flags.get(TraceKind::Func) && !tracer.flags.get(TraceKind::Inst));
+    }
+}
@@ -0,0 +1,407 @@
+// 终端界面（ratatui），适用于没有图形环境的服务器和 SSH 会话
+// 面板与 GUI 相同：寄存器、反汇编、内存、控制台
+
+use std::io::{self, Stdout};
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::{Frame, Terminal};
+
+use super::cpu::REG_NAMES;
+use super::disasm::disassemble;
+use super::breakpoint::Condition;
+use super::command::*;
+use super::config::SimConfig;
+use super::snapshot::load_checkpoint;
+use super::mem::Memory;
+use super::pipe::Pipeline;
+use super::srcmap::SourceMap;
+
+const STEPS_PER_FRAME: u32 = 10000;
+const MEM_ROW_BYTES: u64 = 8;
+const CONSOLE_LINES: usize = 200;
+
+struct TuiApp {
+    pipeline: Pipeline,
+    mem: Memory,
+    config: SimConfig,
+    image: String,
+    srcmap: Result<SourceMap, String>,
+    last_registers: [u64; 32],
+    mem_base: u64,
+    input: String,
+    console: Vec<String>,
+    running: bool, // 处于 c 连续运行状态
+    quit: bool,
+}
+
+impl TuiApp {
+    fn new(output: &str, config: &SimConfig) -> io::Result<Self> {
+        let (mut pipeline, mut mem) = config.build(output).map_err(io::Error::other)?;
+        pipeline.set_quiet(&mut mem, true);
+
+        Ok(Self {
+            last_registers: pipeline.cpu.reg,
+            pipeline,
+            mem,
+            config: config.clone(),
+            image: output.to_string(),
+            srcmap: config.symbols(output),
+            mem_base: 0x8000_0000,
+            input: String::new(),
+            console: vec![String::from("Type 'help' for a list of commands.")],
+            running: false,
+            quit: false,
+        })
+    }
+
+    fn print(&mut self, line: String) {
+        self.console.push(line);
+        if self.console.len() > CONSOLE_LINES {
+            self.console.remove(0);
+        }
+    }
+
+    // 单步执行，返回是否应停下（程序结束或命中断点）
+    fn step(&mut self) -> bool {
+        if !self.pipeline.cpu.running {
+            return true;
+        }
+        self.pipeline.step(&mut self.mem);
+        if !self.pipeline.cpu.running {
+            let code = self.pipeline.cpu.reg[10];
+            self.print(format!("Program exited with code {}", code));
+            for line in self.pipeline.statistics().to_string().lines() {
+                self.print(line.to_string());
+            }
+            return true;
+        }
+        if let Some(hit) = self.pipeline.breakpoint_hit.take() {
+            self.print(hit.to_string());
+            return true;
+        }
+        false
+    }
+
+    fn execute_command(&mut self) {
+        let line = std::mem::take(&mut self.input);
+        // 空行重复单步，类似 gdb
+        let line = if line.trim().is_empty() { String::from("si") } else { line };
+        self.print(format!("> {}", line));
+        self.last_registers = self.pipeline.cpu.reg;
+
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("c") => self.running = true,
+            Some("q") => self.quit = true,
+            Some("si") => {
+                let n = parts.next().and_then(|n| n.parse::<u32>().ok()).unwrap_or(1);
+                for _ in 0..n {
+                    if self.step() { break; }
+                }
+            }
+            Some("rsi") => {
+                let n = parts.next().and_then(|n| n.parse::<u32>().ok()).unwrap_or(1);
+                let msg = reverse_step(&mut self.pipeline, &mut self.mem, n);
+                self.print(msg.trim_end().to_string());
+            }
+            Some("x") => match parts.next().map(|a| resolve_location(a, &self.pipeline, &self.srcmap)) {
+                Some(Ok(addr)) => self.mem_base = addr & !(MEM_ROW_BYTES - 1),
+                Some(Err(e)) => self.print(e),
+                None => self.print(String::from("Usage: x ADDR")),
+            },
+            Some(cmd @ ("b" | "tbreak")) => {
+                let addr = parts.next().map(|a| resolve_location(a, &self.pipeline, &self.srcmap));
+                let condition = match parts.next() {
+                    Some("if") => Condition::parse(&parts.collect::<Vec<_>>().join(" ")).map(Some),
+                    Some(_) => Err(String::from("Usage: b ADDR [if REG OP VALUE]")),
+                    None => Ok(None),
+                };
+                match (addr, condition) {
+                    (Some(Ok(addr)), Ok(condition)) => {
+                        let msg = set_breakpoint(&mut self.pipeline.breakpoints, addr, cmd == "tbreak", condition);
+                        self.print(msg.trim_end().to_string());
+                    }
+                    (Some(Err(e)), _) | (_, Err(e)) => self.print(e),
+                    (None, _) => self.print(String::from("Usage: b ADDR [if REG OP VALUE]")),
+                }
+            }
+            Some("disas") => match parse_command_with(&line, &|s| resolve_location(s, &self.pipeline, &self.srcmap)) {
+                Ok(Some(Command::Disas { addr, count })) => {
+                    for text in format_disas(&self.pipeline, &self.mem, &self.srcmap, addr, count).lines() {
+                        self.print(text.to_string());
+                    }
+                }
+                Err(e) => self.print(e),
+                _ => {}
+            },
+            Some("trace") => match parse_command(&line) {
+                Ok(Some(Command::Trace(setting))) => {
+                    for text in trace_command(&mut self.pipeline.tracer, setting).lines() {
+                        self.print(text.to_string());
+                    }
+                }
+                Err(e) => self.print(e),
+                _ => {}
+            },
+            Some("p") => match parse_command(&line) {
+                Ok(Some(Command::Print(expr))) => {
+                    let msg = print_expression(&expr, &self.pipeline, &self.mem, &self.srcmap);
+                    self.print(msg.trim_end().to_string());
+                }
+                Err(e) => self.print(e),
+                _ => {}
+            },
+            Some("watch") => match parse_command_with(&line, &|s| resolve_location(s, &self.pipeline, &self.srcmap)) {
+                Ok(Some(Command::Watch { addr, len, kind })) => {
+                    let msg = set_watchpoint(&mut self.pipeline.breakpoints, addr, len, kind);
+                    self.print(msg.trim_end().to_string());
+                }
+                Err(e) => self.print(e),
+                _ => {}
+            },
+            Some("d" | "delete") => match parts.next().map(str::parse::<usize>).transpose() {
+                Ok(id) => {
+                    let msg = delete_breakpoints(&mut self.pipeline.breakpoints, id);
+                    self.print(msg.trim_end().to_string());
+                }
+                Err(_) => self.print(String::from("Invalid breakpoint number")),
+            },
+            Some("info") => {
+                let info = format_info(InfoTopic::Breakpoints, &self.pipeline, &self.mem);
+                for line in info.lines() {
+                    self.print(line.to_string());
+                }
+            }
+            Some("save") => match parse_command(&line) {
+                Ok(Some(Command::Save(path))) => {
+                    let msg = save_checkpoint(&self.pipeline, &self.mem, &path);
+                    self.print(msg.trim_end().to_string());
+                }
+                Err(e) => self.print(e),
+                _ => {}
+            },
+            Some("load") => match parse_command(&line) {
+                Ok(Some(Command::Load(path))) => match load_checkpoint(&path, &self.config, &self.image) {
+                    Ok((mut pipeline, mut mem)) => {
+                        pipeline.set_quiet(&mut mem, true);
+                        pipeline.breakpoints = std::mem::take(&mut self.pipeline.breakpoints);
+                        pipeline.tracer.flags = self.pipeline.tracer.flags;
+                        self.pipeline = pipeline;
+                        self.mem = mem;
+                        self.last_registers = self.pipeline.cpu.reg;
+                        self.print(format!("Loaded checkpoint {} at cycle {}", path, self.pipeline.cpu.cycle_count));
+                    }
+                    Err(e) => self.print(format!("Failed to load checkpoint {}", e)),
+                },
+                Err(e) => self.print(e),
+                _ => {}
+            },
+            Some("input") => {
+                // 原样送入串口，末尾补换行
+                let text = line.trim_start().trim_start_matches("input").trim_start();
+                self.mem.uart.push_input(text);
+                self.mem.uart.push_input("\n");
+            }
+            Some("help") => {
+                for help in [
+                    "c          - Continue execution (Esc to pause)",
+                    "q          - Quit the simulator",
+                    "si [N]     - Single step execution (N times, default 1; empty line repeats)",
+                    "rsi [N]    - Step N cycles backwards (default 1)",
+                    "x ADDR     - Show memory at ADDR (hex, symbol or register)",
+                    "b ADDR [if REG OP VALUE] - Set a breakpoint, e.g. b main if a0 == 3",
+                    "tbreak ADDR - Set a temporary breakpoint",
+                    "watch ADDR [LEN] [r|w|rw] - Stop on access to LEN bytes at ADDR",
+                    "p EXPR     - Evaluate an expression, e.g. p $a0 + 4, p *0x80001000",
+                    "disas [ADDR [N]] - Disassemble N instructions, marking pipeline stages",
+                    "d [N]      - Delete breakpoint or watchpoint N, or all",
+                    "info       - List breakpoints and watchpoints",
+                    "trace [itrace|mtrace|ftrace on|off] - Switch a trace, or show them and the last instructions",
+                    "save FILE  - Save a checkpoint",
+                    "load FILE  - Restart from a checkpoint, keeping breakpoints",
+                    "input TEXT - Send a line to the guest UART",
+                    "PgUp/PgDn  - Scroll the memory panel",
+                ] {
+                    self.print(help.to_string());
+                }
+            }
+            Some(cmd) => self.print(format!("Unknown command '{}'. Type 'help' for a list of commands.", cmd)),
+            None => {}
+        }
+    }
+
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Esc => self.running = false,
+            KeyCode::Enter if !self.running => self.execute_command(),
+            KeyCode::Backspace => { self.input.pop(); }
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::PageUp => self.mem_base = self.mem_base.wrapping_sub(MEM_ROW_BYTES * 16),
+            KeyCode::PageDown => self.mem_base = self.mem_base.wrapping_add(MEM_ROW_BYTES * 16),
+            _ => {}
+        }
+    }
+
+    fn draw(&self, f: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(10), Constraint::Length(12)])
+            .split(f.size());
+        let cols = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(46), Constraint::Percentage(50), Constraint::Min(30)])
+            .split(rows[0]);
+
+        self.draw_registers(f, cols[0]);
+        self.draw_disasm(f, cols[1]);
+        self.draw_memory(f, cols[2]);
+        self.draw_console(f, rows[1]);
+    }
+
+    fn draw_registers(&self, f: &mut Frame, area: Rect) {
+        let cpu = &self.pipeline.cpu;
+        let mut lines = vec![Line::from(format!("pc   0x{:016x}  cycle {}", cpu.pc, cpu.cycle_count))];
+        for i in 0..16 {
+            let mut spans = Vec::new();
+            for r in [i, i + 16] {
+                let style = if cpu.reg[r] != self.last_registers[r] {
+                    Style::default().fg(Color::Yellow)
+                } else {
+                    Style::default()
+                };
+                spans.push(Span::styled(format!("{:>4} {:016x} ", REG_NAMES[r], cpu.reg[r]), style));
+            }
+            lines.push(Line::from(spans));
+        }
+        f.render_widget(Paragraph::new(lines).block(titled("Registers")), area);
+    }
+
+    fn draw_disasm(&self, f: &mut Frame, area: Rect) {
+        let pc = self.pipeline.cpu.pc;
+        let rows = area.height.saturating_sub(2) as u64;
+        let start = pc.saturating_sub(rows / 3 * 4);
+        let mut lines = Vec::new();
+        for i in 0..rows {
+            let addr = start + i * 4;
+            let Ok(word) = self.mem.mem_read(addr, 4) else { break };
+            let marker = if self.pipeline.breakpoints.contains(addr) { "●" } else { " " };
+            let label = match &self.srcmap {
+                Ok(map) => map.symbol_at(addr).filter(|(_, off)| *off == 0).map(|(name, _)| format!("<{}>", name)),
+                Err(_) => None,
+            };
+            let text = format!("{} 0x{:08x} {:<10} {}", marker, addr, label.unwrap_or_default(), disassemble(addr, word as u32));
+            let style = if addr == pc {
+                Style::default().fg(Color::Black).bg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            lines.push(Line::styled(text, style));
+        }
+        f.render_widget(Paragraph::new(lines).block(titled("Disassembly")), area);
+    }
+
+    fn draw_memory(&self, f: &mut Frame, area: Rect) {
+        let rows = area.height.saturating_sub(2) as u64;
+        let mut lines = Vec::new();
+        for row in 0..rows {
+            let addr = self.mem_base.wrapping_add(row * MEM_ROW_BYTES);
+            let mut text = format!("0x{:08x} ", addr);
+            for col in 0..MEM_ROW_BYTES {
+                match self.mem.mem_read(addr.wrapping_add(col), 1) {
+                    Ok(b) => text.push_str(&format!(" {:02x}", b)),
+                    Err(_) => text.push_str(" ??"),
+                }
+            }
+            lines.push(Line::from(text));
+        }
+        f.render_widget(Paragraph::new(lines).block(titled("Memory")), area);
+    }
+
+    fn draw_console(&self, f: &mut Frame, area: Rect) {
+        let cols = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .split(area);
+
+        let height = cols[0].height.saturating_sub(3) as usize;
+        let mut lines: Vec<Line> = self.console.iter()
+            .skip(self.console.len().saturating_sub(height))
+            .map(|l| Line::from(l.as_str()))
+            .collect();
+        let prompt = if self.running { "(running, Esc to pause) " } else { "sim> " };
+        lines.push(Line::from(vec![
+            Span::styled(prompt, Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(self.input.as_str()),
+        ]));
+        f.render_widget(Paragraph::new(lines).block(titled("Console")), cols[0]);
+
+        // 客户程序通过串口输出的内容
+        let guest = String::from_utf8_lossy(&self.mem.uart.tx);
+        let height = cols[1].height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = guest.lines()
+            .skip(guest.lines().count().saturating_sub(height))
+            .map(|l| Line::from(l.to_string()))
+            .collect();
+        f.render_widget(Paragraph::new(lines).block(titled("Guest output")), cols[1]);
+    }
+}
+
+fn titled(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+fn event_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, app: &mut TuiApp) -> io::Result<()> {
+    while !app.quit {
+        terminal.draw(|f| app.draw(f))?;
+
+        // 连续运行时不阻塞，只处理已到达的按键
+        let mut timeout = if app.running { Duration::ZERO } else { Duration::from_millis(100) };
+        while event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key.code, key.modifiers);
+                }
+            }
+            timeout = Duration::ZERO;
+        }
+
+        if app.running {
+            for _ in 0..STEPS_PER_FRAME {
+                if app.step() {
+                    app.running = false;
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// 运行 TUI
+pub fn run_tui(output: String, config: SimConfig) -> io::Result<()> {
+    // 镜像加载失败时还没有接管终端，直接返回错误
+    let mut app = TuiApp::new(&output, &config)?;
+
+    enable_raw_mode()?;
+    let result = execute!(io::stdout(), EnterAlternateScreen)
+        .and_then(|_| Terminal::new(CrosstermBackend::new(io::stdout())))
+        .and_then(|mut terminal| {
+            let result = event_loop(&mut terminal, &mut app);
+            terminal.show_cursor()?;
+            result
+        });
+
+    // 无论初始化还是运行出错都要恢复终端
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    result
+}
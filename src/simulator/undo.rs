@@ -0,0 +1,203 @@
+// Reverse stepping for `rsi [N]`. Before every cycle the pipeline saves what the cycle can change:
+// CPUState, the pipeline latches and counters, the call stack and the device state, while Memory
+// journals the old bytes of every RAM write. Stepping back restores one cycle's entry and undoes
+// its writes newest first, so registers, memory and the latches are exactly as they were. Caches
+// are not rewound, so stepping forward again executes the same instructions, but a replayed load
+// or fetch can hit where it missed the first time and the cycle counts can differ.
+//
+// Recording costs a copy of CPUState, the UART input and the call stack every cycle, so it is off
+// (depth 0) unless `pipeline.undo_depth` asks for it; the interactive frontends (GUI, --shell,
+// --tui, --cli) keep the last UNDO_DEPTH cycles. Views and statistics (pipeline diagram, perf
+// samples, profile, instruction mix) are not rewound either, and guest output already echoed to
+// the host stays printed; it is only removed from the UART buffer.
+
+use std::collections::VecDeque;
+
+use super::cpu::{CPUState, EXMEMReg, IDEXReg, IFIDReg, MEMWBReg};
+use super::decode::DecodeError;
+use super::ftrace::CallFrame;
+use super::mem::Memory;
+use super::pipe::Pipeline;
+
+pub const UNDO_DEPTH: usize = 10000;
+
+#[derive(Debug, Clone, Copy)]
+struct Latches {
+    d: IFIDReg,
+    e: IDEXReg,
+    m: EXMEMReg,
+    w: MEMWBReg,
+    next_d: IFIDReg,
+    next_e: IDEXReg,
+    next_m: EXMEMReg,
+    next_w: MEMWBReg,
+}
+
+// everything needed to undo one cycle
+#[derive(Debug)]
+pub struct CycleUndo {
+    cpu: CPUState,
+    latches: Latches,
+    f_stall: bool,
+    d_stall: bool,
+    next_seq: u64,
+    branch_count: u32,
+    data_hazard_count: u32,
+    branch_exec_count: u32,
+    flush_count: u32,
+    cache_stall: u64,
+    cache_stall_cycles: u64,
+    fault: Option<DecodeError>,
+    call_stack: Vec<CallFrame>,
+    uart_rx: VecDeque<u8>,
+    uart_tx_len: usize,
+    clint: (u64, u64, u64), // msip, mtimecmp, mtime
+    reservation: Option<u64>,
+    mem_writes: Vec<(u64, Vec<u8>)>, // old bytes, oldest write first
+}
+
+impl CycleUndo {
+    pub fn capture(p: &Pipeline, mem: &Memory) -> Self {
+        Self {
+            cpu: p.cpu.clone(),
+            latches: Latches {
+                d: p.D_reg,
+                e: p.E_reg,
+                m: p.M_reg,
+                w: p.W_reg,
+                next_d: p.d_reg,
+                next_e: p.e_reg,
+                next_m: p.m_reg,
+                next_w: p.w_reg,
+            },
+            f_stall: p.f_stall,
+            d_stall: p.d_stall,
+            next_seq: p.next_seq,
+            branch_count: p.branch_count,
+            data_hazard_count: p.data_hazard_count,
+            branch_exec_count: p.branch_exec_count,
+            flush_count: p.flush_count,
+            cache_stall: p.cache_stall,
+            cache_stall_cycles: p.cache_stall_cycles,
+            fault: p.fault,
+            call_stack: p.calls.stack.clone(),
+            uart_rx: mem.uart.rx.clone(),
+            uart_tx_len: mem.uart.tx.len(),
+            clint: (mem.clint.msip, mem.clint.mtimecmp, mem.clint.mtime),
+            reservation: mem.reservation,
+            mem_writes: Vec::new(),
+        }
+    }
+
+    pub fn restore(self, p: &mut Pipeline, mem: &mut Memory) {
+        mem.undo_writes(self.mem_writes);
+        mem.uart.rx = self.uart_rx;
+        mem.uart.tx.truncate(self.uart_tx_len);
+        (mem.clint.msip, mem.clint.mtimecmp, mem.clint.mtime) = self.clint;
+        mem.reservation = self.reservation;
+
+        p.cpu = self.cpu;
+        let l = self.latches;
+        (p.D_reg, p.E_reg, p.M_reg, p.W_reg) = (l.d, l.e, l.m, l.w);
+        (p.d_reg, p.e_reg, p.m_reg, p.w_reg) = (l.next_d, l.next_e, l.next_m, l.next_w);
+        p.f_stall = self.f_stall;
+        p.d_stall = self.d_stall;
+        p.next_seq = self.next_seq;
+        p.branch_count = self.branch_count;
+        p.data_hazard_count = self.data_hazard_count;
+        p.branch_exec_count = self.branch_exec_count;
+        p.flush_count = self.flush_count;
+        p.cache_stall = self.cache_stall;
+        p.cache_stall_cycles = self.cache_stall_cycles;
+        p.fault = self.fault;
+        p.calls.stack = self.call_stack;
+        p.breakpoint_hit = None;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct UndoLog {
+    cycles: VecDeque<CycleUndo>,
+    pub depth: usize, // 0 turns recording off
+}
+
+impl UndoLog {
+    pub fn clear(&mut self) {
+        self.cycles.clear();
+    }
+
+    // cycles that can be stepped back
+    pub fn len(&self) -> usize {
+        self.cycles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cycles.is_empty()
+    }
+
+    // the memory writes of the last recorded cycle, once it has finished
+    pub fn attach_writes(&mut self, writes: Vec<(u64, Vec<u8>)>) {
+        if let Some(last) = self.cycles.back_mut() {
+            last.mem_writes.extend(writes);
+        }
+    }
+
+    pub fn push(&mut self, cycle: CycleUndo) {
+        if self.cycles.len() >= self.depth {
+            self.cycles.pop_front();
+        }
+        self.cycles.push_back(cycle);
+    }
+
+    pub fn pop(&mut self) -> Option<CycleUndo> {
+        self.cycles.pop_back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::config::SimConfig;
+    use super::UNDO_DEPTH;
+
+    #[test]
+    fn test_step_back() {
+        let asm = "main:
+  li a0, 1
+  la a1, value
+  sw a0, 0(a1)
+  addi a0, a0, 5
+  sw a0, 0(a1)
+  ret
+value:
+  .word 7
+";
+        let path = crate::runner::test_image(asm);
+        let mut config = SimConfig::default();
+        assert_eq!(config.build(&path).unwrap().0.undo.depth, 0);
+        config.pipeline.undo_depth = Some(UNDO_DEPTH);
+        let (mut pipeline, mut mem) = config.build(&path).unwrap();
+        pipeline.set_quiet(&mut mem, true);
+        let value = crate::codegen::encode::assemble(asm, 0x8000_0000).unwrap().symbols["value"];
+
+        let mut trace = Vec::new();
+        while pipeline.cpu.running {
+            trace.push((pipeline.cpu.pc, pipeline.cpu.reg, mem.mem_read(value, 4).unwrap()));
+            pipeline.step(&mut mem);
+        }
+        assert_eq!(mem.mem_read(value, 4).unwrap(), 6);
+        let cycles = pipeline.cpu.cycle_count;
+
+        // back to every earlier cycle, then forward again to the same state
+        for expected in trace.iter().rev() {
+            assert!(pipeline.step_back(&mut mem));
+            assert_eq!((pipeline.cpu.pc, pipeline.cpu.reg, mem.mem_read(value, 4).unwrap()), *expected);
+        }
+        assert!(!pipeline.step_back(&mut mem));
+        while pipeline.cpu.running {
+            pipeline.step(&mut mem);
+        }
+        assert_eq!(mem.mem_read(value, 4).unwrap(), 6);
+        assert_eq!(pipeline.cpu.cycle_count, cycles);
+    }
+}
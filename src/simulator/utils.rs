@@ -1 +1,17 @@
-// 
\ No newline at end of file
+// Terminal colors come from `colored` with the `gui` feature; headless builds print plain text.
+#[cfg(feature = "gui")]
+pub use colored::Colorize;
+
+#[cfg(not(feature = "gui"))]
+pub trait Colorize {
+    fn red(self) -> String;
+    fn green(self) -> String;
+    fn blue(self) -> String;
+}
+
+#[cfg(not(feature = "gui"))]
+impl Colorize for &str {
+    fn red(self) -> String { self.to_string() }
+    fn green(self) -> String { self.to_string() }
+    fn blue(self) -> String { self.to_string() }
+}
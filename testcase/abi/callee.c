@@ -0,0 +1,21 @@
+// SysY side of the ABI test in src/runner.rs, called from the gcc-compiled caller.c
+int many(int a, int b, int c, int d, int e, int f, int g, int h, int i, int j) {
+  return a + 2 * b + 3 * c + 4 * d + 5 * e + 6 * f + 7 * g + 8 * h + 9 * i + 10 * j;
+}
+
+int sum(int a[], int n) {
+  int s = 0;
+  int i = 0;
+  while (i < n) {
+    s = s + a[i];
+    i = i + 1;
+  }
+  return s;
+}
+
+int spill(int x) {
+  int a = x + 1; int b = a * 2; int c = b + a; int d = c * 3; int e = d - b; int f = e + x;
+  int g = f * 2; int h = g + c; int i = h - d; int j = i + e; int k = j * 2; int l = k + f;
+  int m = many(a, b, c, d, e, f, g, h, i, j);
+  return a + b + c + d + e + f + g + h + i + j + k + l + m;
+}
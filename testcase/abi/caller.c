@@ -0,0 +1,28 @@
+// gcc-compiled caller for the SysY functions in callee.c (see the ABI test in src/runner.rs);
+// exits with 0 when every call returns the right value and its own registers survive the calls
+#pragma GCC optimize("O2")
+
+int many(int a, int b, int c, int d, int e, int f, int g, int h, int i, int j);
+int sum(int a[], int n);
+int spill(int x);
+
+__asm__(".section entry, \"ax\"\n"
+        ".globl _start\n"
+        "_start:\n"
+        "  la sp, _stack_pointer\n"
+        "  call main\n"
+        "  ebreak\n"
+        ".text\n");
+
+volatile int seed = 1;
+
+int main(void) {
+  int a[5] = {1, 2, 3, 4, 5};
+  // kept in callee-saved registers across the calls
+  int x = seed * 7, y = seed * 11;
+  if (many(1, 2, 3, 4, 5, 6, 7, 8, 9, 10) != 385) return 1;
+  if (sum(a, 5) != 15) return 2;
+  if (spill(3) != 3208) return 3;
+  if (x != 7 || y != 11) return 4;
+  return 0;
+}
@@ -0,0 +1,130 @@
+.data
+.text
+.globl ackermann
+ackermann:
+  addi	sp, sp, -64
+  sd	fp, 0(sp)
+  sd	ra, 8(sp)
+  addi	fp, sp, 0
+  sd	s1, 24(fp)
+  sd	s2, 32(fp)
+  sd	s3, 40(fp)
+  sd	s4, 48(fp)
+  mv	s1, a0
+  mv	s2, a1
+ackermann_ackermann_0entry_0:
+  addi	t1, fp, 16
+  sw	s1, 0(t1)
+  addi	t1, fp, 20
+  sw	s2, 0(t1)
+  addi	t0, fp, 16
+  lw	s2, 0(t0)
+  li	t1, 0
+  xor	s1, s2, t1
+  seqz	s1, s1
+  bnez	s1, ackermann_ackermann_2then_1
+  j	ackermann_ackermann_5cond_2
+ackermann_ackermann_2then_1:
+  addi	t0, fp, 20
+  lw	s1, 0(t0)
+  li	t1, 1
+  addw	s2, s1, t1
+  mv	a0, s2
+  ld	s1, 24(fp)
+  ld	s2, 32(fp)
+  ld	s3, 40(fp)
+  ld	s4, 48(fp)
+  ld	ra, 8(sp)
+  ld	fp, 0(sp)
+  addi	sp, sp, 64
+  ret
+ackermann_ackermann_5cond_2:
+  addi	t0, fp, 20
+  lw	s2, 0(t0)
+  li	t1, 0
+  xor	s1, s2, t1
+  seqz	s1, s1
+  bnez	s1, ackermann_ackermann_6then_3
+  j	ackermann_ackermann_7else_4
+ackermann_ackermann_6then_3:
+  addi	t0, fp, 16
+  lw	s1, 0(t0)
+  li	t1, 1
+  subw	s2, s1, t1
+  mv	a0, s2
+  li	a1, 1
+  call	ackermann
+  mv	s1, a0
+  mv	a0, s1
+  ld	s1, 24(fp)
+  ld	s2, 32(fp)
+  ld	s3, 40(fp)
+  ld	s4, 48(fp)
+  ld	ra, 8(sp)
+  ld	fp, 0(sp)
+  addi	sp, sp, 64
+  ret
+ackermann_ackermann_7else_4:
+  addi	t0, fp, 16
+  lw	s1, 0(t0)
+  li	t1, 1
+  subw	s2, s1, t1
+  addi	t0, fp, 16
+  lw	s1, 0(t0)
+  addi	t0, fp, 20
+  lw	s3, 0(t0)
+  li	t1, 1
+  subw	s4, s3, t1
+  mv	a0, s1
+  mv	a1, s4
+  call	ackermann
+  mv	s3, a0
+  mv	a0, s2
+  mv	a1, s3
+  call	ackermann
+  mv	s4, a0
+  mv	a0, s4
+  ld	s1, 24(fp)
+  ld	s2, 32(fp)
+  ld	s3, 40(fp)
+  ld	s4, 48(fp)
+  ld	ra, 8(sp)
+  ld	fp, 0(sp)
+  addi	sp, sp, 64
+  ret
+.globl main
+main:
+  addi	sp, sp, -64
+  sd	fp, 0(sp)
+  sd	ra, 8(sp)
+  addi	fp, sp, 0
+  sd	s1, 32(fp)
+  sd	s2, 40(fp)
+  sd	s3, 48(fp)
+main_main_9entry_0:
+  li	t0, 2
+  addi	t1, fp, 16
+  sw	t0, 0(t1)
+  li	t0, 5
+  addi	t1, fp, 20
+  sw	t0, 0(t1)
+  addi	t0, fp, 16
+  lw	s1, 0(t0)
+  addi	t0, fp, 20
+  lw	s2, 0(t0)
+  mv	a0, s1
+  mv	a1, s2
+  call	ackermann
+  mv	s3, a0
+  addi	t1, fp, 24
+  sw	s3, 0(t1)
+  addi	t0, fp, 24
+  lw	s3, 0(t0)
+  mv	a0, s3
+  ld	s1, 32(fp)
+  ld	s2, 40(fp)
+  ld	s3, 48(fp)
+  ld	ra, 8(sp)
+  ld	fp, 0(sp)
+  addi	sp, sp, 64
+  ret
@@ -0,0 +1,12 @@
+.data
+.text
+.globl main
+main:
+  addi	sp, sp, -16
+  sd	fp, 0(sp)
+  addi	fp, sp, 0
+main_main_0entry_0:
+  li	a0, 0
+  ld	fp, 0(sp)
+  addi	sp, sp, 16
+  ret
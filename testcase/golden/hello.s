@@ -0,0 +1,23 @@
+.data
+  .globl global_0
+global_0:
+  .word 0
+.text
+.globl main
+main:
+  addi	sp, sp, -32
+  sd	fp, 0(sp)
+  addi	fp, sp, 0
+  sd	s1, 16(fp)
+  sd	s2, 24(fp)
+main_main_0entry_0:
+  la	t0, global_0
+  lw	s1, 0(t0)
+  li	t1, 1
+  addw	s2, s1, t1
+  mv	a0, s2
+  ld	s1, 16(fp)
+  ld	s2, 24(fp)
+  ld	fp, 0(sp)
+  addi	sp, sp, 32
+  ret